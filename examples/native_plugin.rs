@@ -0,0 +1,103 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Example of a native plugin, built with `cargo build --example native_plugin`.
+//! Copy the resulting library into the server's "plugins" folder, or use "/plugins load native_plugin".
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+use rcclassic::core::{CoreHandle, PluginApi, PluginDescriptor, PLUGIN_ABI_VERSION};
+
+struct State {
+    api: &'static PluginApi,
+    greeted: usize,
+}
+
+static DESCRIPTOR: PluginDescriptor = PluginDescriptor {
+    abi_version: PLUGIN_ABI_VERSION,
+    name: b"Greeter\0".as_ptr() as *const c_char,
+    version: b"1.0\0".as_ptr() as *const c_char,
+    on_load,
+    on_unload,
+};
+
+#[no_mangle]
+pub extern "C" fn rcclassic_plugin() -> *const PluginDescriptor {
+    &DESCRIPTOR
+}
+
+extern "C" fn on_load(api: *const PluginApi, core: *const CoreHandle) -> *mut c_void {
+    let api = unsafe { &*api };
+
+    (api.register_command)(
+        core,
+        b"hello\0".as_ptr() as *const c_char,
+        b"Greets you.\0".as_ptr() as *const c_char,
+        0,
+        hello_command,
+    );
+
+    // Every five minutes, at 20 ticks a second.
+    (api.schedule)(core, 20 * 60 * 5, 20 * 60 * 5, announce);
+
+    (api.log)(core, b"Greeter is ready.\0".as_ptr() as *const c_char);
+
+    Box::into_raw(Box::new(State { api, greeted: 0 })) as *mut c_void
+}
+
+extern "C" fn on_unload(state: *mut c_void, _core: *const CoreHandle) {
+    drop(unsafe { Box::from_raw(state as *mut State) });
+}
+
+extern "C" fn hello_command(
+    state: *mut c_void,
+    core: *const CoreHandle,
+    uid: usize,
+    _args: *const c_char,
+) {
+    let state = unsafe { &mut *(state as *mut State) };
+    let mut name = [0 as c_char; 64];
+
+    if (state.api.get_player_name)(core, uid, name.as_mut_ptr(), name.len()) < 0 {
+        return;
+    }
+
+    state.greeted += 1;
+
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy();
+    let message = format!(
+        "&eHello {}! You are greeting number {}.\0",
+        name, state.greeted
+    );
+
+    (state.api.send_message)(core, uid, message.as_ptr() as *const c_char);
+}
+
+extern "C" fn announce(state: *mut c_void, core: *const CoreHandle) {
+    let state = unsafe { &*(state as *const State) };
+
+    (state.api.broadcast)(
+        core,
+        b"&eType /hello to get greeted.\0".as_ptr() as *const c_char,
+    );
+}
@@ -0,0 +1,45 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use super::super::network::NetworkPacket;
+use super::Transform;
+
+/// Work the core does once the current message is handled, when no player or world is locked.
+/// Handlers queue these instead of locking other players or worlds themselves.
+pub enum Action {
+    /// Sends a packet to a player.
+    SendPacket(usize, Box<dyn NetworkPacket + Send>),
+    /// Sends a packet about an entity, if the player's client is able to display its id.
+    SendEntityPacket(usize, u8, Box<dyn NetworkPacket + Send>),
+    /// Sends a packet of a CPE extension, if the player's client supports it.
+    SendExtPacket(usize, &'static str, Box<dyn NetworkPacket + Send>),
+    /// Sends a chat message to a player.
+    SendMessage(usize, String),
+    /// Spawns the second player's entity for the first one, if both are in the same world.
+    ShowPlayer(usize, usize),
+    /// Moves a player into a world, loading it if needed.
+    JoinWorld(usize, String),
+    /// Moves a player inside their current world.
+    Teleport(usize, Transform),
+    /// Sends the map of a player's world again, after large changes.
+    ReloadMap(usize),
+}
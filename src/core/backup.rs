@@ -0,0 +1,386 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Numbered copies of saved maps, kept in "backups/{world}/{N}.lvl" inside the maps folder, the highest being the newest.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+
+use super::events::{MapDeleteEvent, MapRenameEvent};
+use super::maps::{get_map_file, MCSharpMap};
+use super::{
+    check_map_name, Action, Command, Core, EventBus, PlayerSnapshot, Plugin, Priority, Rank, World,
+    PHYSICS_OFF, TICK_INTERVAL,
+};
+
+pub fn get_backups_folder(maps_folder: &Path, world: &str) -> PathBuf {
+    maps_folder.join("backups").join(world)
+}
+
+pub fn get_backup_path(maps_folder: &Path, world: &str, number: u32) -> PathBuf {
+    get_backups_folder(maps_folder, world).join(format!("{}.lvl", number))
+}
+
+/// Backups of a map by number, oldest first, with the time they were made.
+pub fn get_backups(maps_folder: &Path, world: &str) -> Vec<(u32, SystemTime)> {
+    let mut backups: Vec<(u32, SystemTime)> = fs::read_dir(get_backups_folder(maps_folder, world))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let path = entry.path();
+
+                    if path.extension()? != "lvl" {
+                        return None;
+                    }
+
+                    let number = path.file_stem()?.to_str()?.parse().ok()?;
+
+                    Some((number, entry.metadata().ok()?.modified().ok()?))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    backups.sort_by_key(|(number, _)| *number);
+    backups
+}
+
+/// Whether the saved map changed since its newest backup, false if it was never saved.
+pub fn needs_backup(maps_folder: &Path, world: &str) -> bool {
+    let saved = match fs::metadata(get_map_file(maps_folder, world, "lvl"))
+        .and_then(|file| file.modified())
+    {
+        Ok(saved) => saved,
+        Err(_) => return false,
+    };
+
+    get_backups(maps_folder, world)
+        .last()
+        .is_none_or(|(_, backed_up)| saved > *backed_up)
+}
+
+/// Copies the saved map into a new backup, then removes the oldest beyond the number kept.
+/// Returns the number of the new backup.
+pub fn backup_map(maps_folder: &Path, world: &str, kept: usize) -> io::Result<u32> {
+    let backups = get_backups(maps_folder, world);
+    let number = backups.last().map_or(1, |(number, _)| number + 1);
+
+    fs::create_dir_all(get_backups_folder(maps_folder, world))?;
+    fs::copy(
+        get_map_file(maps_folder, world, "lvl"),
+        get_backup_path(maps_folder, world, number),
+    )?;
+
+    // The new backup is not in the list, it is always kept.
+    let removed = (backups.len() + 1).saturating_sub(kept.max(1));
+
+    for (old, _) in backups.iter().take(removed) {
+        fs::remove_file(get_backup_path(maps_folder, world, *old))?;
+    }
+
+    Ok(number)
+}
+
+/// Saves changed worlds and backs up the maps which changed since their last backup.
+pub fn backup_worlds(core: &Core) {
+    let kept = core.get_config().backups_kept;
+
+    for name in core.get_world_names() {
+        let modified = core
+            .with_world(&name, |world| world.is_modified() && !world.is_read_only())
+            .unwrap_or(false);
+
+        if modified && !core.save_world(&name) {
+            core.log(&format!("Unable to save \"{}\" for its backup.", name));
+
+            continue;
+        }
+
+        if needs_backup(core.get_maps_folder(), &name) {
+            if let Err(e) = backup_map(core.get_maps_folder(), &name, kept) {
+                core.log(&format!("Unable to back up \"{}\": {}", name, e));
+            }
+        }
+    }
+}
+
+/// Replaces the saved map with one of its backups, reloading the world if it is loaded.
+/// The map is backed up first if it changed, so restoring can be undone.
+pub fn restore_backup(core: &Core, world: &str, number: u32) -> Result<(), String> {
+    check_map_name(world)?;
+
+    let maps_folder = core.get_maps_folder();
+    let path = get_backup_path(maps_folder, world, number);
+
+    if !path.exists() {
+        return Err(format!("\"{}\" has no backup {}.", world, number));
+    }
+
+    if core
+        .with_world(world, |world| world.is_modified())
+        .unwrap_or(false)
+        && !core.save_world(world)
+    {
+        return Err(format!("Unable to save \"{}\" before restoring it.", world));
+    }
+
+    if needs_backup(maps_folder, world) {
+        backup_map(maps_folder, world, core.get_config().backups_kept)
+            .map_err(|e| format!("Unable to back up \"{}\": {}", world, e))?;
+    }
+
+    fs::copy(&path, get_map_file(maps_folder, world, "lvl"))
+        .map_err(|e| format!("Unable to restore \"{}\": {}", world, e))?;
+
+    if core.is_world_loaded(world) {
+        core.reload_world(world)?;
+    }
+
+    core.log(&format!(
+        "Map \"{}\" has been restored from backup {}.",
+        world, number
+    ));
+
+    Ok(())
+}
+
+/// Loads a backup as a read-only world of its own, returning the name of the world.
+/// It is unloaded like other worlds once everyone has left.
+pub fn load_backup(core: &Core, world: &str, number: u32) -> Result<String, String> {
+    check_map_name(world)?;
+
+    let name = format!("{}-backup-{}", world, number);
+
+    if core.is_world_loaded(&name) {
+        return Ok(name);
+    }
+
+    let map = MCSharpMap::load(&get_backup_path(core.get_maps_folder(), world, number))
+        .ok_or_else(|| format!("\"{}\" has no backup {}.", world, number))?;
+
+    let mut backup = World::new(name.clone(), Box::new(map));
+    backup.set_read_only(true);
+
+    if !core.add_world(backup) {
+        return Err(format!(
+            "Unable to load backup {} of \"{}\".",
+            number, world
+        ));
+    }
+
+    core.with_world_mut(&name, |world| {
+        world.get_physics_mut().set_level(PHYSICS_OFF)
+    });
+
+    Ok(name)
+}
+
+/// Backs up worlds on an interval, with /backups and /restore.
+pub struct BackupPlugin;
+
+impl Plugin for BackupPlugin {
+    fn get_name(&self) -> &str {
+        "Backups"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        events.register(
+            Priority::Lowest,
+            |core: &Core, event: &mut MapDeleteEvent| {
+                let folder = get_backups_folder(core.get_maps_folder(), &event.map);
+
+                if folder.exists() {
+                    if let Err(e) = fs::remove_dir_all(&folder) {
+                        core.log(&format!(
+                            "Unable to delete the backups of \"{}\": {}",
+                            event.map, e
+                        ));
+                    }
+                }
+            },
+        );
+
+        events.register(
+            Priority::Lowest,
+            |core: &Core, event: &mut MapRenameEvent| {
+                let maps_folder = core.get_maps_folder();
+                let folder = get_backups_folder(maps_folder, &event.from);
+
+                if folder.exists() {
+                    if let Err(e) = fs::rename(&folder, get_backups_folder(maps_folder, &event.to))
+                    {
+                        core.log(&format!(
+                            "Unable to move the backups of \"{}\": {}",
+                            event.from, e
+                        ));
+                    }
+                }
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let minutes = core.get_config().backup_interval;
+
+        if minutes > 0 {
+            let interval =
+                Duration::from_secs(minutes * 60).as_millis() / TICK_INTERVAL.as_millis();
+
+            core.schedule_repeating(self.get_name(), interval as u64, backup_worlds);
+        }
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "backups",
+            "Lists the backups of a map, your world if none is given. [map]",
+            Rank::Operator,
+            backups_command,
+        ));
+        core.register_command(Command::new(
+            self.get_name(),
+            "restore",
+            "Restores a map from a backup, or visits the backup with view. {map} {number} [view]",
+            Rank::Operator,
+            restore_command,
+        ));
+    }
+}
+
+fn backups_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let world = match args.trim() {
+        "" => player.world.clone(),
+        world => world.to_lowercase(),
+    };
+
+    if let Err(e) = check_map_name(&world) {
+        core.send_message_to(player.uid, &format!("&8{}", e));
+
+        return;
+    }
+
+    let backups = get_backups(core.get_maps_folder(), &world);
+
+    if backups.is_empty() {
+        core.send_message_to(player.uid, &format!("&7\"{}\" has no backups.", world));
+
+        return;
+    }
+
+    core.send_message_to(player.uid, &format!("&6Backups of \"{}\":", world));
+
+    for (number, time) in backups.iter().rev() {
+        core.send_message_to(
+            player.uid,
+            &format!(
+                "&7{} &8- {}",
+                number,
+                DateTime::<Local>::from(*time).format("%Y-%m-%d %H:%M:%S")
+            ),
+        );
+    }
+}
+
+fn restore_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let args: Vec<&str> = args.split_ascii_whitespace().collect();
+    let number = args.get(1).and_then(|number| number.parse::<u32>().ok());
+
+    let (world, number, view) = match (args.as_slice(), number) {
+        ([world, _], Some(number)) => (world.to_lowercase(), number, false),
+        ([world, _, "view"], Some(number)) => (world.to_lowercase(), number, true),
+        _ => {
+            core.send_message_to(player.uid, "&7Usage: /restore {map} {number} [view]");
+
+            return;
+        }
+    };
+
+    if view {
+        match load_backup(core, &world, number) {
+            Ok(name) => core.queue_action(Action::JoinWorld(player.uid, name)),
+            Err(e) => core.send_message_to(player.uid, &format!("&8{}", e)),
+        }
+
+        return;
+    }
+
+    match restore_backup(core, &world, number) {
+        Ok(()) => core.send_message_to(
+            player.uid,
+            &format!("&7\"{}\" has been restored from backup {}.", world, number),
+        ),
+        Err(e) => core.send_message_to(player.uid, &format!("&8{}", e)),
+    }
+}
+
+#[cfg(test)]
+mod test_backup {
+    use super::super::{Config, Vec3D};
+    use super::*;
+    use std::env;
+
+    #[test]
+    /// Backups are numbered, rotated, restored, visited as read-only worlds and follow their map.
+    pub fn rotate_and_restore() {
+        let folder = env::temp_dir().join(format!("rcclassic-backup-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+
+        let world = "test_backup_rotate";
+
+        core.create_map(world, Vec3D::new(16, 16, 16), "flat", 0)
+            .unwrap();
+
+        assert!(needs_backup(core.get_maps_folder(), world));
+        assert_eq!(backup_map(core.get_maps_folder(), world, 2).unwrap(), 1);
+        assert!(!needs_backup(core.get_maps_folder(), world));
+        assert_eq!(backup_map(core.get_maps_folder(), world, 2).unwrap(), 2);
+        assert_eq!(backup_map(core.get_maps_folder(), world, 2).unwrap(), 3);
+
+        let numbers: Vec<u32> = get_backups(core.get_maps_folder(), world)
+            .iter()
+            .map(|(n, _)| *n)
+            .collect();
+        assert_eq!(numbers, vec![2, 3]);
+
+        let name = load_backup(&core, world, 2).unwrap();
+        assert!(core.is_read_only(&name));
+        assert!(load_backup(&core, world, 1).is_err());
+
+        assert!(restore_backup(&core, world, 3).is_ok());
+        assert!(restore_backup(&core, world, 9).is_err());
+
+        let renamed = "test_backup_renamed";
+
+        assert!(core.rename_map(world, renamed).is_ok());
+        assert!(get_backups(core.get_maps_folder(), world).is_empty());
+        assert_eq!(get_backups(core.get_maps_folder(), renamed).len(), 2);
+
+        assert!(core.delete_map(renamed).is_ok());
+        assert!(!get_backups_folder(core.get_maps_folder(), renamed).exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Ids of the classic blocks.
+
+pub const AIR: u8 = 0;
+pub const STONE: u8 = 1;
+pub const GRASS: u8 = 2;
+pub const DIRT: u8 = 3;
+pub const COBBLESTONE: u8 = 4;
+pub const PLANKS: u8 = 5;
+pub const SAPLING: u8 = 6;
+pub const BEDROCK: u8 = 7;
+pub const WATER: u8 = 8;
+pub const STILL_WATER: u8 = 9;
+pub const LAVA: u8 = 10;
+pub const STILL_LAVA: u8 = 11;
+pub const SAND: u8 = 12;
+pub const GRAVEL: u8 = 13;
+pub const GOLD_ORE: u8 = 14;
+pub const IRON_ORE: u8 = 15;
+pub const COAL_ORE: u8 = 16;
+pub const LOG: u8 = 17;
+pub const LEAVES: u8 = 18;
+pub const SPONGE: u8 = 19;
+pub const GLASS: u8 = 20;
+pub const RED_FLOWER: u8 = 37;
+pub const YELLOW_FLOWER: u8 = 38;
+pub const BROWN_MUSHROOM: u8 = 39;
+pub const RED_MUSHROOM: u8 = 40;
+pub const GOLD: u8 = 41;
+pub const IRON: u8 = 42;
+pub const DOUBLE_SLAB: u8 = 43;
+pub const SLAB: u8 = 44;
+pub const BRICK: u8 = 45;
+pub const TNT: u8 = 46;
+pub const BOOKSHELF: u8 = 47;
+pub const MOSSY_COBBLESTONE: u8 = 48;
+pub const OBSIDIAN: u8 = 49;
+
+/// Highest block id of the original classic client.
+pub const MAX_CLASSIC_BLOCK: u8 = OBSIDIAN;
+
+const NAMES: [&str; 50] = [
+    "air",
+    "stone",
+    "grass",
+    "dirt",
+    "cobblestone",
+    "planks",
+    "sapling",
+    "bedrock",
+    "water",
+    "still_water",
+    "lava",
+    "still_lava",
+    "sand",
+    "gravel",
+    "gold_ore",
+    "iron_ore",
+    "coal_ore",
+    "log",
+    "leaves",
+    "sponge",
+    "glass",
+    "red",
+    "orange",
+    "yellow",
+    "lime",
+    "green",
+    "teal",
+    "aqua",
+    "cyan",
+    "blue",
+    "indigo",
+    "violet",
+    "magenta",
+    "pink",
+    "black",
+    "gray",
+    "white",
+    "red_flower",
+    "yellow_flower",
+    "brown_mushroom",
+    "red_mushroom",
+    "gold",
+    "iron",
+    "double_slab",
+    "slab",
+    "brick",
+    "tnt",
+    "bookshelf",
+    "mossy_cobblestone",
+    "obsidian",
+];
+
+/// Classic blocks shown instead of the CPE custom blocks, from 50 up to 65.
+const CPE_FALLBACKS: [u8; 16] = [
+    SLAB,
+    BROWN_MUSHROOM,
+    SAND,
+    AIR,
+    LAVA,
+    33,
+    25,
+    DIRT,
+    29,
+    28,
+    GLASS,
+    IRON,
+    OBSIDIAN,
+    36,
+    PLANKS,
+    STONE,
+];
+
+/// Classic block shown to clients in place of a block other servers support.
+/// Blocks past the CPE custom blocks are shown as stone.
+pub fn get_fallback(id: u16) -> u8 {
+    match id {
+        0..=49 => id as u8,
+        50..=65 => CPE_FALLBACKS[id as usize - 50],
+        _ => STONE,
+    }
+}
+
+pub fn get_name(block: u8) -> &'static str {
+    NAMES.get(block as usize).copied().unwrap_or("unknown")
+}
+
+/// Block by its name or id, names may use spaces or underscores.
+pub fn from_name(name: &str) -> Option<u8> {
+    let name = name.trim().to_lowercase().replace(' ', "_");
+
+    match name.parse::<u8>() {
+        Ok(id) if id <= MAX_CLASSIC_BLOCK => Some(id),
+        Ok(_) => None,
+        Err(_) => NAMES
+            .iter()
+            .position(|known| *known == name || known.replace('_', "") == name)
+            .map(|id| id as u8),
+    }
+}
@@ -0,0 +1,605 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Append-only log of every block players change, one file per world.
+//!
+//! Files start with "RCBD" and a version byte, followed by records of: x, y and z as
+//! little-endian u16, the old and new block, the unix time as little-endian u64, then the
+//! length of the player's name as a byte and the name itself.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+
+use super::events::{
+    BlocksChangedEvent, MapDeleteEvent, MapRenameEvent, PlayerLeaveEvent, SetBlockEvent,
+};
+use super::{
+    block, is_valid_map_name, parse_duration, BlockChange, Command, Core, Event, EventBus,
+    PlayerSnapshot, Plugin, Priority, Rank, Vec3D,
+};
+
+/// Folder block logs are kept in.
+pub const BLOCKDB_FOLDER: &str = "blockdb";
+
+const MAGIC: &[u8; 5] = b"RCBD\x01";
+
+/// Records shown when inspecting a block.
+const INSPECT_RECORDS: usize = 8;
+
+/// Block change read back from a log.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockRecord {
+    pub position: Vec3D,
+    pub old: u8,
+    pub new: u8,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    pub player: String,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Block logs of every world inside a folder.
+pub struct BlockDb {
+    folder: PathBuf,
+}
+
+impl BlockDb {
+    pub fn new<P: AsRef<Path>>(folder: P) -> BlockDb {
+        BlockDb {
+            folder: folder.as_ref().to_path_buf(),
+        }
+    }
+
+    fn get_path(&self, world: &str) -> PathBuf {
+        self.folder.join(format!("{}.bdb", world))
+    }
+
+    /// Appends changes made by a player at the given time.
+    pub fn append(
+        &self,
+        world: &str,
+        player: &str,
+        time: u64,
+        changes: &[BlockChange],
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.get_path(world))?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+
+        if is_new {
+            writer.write_all(MAGIC)?;
+        }
+
+        // Name lengths are stored in a byte, longer names are cut.
+        let name = &player.as_bytes()[..player.len().min(255)];
+
+        for change in changes {
+            let Vec3D(x, y, z) = change.position;
+
+            for value in &[x, y, z] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+
+            writer.write_all(&[change.old, change.new])?;
+            writer.write_all(&time.to_le_bytes())?;
+            writer.write_all(&[name.len() as u8])?;
+            writer.write_all(name)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Records of a world matching a filter, oldest first. Worlds without a log have no records.
+    pub fn read<F: Fn(&BlockRecord) -> bool>(
+        &self,
+        world: &str,
+        filter: F,
+    ) -> io::Result<Vec<BlockRecord>> {
+        let file = match File::open(self.get_path(world)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 5];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a block log",
+            ));
+        }
+
+        let mut records = Vec::new();
+        let mut fixed = [0; 17];
+
+        loop {
+            match reader.read_exact(&mut fixed) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+
+            let short = |index: usize| u16::from_le_bytes([fixed[index], fixed[index + 1]]);
+            let mut time = [0; 8];
+            time.copy_from_slice(&fixed[8..16]);
+
+            let mut name = vec![0; fixed[16] as usize];
+            reader.read_exact(&mut name)?;
+
+            let record = BlockRecord {
+                position: Vec3D::new(short(0), short(2), short(4)),
+                old: fixed[6],
+                new: fixed[7],
+                time: u64::from_le_bytes(time),
+                player: String::from_utf8_lossy(&name).into_owned(),
+            };
+
+            if filter(&record) {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Removes the log of a world, if it has one.
+    pub fn delete(&self, world: &str) -> io::Result<()> {
+        match fs::remove_file(self.get_path(world)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the log of a world to another, if it has one.
+    pub fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        match fs::rename(self.get_path(from), self.get_path(to)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Names of the worlds which have a log.
+    pub fn get_worlds(&self) -> Vec<String> {
+        let mut worlds: Vec<String> = fs::read_dir(&self.folder)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| {
+                        let path = entry.path();
+
+                        match path.extension() {
+                            Some(extension) if extension == "bdb" => {
+                                Some(path.file_stem()?.to_str()?.to_string())
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        worlds.sort();
+        worlds
+    }
+}
+
+type Inspecting = Arc<Mutex<HashSet<usize>>>;
+
+/// Logs block changes of players into a folder, with commands to inspect and roll them back.
+pub struct BlockDbPlugin {
+    db: Arc<BlockDb>,
+    inspecting: Inspecting,
+}
+
+impl BlockDbPlugin {
+    pub fn new<P: AsRef<Path>>(folder: P) -> BlockDbPlugin {
+        BlockDbPlugin {
+            db: Arc::new(BlockDb::new(folder)),
+            inspecting: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+}
+
+impl Plugin for BlockDbPlugin {
+    fn get_name(&self) -> &str {
+        "BlockDB"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        let db = self.db.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut BlocksChangedEvent| {
+                if let Err(e) = db.append(&event.world, &event.player, now(), &event.changes) {
+                    core.log(&format!(
+                        "Unable to log block changes of \"{}\": {}",
+                        event.world, e
+                    ));
+                }
+            },
+        );
+
+        let db = self.db.clone();
+
+        // History follows the map, so a new map of the same name starts without it.
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut MapDeleteEvent| {
+                if let Err(e) = db.delete(&event.map) {
+                    core.log(&format!(
+                        "Unable to delete the block log of \"{}\": {}",
+                        event.map, e
+                    ));
+                }
+            },
+        );
+
+        let db = self.db.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut MapRenameEvent| {
+                if let Err(e) = db.rename(&event.from, &event.to) {
+                    core.log(&format!(
+                        "Unable to move the block log of \"{}\": {}",
+                        event.from, e
+                    ));
+                }
+            },
+        );
+
+        let db = self.db.clone();
+        let inspecting = self.inspecting.clone();
+
+        // Inspecting comes before anything else, so clicks never change the world.
+        events.register(
+            Priority::Highest,
+            move |core: &Core, event: &mut SetBlockEvent| {
+                if inspecting.lock().unwrap().contains(&event.player.uid) {
+                    event.set_cancelled(true);
+                    inspect(&db, core, event);
+                }
+            },
+        );
+
+        let inspecting = self.inspecting.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |_: &Core, event: &mut PlayerLeaveEvent| {
+                inspecting.lock().unwrap().remove(&event.player.uid);
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let inspecting = self.inspecting.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "inspect",
+            "Toggles showing who changed the blocks you click.",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, _args: &str| {
+                let mut inspecting = inspecting.lock().unwrap();
+
+                let message = if inspecting.insert(player.uid) {
+                    "&7Click blocks to see their history, /inspect again to stop."
+                } else {
+                    inspecting.remove(&player.uid);
+
+                    "&7Stopped inspecting blocks."
+                };
+
+                drop(inspecting);
+                core.send_message_to(player.uid, message);
+            },
+        ));
+
+        let db = self.db.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "rollback",
+            "Restores blocks a player changed in every world. {player} {time}",
+            Rank::Operator,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let args: Vec<&str> = args.split_ascii_whitespace().collect();
+
+                match (
+                    args.as_slice(),
+                    args.get(1).and_then(|time| parse_duration(time)),
+                ) {
+                    ([name, _], Some(duration)) => {
+                        let since = now().saturating_sub(duration.as_secs());
+                        let count = rollback(&db, core, player, name, since);
+
+                        core.log(&format!(
+                            "{} rolled back {} blocks of {}.",
+                            player.name, count, name
+                        ));
+                        core.send_message_to(
+                            player.uid,
+                            &format!("&7{} blocks of {} rolled back.", count, name),
+                        );
+                    }
+                    _ => core.send_message_to(
+                        player.uid,
+                        "&7Usage: /rollback {player} {seconds(s/m/h)}",
+                    ),
+                }
+            },
+        ));
+
+        core.register_command_alias("b", "inspect");
+    }
+}
+
+fn format_time(time: u64) -> String {
+    DateTime::from_timestamp(time as i64, 0)
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+fn inspect(db: &BlockDb, core: &Core, event: &SetBlockEvent) {
+    let uid = event.player.uid;
+    let position = event.position;
+
+    let records = match db.read(&event.world, |record| record.position == position) {
+        Ok(records) => records,
+        Err(e) => {
+            core.send_message_to(uid, &format!("&8Unable to read the block log: {}", e));
+
+            return;
+        }
+    };
+
+    let Vec3D(x, y, z) = position;
+    core.send_message_to(
+        uid,
+        &format!(
+            "&6History of ({}, {}, {}): &7{} changes",
+            x,
+            y,
+            z,
+            records.len()
+        ),
+    );
+
+    let shown = records.len().saturating_sub(INSPECT_RECORDS);
+
+    for record in &records[shown..] {
+        core.send_message_to(
+            uid,
+            &format!(
+                "&7{} &f{} &7{} -> {}",
+                format_time(record.time),
+                record.player,
+                block::get_name(record.old),
+                block::get_name(record.new)
+            ),
+        );
+    }
+}
+
+// Puts back what a player changed since a time, latest change first. Blocks changed
+// by someone else since are left alone. Returns the number of blocks restored.
+fn rollback(db: &BlockDb, core: &Core, player: &PlayerSnapshot, name: &str, since: u64) -> usize {
+    if core.get_config().read_only {
+        core.send_message_to(player.uid, "&8Server is on read-only state.");
+
+        return 0;
+    }
+
+    let mut count = 0;
+
+    for world in db.get_worlds() {
+        if !is_valid_map_name(&world) {
+            continue;
+        }
+
+        let records = match db.read(&world, |record| {
+            record.time >= since && record.player.eq_ignore_ascii_case(name)
+        }) {
+            Ok(records) => records,
+            Err(e) => {
+                core.log(&format!(
+                    "Unable to read the block log of \"{}\": {}",
+                    world, e
+                ));
+
+                continue;
+            }
+        };
+
+        if records.is_empty() || (!core.is_world_loaded(&world) && !core.load_world(&world)) {
+            continue;
+        }
+
+        let changes = core.with_world_mut(&world, |world| {
+            let mut changes = Vec::new();
+
+            for record in records.iter().rev() {
+                if world.get_block(&record.position) == record.new {
+                    world.set_block(&record.position, record.old, false);
+
+                    changes.push(BlockChange {
+                        position: record.position,
+                        old: record.new,
+                        new: record.old,
+                        extended: None,
+                    });
+                }
+            }
+
+            changes
+        });
+
+        let changes = changes.unwrap_or_default();
+        let positions: Vec<Vec3D> = changes.iter().map(|change| change.position).collect();
+
+        core.send_blocks(&world, &positions);
+        count += changes.len();
+
+        // Rollbacks are logged and can be undone like any other edit.
+        core.record_edit(&player.name, &world, changes);
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod test_blockdb {
+    use super::super::Config;
+    use super::*;
+    use std::env;
+
+    fn blockdb_folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("rcclassic-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&folder);
+
+        folder
+    }
+
+    #[test]
+    /// Records are read back as written, in the order they were appended, and follow their world.
+    pub fn append_and_read() {
+        let folder = blockdb_folder("blockdb");
+        let db = BlockDb::new(&folder);
+
+        let change = |x, old, new| BlockChange {
+            position: Vec3D::new(x, 2, 3),
+            old,
+            new,
+            extended: None,
+        };
+
+        db.append("main", "alice", 10, &[change(1, 0, 1), change(2, 0, 4)])
+            .unwrap();
+        db.append("main", "bob", 20, &[change(1, 1, 0)]).unwrap();
+
+        let records = db.read("main", |record| record.position.0 == 1).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                BlockRecord {
+                    position: Vec3D::new(1, 2, 3),
+                    old: 0,
+                    new: 1,
+                    time: 10,
+                    player: String::from("alice"),
+                },
+                BlockRecord {
+                    position: Vec3D::new(1, 2, 3),
+                    old: 1,
+                    new: 0,
+                    time: 20,
+                    player: String::from("bob"),
+                },
+            ]
+        );
+
+        assert_eq!(db.get_worlds(), vec![String::from("main")]);
+        assert!(db.read("other", |_| true).unwrap().is_empty());
+
+        db.rename("main", "other").unwrap();
+        assert!(db.read("main", |_| true).unwrap().is_empty());
+        assert_eq!(db.read("other", |_| true).unwrap().len(), 3);
+
+        db.delete("other").unwrap();
+        db.delete("other").unwrap();
+        assert!(db.get_worlds().is_empty());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    /// Rolling back restores a player's blocks, keeping later changes of others.
+    pub fn rollback_player() {
+        let folder = blockdb_folder("rollback");
+
+        let core = Core::with_config(
+            1,
+            Config {
+                read_only: false,
+                ..Config::in_folder(&folder)
+            },
+        );
+
+        let owner = core.get_player_snapshot(0).unwrap();
+        let build = |name: &str, x: u16, block: u8| {
+            let (_, changes) = core
+                .with_world_mut("main", |world| {
+                    world.record(|world| world.set_block(&Vec3D::new(x, 40, 1), block, false))
+                })
+                .unwrap();
+
+            core.record_edit(name, "main", changes);
+        };
+        let block_at = |x: u16| {
+            core.with_world("main", |world| world.get_block(&Vec3D::new(x, 40, 1)))
+                .unwrap()
+        };
+
+        build("griefer", 1, block::TNT);
+        build("griefer", 2, block::TNT);
+        build("builder", 2, block::GLASS);
+
+        assert!(core.try_run_command(&owner, "/rollback griefer 1m"));
+        assert_eq!(block_at(1), block::AIR);
+        assert_eq!(block_at(2), block::GLASS);
+
+        let records = BlockDb::new(folder.join(BLOCKDB_FOLDER))
+            .read("main", |_| true)
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3].player, owner.name);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::{Core, PlayerSnapshot, Rank};
+
+/// Runs a command for a player, with everything after the command name as arguments.
+pub type CommandHandler = Arc<dyn Fn(&Core, &PlayerSnapshot, &str) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Command {
+    owner: String,
+    name: String,
+    description: String,
+    rank: Rank,
+    handler: CommandHandler,
+}
+
+impl Command {
+    /// Creates a command, owned by the plugin with the given name.
+    pub fn new<F>(owner: &str, name: &str, description: &str, rank: Rank, handler: F) -> Command
+    where
+        F: Fn(&Core, &PlayerSnapshot, &str) + Send + Sync + 'static,
+    {
+        Command {
+            owner: String::from(owner),
+            name: name.to_lowercase(),
+            description: String::from(description),
+            rank,
+            handler: Arc::new(handler),
+        }
+    }
+
+    pub fn get_owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    /// Lowest rank allowed to use the command.
+    pub fn get_rank(&self) -> Rank {
+        self.rank
+    }
+
+    pub fn get_handler(&self) -> CommandHandler {
+        self.handler.clone()
+    }
+}
+
+/// Commands registered by plugins, along with their aliases.
+#[derive(Default)]
+pub struct CommandList {
+    commands: BTreeMap<String, Command>,
+    aliases: BTreeMap<String, String>,
+}
+
+impl CommandList {
+    pub fn new() -> CommandList {
+        CommandList::default()
+    }
+
+    /// Adds a command, fails if the name is taken.
+    pub fn add(&mut self, command: Command) -> bool {
+        if self.get(command.get_name()).is_some() {
+            return false;
+        }
+
+        self.commands
+            .insert(String::from(command.get_name()), command);
+
+        true
+    }
+
+    /// Adds another name for an existing command.
+    pub fn add_alias(&mut self, alias: &str, name: &str) -> bool {
+        let alias = alias.to_lowercase();
+
+        if !self.commands.contains_key(&name.to_lowercase()) || self.get(&alias).is_some() {
+            return false;
+        }
+
+        self.aliases.insert(alias, name.to_lowercase());
+
+        true
+    }
+
+    /// Finds a command by its name or alias.
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        let name = name.to_lowercase();
+        let name = self.aliases.get(&name).unwrap_or(&name);
+
+        self.commands.get(name)
+    }
+
+    /// Removes every command (and alias) of a plugin.
+    pub fn remove_owned_by(&mut self, owner: &str) {
+        self.commands.retain(|_, command| command.owner != owner);
+
+        let commands = &self.commands;
+        self.aliases.retain(|_, name| commands.contains_key(name));
+    }
+
+    /// Commands in alphabetical order.
+    pub fn get_commands(&self) -> Vec<&Command> {
+        self.commands.values().collect()
+    }
+}
+
+/// Splits a chat message into a command name and its arguments, None if it is not a command.
+pub fn parse_command(message: &str) -> Option<(&str, &str)> {
+    let message = message.trim().strip_prefix('/')?;
+
+    match message.find(char::is_whitespace) {
+        Some(index) => Some((&message[..index], message[index..].trim())),
+        None if !message.is_empty() => Some((message, "")),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod test_command {
+    use super::*;
+
+    #[test]
+    /// Command names are split from their arguments, regular chat is not a command.
+    pub fn parse() {
+        assert_eq!(parse_command("/tp  bob "), Some(("tp", "bob")));
+        assert_eq!(parse_command("/help"), Some(("help", "")));
+        assert_eq!(parse_command("hello /tp"), None);
+        assert_eq!(parse_command("/"), None);
+    }
+
+    #[test]
+    /// Aliases resolve to their command, and go away with it.
+    pub fn aliases_and_removal() {
+        let mut commands = CommandList::new();
+
+        assert!(commands.add(Command::new("a", "join", "", Rank::Guest, |_, _, _| {})));
+        assert!(commands.add_alias("j", "join"));
+        assert!(!commands.add_alias("j", "join"));
+        assert!(!commands.add(Command::new("b", "J", "", Rank::Guest, |_, _, _| {})));
+
+        assert_eq!(commands.get("J").unwrap().get_name(), "join");
+
+        commands.remove_owned_by("a");
+
+        assert!(commands.get("j").is_none());
+        assert!(commands.get("join").is_none());
+    }
+}
@@ -0,0 +1,232 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+use std::fs;
+#[cfg(test)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::{
+    Core, Rank, BLOCKDB_FOLDER, PHYSICS_NORMAL, SCRIPTS_FOLDER, SPAWNS_FOLDER, WARPS_FOLDER,
+};
+
+const CONFIG_FILE: &str = "server.properties";
+
+/// Server settings, read from "server.properties" (key = value per line, # for comments).
+#[derive(Clone)]
+pub struct Config {
+    pub port: usize,
+    pub max_players: usize,
+    /// Nobody can change blocks while set.
+    pub read_only: bool,
+    /// Rank of players not listed in "ranks.properties".
+    pub default_rank: Rank,
+    /// Most blocks a single draw command may change, for each rank from guest to owner.
+    pub draw_limits: [usize; 4],
+    /// Most changed blocks kept for each player to undo.
+    pub undo_limit: usize,
+    /// Minutes players who left can still be undone after their last edit.
+    pub undo_time: u64,
+    /// Physics level worlds are loaded with, 0 being off.
+    pub physics_level: u8,
+    /// Most blocks physics update on a tick, shared by every world.
+    pub physics_budget: usize,
+    /// Players joining a world go back to where they left it, unless they set a spawn there.
+    pub remember_positions: bool,
+    /// Minutes between saving changed worlds and backing them up, 0 turning it off.
+    pub backup_interval: u64,
+    /// Backups kept for each map, the oldest being removed first.
+    pub backups_kept: usize,
+    /// Players have to log in with the hash of the salt and their name, from the server list.
+    pub verify_names: bool,
+    /// Salt given to the server list, names are hashed with it.
+    pub salt: String,
+    /// Folder maps are kept in, along with their backups and the files next to them.
+    pub maps_folder: PathBuf,
+    /// Folder the block logs of every world are kept in.
+    pub blockdb_folder: PathBuf,
+    /// Folder the spawns players set themselves are kept in.
+    pub spawns_folder: PathBuf,
+    /// Folder the warps are kept in.
+    pub warps_folder: PathBuf,
+    /// Folder scripts are loaded from.
+    pub scripts_folder: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 27015,
+            max_players: 128,
+            read_only: true,
+            default_rank: Rank::Guest,
+            draw_limits: [0, 10_000, 200_000, 5_000_000],
+            undo_limit: 1_000_000,
+            undo_time: 60,
+            physics_level: PHYSICS_NORMAL,
+            physics_budget: 10_000,
+            remember_positions: false,
+            backup_interval: 30,
+            backups_kept: 10,
+            verify_names: false,
+            salt: String::new(),
+            maps_folder: PathBuf::from("maps"),
+            blockdb_folder: PathBuf::from(BLOCKDB_FOLDER),
+            spawns_folder: PathBuf::from(SPAWNS_FOLDER),
+            warps_folder: PathBuf::from(WARPS_FOLDER),
+            scripts_folder: PathBuf::from(SCRIPTS_FOLDER),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, using defaults for missing keys or if the file does not exist.
+    pub fn load() -> Config {
+        let mut config = Config::default();
+
+        let content = match fs::read_to_string(CONFIG_FILE) {
+            Ok(content) => content,
+            Err(_) => {
+                Core::static_log(&format!(
+                    "Configuration file \"{}\" not found, using defaults.",
+                    CONFIG_FILE
+                ));
+
+                return config;
+            }
+        };
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(index) = line.find('=') {
+                let (key, value) = line.split_at(index);
+
+                config.set(key.trim(), value[1..].trim());
+            }
+        }
+
+        config
+    }
+
+    /// Sets a value by its key in the configuration file.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "port" => Config::parse_into(&mut self.port, key, value),
+            "max-players" => Config::parse_into(&mut self.max_players, key, value),
+            "read-only" => Config::parse_into(&mut self.read_only, key, value),
+            "default-rank" => Config::parse_into(&mut self.default_rank, key, value),
+            "draw-limit-guest" => Config::parse_into(&mut self.draw_limits[0], key, value),
+            "draw-limit-builder" => Config::parse_into(&mut self.draw_limits[1], key, value),
+            "draw-limit-operator" => Config::parse_into(&mut self.draw_limits[2], key, value),
+            "draw-limit-owner" => Config::parse_into(&mut self.draw_limits[3], key, value),
+            "undo-limit" => Config::parse_into(&mut self.undo_limit, key, value),
+            "undo-time" => Config::parse_into(&mut self.undo_time, key, value),
+            "physics-level" => Config::parse_into(&mut self.physics_level, key, value),
+            "physics-budget" => Config::parse_into(&mut self.physics_budget, key, value),
+            "remember-positions" => Config::parse_into(&mut self.remember_positions, key, value),
+            "backup-interval" => Config::parse_into(&mut self.backup_interval, key, value),
+            "backups-kept" => Config::parse_into(&mut self.backups_kept, key, value),
+            "verify-names" => Config::parse_into(&mut self.verify_names, key, value),
+            "salt" => self.salt = String::from(value),
+            "maps-folder" => Config::parse_into(&mut self.maps_folder, key, value),
+            "blockdb-folder" => Config::parse_into(&mut self.blockdb_folder, key, value),
+            "spawns-folder" => Config::parse_into(&mut self.spawns_folder, key, value),
+            "warps-folder" => Config::parse_into(&mut self.warps_folder, key, value),
+            "scripts-folder" => Config::parse_into(&mut self.scripts_folder, key, value),
+            _ => {
+                Core::static_log(&format!("Unknown configuration key \"{}\".", key));
+            }
+        }
+    }
+
+    /// Whether the verification key a player logged in with is the hash of the salt and their name.
+    /// Nobody is verified while names are not verified, or without a salt.
+    pub fn is_name_verified(&self, name: &str, verification_key: &str) -> bool {
+        if !self.verify_names || self.salt.is_empty() {
+            return false;
+        }
+
+        let hash = format!("{:x}", md5::compute(format!("{}{}", self.salt, name)));
+
+        hash.eq_ignore_ascii_case(verification_key)
+    }
+
+    pub fn get_draw_limit(&self, rank: Rank) -> usize {
+        self.draw_limits[rank as usize]
+    }
+
+    /// Defaults keeping every folder inside the given one, so tests leave the working directory alone.
+    #[cfg(test)]
+    pub(crate) fn in_folder(folder: &Path) -> Config {
+        Config {
+            maps_folder: folder.join("maps"),
+            blockdb_folder: folder.join(BLOCKDB_FOLDER),
+            spawns_folder: folder.join(SPAWNS_FOLDER),
+            warps_folder: folder.join(WARPS_FOLDER),
+            scripts_folder: folder.join(SCRIPTS_FOLDER),
+            ..Config::default()
+        }
+    }
+
+    fn parse_into<T: FromStr>(field: &mut T, key: &str, value: &str) {
+        match value.parse::<T>() {
+            Ok(parsed) => *field = parsed,
+            Err(_) => {
+                Core::static_log(&format!(
+                    "Invalid value \"{}\" for configuration key \"{}\".",
+                    value, key
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::*;
+
+    #[test]
+    /// Names are verified with the hash of the salt and the name, only while enabled.
+    pub fn name_verification() {
+        let mut config = Config {
+            salt: String::from("abcdef"),
+            ..Config::default()
+        };
+
+        let key = "5dfc9e1f10168a1fc4dc672d21d74056";
+        assert!(!config.is_name_verified("Alice", key));
+
+        config.verify_names = true;
+        assert!(config.is_name_verified("Alice", key));
+        assert!(config.is_name_verified("Alice", &key.to_uppercase()));
+        assert!(!config.is_name_verified("Bob", key));
+        assert!(!config.is_name_verified("Alice", ""));
+
+        config.salt.clear();
+        assert!(!config.is_name_verified("Alice", key));
+    }
+}
@@ -20,47 +20,410 @@
     SOFTWARE.
 */
 
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
 use std::io::prelude::*;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
 
 use std::sync::{
     mpsc::{channel, Receiver, Sender},
-    Arc,
+    Mutex, MutexGuard, RwLock,
 };
 use std::thread;
+use std::time::Duration;
 
-use chashmap::{CHashMap, ReadGuard, WriteGuard};
-use chrono::prelude::*;
+use chashmap::{CHashMap, WriteGuard};
 use chrono::Local;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use num_cpus;
 
 use super::super::network::*;
-use super::events;
-use super::maps::MCSharpMap;
-use super::{Console, Network, Player, Transform, Vec3D, World};
+use super::draw::DrawPlugin;
+use super::events::{
+    BlocksChangedEvent, BuiltinPlugin, MapDeleteEvent, MapRenameEvent, WorldJoinEvent,
+    WorldJoinedEvent, WorldLoadEvent, WorldUnloadEvent,
+};
+use super::generators::{find_generator, generate_map, GENERATOR_NAMES};
+use super::maps::{
+    get_map_file, load_map, load_physics_level, MCSharpMap, MemoryMap, LEVEL_EXTENSIONS,
+};
+use super::{
+    can_see_entity, get_special_blocks_path, get_zones_path, load_special_blocks, load_zones,
+    parse_command, Action, BackupPlugin, BlockChange, BlockDbPlugin, Command, CommandList, Config,
+    Console, Edit, Event, History, LoginState, Map, NativePlugin, Network, Player, PlayerSnapshot,
+    Plugin, PluginList, RankList, Scheduler, ScriptPlugin, SpawnPlugin, SpecialBlockPlugin,
+    Transform, UndoAmount, Vec3D, WarpPlugin, World, ZonePlugin, SELF_ENTITY_ID,
+};
+
+type BoxedPlayer = Box<dyn Player + Send + Sync>;
+
+/// Time between two ticks of the core, tasks and physics run on ticks.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Smallest and largest size of a generated map on each axis.
+pub const MIN_MAP_SIZE: u16 = 16;
+pub const MAX_MAP_SIZE: u16 = 1024;
+
+/// Most blocks a generated map may have.
+pub const MAX_MAP_VOLUME: usize = 1 << 26;
+
+/// Most block updates sent for one change, the map is sent again for larger ones.
+pub const MAX_BLOCK_UPDATES: usize = 8192;
+
+/// Folder native plugins are loaded from.
+const PLUGINS_FOLDER: &str = "plugins";
+
+/// Files kept for a map in the maps folder: the levels, then what is kept next to them.
+const MAP_FILES: [&str; 8] = [
+    "lvl",
+    "dat",
+    "mine",
+    "fcm",
+    "zones",
+    "blocks",
+    "properties",
+    "blockdefs.json",
+];
+
+/// Whether a map is saved in the maps folder.
+pub fn map_exists(maps_folder: &Path, name: &str) -> bool {
+    LEVEL_EXTENSIONS
+        .iter()
+        .any(|extension| get_map_file(maps_folder, name, extension).exists())
+}
+
+/// Fails unless every axis is within the map size limits and the map is not too large.
+pub fn check_map_size(size: Vec3D) -> Result<(), String> {
+    let Vec3D(x, y, z) = size;
+
+    if [x, y, z]
+        .iter()
+        .any(|axis| *axis < MIN_MAP_SIZE || *axis > MAX_MAP_SIZE)
+    {
+        return Err(format!(
+            "Map sizes have to be between {} and {}.",
+            MIN_MAP_SIZE, MAX_MAP_SIZE
+        ));
+    }
+
+    check_map_volume(size)
+}
+
+/// Fails if a map has no blocks or more than maps are allowed, without limiting each axis.
+/// Maps of other software are checked with this before their blocks are read.
+pub fn check_map_volume(size: Vec3D) -> Result<(), String> {
+    match size.0 as usize * size.1 as usize * size.2 as usize {
+        0 => Err(String::from("Map is empty.")),
+        volume if volume > MAX_MAP_VOLUME => Err(String::from("Map is too large.")),
+        _ => Ok(()),
+    }
+}
+
+/// Map names become file names, so only letters, digits, '_' and '-' are allowed.
+pub fn is_valid_map_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Fails unless the name can be used as a map name, so it never leaves the maps folder.
+pub fn check_map_name(name: &str) -> Result<(), String> {
+    if !is_valid_map_name(name) {
+        return Err(format!("\"{}\" is not a valid map name.", name));
+    }
+
+    Ok(())
+}
+
+/// Concurrent list of connected players (and console), keyed by their session uid.
+///
+/// CHashMap cannot be iterated and deadlocks when an entry is locked twice on the same thread,
+/// so a snapshot of every player and the uids currently locked are tracked next to it.
+/// Locking a player that is already locked returns None instead of hanging the core.
+pub struct PlayerList {
+    players: CHashMap<usize, BoxedPlayer>,
+    snapshots: RwLock<BTreeMap<usize, PlayerSnapshot>>,
+    in_use: Mutex<HashSet<usize>>,
+}
+
+impl PlayerList {
+    pub fn new() -> PlayerList {
+        PlayerList {
+            players: CHashMap::new(),
+            snapshots: RwLock::new(BTreeMap::new()),
+            in_use: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Adds a player. Must not be called while any player is locked, inserting may resize the map.
+    pub fn insert(&self, uid: usize, player: BoxedPlayer) -> bool {
+        if !self.in_use.lock().unwrap().is_empty() {
+            Core::static_log(&format!(
+                "Cannot add player {} while other players are in use.",
+                uid
+            ));
+
+            return false;
+        }
+
+        let snapshot = PlayerSnapshot::new(player.as_ref());
+
+        self.players.insert(uid, player);
+        self.snapshots.write().unwrap().insert(uid, snapshot);
+
+        true
+    }
+
+    pub fn remove(&self, uid: usize) -> bool {
+        if self.in_use.lock().unwrap().contains(&uid) {
+            Core::static_log(&format!("Cannot remove player {} while in use.", uid));
+
+            return false;
+        }
+
+        self.snapshots.write().unwrap().remove(&uid);
+        self.players.remove(&uid);
+
+        true
+    }
+
+    pub fn contains(&self, uid: usize) -> bool {
+        self.snapshots.read().unwrap().contains_key(&uid)
+    }
+
+    /// Locks a player, None if the player does not exist.
+    /// Locking it again before the guard is dropped panics in debug builds, and returns None otherwise.
+    pub fn get_mut(&self, uid: usize) -> Option<PlayerGuard<'_>> {
+        let locked = self.in_use.lock().unwrap().insert(uid);
+
+        // Locking a player twice is a bug, the second access has to be queued as an action.
+        debug_assert!(locked, "Player {} is already in use.", uid);
+
+        if !locked {
+            Core::static_log(&format!(
+                "Player {} is already in use, access was skipped.",
+                uid
+            ));
+
+            return None;
+        }
+
+        match self.players.get_mut(&uid) {
+            Some(guard) => Some(PlayerGuard {
+                guard,
+                list: self,
+                uid,
+            }),
+            None => {
+                self.in_use.lock().unwrap().remove(&uid);
+
+                None
+            }
+        }
+    }
+
+    /// Number of players in the list, console included.
+    pub fn len(&self) -> usize {
+        self.snapshots.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.read().unwrap().is_empty()
+    }
+
+    /// Returns the uids of every player at the moment, in ascending order.
+    pub fn uids(&self) -> Vec<usize> {
+        self.snapshots.read().unwrap().keys().copied().collect()
+    }
+
+    /// Player's state as of the last time it was unlocked.
+    pub fn snapshot(&self, uid: usize) -> Option<PlayerSnapshot> {
+        self.snapshots.read().unwrap().get(&uid).cloned()
+    }
+
+    /// States of every player, in ascending order of uids.
+    pub fn snapshots(&self) -> Vec<PlayerSnapshot> {
+        self.snapshots.read().unwrap().values().cloned().collect()
+    }
+}
+
+impl Default for PlayerList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A locked player. Its snapshot is refreshed once unlocked.
+pub struct PlayerGuard<'a> {
+    guard: WriteGuard<'a, usize, BoxedPlayer>,
+    list: &'a PlayerList,
+    uid: usize,
+}
+
+impl<'a> Deref for PlayerGuard<'a> {
+    type Target = BoxedPlayer;
+
+    fn deref(&self) -> &BoxedPlayer {
+        &self.guard
+    }
+}
+
+impl<'a> DerefMut for PlayerGuard<'a> {
+    fn deref_mut(&mut self) -> &mut BoxedPlayer {
+        &mut self.guard
+    }
+}
+
+impl<'a> Drop for PlayerGuard<'a> {
+    fn drop(&mut self) {
+        let snapshot = PlayerSnapshot::new(self.guard.as_ref());
+
+        self.list
+            .snapshots
+            .write()
+            .unwrap()
+            .insert(self.uid, snapshot);
+        self.list.in_use.lock().unwrap().remove(&self.uid);
+    }
+}
+
+/// Concurrent list of loaded worlds, keyed by their name.
+/// Worlds are only reachable through closures, so a world is never locked longer than needed.
+/// Same as players, accessing a world that is already locked returns None.
+pub struct WorldList {
+    worlds: CHashMap<String, World>,
+    names: RwLock<BTreeSet<String>>,
+    in_use: Mutex<HashSet<String>>,
+}
+
+impl WorldList {
+    pub fn new() -> WorldList {
+        WorldList {
+            worlds: CHashMap::new(),
+            names: RwLock::new(BTreeSet::new()),
+            in_use: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Adds a world. Must not be called while any world is locked, inserting may resize the map.
+    pub fn insert(&self, world: World) -> bool {
+        if !self.in_use.lock().unwrap().is_empty() {
+            Core::static_log(&format!(
+                "Cannot add world \"{}\" while other worlds are in use.",
+                world.get_name()
+            ));
+
+            return false;
+        }
+
+        self.names
+            .write()
+            .unwrap()
+            .insert(String::from(world.get_name()));
+        self.worlds.insert(String::from(world.get_name()), world);
+
+        true
+    }
+
+    pub fn remove(&self, name: &str) -> Option<World> {
+        if self.in_use.lock().unwrap().contains(name) {
+            Core::static_log(&format!("Cannot remove world \"{}\" while in use.", name));
+
+            return None;
+        }
+
+        self.names.write().unwrap().remove(name);
+        self.worlds.remove(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.worlds.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.worlds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.worlds.is_empty()
+    }
+
+    /// Names of the loaded worlds, sorted.
+    pub fn names(&self) -> Vec<String> {
+        self.names.read().unwrap().iter().cloned().collect()
+    }
+
+    pub fn with<R, F: FnOnce(&World) -> R>(&self, name: &str, f: F) -> Option<R> {
+        self.with_mut(name, |world| f(world))
+    }
+
+    pub fn with_mut<R, F: FnOnce(&mut World) -> R>(&self, name: &str, f: F) -> Option<R> {
+        let locked = self.in_use.lock().unwrap().insert(String::from(name));
+
+        // Locking a world twice is a bug, the second access has to be queued as an action.
+        debug_assert!(locked, "World \"{}\" is already in use.", name);
+
+        if !locked {
+            Core::static_log(&format!(
+                "World \"{}\" is already in use, access was skipped.",
+                name
+            ));
+
+            return None;
+        }
 
-pub type PlayerList = Arc<CHashMap<usize, Box<dyn Player + Send + Sync>>>;
-pub type WorldList = Arc<CHashMap<String, World>>;
+        let result = self.worlds.get_mut(name).map(|mut world| f(&mut world));
 
-// Shortcut for easier event handling.
-pub type SyncPlayer<'ply> = &'ply mut (dyn Player + Send + Sync);
+        self.in_use.lock().unwrap().remove(name);
+
+        result
+    }
+}
+
+impl Default for WorldList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct Core {
     pub threadsize: usize,
 
+    config: Config,
+
     players: PlayerList,
     worlds: WorldList,
 
+    ranks: RankList,
+
+    plugins: PluginList,
+    natives: Mutex<Vec<NativePlugin>>,
+    commands: RwLock<CommandList>,
+    scheduler: Mutex<Scheduler>,
+    history: Mutex<History>,
+
+    // Work queued by handlers, done once the current message is handled.
+    actions: Mutex<VecDeque<Action>>,
+
     tx: Option<Sender<Box<dyn NetworkPacket + Send>>>,
     rx: Option<Receiver<Box<dyn NetworkPacket + Send>>>,
 }
 
 impl Core {
-    /// Creates a new rcclassic Core with number of threads to handle player connections.
-    /// 'threadsize' can be left 0 to use the the physical core count.
-    pub fn new(mut threadsize: usize) -> Core {
-        if threadsize <= 0 {
+    /// Creates a new rcclassic Core with the number of threads plugins may use for their own work,
+    /// connections having threads of their own. 'threadsize' can be left 0 to use the the physical core count.
+    pub fn new(threadsize: usize) -> Core {
+        Core::with_config(threadsize, Config::load())
+    }
+
+    /// Creates a core with the given settings instead of the configuration file.
+    pub fn with_config(mut threadsize: usize, config: Config) -> Core {
+        if threadsize == 0 {
             threadsize = num_cpus::get_physical();
 
             Core::static_log("Thread size cannot be 0 or less. Using default (# CPU Cores).");
@@ -71,39 +434,68 @@ impl Core {
             threadsize
         ));
 
-        let players: PlayerList = Arc::new(CHashMap::new());
-
-        (*players).insert(0, Box::new(Console::new()));
-
-        let worlds: WorldList = Arc::new(CHashMap::new());
-
-        /*(*worlds).insert(
-            String::from("main"),
-            World::new(
-                String::from("main"),
-                Box::new(MemoryMap::new(Vec3D::new(64, 16, 64))),
-            ),
-        );*/
-        // Load main map from maps/main.lvl (MCSharp/MCLawl Format).
-        (*worlds).insert(
-            String::from("main"),
-            World::new(
-                String::from("main"),
-                Box::new(MCSharpMap::try_new("main").unwrap()),
-            ),
+        let players = PlayerList::new();
+
+        players.insert(0, Box::new(Console::new()));
+
+        let worlds = WorldList::new();
+
+        // Load main map from main.lvl in the maps folder (MCSharp/MCLawl Format), or a Classic level.
+        // Fall back to a flat memory map in case the file is missing, so the server can still start.
+        let main_map: Box<dyn Map + Send + Sync> = match load_map(&config.maps_folder, "main") {
+            Some(map) => map,
+            None => {
+                Core::static_log("Using a generated flat map for \"main\" instead.");
+
+                Box::new(MemoryMap::new(Vec3D::new(64, 64, 64)))
+            }
+        };
+
+        let mut main_world = World::new(String::from("main"), main_map);
+        main_world.get_physics_mut().set_level(
+            load_physics_level(&config.maps_folder, "main").unwrap_or(config.physics_level),
         );
+        main_world.set_zones(load_zones(&get_zones_path(&config.maps_folder, "main")));
+        main_world.set_special_blocks(load_special_blocks(&get_special_blocks_path(
+            &config.maps_folder,
+            "main",
+        )));
 
-        Core::static_log("Core has ben set up, waiting for network.");
+        worlds.insert(main_world);
 
-        Core {
+        let ranks = RankList::load(config.default_rank);
+        let history = History::new(
+            config.undo_limit,
+            Duration::from_secs(config.undo_time.saturating_mul(60)),
+        );
+
+        let mut core = Core {
             threadsize,
 
+            config,
+
             players,
             worlds,
 
+            ranks,
+
+            plugins: PluginList::new(),
+            natives: Mutex::new(Vec::new()),
+            commands: RwLock::new(CommandList::new()),
+            scheduler: Mutex::new(Scheduler::new()),
+            history: Mutex::new(history),
+
+            actions: Mutex::new(VecDeque::new()),
+
             tx: None,
             rx: None,
-        }
+        };
+
+        core.register_builtin_plugins();
+
+        Core::static_log("Core has ben set up, waiting for network.");
+
+        core
     }
 
     /// Logs into the standard output as well as log file, without a core instance.
@@ -117,9 +509,27 @@ impl Core {
         log
     }
 
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Folder maps are loaded from and saved into.
+    pub fn get_maps_folder(&self) -> &Path {
+        &self.config.maps_folder
+    }
+
+    #[cfg(test)]
+    pub(crate) fn get_config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    pub fn get_ranks(&self) -> &RankList {
+        &self.ranks
+    }
+
     /// Logs messages into standard output and log file.
     pub fn log(&self, message: &str) -> String {
-        Core::static_log(&message)
+        Core::static_log(message)
     }
 
     /// Generates the required memory channels for core.
@@ -137,8 +547,8 @@ impl Core {
     ///
     /// Panics if receiver is not instantiated.
     pub fn receiver_take(&mut self) -> Receiver<Box<dyn NetworkPacket + Send>> {
-        if let None = self.rx {
-            panic!(Core::static_log(
+        if self.rx.is_none() {
+            panic!("{}", Core::static_log(
                 "Cannot take Receiver from core; Receiver not present in core's memory channel."
             ));
         }
@@ -152,10 +562,13 @@ impl Core {
     ///
     /// Panics if sender is already taken or not instantiated.
     pub fn sender_take(&mut self) -> Sender<Box<dyn NetworkPacket + Send>> {
-        if let None = self.tx {
-            panic!(Core::static_log(
-                "Cannot take Sender from core; Sender not present in core's memory channel."
-            ));
+        if self.tx.is_none() {
+            panic!(
+                "{}",
+                Core::static_log(
+                    "Cannot take Sender from core; Sender not present in core's memory channel."
+                )
+            );
         }
 
         self.tx.take().unwrap()
@@ -167,241 +580,1844 @@ impl Core {
     ///
     /// Panics if sender is not instantiated or is taken (is not present in class).
     pub fn sender_clone(&self) -> Sender<Box<dyn NetworkPacket + Send>> {
-        if let None = self.tx {
-            panic!(Core::static_log(
-                "Cannot clone a Sender from core; Sender not present in core's memory channel."
-            ));
+        if self.tx.is_none() {
+            panic!(
+                "{}",
+                Core::static_log(
+                    "Cannot clone a Sender from core; Sender not present in core's memory channel."
+                )
+            );
         }
 
         self.tx.clone().unwrap()
     }
 
-    // TODO: Use better method for broadcasting packets.
-    pub fn broadcast_message(&self, sender: &mut (dyn Player), message: &str) {
-        // TODO: better iterating method.
-        for i in 0..128 {
-            if sender.get_uid() != i {
-                if let Some(mut ply) = self.get_player_by_uid_mut(i) {
-                    /*let packet =
-                        Box::new(Message::new(sender.get_uid() as i8, String::from(message)));
-
-                    ply.handle_packet(packet);*/
-                    ply.send_message(message);
-                }
+    /// Sends a message to every player. The sender receives it directly, others once the current message is handled.
+    pub fn broadcast_message(&self, sender: &mut dyn Player, message: &str) {
+        for uid in self.players.uids() {
+            if sender.get_uid() != uid {
+                self.send_message_to(uid, message);
             }
         }
 
         // Sender receives directly.
-        /*let packet = Box::new(Message::new(sender.get_uid() as i8, String::from(message)));
-        sender.handle_packet(packet);*/
         sender.send_message(message);
     }
 
-    /// Removes player from concurrent list. DO NOT Call directly,
-    /// This method should only be invoked if the resources are disposed.
-    pub fn remove_player_by_uid(&mut self, player_id: usize) {
-        self.players.remove(&player_id);
+    /// Queues a message for every player, console included.
+    pub fn broadcast(&self, message: &str) {
+        for uid in self.players.uids() {
+            self.send_message_to(uid, message);
+        }
     }
 
-    // TODO: Change player by_uid to get_player
-    /// Returns a player reference by uid. UID 0 can be used to get 'Console'.
-    pub fn get_player_by_uid<'core>(
-        &'core self,
-        uid: usize,
-    ) -> Option<ReadGuard<'_, usize, Box<dyn Player + Send + Sync>>> {
-        if self.players.contains_key(&uid) {
-            self.players.get(&uid)
-        } else {
-            None
-        }
+    // Plugins shipped with the server. Handlers of the same priority run in this order.
+    fn register_builtin_plugins(&mut self) {
+        let config = self.config.clone();
+
+        self.register_plugin(Box::new(BuiltinPlugin));
+        self.register_plugin(Box::new(DrawPlugin::new()));
+        self.register_plugin(Box::new(ZonePlugin::new()));
+        self.register_plugin(Box::new(SpecialBlockPlugin::new()));
+        self.register_plugin(Box::new(BackupPlugin));
+        self.register_plugin(Box::new(BlockDbPlugin::new(&config.blockdb_folder)));
+        self.register_plugin(Box::new(SpawnPlugin::new(&config.spawns_folder)));
+        self.register_plugin(Box::new(WarpPlugin::new(&config.warps_folder)));
+        self.register_plugin(Box::new(ScriptPlugin::new(&config.scripts_folder)));
     }
 
-    // TODO: Change player by_uid to get_player
-    /// Returns a mutable player reference by uid. UID 0 can be used to get 'Console'.
-    pub fn get_player_by_uid_mut<'core>(
-        &'core self,
-        uid: usize,
-    ) -> Option<WriteGuard<usize, Box<dyn Player + Send + Sync>>> {
-        if self.players.contains_key(&uid) {
-            self.players.get_mut(&uid)
-        } else {
-            None
+    /// Adds a plugin along with its event handlers. Fails if a plugin with the same name exists.
+    pub fn register_plugin(&mut self, mut plugin: Box<dyn Plugin + Send + Sync>) -> bool {
+        let name = String::from(plugin.get_name());
+
+        if self.is_plugin_loaded(&name) {
+            self.log(&format!("Plugin \"{}\" is already registered.", name));
+
+            return false;
         }
+
+        plugin.enable(self);
+        self.plugins.add(plugin);
+
+        self.log(&format!("Plugin \"{}\" has been registered.", name));
+
+        true
     }
 
-    pub fn get_world<'core>(&'core self, name: &str) -> Option<ReadGuard<String, World>> {
-        if self.worlds.contains_key(name) {
-            self.worlds.get(name)
-        } else {
-            None
-        }
+    /// Names of the plugins compiled into the server.
+    pub fn get_plugin_names(&self) -> Vec<String> {
+        self.plugins.get_names()
     }
 
-    pub fn get_world_mut<'core>(&'core self, name: &str) -> Option<WriteGuard<String, World>> {
-        if self.worlds.contains_key(name) {
-            self.worlds.get_mut(name)
-        } else {
-            None
-        }
+    /// Names and versions of the plugins loaded from shared libraries.
+    pub fn get_native_plugins(&self) -> Vec<(String, String)> {
+        self.natives
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|plugin| {
+                (
+                    String::from(plugin.get_name()),
+                    String::from(plugin.get_version()),
+                )
+            })
+            .collect()
     }
 
-    pub fn get_world_count(&self) -> usize {
-        self.worlds.len()
+    /// Whether a plugin, compiled in or native, goes by this name.
+    pub fn is_plugin_loaded(&self, name: &str) -> bool {
+        self.plugins.contains(name)
+            || self
+                .natives
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|plugin| plugin.get_name().eq_ignore_ascii_case(name))
     }
 
-    pub fn try_load_map(&self, player: &mut (dyn Player), map_name: &str) -> bool {
-        self.broadcast_message(player, &format!("&8Loading map \"{}\"...", map_name));
-        let map = MCSharpMap::try_new(map_name);
+    /// Loads a native plugin from the plugins folder, by its file name with or without
+    /// the platform's library prefix and extension. Returns the plugin's name.
+    pub fn load_native_plugin(&self, file: &str) -> Result<String, String> {
+        if file.contains("..") || file.contains('/') || file.contains('\\') {
+            return Err(String::from(
+                "Plugins can only be loaded from the plugins folder.",
+            ));
+        }
 
-        if let Some(level) = map {
-            (*self.worlds).insert(
-                String::from(map_name),
-                World::new(String::from(map_name), Box::new(level)),
-            );
+        let mut path = Path::new(PLUGINS_FOLDER).join(file);
 
-            self.broadcast_message(
-                player,
-                &format!("&8Map \"{}\" has successfully loaded.", map_name),
-            );
+        if !path.exists() {
+            path = Path::new(PLUGINS_FOLDER).join(format!("{}{}{}", DLL_PREFIX, file, DLL_SUFFIX));
+        }
 
-            true
-        } else {
-            player.send_message(&format!("&8Map \"{}\" does not exist.", map_name));
+        let plugin = NativePlugin::load(&path, self, |name| self.is_plugin_loaded(name))?;
+        let name = String::from(plugin.get_name());
 
-            false
-        }
-    }
+        self.log(&format!(
+            "Native plugin \"{}\" {} has been loaded.",
+            name,
+            plugin.get_version()
+        ));
 
-    /// Sends map directly from a WriteGuard to a Dyn reference.
-    pub fn send_map_direct(
-        &self,
-        mut player: WriteGuard<usize, Box<dyn Player + Send + Sync>>,
-        map: &mut World,
-    ) {
-        self.send_map(player.as_mut(), map);
+        self.natives.lock().unwrap().push(plugin);
+
+        Ok(name)
     }
 
-    pub fn send_map(&self, player: &mut (dyn Player), map: &mut World) {
-        let mut players_currentworld_count = 0;
-        // Send the entity remove packet to all current players.
-        if let Some(mut current_world) = self.get_world_mut(player.get_world()) {
-            current_world.remove_player(player.get_uid());
+    /// Unloads a native plugin, removing its commands and tasks first.
+    pub fn unload_native_plugin(&self, name: &str) -> bool {
+        let plugin = {
+            let mut natives = self.natives.lock().unwrap();
+
+            match natives
+                .iter()
+                .position(|plugin| plugin.get_name().eq_ignore_ascii_case(name))
+            {
+                Some(index) => natives.remove(index),
+                None => return false,
+            }
+        };
+
+        self.commands
+            .write()
+            .unwrap()
+            .remove_owned_by(plugin.get_name());
+        self.get_scheduler().cancel_owned_by(plugin.get_name());
 
-            for player_id in current_world.get_players() {
-                if *player_id != player.get_uid() {
-                    if let Some(mut ply) = self.get_player_by_uid_mut(*player_id) {
-                        let packet = Box::new(DespawnPlayer::new(player.get_uid() as i8));
+        self.log(&format!(
+            "Native plugin \"{}\" has been unloaded.",
+            plugin.get_name()
+        ));
 
-                        ply.handle_packet(packet);
+        plugin.unload(self);
 
-                        players_currentworld_count += 1;
-                    }
+        true
+    }
+
+    /// Loads every shared library inside the plugins folder.
+    pub fn load_native_plugins(&self) {
+        let entries = match fs::read_dir(PLUGINS_FOLDER) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let file = entry.file_name().to_string_lossy().to_string();
+
+            if file.ends_with(DLL_SUFFIX) {
+                if let Err(e) = self.load_native_plugin(&file) {
+                    self.log(&format!("Unable to load plugin \"{}\": {}", file, e));
                 }
             }
         }
+    }
 
-        // Old-map should be unloaded.
-        if players_currentworld_count <= 0 {
-            if player.get_world() != "main" {
-                self.worlds.remove(player.get_world());
-            }
-        }
+    /// Adds a command, fails if the name is taken.
+    pub fn register_command(&self, command: Command) -> bool {
+        let name = String::from(command.get_name());
 
-        map.add_player(player.get_uid());
-        player.set_world(map.get_name());
+        if !self.commands.write().unwrap().add(command) {
+            self.log(&format!("Command \"/{}\" is already registered.", name));
 
-        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+            return false;
+        }
 
-        // TODO: Handle both write_all and finish results efficiently.
-        let size = &(map.get_chunks().len() as u32).to_be_bytes();
-        gz.write_all(size).unwrap();
-        gz.write_all(map.get_chunks()).unwrap();
-        let gz_data = gz.finish().unwrap();
+        true
+    }
 
-        player.handle_packet(Box::new(LevelInitialize::new()));
+    /// Adds another name for an existing command.
+    pub fn register_command_alias(&self, alias: &str, name: &str) -> bool {
+        self.commands.write().unwrap().add_alias(alias, name)
+    }
 
-        let chunks = gz_data.chunks(1024);
-        let total_chunks = chunks.len();
+    /// Copies of every registered command, in alphabetical order.
+    pub fn get_commands(&self) -> Vec<Command> {
+        self.commands
+            .read()
+            .unwrap()
+            .get_commands()
+            .into_iter()
+            .cloned()
+            .collect()
+    }
 
-        for (i, chunk) in chunks.enumerate() {
-            let mut chunk_data = chunk.to_vec();
+    /// Runs a command for a player if their rank allows it. Returns false if the command does not exist.
+    pub fn run_command(&self, player: &PlayerSnapshot, name: &str, args: &str) -> bool {
+        // The list is not kept locked while running, commands may register other commands.
+        let command = match self.commands.read().unwrap().get(name) {
+            Some(command) => command.clone(),
+            None => return false,
+        };
+
+        if player.rank < command.get_rank() {
+            self.send_message_to(
+                player.uid,
+                &format!(
+                    "&8You need to be {} to use /{}.",
+                    command.get_rank().get_name(),
+                    command.get_name()
+                ),
+            );
+        } else {
+            (command.get_handler())(self, player, args);
+        }
+
+        true
+    }
 
-            if chunk.len() < 1024 {
-                for _ in chunk.len()..1024 {
-                    chunk_data.push(0x0);
+    /// Runs a chat message as a command, if it is one. Returns true if it was.
+    pub fn try_run_command(&self, player: &PlayerSnapshot, message: &str) -> bool {
+        match parse_command(message) {
+            Some((name, args)) => {
+                if !self.run_command(player, name, args) {
+                    self.send_message_to(
+                        player.uid,
+                        &format!("&8Unknown command \"/{}\", see /help.", name),
+                    );
                 }
-            }
 
-            player.handle_packet(Box::new(LevelDataChunk::new(
-                chunk.len() as u16,
-                chunk_data,
-                (i / total_chunks * 100) as u8,
-            )));
+                true
+            }
+            None => false,
         }
+    }
+
+    pub fn get_scheduler(&self) -> MutexGuard<'_, Scheduler> {
+        self.scheduler.lock().unwrap()
+    }
 
-        player.handle_packet(Box::new(LevelFinalize::new(*map.get_size())));
+    /// Runs a task once, a number of ticks from now.
+    pub fn schedule<F: FnMut(&Core) + Send + 'static>(&self, owner: &str, delay: u64, task: F) {
+        self.get_scheduler().schedule(owner, delay, Box::new(task));
+    }
 
-        // TODO: Set spawn points for player, as well as send entity creation to players in world.
-        let transform = Transform::new(
-            map.get_spawnarea(),
-            map.get_spawnyaw(),
-            map.get_spawnpitch(),
-        );
-        let name = String::from(player.get_display_name());
-        let uid = player.get_uid();
+    /// Runs a task every interval ticks, starting an interval from now.
+    pub fn schedule_repeating<F: FnMut(&Core) + Send + 'static>(
+        &self,
+        owner: &str,
+        interval: u64,
+        task: F,
+    ) {
+        self.get_scheduler()
+            .schedule_repeating(owner, interval, interval, Box::new(task));
+    }
 
-        // Sending spawn area.
-        player.handle_packet(Box::new(SpawnPlayer::new(
-            -1,
-            name.clone(),
-            transform.clone(),
-        )));
-        //drop(player); // Dropping to stop deadlocks because we want to iterate through the list.
-        for pid in map.get_players() {
-            if *pid != uid {
-                if let Some(mut other) = self.get_player_by_uid_mut(*pid) {
-                    // Let other players know about the joining player.
-                    other.handle_packet(Box::new(SpawnPlayer::new(
-                        uid as i8,
-                        name.clone(),
-                        transform.clone(),
-                    )));
+    /// Runs the tasks due on this tick. Called by the core thread on every tick.
+    pub fn tick(&self) {
+        let due = self.get_scheduler().advance();
 
-                    // Let the joining player now about the other(s).
-                    player.handle_packet(Box::new(SpawnPlayer::new(
-                        other.get_uid() as i8,
-                        String::from(other.get_display_name()),
-                        transform.clone(),
-                    )));
-                }
+        for mut task in due {
+            // Its plugin got unloaded, possibly by a task which ran before on this tick.
+            if !self.is_plugin_loaded(&task.owner) {
+                continue;
+            }
+
+            (task.handler)(self);
+
+            // Or by the task itself.
+            if self.is_plugin_loaded(&task.owner) {
+                self.get_scheduler().reschedule(task);
             }
         }
+
+        self.tick_physics();
+        self.process_actions();
     }
 
-    /// Starts a thread which listens for incoming connections.
-    pub fn network_listen(&mut self) {
-        let core_tx = self.sender_take();
-        let players_arc = self.players.clone();
+    // Updates the physics of every world, sharing the budget between them.
+    // Worlds with fewer queued blocks than their share leave the rest to the next ones.
+    fn tick_physics(&self) {
+        let names = self.get_world_names();
+        let mut budget = self.config.physics_budget;
 
-        let thread_size = self.threadsize;
+        for (index, name) in names.iter().enumerate() {
+            let share = budget / (names.len() - index);
 
-        thread::spawn(move || {
-            let network = Network::new(27015, thread_size);
+            let (updated, changed) = self
+                .with_world_mut(name, |world| world.tick_physics(share))
+                .unwrap_or_default();
 
-            network.listen(players_arc, core_tx);
-        });
+            budget -= updated;
+            self.send_blocks(name, &changed);
+        }
     }
 
-    /// Listens for network packets over the memory channel.
-    pub fn handle_received_packets(&mut self) {
-        let receiver = self.receiver_take();
+    /// Calls the handlers registered for an event. Returns true if the event got cancelled.
+    pub fn call_event<E: Event>(&self, event: &mut E) -> bool {
+        self.plugins.get_events().call(self, event)
+    }
 
-        for message in receiver {
-            //self.log(&format!("Received a packet with id: {}", message.get_id()));
+    /// Queues work to be done once the current message is handled.
+    /// Handlers use actions to reach other players, as the sending player is usually locked.
+    pub fn queue_action(&self, action: Action) {
+        self.actions.lock().unwrap().push_back(action);
+    }
 
-            message.handle_receive(self);
-        }
+    /// Queues a packet for a player.
+    pub fn send_packet(&self, uid: usize, packet: Box<dyn NetworkPacket + Send>) {
+        self.queue_action(Action::SendPacket(uid, packet));
+    }
 
-        panic!(self.log("FATAL ERROR: Receiving memory has stopped unexpectedly."));
+    /// Queues a chat message for a player.
+    pub fn send_message_to(&self, uid: usize, message: &str) {
+        self.queue_action(Action::SendMessage(uid, String::from(message)));
+    }
+
+    /// Queues a packet for every player inside a world, except one.
+    pub fn send_to_world<F>(&self, world: &str, except: usize, packet: F)
+    where
+        F: Fn() -> Box<dyn NetworkPacket + Send>,
+    {
+        for uid in self.get_world_players(world) {
+            if uid != except {
+                self.send_packet(uid, packet());
+            }
+        }
+    }
+
+    /// Queues a packet about an entity for every player inside a world, except one.
+    /// Players whose client cannot display the entity id are skipped.
+    pub fn send_entity_to_world<F>(&self, world: &str, entity_id: u8, except: usize, packet: F)
+    where
+        F: Fn() -> Box<dyn NetworkPacket + Send>,
+    {
+        for uid in self.get_world_players(world) {
+            if uid != except {
+                self.queue_action(Action::SendEntityPacket(uid, entity_id, packet()));
+            }
+        }
+    }
+
+    /// Does every queued action, including the ones queued while doing so.
+    /// Nothing is locked in between actions.
+    pub fn process_actions(&self) {
+        loop {
+            let action = match self.actions.lock().unwrap().pop_front() {
+                Some(action) => action,
+                None => break,
+            };
+
+            match action {
+                Action::SendPacket(uid, packet) => {
+                    self.with_player_mut(uid, |player| player.handle_packet(packet));
+                }
+                Action::SendEntityPacket(uid, entity_id, packet) => {
+                    self.with_player_mut(uid, |player| {
+                        if can_see_entity(player, entity_id) {
+                            player.handle_packet(packet);
+                        }
+                    });
+                }
+                Action::SendExtPacket(uid, extension, packet) => {
+                    self.with_player_mut(uid, |player| {
+                        if player.supports_extension(extension) {
+                            player.handle_packet(packet);
+                        }
+                    });
+                }
+                Action::SendMessage(uid, message) => {
+                    self.with_player_mut(uid, |player| player.send_message(&message));
+                }
+                Action::ShowPlayer(viewer, uid) => self.show_player(viewer, uid),
+                Action::JoinWorld(uid, world) => self.join_world(uid, &world),
+                Action::Teleport(uid, transform) => self.teleport(uid, transform),
+                Action::ReloadMap(uid) => self.reload_map(uid),
+            }
+        }
+    }
+
+    /// Registers a freshly connected player. Only the core thread adds players.
+    pub fn add_player(&self, uid: usize, player: Box<dyn Player + Send + Sync>) -> bool {
+        self.players.insert(uid, player)
+    }
+
+    /// Removes player from concurrent list. DO NOT Call directly,
+    /// This method should only be invoked if the resources are disposed.
+    pub fn remove_player_by_uid(&self, player_id: usize) {
+        self.players.remove(player_id);
+    }
+
+    // TODO: Change player by_uid to get_player
+    /// Locks a player by uid, UID 0 can be used to get 'Console'.
+    /// None if the player does not exist or is already locked; prefer `with_player_mut`.
+    pub fn get_player_by_uid_mut(&self, uid: usize) -> Option<PlayerGuard<'_>> {
+        self.players.get_mut(uid)
+    }
+
+    /// Runs a closure on a player, which is only locked during the closure.
+    pub fn with_player<R, F: FnOnce(&dyn Player) -> R>(&self, uid: usize, f: F) -> Option<R> {
+        self.players.get_mut(uid).map(|player| f(&**player))
+    }
+
+    /// Runs a closure on a mutable player, which is only locked during the closure.
+    pub fn with_player_mut<R, F: FnOnce(&mut dyn Player) -> R>(
+        &self,
+        uid: usize,
+        f: F,
+    ) -> Option<R> {
+        self.players.get_mut(uid).map(|mut player| f(&mut **player))
+    }
+
+    /// Player's state as of the last time it was unlocked. Never locks the player.
+    pub fn get_player_snapshot(&self, uid: usize) -> Option<PlayerSnapshot> {
+        self.players.snapshot(uid)
+    }
+
+    /// States of every player, console included. Never locks any player.
+    pub fn get_player_snapshots(&self) -> Vec<PlayerSnapshot> {
+        self.players.snapshots()
+    }
+
+    /// Returns the uids of every connected player, console included.
+    pub fn get_player_uids(&self) -> Vec<usize> {
+        self.players.uids()
+    }
+
+    /// Number of players connected over network.
+    pub fn get_player_count(&self) -> usize {
+        // Console is always part of the list.
+        self.players.len() - 1
+    }
+
+    /// Runs a closure on a world, which is only locked during the closure.
+    pub fn with_world<R, F: FnOnce(&World) -> R>(&self, name: &str, f: F) -> Option<R> {
+        self.worlds.with(name, f)
+    }
+
+    /// Runs a closure on a mutable world, which is only locked during the closure.
+    pub fn with_world_mut<R, F: FnOnce(&mut World) -> R>(&self, name: &str, f: F) -> Option<R> {
+        self.worlds.with_mut(name, f)
+    }
+
+    /// Sets a block in a world and sends it to everyone inside. Returns false if the world is not loaded.
+    pub fn set_block(&self, world: &str, position: Vec3D, block: u8) -> bool {
+        let sending_block = self.with_world_mut(world, |map| {
+            map.set_block(&position, block, false);
+
+            map.get_block(&position)
+        });
+
+        match sending_block {
+            Some(sending_block) => {
+                self.send_to_world(world, 0, || {
+                    Box::new(ServerSetBlock::new(position, sending_block))
+                });
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends changed blocks to the players inside a world. Players get the whole map again
+    /// when there are too many blocks for single updates to be faster.
+    pub fn send_blocks(&self, world: &str, positions: &[Vec3D]) {
+        if positions.is_empty() {
+            return;
+        }
+
+        if positions.len() > MAX_BLOCK_UPDATES {
+            for uid in self.get_world_players(world) {
+                self.queue_action(Action::ReloadMap(uid));
+            }
+
+            return;
+        }
+
+        let blocks = self.with_world(world, |world| {
+            positions
+                .iter()
+                .map(|position| (*position, world.get_block(position)))
+                .collect::<Vec<_>>()
+        });
+
+        for (position, block) in blocks.unwrap_or_default() {
+            self.send_to_world(world, 0, || Box::new(ServerSetBlock::new(position, block)));
+        }
+    }
+
+    /// Keeps blocks a player changed inside a world, so they can be undone.
+    pub fn record_edit(&self, player: &str, world: &str, changes: Vec<BlockChange>) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let mut event = BlocksChangedEvent::new(String::from(player), String::from(world), changes);
+        self.call_event(&mut event);
+
+        self.history
+            .lock()
+            .unwrap()
+            .record(player, Edit::new(event.world, event.changes));
+    }
+
+    /// Reverts edits of a player, returning the number of blocks reverted.
+    /// Edits undone by someone else cannot be redone by the player.
+    pub fn undo(&self, player: &str, amount: UndoAmount, redoable: bool) -> usize {
+        let edits = self.history.lock().unwrap().take_undo(player, amount);
+        let undone = self.apply_edits(edits, true);
+        let count = undone.iter().map(|edit| edit.changes.len()).sum();
+
+        if redoable {
+            self.history.lock().unwrap().push_undone(player, undone);
+        }
+
+        count
+    }
+
+    /// Forgets the edits of players who left, once their last one is older than the undo time.
+    pub fn forget_inactive_histories(&self) {
+        let online: Vec<String> = self
+            .get_player_snapshots()
+            .into_iter()
+            .map(|player| player.name.to_lowercase())
+            .collect();
+
+        self.history
+            .lock()
+            .unwrap()
+            .forget_inactive(|name| online.iter().any(|player| player == name));
+    }
+
+    /// Applies undone edits of a player again, returning the number of blocks changed.
+    pub fn redo(&self, player: &str, count: usize) -> usize {
+        let edits = self.history.lock().unwrap().take_redo(player, count);
+        let redone = self.apply_edits(edits, false);
+        let count = redone.iter().map(|edit| edit.changes.len()).sum();
+
+        self.history.lock().unwrap().push_redone(player, redone);
+
+        count
+    }
+
+    // Reverts or reapplies edits in order, loading their worlds if needed. Blocks changed by
+    // someone else since are left alone. Returns the edits with the changes actually applied.
+    fn apply_edits(&self, edits: Vec<Edit>, undo: bool) -> Vec<Edit> {
+        let mut applied = Vec::new();
+
+        for mut edit in edits {
+            if !self.is_world_loaded(&edit.world) && !self.load_world(&edit.world) {
+                continue;
+            }
+
+            let mut changes = std::mem::take(&mut edit.changes);
+
+            let changes = self.with_world_mut(&edit.world, |world| {
+                if undo {
+                    changes.reverse();
+                }
+
+                changes.retain(|change| {
+                    let (from, to) = match undo {
+                        true => (change.new, change.old),
+                        false => (change.old, change.new),
+                    };
+
+                    if world.get_block(&change.position) != from {
+                        return false;
+                    }
+
+                    world.set_block(&change.position, to, false);
+
+                    true
+                });
+
+                // Edits keep their changes in the order they were made.
+                if undo {
+                    changes.reverse();
+                }
+
+                changes
+            });
+
+            edit.changes = changes.unwrap_or_default();
+
+            let positions: Vec<Vec3D> = edit.changes.iter().map(|change| change.position).collect();
+            self.send_blocks(&edit.world, &positions);
+
+            applied.push(edit);
+        }
+
+        applied
+    }
+
+    /// Uids of the players inside a world.
+    pub fn get_world_players(&self, name: &str) -> Vec<usize> {
+        self.with_world(name, |world| world.get_players().clone())
+            .unwrap_or_default()
+    }
+
+    pub fn is_world_loaded(&self, name: &str) -> bool {
+        self.worlds.contains(name)
+    }
+
+    /// Adds a loaded world. Fails while a world is locked.
+    pub fn add_world(&self, mut world: World) -> bool {
+        world.get_physics_mut().set_level(self.config.physics_level);
+
+        self.worlds.insert(world)
+    }
+
+    /// Whether building is refused in a world, by the server's state or the world's own.
+    pub fn is_read_only(&self, world: &str) -> bool {
+        self.config.read_only
+            || self
+                .with_world(world, |world| world.is_read_only())
+                .unwrap_or(false)
+    }
+
+    /// Names of the loaded worlds, sorted.
+    pub fn get_world_names(&self) -> Vec<String> {
+        self.worlds.names()
+    }
+
+    /// Unloads a world, only possible if no one is inside it. Changed maps are saved first.
+    pub fn unload_world(&self, name: &str) -> bool {
+        self.try_unload_world(name, true).is_ok()
+    }
+
+    /// Unloads a world, saving its map first if asked to and it changed. Errors say why it is kept.
+    pub fn try_unload_world(&self, name: &str, save: bool) -> Result<(), String> {
+        if name == "main" {
+            return Err(String::from("Main cannot be unloaded."));
+        }
+
+        if !self.worlds.contains(name) {
+            return Err(format!("\"{}\" is not loaded.", name));
+        }
+
+        if !self.get_world_players(name).is_empty() {
+            return Err(format!(
+                "Players are inside \"{}\", they have to leave first.",
+                name
+            ));
+        }
+
+        if self.call_event(&mut WorldUnloadEvent::new(String::from(name))) {
+            return Err(format!("\"{}\" cannot be unloaded now.", name));
+        }
+
+        let saved = self.with_world_mut(name, |world| {
+            !save || !world.is_modified() || world.save(self.get_maps_folder())
+        });
+
+        match saved {
+            Some(true) => {
+                self.worlds.remove(name);
+
+                Ok(())
+            }
+            Some(false) => Err(format!("Unable to save \"{}\", it is kept loaded.", name)),
+            None => Err(format!("\"{}\" is not loaded.", name)),
+        }
+    }
+
+    /// Names of the maps saved in the maps folder, sorted.
+    pub fn get_map_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(self.get_maps_folder())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| {
+                        let path = entry.ok()?.path();
+
+                        if !LEVEL_EXTENSIONS.contains(&path.extension()?.to_str()?) {
+                            return None;
+                        }
+
+                        Some(String::from(path.file_stem()?.to_str()?))
+                    })
+                    .filter(|name| is_valid_map_name(name))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Imported maps saved since have more than one level file.
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Saves a loaded world into the maps folder. Returns false if it is not loaded or saving failed.
+    pub fn save_world(&self, name: &str) -> bool {
+        self.with_world_mut(name, |world| world.save(self.get_maps_folder()))
+            .unwrap_or(false)
+    }
+
+    /// Deletes a map and what is kept next to it, unloading it first. Main cannot be deleted.
+    pub fn delete_map(&self, name: &str) -> Result<(), String> {
+        check_map_name(name)?;
+
+        if name == "main" {
+            return Err(String::from("Main cannot be deleted."));
+        }
+
+        let loaded = self.is_world_loaded(name);
+
+        if !loaded && !map_exists(self.get_maps_folder(), name) {
+            return Err(format!("Map \"{}\" does not exist.", name));
+        }
+
+        if loaded {
+            self.try_unload_world(name, false)?;
+        }
+
+        for extension in &MAP_FILES {
+            let path = get_map_file(self.get_maps_folder(), name, extension);
+
+            if path.exists() {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Unable to delete \"{}\": {}", path.display(), e))?;
+            }
+        }
+
+        self.call_event(&mut MapDeleteEvent::new(String::from(name)));
+        self.log(&format!("Map \"{}\" has been deleted.", name));
+
+        Ok(())
+    }
+
+    /// Renames a map and what is kept next to it, saving and unloading it first.
+    pub fn rename_map(&self, from: &str, to: &str) -> Result<(), String> {
+        self.check_new_map(from, to)?;
+
+        if from == "main" {
+            return Err(String::from("Main cannot be renamed."));
+        }
+
+        // Saved even without changes, worlds may not have been saved at all yet.
+        if self.is_world_loaded(from) {
+            if !self.save_world(from) {
+                return Err(format!("Unable to save \"{}\".", from));
+            }
+
+            self.try_unload_world(from, false)?;
+        }
+
+        for extension in &MAP_FILES {
+            let path = get_map_file(self.get_maps_folder(), from, extension);
+
+            if path.exists() {
+                fs::rename(&path, get_map_file(self.get_maps_folder(), to, extension))
+                    .map_err(|e| format!("Unable to rename \"{}\": {}", path.display(), e))?;
+            }
+        }
+
+        self.call_event(&mut MapRenameEvent::new(
+            String::from(from),
+            String::from(to),
+        ));
+        self.log(&format!("Map \"{}\" has been renamed to \"{}\".", from, to));
+
+        Ok(())
+    }
+
+    /// Copies a map and what is kept next to it into a new map, saving the map first if loaded.
+    pub fn copy_map(&self, from: &str, to: &str) -> Result<(), String> {
+        self.check_new_map(from, to)?;
+
+        if self.is_world_loaded(from) && !self.save_world(from) {
+            return Err(format!("Unable to save \"{}\".", from));
+        }
+
+        for extension in &MAP_FILES {
+            let path = get_map_file(self.get_maps_folder(), from, extension);
+
+            if path.exists() {
+                fs::copy(&path, get_map_file(self.get_maps_folder(), to, extension))
+                    .map_err(|e| format!("Unable to copy \"{}\": {}", path.display(), e))?;
+            }
+        }
+
+        self.log(&format!("Map \"{}\" has been copied to \"{}\".", from, to));
+
+        Ok(())
+    }
+
+    // Fails unless the first map exists and the second is a valid name not used yet.
+    fn check_new_map(&self, from: &str, to: &str) -> Result<(), String> {
+        check_map_name(from)?;
+
+        if !self.is_world_loaded(from) && !map_exists(self.get_maps_folder(), from) {
+            return Err(format!("Map \"{}\" does not exist.", from));
+        }
+
+        check_map_name(to)?;
+
+        if self.is_world_loaded(to) || map_exists(self.get_maps_folder(), to) {
+            return Err(format!("Map \"{}\" already exists.", to));
+        }
+
+        Ok(())
+    }
+
+    /// Changes the size of a map, cutting it or adding air. Loaded worlds are sent again to the players inside.
+    pub fn resize_map(&self, name: &str, size: Vec3D) -> Result<(), String> {
+        check_map_name(name)?;
+        check_map_size(size)?;
+
+        let players = self.with_world_mut(name, |world| {
+            let resized = MemoryMap::resized(world.get_map(), size);
+            world.set_map(Box::new(resized), true);
+
+            world.get_players().clone()
+        });
+
+        match players {
+            Some(players) => {
+                for uid in players {
+                    self.queue_action(Action::ReloadMap(uid));
+                }
+            }
+            None => {
+                let map = load_map(self.get_maps_folder(), name)
+                    .ok_or_else(|| format!("Map \"{}\" does not exist.", name))?;
+
+                if !MCSharpMap::save(
+                    self.get_maps_folder(),
+                    name,
+                    &MemoryMap::resized(map.as_ref(), size),
+                ) {
+                    return Err(format!("Unable to save \"{}\".", name));
+                }
+            }
+        }
+
+        self.log(&format!(
+            "Map \"{}\" has been resized to {}x{}x{}.",
+            name, size.0, size.1, size.2
+        ));
+
+        Ok(())
+    }
+
+    /// Reads a loaded world from the maps folder again, dropping changes not saved since.
+    pub fn reload_world(&self, name: &str) -> Result<(), String> {
+        if !self.is_world_loaded(name) {
+            return Err(format!("\"{}\" is not loaded.", name));
+        }
+
+        let map = load_map(self.get_maps_folder(), name)
+            .ok_or_else(|| format!("Map \"{}\" is not saved in the maps folder.", name))?;
+
+        let players = self
+            .with_world_mut(name, |world| {
+                world.set_map(map, false);
+
+                if let Some(level) = load_physics_level(self.get_maps_folder(), name) {
+                    world.get_physics_mut().set_level(level);
+                }
+
+                world.set_zones(load_zones(&get_zones_path(self.get_maps_folder(), name)));
+                world.set_special_blocks(load_special_blocks(&get_special_blocks_path(
+                    self.get_maps_folder(),
+                    name,
+                )));
+
+                world.get_players().clone()
+            })
+            .unwrap_or_default();
+
+        for uid in players {
+            self.queue_action(Action::ReloadMap(uid));
+        }
+
+        self.log(&format!("Map \"{}\" has been reloaded.", name));
+
+        Ok(())
+    }
+
+    pub fn get_world_count(&self) -> usize {
+        self.worlds.len()
+    }
+
+    /// Loads a map from the maps folder into the loaded worlds.
+    pub fn load_world(&self, map_name: &str) -> bool {
+        if !is_valid_map_name(map_name) {
+            return false;
+        }
+
+        self.broadcast_message(
+            &mut Console::new(),
+            &format!("&8Loading map \"{}\"...", map_name),
+        );
+
+        match load_map(self.get_maps_folder(), map_name) {
+            Some(level) => {
+                let mut world = World::new(String::from(map_name), level);
+
+                if let Some(level) = load_physics_level(self.get_maps_folder(), map_name) {
+                    world.get_physics_mut().set_level(level);
+                }
+
+                world.set_zones(load_zones(&get_zones_path(
+                    self.get_maps_folder(),
+                    map_name,
+                )));
+                world.set_special_blocks(load_special_blocks(&get_special_blocks_path(
+                    self.get_maps_folder(),
+                    map_name,
+                )));
+
+                if !self.add_world(world) {
+                    return false;
+                }
+
+                self.broadcast_message(
+                    &mut Console::new(),
+                    &format!("&8Map \"{}\" has successfully loaded.", map_name),
+                );
+
+                self.call_event(&mut WorldLoadEvent::new(String::from(map_name)));
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Generates a new map with a generator and saves it into the maps folder.
+    /// Fails if the name or size is invalid, or a map with the same name exists.
+    pub fn create_map(
+        &self,
+        name: &str,
+        size: Vec3D,
+        generator: &str,
+        seed: u64,
+    ) -> Result<(), String> {
+        check_map_name(name)?;
+        check_map_size(size)?;
+
+        if self.is_world_loaded(name) || map_exists(self.get_maps_folder(), name) {
+            return Err(format!("Map \"{}\" already exists.", name));
+        }
+
+        let generator = find_generator(generator).ok_or_else(|| {
+            format!(
+                "Unknown generator \"{}\", use one of: {}.",
+                generator,
+                GENERATOR_NAMES.join(", ")
+            )
+        })?;
+
+        let map = generate_map(generator.as_ref(), size, seed);
+
+        if !MCSharpMap::save(self.get_maps_folder(), name, &map) {
+            return Err(String::from("Unable to save the map."));
+        }
+
+        self.log(&format!(
+            "Map \"{}\" has been generated by \"{}\" with seed {}.",
+            name,
+            generator.get_name(),
+            seed
+        ));
+
+        Ok(())
+    }
+
+    /// Moves a player into a world, loading it first if needed.
+    fn join_world(&self, uid: usize, world_name: &str) {
+        if !self.is_world_loaded(world_name) && !self.load_world(world_name) {
+            self.send_message_to(
+                uid,
+                &format!("&8The world \"{}\" does not exist.", world_name),
+            );
+
+            return;
+        }
+
+        let world_name = String::from(world_name);
+
+        self.with_player_mut(uid, |player| {
+            let mut event = WorldJoinEvent::new(PlayerSnapshot::new(player), world_name.clone());
+
+            if !self.call_event(&mut event)
+                && self.send_map_at(player, &world_name, event.position.take())
+            {
+                self.call_event(&mut WorldJoinedEvent::new(
+                    PlayerSnapshot::new(player),
+                    world_name,
+                ));
+            }
+        });
+    }
+
+    /// Removes a player from a world and despawns them for the others.
+    /// Worlds other than main are unloaded once empty.
+    pub fn leave_world(&self, uid: usize, world_name: &str) {
+        let entity_id = self
+            .with_world_mut(world_name, |world| world.remove_player(uid))
+            .flatten();
+
+        if let Some(entity_id) = entity_id {
+            self.send_entity_to_world(world_name, entity_id, uid, || {
+                Box::new(DespawnPlayer::new(entity_id))
+            });
+        }
+
+        if world_name != "main" {
+            self.unload_world(world_name);
+        }
+    }
+
+    /// Spawns a player's entity for a viewer, if both are in the same world.
+    fn show_player(&self, viewer: usize, uid: usize) {
+        let (shown, viewer_world) = match (
+            self.get_player_snapshot(uid),
+            self.get_player_snapshot(viewer),
+        ) {
+            (Some(shown), Some(viewer)) => (shown, viewer.world),
+            _ => return,
+        };
+
+        if shown.world != viewer_world {
+            return;
+        }
+
+        if let Some(entity_id) = self
+            .with_world(&shown.world, |world| world.get_entity_id(uid))
+            .flatten()
+        {
+            self.queue_action(Action::SendEntityPacket(
+                viewer,
+                entity_id,
+                Box::new(SpawnPlayer::new(
+                    entity_id,
+                    shown.display_name,
+                    shown.transform,
+                )),
+            ));
+        }
+    }
+
+    /// Moves a player to a position in its world, and lets the others in the world know.
+    fn teleport(&self, uid: usize, transform: Transform) {
+        let world = self.with_player_mut(uid, |player| {
+            player.update_transform(transform.clone());
+
+            String::from(player.get_world())
+        });
+
+        if let Some(world) = world {
+            if let Some(entity_id) = self
+                .with_world(&world, |world| world.get_entity_id(uid))
+                .flatten()
+            {
+                self.send_entity_to_world(&world, entity_id, uid, || {
+                    Box::new(ServerPositionAndOrientation::new(
+                        entity_id,
+                        transform.clone(),
+                    ))
+                });
+            }
+        }
+    }
+
+    /// Moves the player into the world and sends its map. Returns false if the world is full or not loaded.
+    /// The player may be locked by the caller, worlds must not be.
+    pub fn send_map(&self, player: &mut dyn Player, world_name: &str) -> bool {
+        self.send_map_at(player, world_name, None)
+    }
+
+    /// Same as send_map, with the player appearing at a position instead of the spawn if given.
+    pub fn send_map_at(
+        &self,
+        player: &mut dyn Player,
+        world_name: &str,
+        position: Option<Transform>,
+    ) -> bool {
+        let uid = player.get_uid();
+
+        // An entity id is needed in the new world first, players cannot join full worlds.
+        match self.with_world_mut(world_name, |world| world.add_player(uid)) {
+            Some(Some(_)) => {}
+            Some(None) => {
+                player.send_message(&format!("&8World \"{}\" is full.", world_name));
+
+                return false;
+            }
+            None => return false,
+        }
+
+        let old_world = String::from(player.get_world());
+
+        // Players receiving their first map are not inside any world yet.
+        if !old_world.is_empty() && old_world != world_name {
+            self.leave_world(uid, &old_world);
+        }
+
+        player.set_world(world_name);
+        player.set_login_state(LoginState::InWorld);
+
+        let (spawn, players) = match self.send_level(player, world_name) {
+            Some(level) => level,
+            None => return false,
+        };
+
+        let transform = position.unwrap_or(spawn);
+        *player.get_transform_mut() = transform.clone();
+
+        player.handle_packet(Box::new(SpawnPlayer::new(
+            SELF_ENTITY_ID,
+            String::from(player.get_display_name()),
+            transform,
+        )));
+
+        // Spawning is queued, the other players cannot be locked while this one is.
+        for other in players {
+            if other != uid {
+                // Let other players know about the joining player.
+                self.queue_action(Action::ShowPlayer(other, uid));
+
+                // Let the joining player know about the other(s).
+                self.queue_action(Action::ShowPlayer(uid, other));
+            }
+        }
+
+        true
+    }
+
+    // Sends the blocks of a world, returning its spawn and the players inside.
+    fn send_level(
+        &self,
+        player: &mut dyn Player,
+        world_name: &str,
+    ) -> Option<(Transform, Vec<usize>)> {
+        // Everything needed is copied out of the world, so it is not locked while sending.
+        let world_data = self.with_world(world_name, |map| {
+            let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+
+            // TODO: Handle both write_all and finish results efficiently.
+            let size = &(map.get_chunks().len() as u32).to_be_bytes();
+            gz.write_all(size).unwrap();
+            gz.write_all(map.get_chunks()).unwrap();
+
+            let transform = map.get_spawn();
+
+            (
+                gz.finish().unwrap(),
+                *map.get_size(),
+                transform,
+                map.get_players().clone(),
+            )
+        });
+
+        let (gz_data, size, transform, players) = world_data?;
+
+        player.handle_packet(Box::new(LevelInitialize::new()));
+
+        let chunks = gz_data.chunks(1024);
+        let total_chunks = chunks.len();
+
+        for (i, chunk) in chunks.enumerate() {
+            let mut chunk_data = chunk.to_vec();
+
+            // Chunks are always 1024 bytes long, padded with zeroes.
+            chunk_data.resize(1024, 0x0);
+
+            player.handle_packet(Box::new(LevelDataChunk::new(
+                chunk.len() as u16,
+                chunk_data,
+                (i / total_chunks * 100) as u8,
+            )));
+        }
+
+        player.handle_packet(Box::new(LevelFinalize::new(size)));
+
+        Some((transform, players))
+    }
+
+    /// Sends the map of a player's world again, keeping the player where they are.
+    fn reload_map(&self, uid: usize) {
+        let players = self
+            .with_player_mut(uid, |player| {
+                let world = String::from(player.get_world());
+                let (_, players) = self.send_level(player, &world)?;
+
+                // New levels remove every entity, including the player's own.
+                player.handle_packet(Box::new(SpawnPlayer::new(
+                    SELF_ENTITY_ID,
+                    String::from(player.get_display_name()),
+                    player.get_transform().clone(),
+                )));
+
+                Some(players)
+            })
+            .flatten()
+            .unwrap_or_default();
+
+        for other in players {
+            if other != uid {
+                self.show_player(uid, other);
+            }
+        }
+    }
+
+    /// Starts a thread sending a tick to the core every TICK_INTERVAL.
+    pub fn start_ticking(&self) {
+        let core_tx = self.sender_clone();
+
+        thread::spawn(move || loop {
+            thread::sleep(TICK_INTERVAL);
+
+            if core_tx.send(Box::new(Tick::new())).is_err() {
+                break;
+            }
+        });
+    }
+
+    /// Starts a thread which listens for incoming connections.
+    pub fn network_listen(&mut self) {
+        let core_tx = self.sender_take();
+
+        let port = self.config.port;
+
+        thread::spawn(move || {
+            let network = Network::new(port);
+
+            network.listen(core_tx);
+        });
+    }
+
+    /// Handles a single message received over the memory channel.
+    /// Packets sent by a player outside of the login state they belong to are dropped.
+    pub fn handle_message(&mut self, message: Box<dyn NetworkPacket + Send>) {
+        if let Some(required_state) = message.get_required_state() {
+            let state = self
+                .get_player_snapshot(message.get_sender_uid())
+                .map(|player| player.login_state);
+
+            if state != Some(required_state) {
+                return;
+            }
+        }
+
+        message.handle_receive(self);
+
+        self.process_actions();
+    }
+
+    /// Listens for network packets over the memory channel.
+    pub fn handle_received_packets(&mut self) {
+        let receiver = self.receiver_take();
+
+        for message in receiver {
+            //self.log(&format!("Received a packet with id: {}", message.get_id()));
+
+            self.handle_message(message);
+        }
+
+        panic!(
+            "{}",
+            self.log("FATAL ERROR: Receiving memory has stopped unexpectedly.")
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_access {
+    //! Scenarios which used to lock the same player or world twice on the core thread.
+    //! Each scenario runs on its own thread, a deadlock shows up as a timeout instead of a hanging test.
+
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc::channel, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::super::super::network::*;
+    use super::super::events::WorldUnloadEvent;
+    use super::super::maps::MemoryMap;
+    use super::super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Player recording what the core sends to it.
+    struct TestPlayer {
+        uid: usize,
+        name: String,
+        world: String,
+        transform: Transform,
+        login_state: LoginState,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    #[derive(Clone, PartialEq, Debug)]
+    enum Received {
+        Packet(u8),
+        Message(String),
+    }
+
+    impl Player for TestPlayer {
+        fn set_uid(&mut self, id: usize) {
+            self.uid = id;
+        }
+        fn get_uid(&self) -> usize {
+            self.uid
+        }
+
+        fn get_name(&self) -> &str {
+            &self.name
+        }
+        fn get_display_name(&self) -> &str {
+            &self.name
+        }
+
+        fn set_world(&mut self, map: &str) {
+            self.world = String::from(map);
+        }
+        fn get_world(&self) -> &str {
+            &self.world
+        }
+
+        fn get_transform(&self) -> &Transform {
+            &self.transform
+        }
+        fn get_transform_mut(&mut self) -> &mut Transform {
+            &mut self.transform
+        }
+
+        fn set_login_state(&mut self, state: LoginState) {
+            self.login_state = state;
+        }
+        fn get_login_state(&self) -> LoginState {
+            self.login_state
+        }
+
+        fn update_transform(&mut self, transform: Transform) {
+            self.transform = transform;
+        }
+
+        fn handle_packet(&mut self, packet: Box<dyn NetworkPacket>) {
+            self.received
+                .lock()
+                .unwrap()
+                .push(Received::Packet(packet.get_id()));
+        }
+
+        fn send_message(&mut self, message: &str) {
+            self.received
+                .lock()
+                .unwrap()
+                .push(Received::Message(String::from(message)));
+        }
+    }
+
+    type Inbox = Arc<Mutex<Vec<Received>>>;
+
+    /// Runs a scenario on a separate thread, failing if it does not finish in time.
+    fn run_scenario<F: FnOnce() + Send + 'static>(scenario: F) {
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            scenario();
+
+            tx.send(()).ok();
+        });
+
+        rx.recv_timeout(TIMEOUT)
+            .expect("Scenario did not finish, the core has deadlocked (or panicked).");
+    }
+
+    /// Creates a core keeping its files in the given folder, with players 1..=count inside the main world, inboxes cleared.
+    fn core_with_players(folder: &Path, count: usize) -> (Core, Vec<Inbox>) {
+        let core = Core::with_config(1, Config::in_folder(folder));
+        let mut inboxes = Vec::new();
+
+        for uid in 1..=count {
+            let received = Arc::new(Mutex::new(Vec::new()));
+
+            core.add_player(
+                uid,
+                Box::new(TestPlayer {
+                    uid,
+                    name: format!("player{}", uid),
+                    world: String::new(),
+                    transform: Transform::default(),
+                    login_state: LoginState::Identified,
+                    received: received.clone(),
+                }),
+            );
+
+            assert_eq!(
+                core.with_player_mut(uid, |player| core.send_map(player, "main")),
+                Some(true)
+            );
+
+            inboxes.push(received);
+        }
+
+        core.process_actions();
+
+        for inbox in &inboxes {
+            inbox.lock().unwrap().clear();
+        }
+
+        (core, inboxes)
+    }
+
+    fn packets(inbox: &Inbox, id: u8) -> usize {
+        let received = inbox.lock().unwrap();
+
+        received
+            .iter()
+            .filter(|r| **r == Received::Packet(id))
+            .count()
+    }
+
+    fn messages(inbox: &Inbox, text: &str) -> usize {
+        let received = inbox.lock().unwrap();
+
+        received
+            .iter()
+            .filter(|r| matches!(r, Received::Message(m) if m.contains(text)))
+            .count()
+    }
+
+    fn chat(sender: usize, message: &str) -> Box<dyn NetworkPacket + Send> {
+        let mut data = vec![0xff];
+        data.extend(format!("{:<64}", message).bytes());
+
+        Box::new(PlayerMessage::new(&mut BufferReader::new(&data), sender))
+    }
+
+    #[test]
+    /// Joining a world used to lock every player inside it while the joining one was locked.
+    pub fn players_spawn_for_each_other() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-players-spawn-for-each-other-{}",
+                std::process::id()
+            ));
+            let (core, inboxes) = core_with_players(&folder, 2);
+            let received = Arc::new(Mutex::new(Vec::new()));
+
+            core.add_player(
+                3,
+                Box::new(TestPlayer {
+                    uid: 3,
+                    name: String::from("player3"),
+                    world: String::new(),
+                    transform: Transform::default(),
+                    login_state: LoginState::Identified,
+                    received: received.clone(),
+                }),
+            );
+
+            core.with_player_mut(3, |player| core.send_map(player, "main"));
+            core.process_actions();
+
+            assert_eq!(packets(&inboxes[0], 0x07), 1);
+            assert_eq!(packets(&inboxes[1], 0x07), 1);
+
+            // Own spawn and both others.
+            assert_eq!(packets(&received, 0x07), 3);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Setting a block locked the world and then every other player inside it.
+    pub fn setblock_with_players_in_world() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-setblock-with-players-in-world-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 3);
+
+            let data = vec![0, 1, 0, 1, 0, 1, 0x01, 0x01];
+            core.handle_message(Box::new(PlayerSetBlock::new(
+                &mut BufferReader::new(&data),
+                1,
+            )));
+
+            // Sender always receives the block back.
+            assert_eq!(packets(&inboxes[0], 0x06), 1);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Clicks outside of the map are ignored before any event sees them.
+    pub fn setblock_out_of_bounds() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-setblock-out-of-bounds-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 1);
+            let size = core.with_world("main", |world| *world.get_size()).unwrap();
+
+            let mut data = Vec::new();
+            data.extend(&size.0.to_be_bytes());
+            data.extend(&[0, 1, 0, 1, 0x01, 0x01]);
+
+            core.handle_message(Box::new(PlayerSetBlock::new(
+                &mut BufferReader::new(&data),
+                1,
+            )));
+
+            assert_eq!(packets(&inboxes[0], 0x06), 0);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Movement locked the world and every other player while the mover was locked.
+    pub fn movement_reaches_other_players() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-movement-reaches-other-players-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 3);
+
+            let data = vec![0xff, 0, 64, 0, 64, 0, 64, 0, 0];
+            core.handle_message(Box::new(PlayerPositionAndOrientation::new(
+                &mut BufferReader::new(&data),
+                1,
+                false,
+            )));
+
+            assert_eq!(packets(&inboxes[0], 0x08), 0);
+            assert_eq!(packets(&inboxes[1], 0x08), 1);
+            assert_eq!(packets(&inboxes[2], 0x08), 1);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Chat and /players used to lock every player while the sender was locked.
+    pub fn chat_and_player_list() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-chat-and-player-list-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 3);
+
+            core.handle_message(chat(1, "hello"));
+
+            for inbox in &inboxes {
+                assert_eq!(messages(inbox, "hello"), 1);
+            }
+
+            inboxes[1].lock().unwrap().clear();
+            core.handle_message(chat(2, "/players"));
+
+            assert_eq!(messages(&inboxes[1], "player1"), 1);
+            assert_eq!(messages(&inboxes[1], "player3"), 1);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// /main while already inside main locked the main world twice.
+    pub fn rejoin_current_world() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-rejoin-current-world-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 2);
+
+            core.handle_message(chat(1, "/main"));
+
+            assert_eq!(packets(&inboxes[0], 0x02), 1);
+            assert_eq!(core.get_world_players("main").len(), 2);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Switching worlds locked the new world, then the old one and the players inside it.
+    pub fn switch_worlds_with_players_around() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-switch-worlds-with-players-around-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 3);
+
+            core.add_world(World::new(
+                String::from("other"),
+                Box::new(MemoryMap::new(Vec3D::new(16, 16, 16))),
+            ));
+
+            core.handle_message(chat(1, "/j other"));
+
+            assert_eq!(core.get_world_players("other"), vec![1]);
+            assert_eq!(packets(&inboxes[1], 0x0c), 1);
+            assert_eq!(messages(&inboxes[2], "joined the world \"other\""), 1);
+
+            core.handle_message(chat(2, "/j other"));
+
+            // Both see each other in the new world, next to their own spawn.
+            assert_eq!(packets(&inboxes[0], 0x07), 2);
+            assert_eq!(packets(&inboxes[1], 0x07), 2);
+
+            core.handle_message(chat(1, "/main"));
+            core.handle_message(chat(2, "/main"));
+
+            // Empty worlds are unloaded.
+            assert!(!core.is_world_loaded("other"));
+            assert_eq!(core.get_world_players("main").len(), 3);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Teleporting to a player in another world read the target while the sender was locked.
+    pub fn teleport_across_worlds() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-teleport-across-worlds-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 2);
+
+            core.add_world(World::new(
+                String::from("other"),
+                Box::new(MemoryMap::new(Vec3D::new(16, 16, 16))),
+            ));
+
+            core.handle_message(chat(2, "/j other"));
+            core.handle_message(chat(1, "/tp player2"));
+
+            let target = core.get_player_snapshot(2).unwrap();
+            let teleported = core.get_player_snapshot(1).unwrap();
+
+            assert_eq!(teleported.world, "other");
+            let (to, from) = (teleported.transform.get_pos(), target.transform.get_pos());
+
+            assert_eq!((to.0, to.1, to.2), (from.0, from.1, from.2));
+            assert_eq!(packets(&inboxes[1], 0x08), 1);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    /// Disconnecting locked the world and the remaining players while the leaving one was locked.
+    pub fn disconnect_with_players_around() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-disconnect-with-players-around-{}",
+                std::process::id()
+            ));
+            let (mut core, inboxes) = core_with_players(&folder, 3);
+
+            core.handle_message(Box::new(DisconnectPlayer::new(1, String::new())));
+
+            assert!(core.get_player_snapshot(1).is_none());
+            assert_eq!(core.get_world_players("main"), vec![2, 3]);
+
+            for inbox in &inboxes[1..] {
+                assert_eq!(packets(inbox, 0x0c), 1);
+                assert_eq!(messages(inbox, "has left the server"), 1);
+            }
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    /// Counts the worlds asked to unload.
+    struct UnloadCounter(Arc<AtomicUsize>);
+
+    impl Plugin for UnloadCounter {
+        fn get_name(&self) -> &str {
+            "UnloadCounter"
+        }
+
+        fn register(&mut self, events: &mut EventBus) {
+            let unloads = self.0.clone();
+
+            events.register(Priority::Normal, move |_, _: &mut WorldUnloadEvent| {
+                unloads.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    #[test]
+    /// Joining the first world, or unloading worlds which are not loaded, fires no unload event.
+    pub fn unload_only_loaded_worlds() {
+        run_scenario(|| {
+            let folder = env::temp_dir().join(format!(
+                "rcclassic-unload-only-loaded-worlds-{}",
+                std::process::id()
+            ));
+            let mut core = Core::with_config(1, Config::in_folder(&folder));
+            let unloads = Arc::new(AtomicUsize::new(0));
+            core.register_plugin(Box::new(UnloadCounter(unloads.clone())));
+
+            core.add_player(
+                1,
+                Box::new(TestPlayer {
+                    uid: 1,
+                    name: String::from("player1"),
+                    world: String::new(),
+                    transform: Transform::default(),
+                    login_state: LoginState::Identified,
+                    received: Arc::new(Mutex::new(Vec::new())),
+                }),
+            );
+
+            assert_eq!(
+                core.with_player_mut(1, |player| core.send_map(player, "main")),
+                Some(true)
+            );
+            assert!(core.try_unload_world("nowhere", true).is_err());
+
+            assert_eq!(unloads.load(Ordering::SeqCst), 0);
+
+            let _ = fs::remove_dir_all(&folder);
+        });
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is already in use")]
+    /// Locking a player twice on the same thread is a bug, caught by debug builds.
+    pub fn reentrant_player_access() {
+        let folder = env::temp_dir().join(format!(
+            "rcclassic-reentrant-player-access-{}",
+            std::process::id()
+        ));
+        let (core, _inboxes) = core_with_players(&folder, 1);
+
+        core.with_player_mut(1, |_| core.with_player(1, |_| ()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is already in use")]
+    /// Locking a world twice on the same thread is a bug, caught by debug builds.
+    pub fn reentrant_world_access() {
+        let folder = env::temp_dir().join(format!(
+            "rcclassic-reentrant-world-access-{}",
+            std::process::id()
+        ));
+        let (core, _inboxes) = core_with_players(&folder, 1);
+
+        core.with_world_mut("main", |_| core.with_world("main", |_| ()));
+    }
+
+    #[test]
+    /// Maps are resized with players inside, and only copied, renamed or deleted once saved and empty.
+    pub fn manage_maps() {
+        run_scenario(|| {
+            let folder =
+                env::temp_dir().join(format!("rcclassic-manage-maps-{}", std::process::id()));
+            let (mut core, inboxes) = core_with_players(&folder, 1);
+            let maps = folder.join("maps");
+
+            core.create_map("test_manage_a", Vec3D::new(16, 16, 16), "flat", 0)
+                .unwrap();
+            core.handle_message(chat(1, "/j test_manage_a"));
+
+            assert!(core
+                .resize_map("test_manage_a", Vec3D::new(32, 16, 20))
+                .is_ok());
+            core.process_actions();
+
+            // Joining and resizing both send the map.
+            assert_eq!(packets(&inboxes[0], 0x02), 2);
+            assert_eq!(
+                core.with_world("test_manage_a", |world| *world.get_size()),
+                Some(Vec3D::new(32, 16, 20))
+            );
+
+            assert!(core.delete_map("test_manage_a").is_err());
+            assert!(core.rename_map("test_manage_a", "test_manage_b").is_err());
+
+            // Leaving unloads the world, saving the resized map.
+            core.handle_message(chat(1, "/main"));
+            assert!(!core.is_world_loaded("test_manage_a"));
+
+            assert!(core.copy_map("test_manage_a", "test_manage_b").is_ok());
+            assert!(core.copy_map("test_manage_a", "test_manage_b").is_err());
+            assert!(core.rename_map("test_manage_b", "test_manage_c").is_ok());
+
+            let names = core.get_map_names();
+            assert!(names.contains(&String::from("test_manage_a")));
+            assert!(!names.contains(&String::from("test_manage_b")));
+            assert!(names.contains(&String::from("test_manage_c")));
+
+            assert!(core.load_world("test_manage_c"));
+            assert_eq!(
+                core.with_world("test_manage_c", |world| *world.get_size()),
+                Some(Vec3D::new(32, 16, 20))
+            );
+
+            // Paths reaching out of the maps folder, even back into it, are not map names.
+            let outside = format!(
+                "../{}/test_manage_a",
+                maps.file_name().unwrap().to_str().unwrap()
+            );
+            assert!(core.delete_map(&outside).is_err());
+            assert!(core.rename_map(&outside, "test_manage_d").is_err());
+            assert!(core.copy_map(&outside, "test_manage_d").is_err());
+            assert!(core.resize_map(&outside, Vec3D::new(16, 16, 16)).is_err());
+            assert!(!core.load_world(&outside));
+            assert!(map_exists(&maps, "test_manage_a"));
+            assert!(!map_exists(&maps, "test_manage_d"));
+
+            assert!(core.delete_map("test_manage_a").is_ok());
+            assert!(core.delete_map("test_manage_c").is_ok());
+            assert!(!map_exists(&maps, "test_manage_a"));
+            assert!(!map_exists(&maps, "test_manage_c"));
+
+            let _ = fs::remove_dir_all(&folder);
+        });
     }
 }
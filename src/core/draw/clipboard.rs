@@ -0,0 +1,110 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Clipboards saved as files, so copied regions can be pasted into other maps.
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::super::{is_valid_map_name, util::BufferReader, BlockBuffer, Vec3D, MAX_MAP_VOLUME};
+
+/// Folder clipboards are saved into.
+pub const CLIPBOARDS_FOLDER: &str = "clipboards";
+
+const MAGIC: u16 = 0x0c1b;
+
+/// Bytes of the header before the blocks.
+const HEADER_SIZE: usize = 8;
+
+fn get_path(name: &str) -> Result<PathBuf, String> {
+    // Clipboard names become file names, same as map names.
+    if !is_valid_map_name(name) {
+        return Err(format!("\"{}\" is not a valid clipboard name.", name));
+    }
+
+    Ok(PathBuf::from(CLIPBOARDS_FOLDER).join(format!("{}.clip", name)))
+}
+
+/// Writes a clipboard into clipboards/{name}.clip, gzipped: a magic number, the width,
+/// height and length, then the blocks.
+pub fn save(name: &str, buffer: &BlockBuffer) -> Result<(), String> {
+    let path = get_path(name)?;
+    let Vec3D(width, height, length) = *buffer.get_size();
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+
+    for value in &[MAGIC, width, height, length] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+
+    gz.write_all(&header)
+        .and_then(|_| gz.write_all(buffer.get_data()))
+        .and_then(|_| gz.finish())
+        .and_then(|data| {
+            fs::create_dir_all(CLIPBOARDS_FOLDER)?;
+            fs::write(&path, data)
+        })
+        .map_err(|e| format!("Unable to save clipboard \"{}\": {}", name, e))
+}
+
+/// Reads a clipboard saved by `save`.
+pub fn load(name: &str) -> Result<BlockBuffer, String> {
+    let path = get_path(name)?;
+
+    let compressed = fs::read(&path).map_err(|_| format!("No clipboard named \"{}\".", name))?;
+
+    // Copies come from maps, so larger clipboards are refused without reading them whole.
+    let mut data = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .take((HEADER_SIZE + MAX_MAP_VOLUME) as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Unable to read clipboard \"{}\": {}", name, e))?;
+
+    let invalid = || format!("Clipboard \"{}\" is invalid.", name);
+
+    if data.len() < HEADER_SIZE || data.len() > HEADER_SIZE + MAX_MAP_VOLUME {
+        return Err(invalid());
+    }
+
+    let mut reader = BufferReader::new(&data);
+
+    if reader.read_ushort_le() != MAGIC {
+        return Err(invalid());
+    }
+
+    let width = reader.read_ushort_le();
+    let height = reader.read_ushort_le();
+    let length = reader.read_ushort_le();
+
+    BlockBuffer::from_data(
+        Vec3D::new(width, height, length),
+        data[reader.get_index()..].to_vec(),
+    )
+    .ok_or_else(invalid)
+}
@@ -0,0 +1,873 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Draw commands. Players start one, then mark its corners by placing or breaking blocks.
+
+pub mod clipboard;
+pub mod schematic;
+mod shapes;
+
+pub use self::shapes::*;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::events::{PlayerLeaveEvent, SetBlockEvent};
+use super::{
+    block, Axis, BlockBuffer, Command, Core, Event, EventBus, PlayerSnapshot, Plugin, Priority,
+    Rank, Region, Vec3D,
+};
+
+/// What a draw command does once its marks are placed. Blocks left out are the block held
+/// when placing the last mark.
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Cuboid(Option<u8>),
+    Hollow(Option<u8>),
+    Walls(Option<u8>),
+    Sphere(u16, Option<u8>),
+    Line(Option<u8>),
+    Replace(u8, u8),
+    Fill(Option<u8>),
+    /// Copies a cuboid into the clipboard, replacing it with air when cutting.
+    Copy(bool),
+    /// Pastes the clipboard with its lowest corner at the mark.
+    Paste,
+}
+
+impl Operation {
+    fn get_marks(&self) -> usize {
+        match self {
+            Operation::Sphere(_, _) | Operation::Fill(_) | Operation::Paste => 1,
+            _ => 2,
+        }
+    }
+}
+
+// Why a draw command was not done.
+enum Refused {
+    // More blocks than the player's limit, with the number needed.
+    Limit(usize),
+    // Part of the drawing was inside a zone the player cannot build in.
+    Zone(String),
+}
+
+// Draw command a player has started, and the marks placed for it so far.
+#[derive(Default)]
+struct Session {
+    pending: Option<Operation>,
+    world: String,
+    marks: Vec<Vec3D>,
+}
+
+type Sessions = Arc<Mutex<HashMap<usize, Session>>>;
+
+// Blocks each player copied last.
+type Clipboards = Arc<Mutex<HashMap<usize, BlockBuffer>>>;
+
+// Reads the arguments of a draw command, None if they are invalid.
+type ParseArgs = fn(&str) -> Option<Operation>;
+
+/// Commands drawing shapes into worlds, limited per rank by the "draw-limit-*" settings.
+pub struct DrawPlugin {
+    sessions: Sessions,
+    clipboards: Clipboards,
+}
+
+impl DrawPlugin {
+    pub fn new() -> DrawPlugin {
+        DrawPlugin {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            clipboards: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl Default for DrawPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for DrawPlugin {
+    fn get_name(&self) -> &str {
+        "Draw"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        let sessions = self.sessions.clone();
+        let clipboards = self.clipboards.clone();
+
+        // Marks are taken before anything else sees the click, so read-only worlds can be marked too.
+        events.register(
+            Priority::Highest,
+            move |core: &Core, event: &mut SetBlockEvent| mark(&sessions, &clipboards, core, event),
+        );
+
+        let sessions = self.sessions.clone();
+        let clipboards = self.clipboards.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |_: &Core, event: &mut PlayerLeaveEvent| {
+                sessions.lock().unwrap().remove(&event.player.uid);
+                clipboards.lock().unwrap().remove(&event.player.uid);
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let commands: [(&str, &str, ParseArgs); 9] = [
+            ("cuboid", "Fills a cuboid. [block]", |args| {
+                Some(Operation::Cuboid(parse_block(args)?))
+            }),
+            ("hollow", "Builds the shell of a cuboid. [block]", |args| {
+                Some(Operation::Hollow(parse_block(args)?))
+            }),
+            ("walls", "Builds the sides of a cuboid. [block]", |args| {
+                Some(Operation::Walls(parse_block(args)?))
+            }),
+            ("sphere", "Builds a ball. {radius} [block]", |args| {
+                let mut args = args.splitn(2, ' ');
+                let radius = args.next()?.parse().ok().filter(|radius| *radius > 0)?;
+
+                Some(Operation::Sphere(
+                    radius,
+                    parse_block(args.next().unwrap_or(""))?,
+                ))
+            }),
+            ("line", "Draws a line. [block]", |args| {
+                Some(Operation::Line(parse_block(args)?))
+            }),
+            (
+                "replace",
+                "Replaces a block inside a cuboid. {block} {new block}",
+                |args| {
+                    let mut args = args.split_ascii_whitespace();
+                    let from = block::from_name(args.next()?)?;
+                    let to = block::from_name(args.next()?)?;
+
+                    Some(Operation::Replace(from, to))
+                },
+            ),
+            (
+                "fill",
+                "Fills the area connected to a block. [block]",
+                |args| Some(Operation::Fill(parse_block(args)?)),
+            ),
+            ("copy", "Copies a cuboid into your clipboard.", |args| {
+                Some(Operation::Copy(false)).filter(|_| args.is_empty())
+            }),
+            (
+                "cut",
+                "Copies a cuboid into your clipboard and removes it.",
+                |args| Some(Operation::Copy(true)).filter(|_| args.is_empty()),
+            ),
+        ];
+
+        for (name, description, parse) in commands.iter() {
+            let sessions = self.sessions.clone();
+            let usage = description[description.find(". ").map_or(0, |i| i + 2)..].to_string();
+            let parse = *parse;
+            let command = *name;
+
+            core.register_command(Command::new(
+                self.get_name(),
+                name,
+                description,
+                Rank::Builder,
+                move |core: &Core, player: &PlayerSnapshot, args: &str| match parse(args.trim()) {
+                    Some(operation) => start(&sessions, core, player, operation),
+                    None => core
+                        .send_message_to(player.uid, &format!("&7Usage: /{} {}", command, usage)),
+                },
+            ));
+        }
+
+        let sessions = self.sessions.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "abort",
+            "Cancels the draw command being marked.",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, _args: &str| {
+                sessions.lock().unwrap().remove(&player.uid);
+                core.send_message_to(player.uid, "&7Draw command cancelled.");
+            },
+        ));
+
+        core.register_command_alias("z", "cuboid");
+        core.register_command_alias("r", "replace");
+
+        self.register_clipboard_commands(core);
+    }
+}
+
+impl DrawPlugin {
+    fn register_clipboard_commands(&self, core: &Core) {
+        let sessions = self.sessions.clone();
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "paste",
+            "Pastes your clipboard at a marked block.",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, _args: &str| {
+                if clipboards.lock().unwrap().contains_key(&player.uid) {
+                    start(&sessions, core, player, Operation::Paste);
+                } else {
+                    core.send_message_to(player.uid, "&8Your clipboard is empty.");
+                }
+            },
+        ));
+
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "rotate",
+            "Turns your clipboard. {90|180|270} [x|y|z]",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let mut args = args.split_ascii_whitespace();
+
+                let turns = args
+                    .next()
+                    .and_then(|angle| angle.parse::<i32>().ok())
+                    .filter(|angle| angle % 90 == 0)
+                    .map(|angle| (angle / 90).rem_euclid(4) as u8);
+                let axis = args.next().map_or(Ok(Axis::Y), str::parse);
+
+                match (turns, axis) {
+                    (Some(turns), Ok(axis)) => {
+                        edit_clipboard(&clipboards, core, player, |buffer| {
+                            buffer.rotate(axis, turns)
+                        })
+                    }
+                    _ => core.send_message_to(player.uid, "&7Usage: /rotate {90|180|270} [x|y|z]"),
+                }
+            },
+        ));
+
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "mirror",
+            "Flips your clipboard along an axis. {x|y|z}",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| match args.trim().parse() {
+                Ok(axis) => edit_clipboard(&clipboards, core, player, |buffer| buffer.mirror(axis)),
+                Err(_) => core.send_message_to(player.uid, "&7Usage: /mirror {x|y|z}"),
+            },
+        ));
+
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "csave",
+            "Saves your clipboard to use it later. {name}",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim();
+                let buffer = clipboards.lock().unwrap().get(&player.uid).cloned();
+
+                let message = match buffer {
+                    Some(buffer) => match clipboard::save(name, &buffer) {
+                        Ok(()) => format!("&7Clipboard saved as \"{}\".", name),
+                        Err(e) => format!("&8{}", e),
+                    },
+                    None => String::from("&8Your clipboard is empty."),
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "cload",
+            "Loads a saved clipboard. {name}",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim();
+
+                let message = match clipboard::load(name) {
+                    Ok(buffer) => {
+                        let Vec3D(x, y, z) = *buffer.get_size();
+                        clipboards.lock().unwrap().insert(player.uid, buffer);
+
+                        format!("&7Loaded clipboard \"{}\" ({}x{}x{}).", name, x, y, z)
+                    }
+                    Err(e) => format!("&8{}", e),
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "schemsave",
+            "Saves your clipboard as a schematic for other editors. {name}",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim();
+                let buffer = clipboards.lock().unwrap().get(&player.uid).cloned();
+
+                let message = match buffer {
+                    Some(buffer) => match schematic::save(name, &buffer) {
+                        Ok(()) => format!("&7Clipboard saved as schematic \"{}\".", name),
+                        Err(e) => format!("&8{}", e),
+                    },
+                    None => String::from("&8Your clipboard is empty."),
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let clipboards = self.clipboards.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "schemload",
+            "Loads a schematic into your clipboard. {name}",
+            Rank::Builder,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim();
+
+                let message = match schematic::load(name) {
+                    Ok(buffer) => {
+                        let Vec3D(x, y, z) = *buffer.get_size();
+                        clipboards.lock().unwrap().insert(player.uid, buffer);
+
+                        format!("&7Loaded schematic \"{}\" ({}x{}x{}).", name, x, y, z)
+                    }
+                    Err(e) => format!("&8{}", e),
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+    }
+}
+
+fn edit_clipboard<F: FnOnce(&BlockBuffer) -> BlockBuffer>(
+    clipboards: &Clipboards,
+    core: &Core,
+    player: &PlayerSnapshot,
+    edit: F,
+) {
+    let mut clipboards = clipboards.lock().unwrap();
+
+    let message = match clipboards.get_mut(&player.uid) {
+        Some(buffer) => {
+            *buffer = edit(buffer);
+            let Vec3D(x, y, z) = *buffer.get_size();
+
+            format!("&7Clipboard is now {}x{}x{}.", x, y, z)
+        }
+        None => String::from("&8Your clipboard is empty."),
+    };
+
+    drop(clipboards);
+    core.send_message_to(player.uid, &message);
+}
+
+// Optional block argument: Some(None) if left out, None if it is not a block.
+fn parse_block(args: &str) -> Option<Option<u8>> {
+    match args.trim() {
+        "" => Some(None),
+        name => block::from_name(name).map(Some),
+    }
+}
+
+fn start(sessions: &Sessions, core: &Core, player: &PlayerSnapshot, operation: Operation) {
+    sessions.lock().unwrap().insert(
+        player.uid,
+        Session {
+            pending: Some(operation),
+            world: player.world.clone(),
+            marks: Vec::new(),
+        },
+    );
+
+    let message = match operation.get_marks() {
+        1 => "&7Place or break a block to mark where to draw.",
+        _ => "&7Place or break two blocks to mark the corners.",
+    };
+
+    core.send_message_to(player.uid, message);
+}
+
+fn mark(sessions: &Sessions, clipboards: &Clipboards, core: &Core, event: &mut SetBlockEvent) {
+    let mut sessions = sessions.lock().unwrap();
+
+    let session = match sessions.get_mut(&event.player.uid) {
+        Some(session) if session.pending.is_some() => session,
+        _ => return,
+    };
+
+    event.set_cancelled(true);
+
+    // Marks have to be in the same world, moving to another one starts over.
+    if session.world != event.world {
+        session.world = event.world.clone();
+        session.marks.clear();
+    }
+
+    session.marks.push(event.position);
+
+    let operation = match session.pending {
+        Some(operation) if session.marks.len() >= operation.get_marks() => operation,
+        _ => {
+            let Vec3D(x, y, z) = event.position;
+
+            core.send_message_to(
+                event.player.uid,
+                &format!("&7Marked ({}, {}, {}), place the next mark.", x, y, z),
+            );
+
+            return;
+        }
+    };
+
+    let marks = std::mem::take(&mut session.marks);
+    sessions.remove(&event.player.uid);
+    drop(sessions);
+
+    draw(
+        core,
+        clipboards,
+        &event.player,
+        &event.world,
+        operation,
+        &marks,
+        event.block,
+    );
+}
+
+fn draw(
+    core: &Core,
+    clipboards: &Clipboards,
+    player: &PlayerSnapshot,
+    world: &str,
+    operation: Operation,
+    marks: &[Vec3D],
+    held: u8,
+) {
+    // Copying does not change the world.
+    if core.is_read_only(world) && !matches!(operation, Operation::Copy(false)) {
+        let message = if core.get_config().read_only {
+            "&8Server is on read-only state."
+        } else {
+            "&8This world is read-only."
+        };
+
+        core.send_message_to(player.uid, message);
+
+        return;
+    }
+
+    let limit = core.get_config().get_draw_limit(player.rank);
+    let within = |count: usize| {
+        if count <= limit {
+            Ok(())
+        } else {
+            Err(Refused::Limit(count))
+        }
+    };
+
+    // Clipboards are not locked along with the world.
+    let pasted = match operation {
+        Operation::Paste => clipboards.lock().unwrap().get(&player.uid).cloned(),
+        _ => None,
+    };
+    let mut copied = None;
+
+    let result = core.with_world_mut(world, |world| {
+        let (result, changes) = world.record(|world| match operation {
+            Operation::Cuboid(block) => {
+                let region = Region::new(marks[0], marks[1]);
+                within(region.get_volume())?;
+
+                Ok(world.fill(&region, block.unwrap_or(held)))
+            }
+            Operation::Hollow(block) => {
+                let region = Region::new(marks[0], marks[1]);
+                within(hollow_volume(&region))?;
+
+                Ok(hollow(world, &region, block.unwrap_or(held)))
+            }
+            Operation::Walls(block) => {
+                let region = Region::new(marks[0], marks[1]);
+                within(walls_volume(&region))?;
+
+                Ok(walls(world, &region, block.unwrap_or(held)))
+            }
+            Operation::Sphere(radius, block) => {
+                let radius = radius.min(max_sphere_radius(world.get_size()));
+                // Measuring the ball takes long for big radii, the estimate is checked first.
+                within(sphere_estimate(radius))?;
+                within(sphere_volume(radius))?;
+
+                Ok(sphere(world, &marks[0], radius, block.unwrap_or(held)))
+            }
+            Operation::Line(block) => {
+                within(line_positions(&marks[0], &marks[1]).len())?;
+
+                Ok(line(world, &marks[0], &marks[1], block.unwrap_or(held)))
+            }
+            Operation::Replace(from, to) => {
+                let region = Region::new(marks[0], marks[1]);
+                within(world.count(&region, from))?;
+
+                Ok(world.replace(&region, from, to))
+            }
+            Operation::Fill(block) => flood_fill(world, &marks[0], block.unwrap_or(held), limit)
+                .ok_or(Refused::Limit(limit + 1)),
+            Operation::Copy(cut) => {
+                let region = Region::new(marks[0], marks[1]);
+                within(region.get_volume())?;
+
+                copied = Some(world.read_region(&region));
+
+                match cut {
+                    true => Ok(world.fill(&region, block::AIR)),
+                    false => Ok(Vec::new()),
+                }
+            }
+            Operation::Paste => match &pasted {
+                Some(buffer) => {
+                    within(buffer.get_data().len())?;
+
+                    Ok(world.paste(&marks[0], buffer))
+                }
+                None => Ok(Vec::new()),
+            },
+        });
+
+        // Zones are checked on what got drawn, which is put back if any of it is inside one.
+        let blocking = changes
+            .iter()
+            .find_map(|change| world.get_blocking_zone(&player.name, player.rank, &change.position))
+            .map(|zone| zone.name.clone());
+
+        match blocking {
+            Some(zone) => {
+                world.revert(&changes);
+
+                (Err(Refused::Zone(zone)), Vec::new())
+            }
+            None => (result, changes),
+        }
+    });
+
+    let result = result.map(|(result, changes)| {
+        core.record_edit(&player.name, world, changes);
+
+        result
+    });
+
+    if let Some(buffer) = copied {
+        let Vec3D(x, y, z) = *buffer.get_size();
+        clipboards.lock().unwrap().insert(player.uid, buffer);
+
+        core.send_message_to(
+            player.uid,
+            &format!("&7Copied {}x{}x{} blocks into your clipboard.", x, y, z),
+        );
+
+        if operation == Operation::Copy(false) {
+            return;
+        }
+    }
+
+    match result {
+        Some(Ok(changed)) => {
+            core.send_blocks(world, &changed);
+            core.send_message_to(player.uid, &format!("&7{} blocks changed.", changed.len()));
+        }
+        Some(Err(Refused::Limit(count))) => core.send_message_to(
+            player.uid,
+            &format!(
+                "&8You can draw {} blocks at once, this needs {}.",
+                limit, count
+            ),
+        ),
+        Some(Err(Refused::Zone(zone))) => core.send_message_to(
+            player.uid,
+            &format!("&8You cannot build in the zone \"{}\".", zone),
+        ),
+        None => core.send_message_to(player.uid, "&8Unable to draw in this world."),
+    }
+}
+
+#[cfg(test)]
+mod test_draw {
+    use std::env;
+    use std::fs;
+
+    use super::super::maps::MemoryMap;
+    use super::super::{Config, World};
+    use super::*;
+
+    fn count(world: &World, block: u8) -> usize {
+        world.get_chunks().iter().filter(|b| **b == block).count()
+    }
+
+    #[test]
+    /// Shapes change as many blocks as their limits are checked against.
+    pub fn shapes() {
+        let mut world = World::new(
+            String::from("test"),
+            Box::new(MemoryMap::empty(Vec3D::new(32, 32, 32))),
+        );
+        let region = Region::new(Vec3D::new(10, 2, 3), Vec3D::new(4, 9, 12));
+
+        assert_eq!(hollow(&mut world, &region, 1).len(), hollow_volume(&region));
+        world.fill(&region, 0);
+        assert_eq!(walls(&mut world, &region, 1).len(), walls_volume(&region));
+        world.fill(&region, 0);
+
+        let ball = sphere(&mut world, &Vec3D::new(16, 16, 16), 4, 2);
+        assert_eq!(ball.len(), sphere_volume(4));
+        assert!(sphere_estimate(4) <= sphere_volume(4));
+        assert!(sphere_estimate(u16::MAX) > 5_000_000);
+        for position in &[
+            Vec3D::new(12, 16, 16),
+            Vec3D::new(20, 16, 16),
+            Vec3D::new(16, 20, 16),
+        ] {
+            assert_eq!(world.get_block(position), 2);
+        }
+        assert_eq!(world.get_block(&Vec3D::new(20, 20, 16)), 0);
+
+        let drawn = line(&mut world, &Vec3D::new(0, 0, 0), &Vec3D::new(9, 3, 0), 3);
+        assert_eq!(drawn.len(), 10);
+        assert_eq!(world.get_block(&Vec3D::new(9, 3, 0)), 3);
+
+        // The ball is hollow on the inside once filled with air from the outside.
+        assert!(flood_fill(&mut world, &Vec3D::new(16, 16, 16), 4, 10).is_none());
+        let filled = flood_fill(&mut world, &Vec3D::new(16, 16, 16), 4, 1000).unwrap();
+        assert_eq!(filled.len(), ball.len());
+        assert_eq!(count(&world, 2), 0);
+    }
+
+    #[test]
+    /// Clicks mark the corners instead of building, limits depend on the rank.
+    pub fn marks_and_limits() {
+        let folder = env::temp_dir().join(format!("rcclassic-draw-limits-{}", std::process::id()));
+        let mut core = Core::with_config(
+            1,
+            Config {
+                read_only: false,
+                ..Config::in_folder(&folder)
+            },
+        );
+
+        let owner = core.get_player_snapshot(0).unwrap();
+
+        let click = |core: &Core, player: &PlayerSnapshot, position: Vec3D| {
+            let mut event =
+                SetBlockEvent::new(player.clone(), String::from("main"), position, 1, false);
+
+            core.call_event(&mut event)
+        };
+
+        assert!(core.try_run_command(&owner, "/cuboid stone"));
+        assert!(click(&core, &owner, Vec3D::new(1, 40, 1)));
+        assert!(click(&core, &owner, Vec3D::new(3, 42, 4)));
+        assert_eq!(
+            core.with_world("main", |world| count(world, block::STONE)),
+            Some(36)
+        );
+
+        // Marks are done, the next click builds as usual.
+        assert!(!click(&core, &owner, Vec3D::new(1, 50, 1)));
+
+        // Builders are limited to fewer blocks.
+        let mut builder = owner.clone();
+        builder.rank = Rank::Builder;
+        core.get_config_mut().draw_limits[1] = 10;
+
+        assert!(core.try_run_command(&builder, "/fill gold"));
+        assert!(click(&core, &builder, Vec3D::new(2, 41, 2)));
+        assert_eq!(
+            core.with_world("main", |world| count(world, block::GOLD)),
+            Some(0)
+        );
+
+        assert!(core.try_run_command(&owner, "/fill gold"));
+        assert!(click(&core, &owner, Vec3D::new(2, 41, 2)));
+        assert_eq!(
+            core.with_world("main", |world| count(world, block::GOLD)),
+            Some(36)
+        );
+
+        // Huge balls are refused without being measured.
+        assert!(core.try_run_command(&builder, "/sphere 65535 iron"));
+        assert!(click(&core, &builder, Vec3D::new(2, 41, 2)));
+        assert_eq!(
+            core.with_world("main", |world| count(world, block::IRON)),
+            Some(0)
+        );
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    /// Copied blocks keep their shape through rotating, mirroring and pasting.
+    pub fn clipboard() {
+        let folder =
+            env::temp_dir().join(format!("rcclassic-draw-clipboard-{}", std::process::id()));
+        let core = Core::with_config(
+            1,
+            Config {
+                read_only: false,
+                ..Config::in_folder(&folder)
+            },
+        );
+
+        let owner = core.get_player_snapshot(0).unwrap();
+
+        let click = |core: &Core, position: Vec3D| {
+            let mut event =
+                SetBlockEvent::new(owner.clone(), String::from("main"), position, 1, false);
+
+            core.call_event(&mut event)
+        };
+
+        // An L of three blocks, its corner at (1, 40, 1).
+        for (position, block) in &[
+            (Vec3D::new(1, 40, 1), block::GOLD),
+            (Vec3D::new(2, 40, 1), block::IRON),
+            (Vec3D::new(1, 40, 2), block::BRICK),
+        ] {
+            core.with_world_mut("main", |world| world.set_block(position, *block, false));
+        }
+
+        assert!(core.try_run_command(&owner, "/paste"));
+        assert!(!click(&core, Vec3D::new(1, 50, 1)));
+
+        assert!(core.try_run_command(&owner, "/cut"));
+        assert!(click(&core, Vec3D::new(1, 40, 1)));
+        assert!(click(&core, Vec3D::new(2, 40, 2)));
+        assert_eq!(
+            core.with_world("main", |world| count(world, block::GOLD)),
+            Some(0)
+        );
+
+        // A quarter turn around y moves x onto z, then z is flipped back.
+        assert!(core.try_run_command(&owner, "/rotate 90"));
+        assert!(core.try_run_command(&owner, "/mirror z"));
+        assert!(core.try_run_command(&owner, "/paste"));
+        assert!(click(&core, Vec3D::new(10, 40, 10)));
+
+        let block_at = |x, z| {
+            core.with_world("main", |world| world.get_block(&Vec3D::new(x, 40, z)))
+                .unwrap()
+        };
+
+        assert_eq!(block_at(10, 10), block::GOLD);
+        assert_eq!(block_at(10, 11), block::IRON);
+        assert_eq!(block_at(11, 10), block::BRICK);
+        assert_eq!(block_at(11, 11), block::AIR);
+
+        // Turning four times changes nothing.
+        let buffer = BlockBuffer::from_data(Vec3D::new(2, 3, 1), vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        for axis in &[Axis::X, Axis::Y, Axis::Z] {
+            let turned = (0..4).fold(buffer.clone(), |turned, _| turned.rotate(*axis, 1));
+
+            assert_eq!(turned.get_data(), buffer.get_data());
+            assert_eq!(
+                turned.mirror(*axis).mirror(*axis).get_data(),
+                buffer.get_data()
+            );
+        }
+
+        let turned = buffer.rotate(Axis::Z, 1);
+        assert_eq!(*turned.get_size(), Vec3D::new(3, 2, 1));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    /// Draws are undone and redone as one edit, leaving blocks changed since alone.
+    pub fn undo_redo() {
+        let folder = env::temp_dir().join(format!("rcclassic-draw-undo-{}", std::process::id()));
+        let core = Core::with_config(
+            1,
+            Config {
+                read_only: false,
+                ..Config::in_folder(&folder)
+            },
+        );
+
+        let owner = core.get_player_snapshot(0).unwrap();
+
+        let click = |core: &Core, position: Vec3D| {
+            let mut event =
+                SetBlockEvent::new(owner.clone(), String::from("main"), position, 1, false);
+
+            core.call_event(&mut event)
+        };
+        let gold = |core: &Core| core.with_world("main", |world| count(world, block::GOLD));
+
+        assert!(core.try_run_command(&owner, "/cuboid gold"));
+        assert!(click(&core, Vec3D::new(1, 40, 1)));
+        assert!(click(&core, Vec3D::new(2, 40, 2)));
+        assert_eq!(gold(&core), Some(4));
+
+        // Someone else changed one of the blocks.
+        core.with_world_mut("main", |world| {
+            world.set_block(&Vec3D::new(1, 40, 1), block::STONE, false)
+        });
+
+        assert!(core.try_run_command(&owner, "/undo"));
+        assert_eq!(gold(&core), Some(0));
+        assert_eq!(
+            core.with_world("main", |world| world.get_block(&Vec3D::new(1, 40, 1))),
+            Some(block::STONE)
+        );
+
+        assert!(core.try_run_command(&owner, "/redo"));
+        assert_eq!(gold(&core), Some(3));
+
+        // Operators undo other players by time, which cannot be redone.
+        assert!(core.try_run_command(&owner, &format!("/undo {} 1m", owner.name)));
+        assert_eq!(gold(&core), Some(0));
+        assert!(core.try_run_command(&owner, "/redo"));
+        assert_eq!(gold(&core), Some(0));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,717 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Schematics, the NBT files external editors keep structures in, loaded into and saved from clipboards.
+//!
+//! MCEdit schematics keep legacy Minecraft ids with their data values, Sponge schematics a palette
+//! of block names. Both are read, and clipboards are saved as MCEdit schematics. Blocks without a
+//! classic counterpart become the closest classic block.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::super::{block, is_valid_map_name, BlockBuffer, Vec3D, MAX_MAP_VOLUME};
+
+/// Folder schematics are saved into.
+pub const SCHEMATICS_FOLDER: &str = "schematics";
+
+/// Legacy id of wool, its color being the data value.
+const LEGACY_WOOL: u8 = 35;
+
+/// Legacy wool colors of the classic wool blocks, from red up to white.
+const WOOL_COLORS: [u8; 16] = [14, 1, 4, 5, 13, 9, 3, 9, 11, 10, 10, 2, 6, 15, 7, 0];
+
+/// Classic wool blocks of the legacy wool colors, from white up to black.
+const WOOL_BLOCKS: [u8; 16] = [
+    36, 22, 32, 27, 23, 24, 33, 35, 35, 28, 31, 29, 3, 25, 21, 34,
+];
+
+/// Deepest nesting of lists and compounds read.
+const MAX_DEPTH: usize = 64;
+
+/// Palette indices accepted, more than there are Minecraft block states.
+const MAX_PALETTE: usize = 1 << 16;
+
+/// Most bytes read from a schematic, enough for the blocks of a map with several bytes each.
+const MAX_SCHEMATIC_SIZE: usize = 4 * MAX_MAP_VOLUME;
+
+fn get_path(name: &str) -> Result<PathBuf, String> {
+    // Schematic names become file names, same as map names.
+    if !is_valid_map_name(name) {
+        return Err(format!("\"{}\" is not a valid schematic name.", name));
+    }
+
+    Ok(PathBuf::from(SCHEMATICS_FOLDER).join(format!("{}.schematic", name)))
+}
+
+/// Writes a clipboard into schematics/{name}.schematic as an MCEdit schematic.
+pub fn save(name: &str, buffer: &BlockBuffer) -> Result<(), String> {
+    let path = get_path(name)?;
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+
+    gz.write_all(&write_schematic(buffer))
+        .and_then(|_| gz.finish())
+        .and_then(|data| {
+            fs::create_dir_all(SCHEMATICS_FOLDER)?;
+            fs::write(&path, data)
+        })
+        .map_err(|e| format!("Unable to save schematic \"{}\": {}", name, e))
+}
+
+/// Reads an MCEdit or Sponge schematic from schematics/{name}.schematic.
+pub fn load(name: &str) -> Result<BlockBuffer, String> {
+    let path = get_path(name)?;
+
+    let compressed = fs::read(&path).map_err(|_| format!("No schematic named \"{}\".", name))?;
+
+    let mut data = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .take(MAX_SCHEMATIC_SIZE as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Unable to read schematic \"{}\": {}", name, e))?;
+
+    if data.len() > MAX_SCHEMATIC_SIZE {
+        return Err(format!("Schematic \"{}\" is too large.", name));
+    }
+
+    read_schematic(&data).map_err(|e| format!("Schematic \"{}\" is invalid: {}", name, e))
+}
+
+/// Legacy id and data value of a classic block.
+pub fn to_legacy(block: u8) -> (u8, u8) {
+    match block {
+        21..=36 => (LEGACY_WOOL, WOOL_COLORS[block as usize - 21]),
+        0..=block::MAX_CLASSIC_BLOCK => (block, 0),
+        _ => (block::STONE, 0),
+    }
+}
+
+/// Classic block closest to a legacy id and data value.
+pub fn from_legacy(id: u16, data: u8) -> u8 {
+    match id {
+        0..=20 | 37..=49 => id as u8,
+        // Wool and stained clay.
+        35 | 159 => WOOL_BLOCKS[data as usize & 0x0f],
+        // Decorations and other blocks players walk through.
+        27
+        | 28
+        | 30..=32
+        | 50
+        | 51
+        | 55
+        | 59
+        | 63
+        | 65
+        | 66
+        | 68..=70
+        | 72
+        | 75..=78
+        | 83
+        | 90
+        | 104..=106
+        | 111
+        | 115
+        | 131
+        | 132
+        | 141
+        | 142
+        | 171
+        | 175 => block::AIR,
+        22 => 29,
+        24 | 128 => block::SAND,
+        53 | 125 | 126 | 134..=136 => block::PLANKS,
+        60 => block::DIRT,
+        67 => block::COBBLESTONE,
+        79 | 95 | 102 | 160 | 174 => block::GLASS,
+        80 => 36,
+        82 => 35,
+        89 => 23,
+        98 | 109 => block::STONE,
+        108 | 112 | 114 => block::BRICK,
+        161 => block::LEAVES,
+        162 => block::LOG,
+        _ => block::STONE,
+    }
+}
+
+/// Classic block closest to a block name of a Sponge palette, such as "minecraft:oak_log[axis=y]".
+pub fn from_block_name(name: &str) -> u8 {
+    let (name, properties) = match name.find('[') {
+        Some(index) => (&name[..index], &name[index..]),
+        None => (name, ""),
+    };
+
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+
+    if let Some(color) = name
+        .strip_suffix("_wool")
+        .or_else(|| name.strip_suffix("_terracotta"))
+    {
+        let colors = [
+            "white",
+            "orange",
+            "magenta",
+            "light_blue",
+            "yellow",
+            "lime",
+            "pink",
+            "gray",
+            "light_gray",
+            "cyan",
+            "purple",
+            "blue",
+            "brown",
+            "green",
+            "red",
+            "black",
+        ];
+
+        if let Some(index) = colors.iter().position(|known| *known == color) {
+            return WOOL_BLOCKS[index];
+        }
+    }
+
+    match name {
+        "air" | "cave_air" | "void_air" | "grass" | "short_grass" | "tall_grass" | "fern"
+        | "dead_bush" | "torch" | "wall_torch" | "snow" | "vine" => block::AIR,
+        "grass_block" => block::GRASS,
+        "dirt" | "coarse_dirt" | "farmland" => block::DIRT,
+        "cobblestone" | "cobblestone_stairs" => block::COBBLESTONE,
+        "water" => block::WATER,
+        "lava" => block::LAVA,
+        "sand" | "red_sand" | "sandstone" => block::SAND,
+        "dandelion" => 37,
+        "poppy" | "rose" => 38,
+        "gold_block" => block::GOLD,
+        "iron_block" => block::IRON,
+        "bricks" => block::BRICK,
+        "mossy_cobblestone" => block::MOSSY_COBBLESTONE,
+        "glass" | "ice" => block::GLASS,
+        "snow_block" => 36,
+        _ if name.ends_with("_planks") || name.ends_with("_stairs") => block::PLANKS,
+        _ if name.ends_with("_sapling") => block::SAPLING,
+        _ if name.ends_with("_log") || name.ends_with("_wood") => block::LOG,
+        _ if name.ends_with("_leaves") => block::LEAVES,
+        _ if name.ends_with("_slab") && properties.contains("type=double") => block::DOUBLE_SLAB,
+        _ if name.ends_with("_slab") => block::SLAB,
+        _ if name.ends_with("_glass") || name.ends_with("_glass_pane") => block::GLASS,
+        _ => block::from_name(name).unwrap_or(block::STONE),
+    }
+}
+
+// Tags of the NBT format, compounds keeping the order of their tags.
+#[derive(Clone, PartialEq, Debug)]
+enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(u8, Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn get_id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(..) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.iter().find(|(key, _)| key == name).map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
+
+    // Sizes are shorts, though any number is accepted.
+    fn get_number(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn get_bytes(&self, name: &str) -> Option<&Vec<u8>> {
+        match self.get(name)? {
+            Tag::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        let length = |data: &mut Vec<u8>, length: usize| {
+            data.extend_from_slice(&(length as i32).to_be_bytes());
+        };
+
+        match self {
+            Tag::Byte(value) => data.push(*value as u8),
+            Tag::Short(value) => data.extend_from_slice(&value.to_be_bytes()),
+            Tag::Int(value) => data.extend_from_slice(&value.to_be_bytes()),
+            Tag::Long(value) => data.extend_from_slice(&value.to_be_bytes()),
+            Tag::Float(value) => data.extend_from_slice(&value.to_be_bytes()),
+            Tag::Double(value) => data.extend_from_slice(&value.to_be_bytes()),
+            Tag::ByteArray(bytes) => {
+                length(data, bytes.len());
+                data.extend_from_slice(bytes);
+            }
+            Tag::String(value) => write_string(data, value),
+            Tag::List(id, tags) => {
+                data.push(*id);
+                length(data, tags.len());
+
+                for tag in tags {
+                    tag.write(data);
+                }
+            }
+            Tag::Compound(tags) => {
+                for (name, tag) in tags {
+                    data.push(tag.get_id());
+                    write_string(data, name);
+                    tag.write(data);
+                }
+
+                data.push(0);
+            }
+            Tag::IntArray(values) => {
+                length(data, values.len());
+
+                for value in values {
+                    data.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Tag::LongArray(values) => {
+                length(data, values.len());
+
+                for value in values {
+                    data.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+    }
+}
+
+// Lengths are stored in a short, longer strings are cut at the last character fitting.
+fn write_string(data: &mut Vec<u8>, value: &str) {
+    let mut length = value.len().min(u16::MAX as usize);
+
+    while !value.is_char_boundary(length) {
+        length -= 1;
+    }
+
+    data.extend_from_slice(&(length as u16).to_be_bytes());
+    data.extend_from_slice(&value.as_bytes()[..length]);
+}
+
+// Reads the tags of an NBT file.
+struct NbtReader<'a> {
+    data: &'a [u8],
+    index: usize,
+}
+
+impl<'a> NbtReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .index
+            .checked_add(count)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| String::from("unexpected end of file."))?;
+
+        let bytes = &self.data[self.index..end];
+        self.index = end;
+
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+
+        Ok(bytes)
+    }
+
+    fn read_length(&mut self) -> Result<usize, String> {
+        let length = i32::from_be_bytes(self.read_array()?);
+
+        usize::try_from(length).map_err(|_| format!("invalid length {}.", length))
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        let length = u16::from_be_bytes(self.read_array()?) as usize;
+
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    // Reads the named tag at the root of a file.
+    fn read_root(&mut self) -> Result<(String, Tag), String> {
+        let id = self.read_array::<1>()?[0];
+        let name = self.read_string()?;
+
+        Ok((name, self.read_tag(id, 0)?))
+    }
+
+    fn read_tag(&mut self, id: u8, depth: usize) -> Result<Tag, String> {
+        if depth > MAX_DEPTH {
+            return Err(String::from("tags are nested too deep."));
+        }
+
+        let tag = match id {
+            1 => Tag::Byte(i8::from_be_bytes(self.read_array()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.read_array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.read_array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.read_array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.read_array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.read_array()?)),
+            7 => {
+                let length = self.read_length()?;
+                Tag::ByteArray(self.take(length)?.to_vec())
+            }
+            8 => Tag::String(self.read_string()?),
+            9 => {
+                let id = self.read_array::<1>()?[0];
+                let length = self.read_length()?;
+                let mut tags = Vec::new();
+
+                for _ in 0..length {
+                    tags.push(self.read_tag(id, depth + 1)?);
+                }
+
+                Tag::List(id, tags)
+            }
+            10 => {
+                let mut tags = Vec::new();
+
+                loop {
+                    let id = self.read_array::<1>()?[0];
+
+                    if id == 0 {
+                        break;
+                    }
+
+                    let name = self.read_string()?;
+                    tags.push((name, self.read_tag(id, depth + 1)?));
+                }
+
+                Tag::Compound(tags)
+            }
+            11 => {
+                let length = self.read_length()?;
+                let mut values = Vec::new();
+
+                for _ in 0..length {
+                    values.push(i32::from_be_bytes(self.read_array()?));
+                }
+
+                Tag::IntArray(values)
+            }
+            12 => {
+                let length = self.read_length()?;
+                let mut values = Vec::new();
+
+                for _ in 0..length {
+                    values.push(i64::from_be_bytes(self.read_array()?));
+                }
+
+                Tag::LongArray(values)
+            }
+            id => return Err(format!("unknown tag {}.", id)),
+        };
+
+        Ok(tag)
+    }
+}
+
+// Blocks of both formats go by X, then Z, then Y, same as clipboards.
+fn read_schematic(data: &[u8]) -> Result<BlockBuffer, String> {
+    let (_, root) = NbtReader { data, index: 0 }.read_root()?;
+
+    // Version 3 of Sponge schematics nests everything one level deeper.
+    let schematic = root.get("Schematic").unwrap_or(&root);
+
+    let axis = |name: &str| {
+        schematic
+            .get_number(name)
+            // Shorts past 32767 are negative.
+            .map(|value| value as u16)
+            .filter(|value| *value > 0)
+            .ok_or_else(|| format!("no valid {}.", name))
+    };
+
+    let size = Vec3D::new(axis("Width")?, axis("Height")?, axis("Length")?);
+    let volume = size.0 as usize * size.1 as usize * size.2 as usize;
+
+    let blocks = match schematic.get("Blocks") {
+        Some(Tag::ByteArray(ids)) => read_legacy_blocks(schematic, ids, volume)?,
+        Some(blocks @ Tag::Compound(_)) => read_palette_blocks(blocks, "Data", volume)?,
+        _ => read_palette_blocks(schematic, "BlockData", volume)?,
+    };
+
+    BlockBuffer::from_data(size, blocks).ok_or_else(|| String::from("wrong number of blocks."))
+}
+
+// MCEdit blocks: legacy ids and data values, with the upper bits of larger ids kept apart.
+fn read_legacy_blocks(schematic: &Tag, ids: &[u8], volume: usize) -> Result<Vec<u8>, String> {
+    if ids.len() != volume {
+        return Err(String::from("wrong number of blocks."));
+    }
+
+    let data = schematic.get_bytes("Data");
+    let add = schematic.get_bytes("AddBlocks");
+
+    Ok(ids
+        .iter()
+        .enumerate()
+        .map(|(index, id)| {
+            // Two blocks share each byte, the first one in the upper half.
+            let upper = add.and_then(|add| add.get(index / 2)).map_or(0, |byte| {
+                if index % 2 == 0 {
+                    byte >> 4
+                } else {
+                    byte & 0x0f
+                }
+            });
+            let value = data.and_then(|data| data.get(index)).copied().unwrap_or(0);
+
+            from_legacy((upper as u16) << 8 | *id as u16, value)
+        })
+        .collect())
+}
+
+// Sponge blocks: indices into a palette of block names, written as variable length integers.
+fn read_palette_blocks(blocks: &Tag, data: &str, volume: usize) -> Result<Vec<u8>, String> {
+    let palette = match blocks.get("Palette") {
+        Some(Tag::Compound(palette)) => palette,
+        _ => return Err(String::from("no palette.")),
+    };
+
+    let mut classic = Vec::new();
+
+    for (name, index) in palette {
+        let index = match index {
+            Tag::Int(index) => usize::try_from(*index)
+                .ok()
+                .filter(|index| *index < MAX_PALETTE)
+                .ok_or("invalid palette.")?,
+            _ => return Err(String::from("invalid palette.")),
+        };
+
+        if classic.len() <= index {
+            classic.resize(index + 1, block::AIR);
+        }
+
+        classic[index] = from_block_name(name);
+    }
+
+    let data = blocks
+        .get_bytes(data)
+        .ok_or_else(|| String::from("no blocks."))?;
+
+    let mut result = Vec::with_capacity(volume);
+    let (mut value, mut shift) = (0usize, 0);
+
+    for byte in data {
+        value |= ((byte & 0x7f) as usize) << shift;
+
+        if byte & 0x80 != 0 {
+            shift += 7;
+
+            if shift > 28 {
+                return Err(String::from("invalid block data."));
+            }
+
+            continue;
+        }
+
+        if result.len() == volume {
+            return Err(String::from("wrong number of blocks."));
+        }
+
+        result.push(
+            *classic
+                .get(value)
+                .ok_or("block missing from the palette.")?,
+        );
+        value = 0;
+        shift = 0;
+    }
+
+    if result.len() != volume || shift != 0 {
+        return Err(String::from("wrong number of blocks."));
+    }
+
+    Ok(result)
+}
+
+// MCEdit schematic, without compression.
+fn write_schematic(buffer: &BlockBuffer) -> Vec<u8> {
+    let Vec3D(width, height, length) = *buffer.get_size();
+    let (ids, data): (Vec<u8>, Vec<u8>) = buffer.get_data().iter().map(|b| to_legacy(*b)).unzip();
+
+    let schematic = Tag::Compound(vec![
+        (String::from("Width"), Tag::Short(width as i16)),
+        (String::from("Height"), Tag::Short(height as i16)),
+        (String::from("Length"), Tag::Short(length as i16)),
+        (
+            String::from("Materials"),
+            Tag::String(String::from("Alpha")),
+        ),
+        (String::from("Blocks"), Tag::ByteArray(ids)),
+        (String::from("Data"), Tag::ByteArray(data)),
+        (String::from("Entities"), Tag::List(10, Vec::new())),
+        (String::from("TileEntities"), Tag::List(10, Vec::new())),
+    ]);
+
+    let mut file = vec![schematic.get_id()];
+    write_string(&mut file, "Schematic");
+    schematic.write(&mut file);
+
+    file
+}
+
+#[cfg(test)]
+mod test_schematic {
+    use super::*;
+
+    #[test]
+    /// Clipboards come back the same from MCEdit schematics, wool going through its colors.
+    pub fn mcedit() {
+        let blocks = vec![
+            block::AIR,
+            block::STONE,
+            21,
+            36,
+            block::RED_FLOWER,
+            block::OBSIDIAN,
+        ];
+        let buffer = BlockBuffer::from_data(Vec3D::new(3, 1, 2), blocks).unwrap();
+
+        let file = write_schematic(&buffer);
+        let (name, root) = NbtReader {
+            data: &file,
+            index: 0,
+        }
+        .read_root()
+        .unwrap();
+
+        assert_eq!(name, "Schematic");
+        assert_eq!(
+            root.get_bytes("Blocks"),
+            Some(&vec![0, 1, LEGACY_WOOL, LEGACY_WOOL, 37, 49])
+        );
+        assert_eq!(root.get_bytes("Data"), Some(&vec![0, 0, 14, 0, 0, 0]));
+        let loaded = read_schematic(&file).unwrap();
+
+        assert_eq!(loaded.get_size(), buffer.get_size());
+        assert_eq!(loaded.get_data(), buffer.get_data());
+    }
+
+    #[test]
+    /// Sponge schematics map their palette to classic blocks.
+    pub fn sponge() {
+        let palette = vec![
+            (String::from("minecraft:air"), Tag::Int(0)),
+            (String::from("minecraft:oak_log[axis=y]"), Tag::Int(1)),
+            (String::from("minecraft:lime_wool"), Tag::Int(200)),
+        ];
+
+        // The third block needs two bytes.
+        let schematic = Tag::Compound(vec![
+            (String::from("Version"), Tag::Int(2)),
+            (String::from("Width"), Tag::Short(3)),
+            (String::from("Height"), Tag::Short(1)),
+            (String::from("Length"), Tag::Short(1)),
+            (String::from("Palette"), Tag::Compound(palette)),
+            (
+                String::from("BlockData"),
+                Tag::ByteArray(vec![1, 0, 0xc8, 0x01]),
+            ),
+        ]);
+
+        let mut file = vec![schematic.get_id()];
+        write_string(&mut file, "Schematic");
+        schematic.write(&mut file);
+
+        let buffer = read_schematic(&file).unwrap();
+
+        assert_eq!(*buffer.get_size(), Vec3D::new(3, 1, 1));
+        assert_eq!(buffer.get_data(), &vec![block::LOG, block::AIR, 24]);
+    }
+
+    #[test]
+    /// Palettes out of range and block data not matching the size are refused.
+    pub fn sponge_invalid() {
+        let read = |index: i32, data: Vec<u8>| {
+            let palette = vec![(String::from("minecraft:stone"), Tag::Int(index))];
+            let schematic = Tag::Compound(vec![
+                (String::from("Width"), Tag::Short(2)),
+                (String::from("Height"), Tag::Short(1)),
+                (String::from("Length"), Tag::Short(1)),
+                (String::from("Palette"), Tag::Compound(palette)),
+                (String::from("BlockData"), Tag::ByteArray(data)),
+            ]);
+
+            let mut file = vec![schematic.get_id()];
+            write_string(&mut file, "Schematic");
+            schematic.write(&mut file);
+
+            read_schematic(&file)
+        };
+
+        assert!(read(0, vec![0, 0]).is_ok());
+        assert!(read(-1, vec![0, 0]).is_err());
+        assert!(read(i32::MAX, vec![0, 0]).is_err());
+        assert!(read(0, vec![0]).is_err());
+        assert!(read(0, vec![0, 0, 0]).is_err());
+        assert!(read(0, vec![0, 0x80]).is_err());
+
+        let mut file = Vec::new();
+        write_string(&mut file, &"é".repeat(40000));
+        assert_eq!(file.len(), 2 + 65534);
+        assert_eq!(&file[..2], &65534u16.to_be_bytes());
+    }
+}
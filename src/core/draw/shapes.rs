@@ -0,0 +1,234 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Shapes drawn into worlds. Every function returns the positions which changed.
+
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+
+use super::super::{Region, Vec3D, World};
+
+// Sides of a region, with or without its top and bottom.
+fn faces(region: &Region, top_and_bottom: bool) -> Vec<Region> {
+    let (min, max) = (*region.get_min(), *region.get_max());
+
+    let mut faces = vec![
+        Region::new(min, Vec3D::new(min.0, max.1, max.2)),
+        Region::new(Vec3D::new(max.0, min.1, min.2), max),
+        Region::new(min, Vec3D::new(max.0, max.1, min.2)),
+        Region::new(Vec3D::new(min.0, min.1, max.2), max),
+    ];
+
+    if top_and_bottom {
+        faces.push(Region::new(min, Vec3D::new(max.0, min.1, max.2)));
+        faces.push(Region::new(Vec3D::new(min.0, max.1, min.2), max));
+    }
+
+    faces
+}
+
+fn fill_all(world: &mut World, regions: &[Region], block: u8) -> Vec<Vec3D> {
+    regions
+        .iter()
+        .flat_map(|region| world.fill(region, block))
+        .collect()
+}
+
+/// Outer shell of a cuboid.
+pub fn hollow(world: &mut World, region: &Region, block: u8) -> Vec<Vec3D> {
+    fill_all(world, &faces(region, true), block)
+}
+
+pub fn hollow_volume(region: &Region) -> usize {
+    let Vec3D(x, y, z) = region.get_size();
+
+    x * y * z - x.saturating_sub(2) * y.saturating_sub(2) * z.saturating_sub(2)
+}
+
+/// Four sides of a cuboid, without its top and bottom.
+pub fn walls(world: &mut World, region: &Region, block: u8) -> Vec<Vec3D> {
+    fill_all(world, &faces(region, false), block)
+}
+
+pub fn walls_volume(region: &Region) -> usize {
+    let Vec3D(x, y, z) = region.get_size();
+
+    y * (x * z - x.saturating_sub(2) * z.saturating_sub(2))
+}
+
+// Rows of a ball as offsets from its center on y and z, and the half length of the row on x.
+fn sphere_rows(radius: u16) -> Vec<(i32, i32, i32)> {
+    let limit = (f64::from(radius) + 0.5).powi(2);
+    let radius = i32::from(radius);
+    let mut rows = Vec::new();
+
+    for dy in -radius..=radius {
+        for dz in -radius..=radius {
+            let remaining = limit - f64::from(dy * dy + dz * dz);
+
+            if remaining >= 0.0 {
+                rows.push((dy, dz, remaining.sqrt() as i32));
+            }
+        }
+    }
+
+    rows
+}
+
+/// Ball around a center, filled row by row.
+pub fn sphere(world: &mut World, center: &Vec3D, radius: u16, block: u8) -> Vec<Vec3D> {
+    let coordinate = |value: i32| u16::try_from(value).ok();
+
+    let rows: Vec<Region> = sphere_rows(radius)
+        .into_iter()
+        .filter_map(|(dy, dz, dx)| {
+            let y = coordinate(i32::from(center.1) + dy)?;
+            let z = coordinate(i32::from(center.2) + dz)?;
+            let to = coordinate(i32::from(center.0) + dx)?;
+            let from = coordinate((i32::from(center.0) - dx).max(0))?;
+
+            Some(Region::new(Vec3D::new(from, y, z), Vec3D::new(to, y, z)))
+        })
+        .collect();
+
+    fill_all(world, &rows, block)
+}
+
+/// Volume of a perfect ball, never above sphere_volume and fast to compute for any radius.
+pub fn sphere_estimate(radius: u16) -> usize {
+    (4.0 / 3.0 * std::f64::consts::PI * f64::from(radius).powi(3)) as usize
+}
+
+/// Largest radius changing anything in a world, bigger balls would fill the same blocks.
+pub fn max_sphere_radius(size: &Vec3D) -> u16 {
+    size.0.saturating_add(size.1).saturating_add(size.2)
+}
+
+pub fn sphere_volume(radius: u16) -> usize {
+    sphere_rows(radius)
+        .iter()
+        .map(|(_, _, dx)| *dx as usize * 2 + 1)
+        .sum()
+}
+
+/// Straight line between two positions, both included.
+pub fn line(world: &mut World, from: &Vec3D, to: &Vec3D, block: u8) -> Vec<Vec3D> {
+    let size = *world.get_size();
+    let mut changed = Vec::new();
+
+    for position in line_positions(from, to) {
+        if position.0 < size.0
+            && position.1 < size.1
+            && position.2 < size.2
+            && world.get_block(&position) != block
+        {
+            world.set_block(&position, block, false);
+            changed.push(position);
+        }
+    }
+
+    changed
+}
+
+pub fn line_positions(from: &Vec3D, to: &Vec3D) -> Vec<Vec3D> {
+    let delta = [
+        f64::from(to.0) - f64::from(from.0),
+        f64::from(to.1) - f64::from(from.1),
+        f64::from(to.2) - f64::from(from.2),
+    ];
+    let steps = delta.iter().fold(0.0_f64, |max, d| max.max(d.abs())) as usize;
+
+    (0..=steps)
+        .map(|step| {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                step as f64 / steps as f64
+            };
+
+            Vec3D::new(
+                (f64::from(from.0) + delta[0] * t).round() as u16,
+                (f64::from(from.1) + delta[1] * t).round() as u16,
+                (f64::from(from.2) + delta[2] * t).round() as u16,
+            )
+        })
+        .collect()
+}
+
+/// Replaces the blocks connected to the start which are the same as it.
+/// Nothing is changed and None is returned if more than the limit would change.
+pub fn flood_fill(world: &mut World, start: &Vec3D, block: u8, limit: usize) -> Option<Vec<Vec3D>> {
+    let size = *world.get_size();
+    let target = world.get_block(start);
+
+    if target == block {
+        return Some(Vec::new());
+    }
+
+    let mut found = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    found.insert(*start);
+    queue.push_back(*start);
+
+    while let Some(position) = queue.pop_front() {
+        let Vec3D(x, y, z) = position;
+
+        let neighbours = [
+            (x.checked_sub(1), Some(y), Some(z)),
+            (x.checked_add(1), Some(y), Some(z)),
+            (Some(x), y.checked_sub(1), Some(z)),
+            (Some(x), y.checked_add(1), Some(z)),
+            (Some(x), Some(y), z.checked_sub(1)),
+            (Some(x), Some(y), z.checked_add(1)),
+        ];
+
+        for neighbour in neighbours.iter() {
+            if let (Some(x), Some(y), Some(z)) = *neighbour {
+                let neighbour = Vec3D::new(x, y, z);
+
+                if x < size.0
+                    && y < size.1
+                    && z < size.2
+                    && !found.contains(&neighbour)
+                    && world.get_block(&neighbour) == target
+                {
+                    if found.len() >= limit {
+                        return None;
+                    }
+
+                    found.insert(neighbour);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    let mut changed: Vec<Vec3D> = found.into_iter().collect();
+    changed.sort_by_key(|position| (position.1, position.2, position.0));
+
+    for position in &changed {
+        world.set_block(position, block, false);
+    }
+
+    Some(changed)
+}
@@ -19,9 +19,400 @@
     OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
     SOFTWARE.
 */
-use super::{Core, Player, SyncPlayer};
-use chashmap::WriteGuard;
 
-pub mod player;
-pub mod server;
-pub mod world;
+//! Events the core calls plugins for, and the builtin plugin handling them.
+
+mod player;
+mod server;
+mod world;
+
+use super::{
+    BlockChange, Command, Core, Event, EventBus, PlayerSnapshot, Plugin, Priority, Rank, Transform,
+    Vec3D,
+};
+
+macro_rules! cancellable {
+    ($event:ty) => {
+        impl Event for $event {
+            fn is_cancelled(&self) -> bool {
+                self.cancelled
+            }
+
+            fn set_cancelled(&mut self, cancelled: bool) {
+                self.cancelled = cancelled;
+            }
+        }
+    };
+}
+
+/// Player has identified. Cancelling disconnects them.
+pub struct PlayerJoinEvent {
+    pub player: PlayerSnapshot,
+    cancelled: bool,
+}
+
+impl PlayerJoinEvent {
+    pub fn new(player: PlayerSnapshot) -> PlayerJoinEvent {
+        PlayerJoinEvent {
+            player,
+            cancelled: false,
+        }
+    }
+}
+
+cancellable!(PlayerJoinEvent);
+
+/// Player has left the server, its connection is already closed.
+pub struct PlayerLeaveEvent {
+    pub player: PlayerSnapshot,
+}
+
+impl PlayerLeaveEvent {
+    pub fn new(player: PlayerSnapshot) -> PlayerLeaveEvent {
+        PlayerLeaveEvent { player }
+    }
+}
+
+impl Event for PlayerLeaveEvent {}
+
+/// Player has sent a chat message. Cancelling keeps it from being broadcast, commands cancel it.
+pub struct PlayerChatEvent {
+    pub player: PlayerSnapshot,
+    pub message: String,
+    cancelled: bool,
+}
+
+impl PlayerChatEvent {
+    pub fn new(player: PlayerSnapshot, message: String) -> PlayerChatEvent {
+        PlayerChatEvent {
+            player,
+            message,
+            cancelled: false,
+        }
+    }
+}
+
+cancellable!(PlayerChatEvent);
+
+/// Player has moved. Cancelling sends them back to where they were.
+pub struct PlayerMoveEvent {
+    pub player: PlayerSnapshot,
+    pub from: Transform,
+    pub to: Transform,
+    cancelled: bool,
+}
+
+impl PlayerMoveEvent {
+    pub fn new(player: PlayerSnapshot, from: Transform, to: Transform) -> PlayerMoveEvent {
+        PlayerMoveEvent {
+            player,
+            from,
+            to,
+            cancelled: false,
+        }
+    }
+}
+
+cancellable!(PlayerMoveEvent);
+
+/// Player tries to place or destroy a block. Cancelling leaves the world as it is.
+pub struct SetBlockEvent {
+    pub player: PlayerSnapshot,
+    pub world: String,
+    pub position: Vec3D,
+    pub block: u8,
+    pub destroy: bool,
+    cancelled: bool,
+}
+
+impl SetBlockEvent {
+    pub fn new(
+        player: PlayerSnapshot,
+        world: String,
+        position: Vec3D,
+        block: u8,
+        destroy: bool,
+    ) -> SetBlockEvent {
+        SetBlockEvent {
+            player,
+            world,
+            position,
+            block,
+            destroy,
+            cancelled: false,
+        }
+    }
+}
+
+cancellable!(SetBlockEvent);
+
+/// Player has changed blocks, by building or with a command.
+pub struct BlocksChangedEvent {
+    pub player: String,
+    pub world: String,
+    pub changes: Vec<BlockChange>,
+}
+
+impl BlocksChangedEvent {
+    pub fn new(player: String, world: String, changes: Vec<BlockChange>) -> BlocksChangedEvent {
+        BlocksChangedEvent {
+            player,
+            world,
+            changes,
+        }
+    }
+}
+
+impl Event for BlocksChangedEvent {}
+
+/// Player is about to join a loaded world. Cancelling keeps them where they are.
+pub struct WorldJoinEvent {
+    pub player: PlayerSnapshot,
+    pub world: String,
+    /// Where the player appears, the spawn of the world if None.
+    pub position: Option<Transform>,
+    cancelled: bool,
+}
+
+impl WorldJoinEvent {
+    pub fn new(player: PlayerSnapshot, world: String) -> WorldJoinEvent {
+        WorldJoinEvent {
+            player,
+            world,
+            position: None,
+            cancelled: false,
+        }
+    }
+}
+
+cancellable!(WorldJoinEvent);
+
+/// Player has received the map of a world and is inside it.
+pub struct WorldJoinedEvent {
+    pub player: PlayerSnapshot,
+    pub world: String,
+}
+
+impl WorldJoinedEvent {
+    pub fn new(player: PlayerSnapshot, world: String) -> WorldJoinedEvent {
+        WorldJoinedEvent { player, world }
+    }
+}
+
+impl Event for WorldJoinedEvent {}
+
+/// World was loaded from the maps folder.
+pub struct WorldLoadEvent {
+    pub world: String,
+}
+
+impl WorldLoadEvent {
+    pub fn new(world: String) -> WorldLoadEvent {
+        WorldLoadEvent { world }
+    }
+}
+
+impl Event for WorldLoadEvent {}
+
+/// World is about to be unloaded. Cancelling keeps it loaded.
+pub struct WorldUnloadEvent {
+    pub world: String,
+    cancelled: bool,
+}
+
+impl WorldUnloadEvent {
+    pub fn new(world: String) -> WorldUnloadEvent {
+        WorldUnloadEvent {
+            world,
+            cancelled: false,
+        }
+    }
+}
+
+cancellable!(WorldUnloadEvent);
+
+/// Map was deleted from the maps folder, its world is not loaded.
+pub struct MapDeleteEvent {
+    pub map: String,
+}
+
+impl MapDeleteEvent {
+    pub fn new(map: String) -> MapDeleteEvent {
+        MapDeleteEvent { map }
+    }
+}
+
+impl Event for MapDeleteEvent {}
+
+/// Map was renamed in the maps folder, its world is not loaded.
+pub struct MapRenameEvent {
+    pub from: String,
+    pub to: String,
+}
+
+impl MapRenameEvent {
+    pub fn new(from: String, to: String) -> MapRenameEvent {
+        MapRenameEvent { from, to }
+    }
+}
+
+impl Event for MapRenameEvent {}
+
+/// Behaviour shipped with the server: messages, commands and the read-only mode.
+pub struct BuiltinPlugin;
+
+impl Plugin for BuiltinPlugin {
+    fn get_name(&self) -> &str {
+        "Builtin"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        events.register(Priority::Lowest, player::join_welcome);
+        events.register(Priority::Lowest, player::leave_goodbye);
+        events.register(Priority::Lowest, player::leave_forget_history);
+
+        events.register(Priority::Normal, server::dispatch_command);
+
+        events.register(Priority::High, world::readonly_build);
+        events.register(Priority::High, world::zone_build);
+        events.register(Priority::Lowest, world::notify_join_world);
+        events.register(Priority::Lowest, world::outline_zones);
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let name = self.get_name();
+
+        core.register_command(Command::new(
+            name,
+            "help",
+            "List of commands.",
+            Rank::Guest,
+            server::help_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "main",
+            "Go to main.",
+            Rank::Guest,
+            server::main_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "join",
+            "Joins or loads the specified map. (/j)",
+            Rank::Guest,
+            server::join_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "tp",
+            "Go to {player} if any.",
+            Rank::Guest,
+            server::tp_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "worlds",
+            "Lists loaded worlds with their players, and unloaded maps.",
+            Rank::Guest,
+            server::worlds_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "players",
+            "List of online players.",
+            Rank::Guest,
+            server::players_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "plugins",
+            "Lists, loads or unloads plugins.",
+            Rank::Guest,
+            server::plugins_command,
+        ));
+
+        core.register_command(Command::new(
+            name,
+            "newlvl",
+            "Generates a new map.",
+            Rank::Operator,
+            server::newlvl_command,
+        ));
+
+        core.register_command(Command::new(
+            name,
+            "save",
+            "Saves a map, your world if none is given. [map]",
+            Rank::Operator,
+            server::save_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "unload",
+            "Saves and unloads a map nobody is in. {map}",
+            Rank::Operator,
+            server::unload_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "reload",
+            "Reads a map from disk again, dropping unsaved changes. [map]",
+            Rank::Operator,
+            server::reload_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "copylvl",
+            "Copies a map into a new one. {map} {copy name}",
+            Rank::Operator,
+            server::copylvl_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "renamelvl",
+            "Renames a map. {map} {new name}",
+            Rank::Operator,
+            server::renamelvl_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "resizelvl",
+            "Changes the size of a map. {map} {x} {y} {z}",
+            Rank::Operator,
+            server::resizelvl_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "deletelvl",
+            "Deletes a map for good. {map}",
+            Rank::Owner,
+            server::deletelvl_command,
+        ));
+
+        core.register_command(Command::new(
+            name,
+            "physics",
+            "Shows or sets the physics level of your world. [0-2]",
+            Rank::Operator,
+            server::physics_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "undo",
+            "Reverts your last edits. [edits|time] or {player} {time}",
+            Rank::Guest,
+            server::undo_command,
+        ));
+        core.register_command(Command::new(
+            name,
+            "redo",
+            "Applies undone edits again. [edits]",
+            Rank::Guest,
+            server::redo_command,
+        ));
+
+        core.register_command_alias("j", "join");
+        core.register_command_alias("teleport", "tp");
+    }
+}
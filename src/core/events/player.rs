@@ -19,37 +19,24 @@
     OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
     SOFTWARE.
 */
-use super::super::super::network::*;
-use super::super::{Core, Player};
-use chashmap::WriteGuard;
 
-fn join_welcome(core: &Core, player: &mut (dyn Player), surpress: &mut bool) {
-    let nick = String::from(player.get_display_name());
+use super::super::Core;
+use super::{PlayerJoinEvent, PlayerLeaveEvent};
 
-    core.broadcast_message(player, &format!("{} &6has joined the server!", nick));
+pub fn join_welcome(core: &Core, event: &mut PlayerJoinEvent) {
+    core.broadcast(&format!(
+        "{} &6has joined the server!",
+        event.player.display_name
+    ));
 }
 
-fn leave_goodbye(core: &Core, player: &mut (dyn Player), surpress: &mut bool) {
-    let nick = String::from(player.get_display_name());
-
-    core.broadcast_message(player, &format!("{} &6has left the server.", nick));
+pub fn leave_goodbye(core: &Core, event: &mut PlayerLeaveEvent) {
+    core.broadcast(&format!(
+        "{} &6has left the server.",
+        event.player.display_name
+    ));
 }
 
-// Called after player joined the server.
-pub fn on_joined(core: &Core, player: &mut (dyn Player)) -> bool {
-    let mut surpress = false;
-
-    join_welcome(core, player, &mut surpress);
-
-    surpress
-}
-
-// Called after player left the server.
-// Player is valid, but network stream is not.
-pub fn on_left(core: &Core, player: &mut (dyn Player)) -> bool {
-    let mut surpress = false;
-
-    leave_goodbye(core, player, &mut surpress);
-
-    surpress
+pub fn leave_forget_history(core: &Core, _event: &mut PlayerLeaveEvent) {
+    core.forget_inactive_histories();
 }
@@ -19,169 +19,420 @@
     OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
     SOFTWARE.
 */
-use super::super::super::network::*;
-use super::super::{Core, Player, SyncPlayer};
-use chashmap::WriteGuard;
 
-fn help_command(core: &Core, player: &mut (dyn Player), message: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
-        return;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::super::generators::{parse_seed, GENERATOR_NAMES};
+use super::super::{
+    parse_duration, Action, Core, Event, PlayerSnapshot, Rank, UndoAmount, Vec3D, PHYSICS_FULL,
+};
+use super::PlayerChatEvent;
+
+/// Runs chat messages starting with a slash as commands.
+pub fn dispatch_command(core: &Core, event: &mut PlayerChatEvent) {
+    if core.try_run_command(&event.player, &event.message) {
+        event.set_cancelled(true);
     }
+}
 
-    if message.eq_ignore_ascii_case("/help") {
-        player.send_message("&cMC Classic Written in Rust by Ali Deym.");
-        player.send_message("&7/main - Go to main.");
-        player.send_message("&7/join {map} - Joins or loads the specified map. (/j)");
-        player.send_message("&7/tp {player} - Go to {player} if any.");
-        player.send_message("&7/players - List of online players.");
+pub fn help_command(core: &Core, player: &PlayerSnapshot, _args: &str) {
+    core.send_message_to(player.uid, "&cMC Classic Written in Rust by Ali Deym.");
 
-        *surpress = true;
+    for command in core.get_commands() {
+        if command.get_rank() <= player.rank {
+            core.send_message_to(
+                player.uid,
+                &format!("&7/{} - {}", command.get_name(), command.get_description()),
+            );
+        }
     }
 }
 
-fn main_command(core: &Core, player: &mut (dyn Player), message: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
-        return;
+pub fn main_command(core: &Core, player: &PlayerSnapshot, _args: &str) {
+    core.queue_action(Action::JoinWorld(player.uid, String::from("main")));
+}
+
+pub fn join_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    match args.split_ascii_whitespace().next() {
+        Some(world_name) => {
+            core.queue_action(Action::JoinWorld(player.uid, world_name.to_lowercase()))
+        }
+        None => core.send_message_to(player.uid, "&7Usage: /join {map}"),
     }
+}
+
+pub fn tp_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let query = args.to_lowercase();
 
-    if message.eq_ignore_ascii_case("/main") {
-        player.try_join_world(core, "main");
+    let found = core
+        .get_player_snapshots()
+        .into_iter()
+        .find(|other| other.uid != player.uid && other.name.to_lowercase().contains(&query));
 
-        *surpress = true;
+    if let Some(other) = found {
+        // In a different world, try to join.
+        if other.world != player.world {
+            core.queue_action(Action::JoinWorld(player.uid, other.world));
+        }
+
+        core.queue_action(Action::Teleport(player.uid, other.transform));
+    } else {
+        core.send_message_to(
+            player.uid,
+            &format!("&7Couldn't find a player with name \"{}\".", query),
+        );
     }
 }
 
-fn join_command(core: &Core, player: &mut (dyn Player), message: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
-        return;
+pub fn worlds_command(core: &Core, player: &PlayerSnapshot, _args: &str) {
+    let loaded = core.get_world_names();
+
+    let worlds: Vec<String> = loaded
+        .iter()
+        .map(|name| format!("{} ({})", name, core.get_world_players(name).len()))
+        .collect();
+
+    let unloaded: Vec<String> = core
+        .get_map_names()
+        .into_iter()
+        .filter(|name| !loaded.contains(name))
+        .collect();
+
+    core.send_message_to(
+        player.uid,
+        &format!("&6Loaded worlds (players): &7{}", worlds.join(", ")),
+    );
+
+    if !unloaded.is_empty() {
+        core.send_message_to(
+            player.uid,
+            &format!("&6Unloaded maps: &8{}", unloaded.join(", ")),
+        );
+    }
+}
+
+pub fn players_command(core: &Core, player: &PlayerSnapshot, _args: &str) {
+    core.send_message_to(player.uid, "&6Players Online:");
+    core.send_message_to(player.uid, &player.display_name);
+
+    for other in core.get_player_snapshots() {
+        if other.uid != player.uid {
+            core.send_message_to(player.uid, &other.display_name);
+        }
     }
+}
 
-    if message.to_lowercase().starts_with("/j") || message.to_lowercase().starts_with("/join") {
-        let message_lowercase = message.to_lowercase();
-        let world_name = message_lowercase.split_ascii_whitespace().last();
+pub fn plugins_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let mut args = args.split_ascii_whitespace();
 
-        if world_name.is_none() {
-            help_command(core, player, "/help", surpress);
+    match (args.next(), args.next()) {
+        (None, _) => {
+            core.send_message_to(player.uid, "&6Plugins:");
 
-            return;
+            for name in core.get_plugin_names() {
+                core.send_message_to(player.uid, &format!("&7{}", name));
+            }
+
+            for (name, version) in core.get_native_plugins() {
+                core.send_message_to(player.uid, &format!("&7{} {} &8(native)", name, version));
+            }
+        }
+        (Some(action), Some(_)) if player.rank < Rank::Operator => {
+            core.send_message_to(
+                player.uid,
+                &format!("&8You need to be operator to {} plugins.", action),
+            );
+        }
+        (Some("load"), Some(file)) => match core.load_native_plugin(file) {
+            Ok(name) => core.send_message_to(player.uid, &format!("&7Loaded plugin \"{}\".", name)),
+            Err(e) => core.send_message_to(player.uid, &format!("&8Unable to load plugin: {}", e)),
+        },
+        (Some("unload"), Some(name)) => {
+            if core.unload_native_plugin(name) {
+                core.send_message_to(player.uid, &format!("&7Unloaded plugin \"{}\".", name));
+            } else {
+                core.send_message_to(
+                    player.uid,
+                    &format!("&8No native plugin named \"{}\" is loaded.", name),
+                );
+            }
         }
+        _ => core.send_message_to(player.uid, "&7Usage: /plugins [load {file}|unload {name}]"),
+    }
+}
+
+pub fn newlvl_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let args: Vec<&str> = args.split_ascii_whitespace().collect();
 
-        player.try_join_world(core, world_name.unwrap());
+    let size = |index: usize| args.get(index).and_then(|value| value.parse::<u16>().ok());
+
+    let (name, size, generator) = match (args.first(), size(1), size(2), size(3), args.get(4)) {
+        (Some(name), Some(x), Some(y), Some(z), Some(generator)) if args.len() <= 6 => {
+            (name.to_lowercase(), Vec3D::new(x, y, z), *generator)
+        }
+        _ => {
+            core.send_message_to(
+                player.uid,
+                "&7Usage: /newlvl {name} {x} {y} {z} {generator} [seed]",
+            );
+            core.send_message_to(
+                player.uid,
+                &format!("&7Generators: {}", GENERATOR_NAMES.join(", ")),
+            );
 
-        *surpress = true;
+            return;
+        }
+    };
+
+    // Without a seed, the time makes every map different.
+    let seed = match args.get(5) {
+        Some(seed) => parse_seed(seed),
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default(),
+    };
+
+    match core.create_map(&name, size, generator, seed) {
+        Ok(()) => {
+            core.send_message_to(
+                player.uid,
+                &format!("&7Map \"{}\" has been generated.", name),
+            );
+            core.send_message_to(player.uid, &format!("&7Seed: {}", seed));
+            core.send_message_to(player.uid, &format!("&7Use /join {} to visit it.", name));
+        }
+        Err(e) => core.send_message_to(player.uid, &format!("&8{}", e)),
     }
 }
 
-fn tp_command(core: &Core, player: &mut (dyn Player), message: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
-        return;
+// Tells a player how a map command went.
+fn report(core: &Core, player: &PlayerSnapshot, result: Result<(), String>, done: String) {
+    match result {
+        Ok(()) => core.send_message_to(player.uid, &format!("&7{}", done)),
+        Err(e) => core.send_message_to(player.uid, &format!("&8{}", e)),
     }
+}
 
-    if message.to_lowercase().starts_with("/tp") || message.to_lowercase().starts_with("/teleport")
-    {
-        *surpress = true;
+pub fn unload_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    match args.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+        [name] => {
+            let name = name.to_lowercase();
+
+            report(
+                core,
+                player,
+                core.try_unload_world(&name, true),
+                format!("Map \"{}\" has been saved and unloaded.", name),
+            );
+        }
+        _ => core.send_message_to(player.uid, "&7Usage: /unload {map}"),
+    }
+}
 
-        let message_lowercase = message.to_lowercase();
-        let query: String = message_lowercase.split_ascii_whitespace().skip(1).collect();
+pub fn save_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let name = match args.trim() {
+        "" => player.world.clone(),
+        name => name.to_lowercase(),
+    };
 
-        let mut found_position = None;
-        let mut other_world = String::from(player.get_world());
-        // TODO: Find a better way to iterate.
-        for i in 0..128 {
-            if i != player.get_uid() {
-                if let Some(other) = core.get_player_by_uid(i) {
-                    if other.get_name().to_lowercase().contains(&query) {
-                        // Found a match.
-                        // Check the worlds.
-                        if other.get_world() != player.get_world() {
-                            other_world = String::from(other.get_world());
-                        }
+    let message = if core.save_world(&name) {
+        format!("&7Map \"{}\" has been saved.", name)
+    } else {
+        format!("&8Unable to save \"{}\", is it loaded?", name)
+    };
 
-                        found_position = Some(other.get_transform().clone());
+    core.send_message_to(player.uid, &message);
+}
 
-                        break;
-                    }
-                }
-            }
-        }
+pub fn reload_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let name = match args.trim() {
+        "" => player.world.clone(),
+        name => name.to_lowercase(),
+    };
+
+    report(
+        core,
+        player,
+        core.reload_world(&name),
+        format!("Map \"{}\" has been read from disk again.", name),
+    );
+}
 
-        // This action is done outside the loop, to prevent deadlocks.
-        if let Some(pos) = found_position {
-            // In a different world, try to join.
-            if other_world != player.get_world() {
-                player.try_join_world(core, &other_world);
-            }
+pub fn deletelvl_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    match args.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+        [name] => {
+            let name = name.to_lowercase();
+
+            report(
+                core,
+                player,
+                core.delete_map(&name),
+                format!("Map \"{}\" has been deleted.", name),
+            );
+        }
+        _ => core.send_message_to(player.uid, "&7Usage: /deletelvl {map}"),
+    }
+}
 
-            player.update_transform(pos);
-        } else {
-            player.send_message(&format!(
-                "&7Couldn't find a player with name \"{}\".",
-                query
-            ));
+pub fn renamelvl_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    match args.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+        [from, to] => {
+            let (from, to) = (from.to_lowercase(), to.to_lowercase());
+
+            report(
+                core,
+                player,
+                core.rename_map(&from, &to),
+                format!("Map \"{}\" has been renamed to \"{}\".", from, to),
+            );
         }
+        _ => core.send_message_to(player.uid, "&7Usage: /renamelvl {map} {new name}"),
     }
 }
 
-fn worlds_command(core: &Core, player: &mut (dyn Player), message: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
-        return;
+pub fn copylvl_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    match args.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+        [from, to] => {
+            let (from, to) = (from.to_lowercase(), to.to_lowercase());
+
+            report(
+                core,
+                player,
+                core.copy_map(&from, &to),
+                format!("Map \"{}\" has been copied to \"{}\".", from, to),
+            );
+        }
+        _ => core.send_message_to(player.uid, "&7Usage: /copylvl {map} {copy name}"),
     }
+}
 
-    if message.to_lowercase().starts_with("/worlds") {
-        player.send_message("&6Due to API Limitations, we can only display");
-        player.send_message(&format!(
-            "&6the number of worlds loaded: &8{}",
-            core.get_world_count()
-        ));
+pub fn resizelvl_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let args: Vec<&str> = args.split_ascii_whitespace().collect();
+    let size = |index: usize| args.get(index).and_then(|value| value.parse::<u16>().ok());
 
-        *surpress = true;
+    match (args.len(), size(1), size(2), size(3)) {
+        (4, Some(x), Some(y), Some(z)) => {
+            let name = args[0].to_lowercase();
+
+            report(
+                core,
+                player,
+                core.resize_map(&name, Vec3D::new(x, y, z)),
+                format!("Map \"{}\" has been resized to {}x{}x{}.", name, x, y, z),
+            );
+        }
+        _ => core.send_message_to(player.uid, "&7Usage: /resizelvl {map} {x} {y} {z}"),
     }
 }
 
-fn players_command(core: &Core, player: &mut (dyn Player), message: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
+pub fn undo_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    if core.get_config().read_only {
+        core.send_message_to(player.uid, "&8Server is on read-only state.");
+
         return;
     }
 
-    if message.to_lowercase().starts_with("/players") {
-        player.send_message("&6Players Online:");
+    let args: Vec<&str> = args.split_ascii_whitespace().collect();
+
+    match args.as_slice() {
+        [] | [_] => {
+            let amount = match args.first() {
+                Some(amount) => UndoAmount::parse(amount),
+                None => Some(UndoAmount::Edits(1)),
+            };
 
-        let user_self = String::from(player.get_display_name()); // Cannot borrow at the same time and call.
-        player.send_message(&user_self);
+            match amount {
+                Some(amount) => {
+                    let count = core.undo(&player.name, amount, true);
 
-        // TODO: find better way to iterate.
-        for i in 0..128 {
-            if i != player.get_uid() {
-                if let Some(other) = core.get_player_by_uid(i) {
-                    player.send_message(other.get_display_name());
+                    core.send_message_to(player.uid, &format!("&7{} blocks undone.", count));
                 }
+                None => core.send_message_to(player.uid, "&7Usage: /undo [edits|seconds(s/m/h)]"),
             }
         }
+        [_, _] if player.rank < Rank::Operator => {
+            core.send_message_to(
+                player.uid,
+                "&8You need to be operator to undo other players.",
+            );
+        }
+        [name, time] => match parse_duration(time) {
+            Some(duration) => {
+                let count = core.undo(name, UndoAmount::Since(duration), false);
+
+                core.log(&format!(
+                    "{} undid {} blocks of {}.",
+                    player.name, count, name
+                ));
+                core.send_message_to(
+                    player.uid,
+                    &format!("&7{} blocks of {} undone.", count, name),
+                );
+            }
+            None => core.send_message_to(player.uid, "&7Usage: /undo {player} {seconds(s/m/h)}"),
+        },
+        _ => core.send_message_to(player.uid, "&7Usage: /undo [edits|seconds(s/m/h)]"),
+    }
+}
+
+pub fn redo_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    if core.get_config().read_only {
+        core.send_message_to(player.uid, "&8Server is on read-only state.");
 
-        *surpress = true;
+        return;
+    }
+
+    let count = match args.trim() {
+        "" => Some(1),
+        count => count.parse().ok(),
+    };
+
+    match count {
+        Some(count) => {
+            let count = core.redo(&player.name, count);
+
+            core.send_message_to(player.uid, &format!("&7{} blocks redone.", count));
+        }
+        None => core.send_message_to(player.uid, "&7Usage: /redo [edits]"),
     }
 }
 
-// Calls the chat hook. Register your own event systems down below.
-pub fn on_message(
-    core: &Core,
-    player: &mut WriteGuard<usize, Box<dyn Player + Send + Sync>>,
-    message: String,
-) -> bool {
-    let mut surpress = false;
+pub fn physics_command(core: &Core, player: &PlayerSnapshot, args: &str) {
+    let level = match args.trim() {
+        "" => None,
+        level => match level.parse::<u8>() {
+            Ok(level) if level <= PHYSICS_FULL => Some(level),
+            _ => {
+                core.send_message_to(
+                    player.uid,
+                    &format!("&7Usage: /physics [0-{}]", PHYSICS_FULL),
+                );
+
+                return;
+            }
+        },
+    };
+
+    let physics = core.with_world_mut(&player.world, |world| {
+        let physics = world.get_physics_mut();
 
-    help_command(core, player.as_mut(), &message, &mut surpress);
-    main_command(core, player.as_mut(), &message, &mut surpress);
-    join_command(core, player.as_mut(), &message, &mut surpress);
-    tp_command(core, player.as_mut(), &message, &mut surpress);
-    worlds_command(core, player.as_mut(), &message, &mut surpress);
-    players_command(core, player.as_mut(), &message, &mut surpress);
+        if let Some(level) = level {
+            physics.set_level(level);
+        }
 
-    surpress
+        (physics.get_level(), physics.get_queued())
+    });
+
+    match (physics, level) {
+        (Some((level, _)), Some(_)) => core.broadcast(&format!(
+            "&8Physics of \"{}\" set to {} by {}.",
+            player.world, level, player.display_name
+        )),
+        (Some((level, queued)), None) => core.send_message_to(
+            player.uid,
+            &format!("&7Physics level {}, {} blocks queued.", level, queued),
+        ),
+        (None, _) => core.send_message_to(player.uid, "&8You are not in a world."),
+    }
 }
@@ -19,104 +19,53 @@
     OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
     SOFTWARE.
 */
-use super::super::super::network::*;
-use super::super::{Core, Player, Vec3D, World};
-use chashmap::WriteGuard;
 
-// TODO: Add world load event.
-// TODO: Add world unload event.
+use super::super::{show_zones, Core, Event};
+use super::{SetBlockEvent, WorldJoinedEvent};
 
-fn notify_join_world(
-    core: &Core,
-    player: &mut (dyn Player),
-    world: &mut World,
-    surpress: &mut bool,
-) {
-    // Event already handled.
-    if *surpress {
-        return;
-    }
-
-    let nick_name = String::from(player.get_display_name());
-
-    core.broadcast_message(
-        player,
-        &format!("{} &8joined the world \"{}\".", nick_name, world.get_name()),
-    );
-}
-
-fn notify_not_found(core: &Core, player: &mut (dyn Player), world_name: &str, surpress: &mut bool) {
-    // Event already handled.
-    if *surpress {
-        return;
-    }
-
-    player.send_message(&format!("&8The world \"{}\" does not exist.", world_name));
+pub fn notify_join_world(core: &Core, event: &mut WorldJoinedEvent) {
+    core.broadcast(&format!(
+        "{} &8joined the world \"{}\".",
+        event.player.display_name, event.world
+    ));
 }
 
-fn readonly_build(
-    core: &Core,
-    player: &mut (dyn Player),
-    world: &mut World,
-    position: Vec3D,
-    block: u8,
-    destroy: bool,
-    surpress: &mut bool,
-) {
-    // Event already handled.
-    if *surpress {
+pub fn readonly_build(core: &Core, event: &mut SetBlockEvent) {
+    if !core.is_read_only(&event.world) {
         return;
     }
 
-    player.send_message("&8Server is on read-only state.");
-    *surpress = true;
-}
-
-/// Called when user tries to set block. surpress to prevent saving on underlying struct.
-pub fn on_setblock(
-    core: &Core,
-    player: &mut (dyn Player),
-    world: &mut World,
-    position: Vec3D,
-    block: u8,
-    destroy: bool,
-) -> bool {
-    let mut surpress = false;
-
-    readonly_build(core, player, world, position, block, destroy, &mut surpress);
-
-    surpress
-}
-
-/// Called when user tries to join a world that does not exist.
-pub fn on_notfound(core: &Core, player: &mut (dyn Player), world: &str) {
-    let mut surpress = false;
+    let message = if core.get_config().read_only {
+        "&8Server is on read-only state."
+    } else {
+        "&8This world is read-only."
+    };
 
-    notify_not_found(core, player, world, &mut surpress);
+    core.send_message_to(event.player.uid, message);
+    event.set_cancelled(true);
 }
 
-/// Called before player joins a world. Surpressing the event here prevents joining.
-pub fn on_join(
-    core: &Core,
-    mut player: &mut (dyn Player),
-    world: &mut WriteGuard<String, World>,
-) -> bool {
-    let mut surpress = false;
-
-    // TODO: More methods to prevent/check joining.
-
-    surpress
+pub fn zone_build(core: &Core, event: &mut SetBlockEvent) {
+    let player = &event.player;
+
+    let blocking = core
+        .with_world(&event.world, |world| {
+            world
+                .get_blocking_zone(&player.name, player.rank, &event.position)
+                .map(|zone| zone.name.clone())
+        })
+        .flatten();
+
+    if let Some(zone) = blocking {
+        core.send_message_to(
+            player.uid,
+            &format!("&8You cannot build in the zone \"{}\".", zone),
+        );
+        event.set_cancelled(true);
+    }
 }
 
-/// Called after player has joined a world.
-pub fn on_joined(
-    core: &Core,
-    player: &mut (dyn Player),
-    mut world: WriteGuard<String, World>,
-) -> bool {
-    let mut surpress = false;
-
-    notify_join_world(core, player, &mut world, &mut surpress);
-
-    surpress
+pub fn outline_zones(core: &Core, event: &mut WorldJoinedEvent) {
+    // Outlines of the previous world are removed along with its map.
+    show_zones(core, event.player.uid, &event.world, 0);
 }
@@ -0,0 +1,51 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use super::super::{block, Map, Region, Vec3D};
+use super::WorldGenerator;
+
+/// Grass on top of dirt and stone, up to half the map's height.
+pub struct FlatGenerator;
+
+impl WorldGenerator for FlatGenerator {
+    fn get_name(&self) -> &str {
+        "flat"
+    }
+
+    fn generate(&self, map: &mut dyn Map, _seed: u64) {
+        let Vec3D(width, height, length) = *map.get_size();
+        let surface = (height / 2).saturating_sub(1);
+
+        for y in 0..=surface.min(height.saturating_sub(1)) {
+            let block = match y {
+                0 => block::BEDROCK,
+                y if y == surface => block::GRASS,
+                y if y + 3 >= surface => block::DIRT,
+                _ => block::STONE,
+            };
+
+            let layer = Region::new(Vec3D::new(0, y, 0), Vec3D::new(width - 1, y, length - 1));
+
+            map.fill(&layer, block);
+        }
+    }
+}
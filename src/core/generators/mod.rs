@@ -0,0 +1,163 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Generators filling new maps.
+
+mod flat;
+mod noise;
+mod terrain;
+
+pub use self::flat::*;
+pub use self::noise::*;
+pub use self::terrain::*;
+
+use super::maps::MemoryMap;
+use super::{block, Map, Transform, Vec3D};
+
+/// Fills an empty map with blocks. The same seed and size always give the same map.
+pub trait WorldGenerator {
+    fn get_name(&self) -> &str;
+
+    fn generate(&self, map: &mut dyn Map, seed: u64);
+}
+
+/// Names of the generators `find_generator` knows.
+pub const GENERATOR_NAMES: &[&str] = &["flat", "terrain", "islands"];
+
+pub fn find_generator(name: &str) -> Option<Box<dyn WorldGenerator>> {
+    match name.to_lowercase().as_str() {
+        "flat" => Some(Box::new(FlatGenerator)),
+        "terrain" | "normal" | "hills" => Some(Box::new(TerrainGenerator::new(false))),
+        "islands" | "island" => Some(Box::new(TerrainGenerator::new(true))),
+        _ => None,
+    }
+}
+
+/// Turns a seed given by a player into a number, words are hashed so they can be used as seeds too.
+pub fn parse_seed(seed: &str) -> u64 {
+    seed.parse().unwrap_or_else(|_| {
+        // FNV-1a, stable unlike the standard library's hasher.
+        seed.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    })
+}
+
+/// Generates a map, spawning players on top of the highest block at its center.
+pub fn generate_map(generator: &dyn WorldGenerator, size: Vec3D, seed: u64) -> MemoryMap {
+    let mut map = MemoryMap::empty(size);
+
+    generator.generate(&mut map, seed);
+
+    let (x, z) = (size.get_x() / 2, size.get_z() / 2);
+    let surface = (0..size.get_y())
+        .rev()
+        .find(|y| map.get_block(&Vec3D::new(x, *y, z)) != block::AIR)
+        .unwrap_or(0);
+
+    let y = (surface + 1).min(size.get_y() - 1);
+    map.set_spawn(Transform::at_block(Vec3D::new(x, y, z), 0, 0));
+
+    map
+}
+
+#[cfg(test)]
+mod test_generators {
+    use std::env;
+    use std::fs;
+
+    use super::super::maps::{load_map, MCSharpMap};
+    use super::*;
+
+    fn count(map: &MemoryMap, block: u8) -> usize {
+        map.get_chunks().iter().filter(|b| **b == block).count()
+    }
+
+    #[test]
+    /// The same seed gives the same map, another seed a different one.
+    pub fn deterministic() {
+        let generator = find_generator("terrain").unwrap();
+        let size = Vec3D::new(64, 48, 64);
+
+        let first = generate_map(generator.as_ref(), size, 42);
+        let second = generate_map(generator.as_ref(), size, 42);
+        let other = generate_map(generator.as_ref(), size, 43);
+
+        assert!(first.get_chunks() == second.get_chunks());
+        assert!(first.get_chunks() != other.get_chunks());
+        assert_eq!(parse_seed("hello"), parse_seed("hello"));
+        assert_eq!(parse_seed("12"), 12);
+    }
+
+    #[test]
+    /// Terrain has land, sea, beaches, caves with ores and trees on top, standing on bedrock.
+    pub fn terrain_features() {
+        let generator = find_generator("terrain").unwrap();
+        let map = generate_map(generator.as_ref(), Vec3D::new(128, 64, 128), 7);
+
+        for block in &[
+            block::GRASS,
+            block::STILL_WATER,
+            block::SAND,
+            block::LOG,
+            block::LEAVES,
+            block::COAL_ORE,
+            block::IRON_ORE,
+        ] {
+            assert!(count(&map, *block) > 0, "no block {} generated", block);
+        }
+
+        assert_eq!(count(&map, block::BEDROCK), 128 * 128);
+
+        // Islands are mostly sea.
+        let islands = find_generator("islands").unwrap();
+        let map = generate_map(islands.as_ref(), Vec3D::new(128, 64, 128), 7);
+        let surface_water = (0..128)
+            .flat_map(|x| (0..128).map(move |z| (x, z)))
+            .filter(|(x, z)| map.get_block(&Vec3D::new(*x, 32, *z)) == block::STILL_WATER)
+            .count();
+
+        assert!(surface_water > 128 * 128 / 2);
+    }
+
+    #[test]
+    /// Generated maps are saved as .lvl files and load back the same, with players spawning above ground.
+    pub fn save_and_load() {
+        let map = generate_map(&FlatGenerator, Vec3D::new(32, 16, 48), 0);
+
+        assert_eq!(map.get_block(&Vec3D::new(3, 7, 5)), block::GRASS);
+        assert_eq!(map.get_block(&Vec3D::new(3, 8, 5)), block::AIR);
+        assert_eq!(map.get_spawn().get_feet_block(), Vec3D::new(16, 8, 24));
+
+        let folder = env::temp_dir().join(format!("rcclassic-generated-{}", std::process::id()));
+        assert!(MCSharpMap::save(&folder, "flat", &map));
+
+        let loaded = load_map(&folder, "flat").unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(loaded.get_size().0, 32);
+        assert_eq!(loaded.get_size().1, 16);
+        assert_eq!(loaded.get_size().2, 48);
+        assert!(loaded.get_chunks() == map.get_chunks());
+        assert_eq!(loaded.get_spawn().get_pos(), map.get_spawn().get_pos());
+    }
+}
@@ -0,0 +1,173 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Seeded randomness for the generators. Implemented here rather than taken from a crate,
+//! so a seed keeps giving the same map across versions of the server.
+
+/// SplitMix64 pseudo random numbers.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+        z ^ (z >> 31)
+    }
+
+    /// Number in 0..bound, bound has to be above 0.
+    pub fn next_int(&mut self, bound: u32) -> u32 {
+        (self.next_u64() % u64::from(bound)) as u32
+    }
+
+    /// Number in 0..1.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// Perlin's improved gradient noise, with a permutation shuffled by the seed.
+pub struct Noise {
+    permutation: [u8; 512],
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Noise {
+        let mut random = Random::new(seed);
+        let mut table = [0_u8; 256];
+
+        for (i, value) in table.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+
+        for i in (1..256).rev() {
+            table.swap(i, random.next_int(i as u32 + 1) as usize);
+        }
+
+        let mut permutation = [0_u8; 512];
+
+        for (i, value) in permutation.iter_mut().enumerate() {
+            *value = table[i & 255];
+        }
+
+        Noise { permutation }
+    }
+
+    /// Noise in -1..1 at a point, changing smoothly over about one unit.
+    pub fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        let p = &self.permutation;
+
+        let (xi, yi, zi) = (
+            x.floor() as i64 as usize & 255,
+            y.floor() as i64 as usize & 255,
+            z.floor() as i64 as usize & 255,
+        );
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    pub fn get_2d(&self, x: f64, y: f64) -> f64 {
+        self.get_3d(x, y, 0.0)
+    }
+
+    /// Sum of octaves of the noise, each twice as detailed and half as strong as the last.
+    pub fn get_octaves_2d(&self, x: f64, y: f64, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut max = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+
+        for _ in 0..octaves {
+            sum += self.get_2d(x * frequency, y * frequency) * amplitude;
+            max += amplitude;
+
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+
+        sum / max
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
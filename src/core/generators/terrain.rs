@@ -0,0 +1,281 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use super::super::{block, Map, Vec3D};
+use super::{Noise, Random, WorldGenerator};
+
+/// Rolling hills around a sea at half the map's height, with beaches, caves, ores and trees.
+/// Islands sink most of the land under the sea, leaving islands away from the map's edges.
+pub struct TerrainGenerator {
+    islands: bool,
+}
+
+impl TerrainGenerator {
+    pub fn new(islands: bool) -> TerrainGenerator {
+        TerrainGenerator { islands }
+    }
+
+    // Height of the ground for each column, indexed by x + z * width.
+    fn generate_heights(&self, size: &Vec3D, random: &mut Random) -> Vec<u16> {
+        let Vec3D(width, height, length) = *size;
+        let terrain = Noise::new(random.next_u64());
+        let hills = Noise::new(random.next_u64());
+
+        let water_level = f64::from(height / 2);
+        let amplitude = f64::from(height) / 4.0;
+        let mut heights = Vec::with_capacity(width as usize * length as usize);
+
+        for z in 0..length {
+            for x in 0..width {
+                let (nx, nz) = (f64::from(x) / 64.0, f64::from(z) / 64.0);
+
+                let base = terrain.get_octaves_2d(nx, nz, 4);
+                let hill = hills.get_octaves_2d(nx / 2.0, nz / 2.0, 2).max(0.0);
+                let mut value = base * 1.5 + hill * hill * 3.0 + 0.1;
+
+                if self.islands {
+                    // 0 at the center, 1 at the edges.
+                    let dx = f64::from(x) / f64::from(width) * 2.0 - 1.0;
+                    let dz = f64::from(z) / f64::from(length) * 2.0 - 1.0;
+                    let edge = dx.abs().max(dz.abs());
+
+                    value = base * 1.5 + hill * 0.5 - edge * edge * 0.8;
+                }
+
+                let ground = (water_level + value * amplitude).round();
+
+                heights.push(ground.max(1.0).min(f64::from(height) - 2.0) as u16);
+            }
+        }
+
+        heights
+    }
+
+    // Stone, dirt and grass up to the heights, sand on beaches and water up to the sea level.
+    fn fill_columns(&self, map: &mut dyn Map, heights: &[u16], random: &mut Random) {
+        let Vec3D(width, height, length) = *map.get_size();
+        let beaches = Noise::new(random.next_u64());
+        let water_level = height / 2;
+
+        for z in 0..length {
+            for x in 0..width {
+                let ground = heights[x as usize + z as usize * width as usize];
+                let sandy = beaches.get_2d(f64::from(x) / 24.0, f64::from(z) / 24.0) > -0.2;
+
+                let (top, soil) = if ground + 2 < water_level {
+                    if sandy {
+                        (block::SAND, block::SAND)
+                    } else {
+                        (block::GRAVEL, block::DIRT)
+                    }
+                } else if ground <= water_level + 1 && sandy {
+                    (block::SAND, block::SAND)
+                } else if ground < water_level {
+                    (block::DIRT, block::DIRT)
+                } else {
+                    (block::GRASS, block::DIRT)
+                };
+
+                for y in 0..=ground.max(water_level) {
+                    let block = if y == 0 {
+                        block::BEDROCK
+                    } else if y + 3 < ground {
+                        block::STONE
+                    } else if y < ground {
+                        soil
+                    } else if y == ground {
+                        top
+                    } else {
+                        block::STILL_WATER
+                    };
+
+                    map.set_block(&Vec3D::new(x, y, z), block);
+                }
+            }
+        }
+    }
+
+    // Tunnels where two noises are both close to zero, flooded with lava near the bottom.
+    fn carve_caves(&self, map: &mut dyn Map, heights: &[u16], random: &mut Random) {
+        let Vec3D(width, height, length) = *map.get_size();
+        let first = Noise::new(random.next_u64());
+        let second = Noise::new(random.next_u64());
+        let lava_level = (height / 16).max(2);
+
+        for z in 0..length {
+            for x in 0..width {
+                // Leave a roof so caves do not drain the sea or open holes in the ground.
+                let roof = heights[x as usize + z as usize * width as usize].saturating_sub(4);
+                let (nx, nz) = (f64::from(x) / 24.0, f64::from(z) / 24.0);
+
+                for y in 1..roof {
+                    let ny = f64::from(y) / 12.0;
+
+                    if first.get_3d(nx, ny, nz).abs() < 0.08
+                        && second.get_3d(nx, ny + 50.0, nz).abs() < 0.08
+                    {
+                        let block = if y <= lava_level {
+                            block::STILL_LAVA
+                        } else {
+                            block::AIR
+                        };
+
+                        map.set_block(&Vec3D::new(x, y, z), block);
+                    }
+                }
+            }
+        }
+    }
+
+    // Short veins replacing stone, rarer ores deeper down.
+    fn place_ores(&self, map: &mut dyn Map, random: &mut Random) {
+        let Vec3D(width, height, length) = *map.get_size();
+        let veins = width as usize * height as usize * length as usize / 2048;
+
+        for _ in 0..veins {
+            let (ore, depth) = match random.next_int(10) {
+                0..=5 => (block::COAL_ORE, height / 2),
+                6..=8 => (block::IRON_ORE, height / 3),
+                _ => (block::GOLD_ORE, height / 5),
+            };
+
+            let mut position = Vec3D::new(
+                random.next_int(u32::from(width)) as u16,
+                1 + random.next_int(u32::from(depth.max(2) - 1)) as u16,
+                random.next_int(u32::from(length)) as u16,
+            );
+
+            for _ in 0..3 + random.next_int(6) {
+                if map.get_block(&position) == block::STONE {
+                    map.set_block(&position, ore);
+                }
+
+                let step = |value: u16, max: u16, random: &mut Random| match random.next_int(2) {
+                    0 => value.saturating_sub(1),
+                    _ => (value + 1).min(max - 1),
+                };
+
+                match random.next_int(3) {
+                    0 => position.0 = step(position.0, width, random),
+                    1 => position.1 = step(position.1, height, random).max(1),
+                    _ => position.2 = step(position.2, length, random),
+                }
+            }
+        }
+    }
+
+    // Trees on grass, in forests where a noise is high and scattered elsewhere.
+    fn plant_trees(&self, map: &mut dyn Map, heights: &[u16], random: &mut Random) {
+        let Vec3D(width, height, length) = *map.get_size();
+        let forests = Noise::new(random.next_u64());
+
+        if width < 5 || length < 5 {
+            return;
+        }
+
+        for _ in 0..width as usize * length as usize / 48 {
+            let x = 2 + random.next_int(u32::from(width - 4)) as u16;
+            let z = 2 + random.next_int(u32::from(length - 4)) as u16;
+            let ground = heights[x as usize + z as usize * width as usize];
+            let trunk = 4 + random.next_int(3) as u16;
+
+            let density = forests.get_2d(f64::from(x) / 48.0, f64::from(z) / 48.0);
+
+            if random.next_f64() > density + 0.15
+                || ground + trunk + 3 >= height
+                || map.get_block(&Vec3D::new(x, ground, z)) != block::GRASS
+            {
+                continue;
+            }
+
+            let top = ground + trunk;
+            let clear =
+                (ground + 1..=top + 1).all(|y| map.get_block(&Vec3D::new(x, y, z)) == block::AIR);
+
+            if !clear {
+                continue;
+            }
+
+            map.set_block(&Vec3D::new(x, ground, z), block::DIRT);
+
+            for ((dx, dy, dz), tree_block) in tree_blocks(random, trunk) {
+                let position = Vec3D::new(
+                    (i32::from(x) + dx) as u16,
+                    (i32::from(ground) + dy) as u16,
+                    (i32::from(z) + dz) as u16,
+                );
+
+                if tree_block == block::LOG || map.get_block(&position) == block::AIR {
+                    map.set_block(&position, tree_block);
+                }
+            }
+        }
+    }
+}
+
+/// Blocks of a tree as offsets from the ground under its trunk, leaves first.
+/// Leaves reach 2 blocks around the trunk and 1 above it, and only replace air.
+pub fn tree_blocks(random: &mut Random, trunk: u16) -> Vec<((i32, i32, i32), u8)> {
+    let top = i32::from(trunk);
+    let mut blocks = Vec::new();
+
+    for y in top - 2..=top + 1 {
+        let radius: i32 = if y >= top { 1 } else { 2 };
+
+        for dz in -radius..=radius {
+            for dx in -radius..=radius {
+                // Corners are left out at random, so the leaves look rounder.
+                if dx.abs() == radius && dz.abs() == radius && random.next_int(2) == 0 {
+                    continue;
+                }
+
+                blocks.push(((dx, y, dz), block::LEAVES));
+            }
+        }
+    }
+
+    for y in 1..=top {
+        blocks.push(((0, y, 0), block::LOG));
+    }
+
+    blocks
+}
+
+impl WorldGenerator for TerrainGenerator {
+    fn get_name(&self) -> &str {
+        if self.islands {
+            "islands"
+        } else {
+            "terrain"
+        }
+    }
+
+    fn generate(&self, map: &mut dyn Map, seed: u64) {
+        let mut random = Random::new(seed);
+        let heights = self.generate_heights(map.get_size(), &mut random);
+
+        self.fill_columns(map, &heights, &mut random);
+        self.carve_caves(map, &heights, &mut random);
+        self.place_ores(map, &mut random);
+        self.plant_trees(map, &heights, &mut random);
+    }
+}
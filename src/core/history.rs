@@ -0,0 +1,337 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::BlockChange;
+
+/// Blocks changed at once by a player, such as a click or a draw command.
+#[derive(Clone)]
+pub struct Edit {
+    pub world: String,
+    pub time: Instant,
+    pub changes: Vec<BlockChange>,
+}
+
+impl Edit {
+    pub fn new(world: String, changes: Vec<BlockChange>) -> Edit {
+        Edit {
+            world,
+            time: Instant::now(),
+            changes,
+        }
+    }
+}
+
+/// How far back an undo goes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UndoAmount {
+    /// Number of edits.
+    Edits(usize),
+    /// Every edit made within the duration.
+    Since(Duration),
+}
+
+impl UndoAmount {
+    /// Plain numbers are edits, numbers ending in 's', 'm' or 'h' are durations.
+    pub fn parse(value: &str) -> Option<UndoAmount> {
+        if let Ok(count) = value.parse() {
+            return Some(UndoAmount::Edits(count));
+        }
+
+        parse_duration(value).map(UndoAmount::Since)
+    }
+}
+
+/// Duration in seconds, or ending in 's', 'm' or 'h'.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.char_indices().last()? {
+        (index, unit) if unit.is_ascii_alphabetic() => (&value[..index], unit),
+        _ => (value, 's'),
+    };
+
+    let multiplier = match unit.to_ascii_lowercase() {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        _ => return None,
+    };
+
+    let number: u64 = number.parse().ok()?;
+
+    Some(Duration::from_secs(number.checked_mul(multiplier)?))
+}
+
+#[derive(Default)]
+struct PlayerHistory {
+    done: VecDeque<Edit>,
+    undone: Vec<Edit>,
+    // Changes inside done and undone edits.
+    blocks: usize,
+}
+
+impl PlayerHistory {
+    // Forgets the oldest edits, then the undone ones which would be redone last, until the blocks fit.
+    fn trim(&mut self, limit: usize) {
+        while self.blocks > limit {
+            let oldest = match self.done.pop_front() {
+                Some(oldest) => oldest,
+                None if !self.undone.is_empty() => self.undone.remove(0),
+                None => break,
+            };
+
+            self.blocks -= oldest.changes.len();
+        }
+    }
+
+    fn get_last_time(&self) -> Option<Instant> {
+        self.done
+            .iter()
+            .chain(&self.undone)
+            .map(|edit| edit.time)
+            .max()
+    }
+}
+
+/// Edits of every player by name, so they outlive sessions. Each player keeps up to
+/// a number of changed blocks, forgetting their oldest edits first. Players who left
+/// are forgotten once their last edit is older than the window.
+pub struct History {
+    players: HashMap<String, PlayerHistory>,
+    limit: usize,
+    window: Duration,
+}
+
+impl History {
+    pub fn new(limit: usize, window: Duration) -> History {
+        History {
+            players: HashMap::new(),
+            limit,
+            window,
+        }
+    }
+
+    fn get_mut(&mut self, player: &str) -> &mut PlayerHistory {
+        self.players.entry(player.to_lowercase()).or_default()
+    }
+
+    /// Forgets players who are not online, once they edited nothing within the window.
+    /// Names are given lowercase.
+    pub fn forget_inactive<F: Fn(&str) -> bool>(&mut self, online: F) {
+        let window = self.window;
+
+        self.players.retain(|name, history| {
+            online(name)
+                || history
+                    .get_last_time()
+                    .is_some_and(|time| time.elapsed() < window)
+        });
+    }
+
+    /// Adds a new edit, which cannot be redone past anymore.
+    pub fn record(&mut self, player: &str, edit: Edit) {
+        if edit.changes.is_empty() {
+            return;
+        }
+
+        let limit = self.limit;
+        let history = self.get_mut(player);
+
+        history.blocks -= history
+            .undone
+            .drain(..)
+            .map(|edit| edit.changes.len())
+            .sum::<usize>();
+
+        history.blocks += edit.changes.len();
+        history.done.push_back(edit);
+        history.trim(limit);
+    }
+
+    /// Takes edits to undo out of the history, latest first.
+    pub fn take_undo(&mut self, player: &str, amount: UndoAmount) -> Vec<Edit> {
+        let mut taken = Vec::new();
+        let history = match self.players.get_mut(&player.to_lowercase()) {
+            Some(history) => history,
+            None => return taken,
+        };
+
+        while let Some(edit) = history.done.back() {
+            let more = match amount {
+                UndoAmount::Edits(count) => taken.len() < count,
+                UndoAmount::Since(duration) => edit.time.elapsed() <= duration,
+            };
+
+            if !more {
+                break;
+            }
+
+            let edit = history.done.pop_back().unwrap();
+            history.blocks -= edit.changes.len();
+            taken.push(edit);
+        }
+
+        taken
+    }
+
+    /// Keeps undone edits so they can be redone, latest first as taken.
+    pub fn push_undone(&mut self, player: &str, edits: Vec<Edit>) {
+        let limit = self.limit;
+        let history = self.get_mut(player);
+
+        for edit in edits.into_iter().filter(|edit| !edit.changes.is_empty()) {
+            history.blocks += edit.changes.len();
+            history.undone.push(edit);
+        }
+
+        history.trim(limit);
+    }
+
+    /// Takes edits to redo out of the history, the last undone first.
+    pub fn take_redo(&mut self, player: &str, count: usize) -> Vec<Edit> {
+        let history = match self.players.get_mut(&player.to_lowercase()) {
+            Some(history) => history,
+            None => return Vec::new(),
+        };
+        let start = history.undone.len().saturating_sub(count);
+        let taken: Vec<Edit> = history.undone.drain(start..).rev().collect();
+
+        history.blocks -= taken.iter().map(|edit| edit.changes.len()).sum::<usize>();
+
+        taken
+    }
+
+    /// Puts redone edits back, without dropping the ones left to redo.
+    pub fn push_redone(&mut self, player: &str, edits: Vec<Edit>) {
+        let limit = self.limit;
+        let history = self.get_mut(player);
+
+        for edit in edits.into_iter().filter(|edit| !edit.changes.is_empty()) {
+            history.blocks += edit.changes.len();
+            history.done.push_back(edit);
+        }
+
+        history.trim(limit);
+    }
+}
+
+#[cfg(test)]
+mod test_history {
+    use super::super::Vec3D;
+    use super::*;
+
+    fn edit(blocks: u16) -> Edit {
+        Edit::new(
+            String::from("main"),
+            (0..blocks)
+                .map(|x| BlockChange {
+                    position: Vec3D::new(x, 0, 0),
+                    old: 0,
+                    new: 1,
+                    extended: None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    /// Undone edits can be redone until something new is built.
+    pub fn undo_redo() {
+        let mut history = History::new(100, Duration::from_secs(60));
+
+        history.record("Builder", edit(1));
+        history.record("builder", edit(2));
+        history.record("builder", edit(3));
+
+        let undone = history.take_undo("builder", UndoAmount::Edits(2));
+        assert_eq!(
+            undone.iter().map(|e| e.changes.len()).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        history.push_undone("builder", undone);
+
+        let redone = history.take_redo("builder", 1);
+        assert_eq!(redone[0].changes.len(), 2);
+        history.push_redone("builder", redone);
+
+        history.record("builder", edit(4));
+        assert!(history.take_redo("builder", 1).is_empty());
+
+        let undone = history.take_undo("builder", UndoAmount::Since(Duration::from_secs(60)));
+        assert_eq!(undone.len(), 3);
+    }
+
+    #[test]
+    /// Oldest edits are forgotten once a player changed too many blocks.
+    pub fn limit() {
+        let mut history = History::new(10, Duration::from_secs(60));
+
+        history.record("builder", edit(4));
+        history.record("builder", edit(4));
+        history.record("builder", edit(4));
+
+        assert_eq!(history.take_undo("builder", UndoAmount::Edits(5)).len(), 2);
+
+        // Undone edits count too, the oldest edits going first.
+        history.record("builder", edit(4));
+        history.push_undone("builder", vec![edit(7)]);
+        assert!(history
+            .take_undo("builder", UndoAmount::Edits(1))
+            .is_empty());
+        assert_eq!(history.take_redo("builder", 5).len(), 1);
+
+        // An edit larger than the limit cannot be undone at all.
+        history.record("builder", edit(11));
+        assert!(history
+            .take_undo("builder", UndoAmount::Edits(1))
+            .is_empty());
+
+        assert_eq!(UndoAmount::parse("5"), Some(UndoAmount::Edits(5)));
+        assert_eq!(
+            UndoAmount::parse("2m"),
+            Some(UndoAmount::Since(Duration::from_secs(120)))
+        );
+        assert_eq!(UndoAmount::parse("2x"), None);
+    }
+
+    #[test]
+    /// Players who left are forgotten once they edited nothing within the window.
+    pub fn forget_inactive() {
+        let mut history = History::new(100, Duration::from_secs(60));
+
+        history.record("Builder", edit(1));
+        history.record("guest", edit(1));
+        history.forget_inactive(|name| name == "builder");
+        assert_eq!(history.players.len(), 2);
+
+        // Looking up someone never seen keeps nothing around.
+        assert!(history.take_undo("nobody", UndoAmount::Edits(1)).is_empty());
+        assert!(history.take_redo("nobody", 1).is_empty());
+        assert_eq!(history.players.len(), 2);
+
+        history.window = Duration::from_secs(0);
+        history.forget_inactive(|name| name == "builder");
+        assert_eq!(history.players.len(), 1);
+        assert_eq!(history.take_undo("builder", UndoAmount::Edits(1)).len(), 1);
+    }
+}
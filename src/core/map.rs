@@ -20,7 +20,242 @@
     SOFTWARE.
 */
 
-use super::Vec3D;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::{block, Transform, Vec3D};
+
+/// Cuboid between two corners, both of them included.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Region {
+    min: Vec3D,
+    max: Vec3D,
+}
+
+impl Region {
+    /// Region between any two corners.
+    pub fn new(first: Vec3D, second: Vec3D) -> Region {
+        Region {
+            min: Vec3D::new(
+                first.0.min(second.0),
+                first.1.min(second.1),
+                first.2.min(second.2),
+            ),
+            max: Vec3D::new(
+                first.0.max(second.0),
+                first.1.max(second.1),
+                first.2.max(second.2),
+            ),
+        }
+    }
+
+    /// Region starting at a corner, spanning the given size.
+    pub fn with_size(origin: Vec3D, size: Vec3D) -> Option<Region> {
+        let end = |start: u16, length: u16| start.checked_add(length.checked_sub(1)?);
+
+        Some(Region {
+            min: origin,
+            max: Vec3D::new(
+                end(origin.0, size.0)?,
+                end(origin.1, size.1)?,
+                end(origin.2, size.2)?,
+            ),
+        })
+    }
+
+    pub fn get_min(&self) -> &Vec3D {
+        &self.min
+    }
+
+    pub fn get_max(&self) -> &Vec3D {
+        &self.max
+    }
+
+    /// Number of blocks on each axis.
+    pub fn get_size(&self) -> Vec3D<usize> {
+        Vec3D::new(
+            (self.max.0 - self.min.0) as usize + 1,
+            (self.max.1 - self.min.1) as usize + 1,
+            (self.max.2 - self.min.2) as usize + 1,
+        )
+    }
+
+    pub fn get_volume(&self) -> usize {
+        let Vec3D(x, y, z) = self.get_size();
+
+        x * y * z
+    }
+
+    pub fn contains(&self, position: &Vec3D) -> bool {
+        (self.min.0..=self.max.0).contains(&position.0)
+            && (self.min.1..=self.max.1).contains(&position.1)
+            && (self.min.2..=self.max.2).contains(&position.2)
+    }
+
+    /// Part of the region inside a map of the given size, None if it is completely outside.
+    pub fn clamp(&self, size: &Vec3D) -> Option<Region> {
+        if size.0 == 0 || size.1 == 0 || size.2 == 0 {
+            return None;
+        }
+
+        if self.min.0 >= size.0 || self.min.1 >= size.1 || self.min.2 >= size.2 {
+            return None;
+        }
+
+        Some(Region {
+            min: self.min,
+            max: Vec3D::new(
+                self.max.0.min(size.0 - 1),
+                self.max.1.min(size.1 - 1),
+                self.max.2.min(size.2 - 1),
+            ),
+        })
+    }
+
+    /// Every position of the region, x changing first, then z, then y (the order of map chunks).
+    pub fn positions(&self) -> impl Iterator<Item = Vec3D> {
+        let (min, max) = (self.min, self.max);
+
+        (min.1..=max.1).flat_map(move |y| {
+            (min.2..=max.2).flat_map(move |z| (min.0..=max.0).map(move |x| Vec3D::new(x, y, z)))
+        })
+    }
+}
+
+/// Axis of a map, Y being the height.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl FromStr for Axis {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Axis, ()> {
+        match name.to_lowercase().as_str() {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            "z" => Ok(Axis::Z),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Blocks copied out of a map, in the same order as map chunks.
+#[derive(Clone)]
+pub struct BlockBuffer {
+    size: Vec3D,
+    data: Vec<u8>,
+}
+
+impl BlockBuffer {
+    /// Buffer of the given size, filled with one block.
+    pub fn new(size: Vec3D, block: u8) -> BlockBuffer {
+        BlockBuffer {
+            size,
+            data: vec![block; size.0 as usize * size.1 as usize * size.2 as usize],
+        }
+    }
+
+    /// Buffer of existing data, None if it does not match the size.
+    pub fn from_data(size: Vec3D, data: Vec<u8>) -> Option<BlockBuffer> {
+        if data.len() != size.0 as usize * size.1 as usize * size.2 as usize {
+            return None;
+        }
+
+        Some(BlockBuffer { size, data })
+    }
+
+    pub fn get_size(&self) -> &Vec3D {
+        &self.size
+    }
+
+    pub fn get_data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    pub fn get_data_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.data
+    }
+
+    pub fn get_index(&self, position: &Vec3D) -> usize {
+        let (width, length) = (self.size.0 as usize, self.size.2 as usize);
+
+        position.0 as usize + position.2 as usize * width + position.1 as usize * width * length
+    }
+
+    pub fn get_block(&self, position: &Vec3D) -> u8 {
+        self.data[self.get_index(position)]
+    }
+
+    pub fn set_block(&mut self, position: &Vec3D, block: u8) {
+        let index = self.get_index(position);
+
+        self.data[index] = block;
+    }
+
+    // Copy of the buffer with every block moved by a function of its position, into a buffer of the new size.
+    fn transform<F: Fn([u16; 3]) -> [u16; 3]>(&self, size: Vec3D, f: F) -> BlockBuffer {
+        let mut buffer = BlockBuffer::new(size, 0);
+        let Vec3D(x, y, z) = self.size;
+
+        for position in Region::with_size(Vec3D::new(0, 0, 0), Vec3D::new(x, y, z))
+            .into_iter()
+            .flat_map(|region| region.positions())
+        {
+            let [x, y, z] = f([position.0, position.1, position.2]);
+
+            buffer.set_block(&Vec3D::new(x, y, z), self.get_block(&position));
+        }
+
+        buffer
+    }
+
+    /// Buffer turned by quarter turns around an axis.
+    pub fn rotate(&self, axis: Axis, turns: u8) -> BlockBuffer {
+        // The two axes turning into each other.
+        let (a, b) = match axis {
+            Axis::X => (1, 2),
+            Axis::Y => (2, 0),
+            Axis::Z => (0, 1),
+        };
+
+        let mut buffer = self.clone();
+
+        for _ in 0..turns % 4 {
+            let mut size = [buffer.size.0, buffer.size.1, buffer.size.2];
+            let old = size;
+            size.swap(a, b);
+
+            buffer = buffer.transform(Vec3D::new(size[0], size[1], size[2]), |mut position| {
+                let (from_a, from_b) = (position[a], position[b]);
+
+                position[a] = old[b] - 1 - from_b;
+                position[b] = from_a;
+
+                position
+            });
+        }
+
+        buffer
+    }
+
+    /// Buffer flipped along an axis.
+    pub fn mirror(&self, axis: Axis) -> BlockBuffer {
+        let index = axis as usize;
+        let length = [self.size.0, self.size.1, self.size.2][index];
+
+        self.transform(self.size, |mut position| {
+            position[index] = length - 1 - position[index];
+
+            position
+        })
+    }
+}
+
+static NO_EXTENDED_BLOCKS: BTreeMap<usize, u16> = BTreeMap::new();
 
 pub trait Map {
     fn get_magic_id(&self) -> i32 {
@@ -34,20 +269,137 @@ pub trait Map {
 
     fn get_chunks(&self) -> &Vec<u8>;
 
-    fn get_spawnarea(&self) -> Vec3D {
-        let size = self.get_size();
-
-        Vec3D::new(size.get_x() * 16, size.get_y() * 32, size.get_z() * 16)
+    /// Blocks past the classic ones by their index into the chunks, which hold their fallback.
+    /// They are kept so saving the map writes them back, until they are built over.
+    fn get_extended_blocks(&self) -> &BTreeMap<usize, u16> {
+        &NO_EXTENDED_BLOCKS
     }
 
-    fn get_spawnyaw(&self) -> u8 {
-        0
+    /// State MCGalaxy keeps for its physics, left as it was read so saving the map keeps it.
+    fn get_physics_states(&self) -> &[u8] {
+        &[]
     }
 
-    fn get_spawnpitch(&self) -> u8 {
-        255
+    /// Where players join the map, above its center unless the map keeps a spawn.
+    fn get_spawn(&self) -> Transform {
+        let Vec3D(x, y, z) = *self.get_size();
+
+        Transform::at_block(Vec3D::new(x / 2, y, z / 2), 0, 0)
     }
 
+    /// Moves the spawn, maps without one of their own ignore it.
+    fn set_spawn(&mut self, _spawn: Transform) {}
+
     fn get_block(&self, position: &Vec3D) -> u8;
     fn set_block(&mut self, position: &Vec3D, block: u8);
+
+    /// Extended block at a position, its fallback being the block there.
+    fn get_extended_block(&self, _position: &Vec3D) -> Option<u16> {
+        None
+    }
+
+    /// Puts an extended block back at a position, maps without extended blocks only keep its fallback.
+    fn restore_extended_block(&mut self, position: &Vec3D, id: u16) {
+        self.set_block(position, block::get_fallback(id));
+    }
+
+    // Region functions below go block by block, maps keeping their blocks in memory do it row by row.
+    // They work on the part of the region inside the map and return the positions which changed.
+
+    /// Sets every block of a region.
+    fn fill(&mut self, region: &Region, block: u8) -> Vec<Vec3D> {
+        let region = match region.clamp(self.get_size()) {
+            Some(region) => region,
+            None => return Vec::new(),
+        };
+
+        let mut changed = Vec::new();
+
+        for position in region.positions() {
+            if self.get_block(&position) != block {
+                self.set_block(&position, block);
+                changed.push(position);
+            }
+        }
+
+        changed
+    }
+
+    /// Replaces one block with another inside a region.
+    fn replace(&mut self, region: &Region, from: u8, to: u8) -> Vec<Vec3D> {
+        let region = match region.clamp(self.get_size()) {
+            Some(region) => region,
+            None => return Vec::new(),
+        };
+
+        let mut changed = Vec::new();
+
+        if from == to {
+            return changed;
+        }
+
+        for position in region.positions() {
+            if self.get_block(&position) == from {
+                self.set_block(&position, to);
+                changed.push(position);
+            }
+        }
+
+        changed
+    }
+
+    /// Counts one block inside a region.
+    fn count(&self, region: &Region, block: u8) -> usize {
+        region.clamp(self.get_size()).map_or(0, |region| {
+            region
+                .positions()
+                .filter(|position| self.get_block(position) == block)
+                .count()
+        })
+    }
+
+    /// Copies the blocks of a region, parts outside of the map are read as air.
+    fn read_region(&self, region: &Region) -> BlockBuffer {
+        let min = *region.get_min();
+        let Vec3D(x, y, z) = region.get_size();
+        let mut buffer = BlockBuffer::new(Vec3D::new(x as u16, y as u16, z as u16), 0);
+
+        if let Some(inside) = region.clamp(self.get_size()) {
+            for position in inside.positions() {
+                let relative =
+                    Vec3D::new(position.0 - min.0, position.1 - min.1, position.2 - min.2);
+
+                buffer.set_block(&relative, self.get_block(&position));
+            }
+        }
+
+        buffer
+    }
+
+    /// Writes a buffer with its lowest corner at the origin.
+    fn paste(&mut self, origin: &Vec3D, buffer: &BlockBuffer) -> Vec<Vec3D> {
+        let region = match Region::with_size(*origin, *buffer.get_size())
+            .and_then(|region| region.clamp(self.get_size()))
+        {
+            Some(region) => region,
+            None => return Vec::new(),
+        };
+
+        let mut changed = Vec::new();
+
+        for position in region.positions() {
+            let block = buffer.get_block(&Vec3D::new(
+                position.0 - origin.0,
+                position.1 - origin.1,
+                position.2 - origin.2,
+            ));
+
+            if self.get_block(&position) != block {
+                self.set_block(&position, block);
+                changed.push(position);
+            }
+        }
+
+        changed
+    }
 }
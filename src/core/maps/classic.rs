@@ -0,0 +1,764 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Maps saved by the original Minecraft Classic, as ".dat" (version 2) or ".mine" (version 1) files.
+//!
+//! Both are gzipped and start with a magic number and a version. Version 1 is followed by the name,
+//! creator, creation time, size and blocks. Version 2 holds a serialized Java "Level" object, which
+//! is only read as far as its size, blocks and spawn. Files without the magic number are the
+//! earliest levels, blocks of a 256x64x256 map only.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
+
+use super::super::{BlockBuffer, Core, Map, Region, Transform, Vec3D, MAX_MAP_VOLUME};
+use super::MemoryMap;
+
+use flate2::read::GzDecoder;
+
+const MAGIC: u32 = 0x271b_b788;
+
+/// Size of levels saved before the magic number was added.
+const FIRST_LEVEL_SIZE: Vec3D = Vec3D(256, 64, 256);
+
+/// Most bytes read from a level, its blocks leaving room for what else Classic kept in it.
+const MAX_LEVEL_SIZE: usize = 2 * MAX_MAP_VOLUME;
+
+/// Deepest nesting of objects and classes read.
+const MAX_DEPTH: usize = 64;
+
+// Java serialization stream constants.
+const STREAM_MAGIC: u16 = 0xaced;
+const TC_NULL: u8 = 0x70;
+const TC_REFERENCE: u8 = 0x71;
+const TC_CLASSDESC: u8 = 0x72;
+const TC_OBJECT: u8 = 0x73;
+const TC_STRING: u8 = 0x74;
+const TC_ARRAY: u8 = 0x75;
+const TC_CLASS: u8 = 0x76;
+const TC_BLOCKDATA: u8 = 0x77;
+const TC_ENDBLOCKDATA: u8 = 0x78;
+const TC_RESET: u8 = 0x79;
+const TC_BLOCKDATALONG: u8 = 0x7a;
+const TC_LONGSTRING: u8 = 0x7c;
+const TC_PROXYCLASSDESC: u8 = 0x7d;
+const TC_ENUM: u8 = 0x7e;
+const BASE_HANDLE: i32 = 0x7e_0000;
+
+const SC_WRITE_METHOD: u8 = 0x01;
+const SC_SERIALIZABLE: u8 = 0x02;
+const SC_EXTERNALIZABLE: u8 = 0x04;
+const SC_BLOCK_DATA: u8 = 0x08;
+
+// Uses an internal memory map which is generated by the given file.
+pub struct ClassicMap {
+    size: Vec3D,
+
+    internal_map: MemoryMap,
+}
+
+impl ClassicMap {
+    /// Reads a map from a Classic level file, logging why it could not be read.
+    pub fn load(path: &Path) -> Option<ClassicMap> {
+        let read = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|compressed| {
+                let mut data = Vec::new();
+
+                GzDecoder::new(&compressed[..])
+                    .take(MAX_LEVEL_SIZE as u64 + 1)
+                    .read_to_end(&mut data)
+                    .map_err(|e| e.to_string())?;
+
+                if data.len() > MAX_LEVEL_SIZE {
+                    return Err(String::from("Level is too large."));
+                }
+
+                read_level(&data)
+            });
+
+        match read {
+            Ok(internal_map) => Some(ClassicMap {
+                size: *internal_map.get_size(),
+                internal_map,
+            }),
+            Err(e) => {
+                Core::static_log(&format!(
+                    "Unable to read Classic map \"{}\": {}",
+                    path.display(),
+                    e
+                ));
+
+                None
+            }
+        }
+    }
+}
+
+// Builds the map from the decompressed contents of a level file.
+fn read_level(data: &[u8]) -> Result<MemoryMap, String> {
+    let mut reader = JavaReader::new(data);
+
+    if data.len() < 4 || reader.read_int()? as u32 != MAGIC {
+        return build_map(FIRST_LEVEL_SIZE, data, None);
+    }
+
+    match reader.read_byte()? {
+        1 => {
+            // Name, creator and creation time.
+            reader.read_utf()?;
+            reader.read_utf()?;
+            reader.read_long()?;
+
+            // Height is the Z axis in Classic, and depth the Y axis.
+            let width = reader.read_short()?;
+            let height = reader.read_short()?;
+            let depth = reader.read_short()?;
+
+            let size = Vec3D::new(width, depth, height);
+
+            build_map(size, reader.read_to_end(), None)
+        }
+        2 => read_serialized_level(&mut reader),
+        version => Err(format!("Unknown version {}.", version)),
+    }
+}
+
+fn read_serialized_level(reader: &mut JavaReader) -> Result<MemoryMap, String> {
+    if reader.read_short()? != STREAM_MAGIC {
+        return Err(String::from("Not a serialized level."));
+    }
+
+    // Stream version.
+    reader.read_short()?;
+
+    let fields = match reader.read_content()? {
+        Value::Object(class, fields) if class.ends_with(".Level") => fields,
+        _ => return Err(String::from("No level is stored.")),
+    };
+
+    let int = |name: &str| match fields.get(name) {
+        Some(Value::Int(value)) => Ok(*value),
+        _ => Err(format!("Level has no \"{}\".", name)),
+    };
+
+    let axis = |name: &str| {
+        u16::try_from(int(name)?).map_err(|_| format!("Level has an invalid \"{}\".", name))
+    };
+
+    let size = Vec3D::new(axis("width")?, axis("depth")?, axis("height")?);
+
+    let blocks = match fields.get("blocks") {
+        Some(Value::Bytes(blocks)) => blocks,
+        _ => return Err(String::from("Level has no blocks.")),
+    };
+
+    // Older levels may not have a spawn yet.
+    let spawn = match (int("xSpawn"), int("ySpawn"), int("zSpawn")) {
+        (Ok(x), Ok(y), Ok(z)) => {
+            let clamp = |value: i64| value.clamp(0, u16::MAX as i64) as u16;
+
+            let yaw = match fields.get("rotSpawn") {
+                Some(Value::Float(degrees)) => (degrees * 256.0 / 360.0).rem_euclid(256.0) as u8,
+                _ => 0,
+            };
+
+            Some(Transform::at_block(
+                Vec3D::new(clamp(x), clamp(y), clamp(z)),
+                yaw,
+                0,
+            ))
+        }
+        _ => None,
+    };
+
+    build_map(size, blocks, spawn)
+}
+
+// Blocks are ordered the same way as in memory maps, by X, then Z, then Y.
+fn build_map(size: Vec3D, blocks: &[u8], spawn: Option<Transform>) -> Result<MemoryMap, String> {
+    let Vec3D(x, y, z) = size;
+
+    if x == 0 || y == 0 || z == 0 {
+        return Err(String::from("Level is empty."));
+    }
+
+    if blocks.len() != x as usize * y as usize * z as usize {
+        return Err(format!(
+            "Expected {}x{}x{} blocks, found {}.",
+            x,
+            y,
+            z,
+            blocks.len()
+        ));
+    }
+
+    let mut map = MemoryMap::empty(size);
+    map.set_data_chunks(blocks.to_vec());
+
+    if let Some(spawn) = spawn {
+        map.set_spawn(spawn);
+    }
+
+    Ok(map)
+}
+
+// What is read from a serialization stream. Only what levels need is kept, strings are skipped.
+#[derive(Clone, Debug)]
+enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Object(String, HashMap<String, Value>),
+    Other,
+}
+
+#[derive(Debug)]
+struct ClassDesc {
+    name: String,
+    flags: u8,
+    // Type code and name of each field.
+    fields: Vec<(u8, String)>,
+    super_class: Option<Rc<ClassDesc>>,
+}
+
+#[derive(Clone)]
+enum Handle {
+    Class(Rc<ClassDesc>),
+    Value(Value),
+}
+
+// Reads big endian values and the contents of a Java serialization stream.
+struct JavaReader<'a> {
+    data: &'a [u8],
+    index: usize,
+    handles: Vec<Handle>,
+    depth: usize,
+}
+
+impl<'a> JavaReader<'a> {
+    fn new(data: &'a [u8]) -> JavaReader<'a> {
+        JavaReader {
+            data,
+            index: 0,
+            handles: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .index
+            .checked_add(count)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| String::from("Unexpected end of file."))?;
+
+        let bytes = &self.data[self.index..end];
+        self.index = end;
+
+        Ok(bytes)
+    }
+
+    fn read_to_end(&mut self) -> &'a [u8] {
+        let bytes = &self.data[self.index..];
+        self.index = self.data.len();
+
+        bytes
+    }
+
+    fn read_byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn peek_byte(&self) -> Result<u8, String> {
+        self.data
+            .get(self.index)
+            .copied()
+            .ok_or_else(|| String::from("Unexpected end of file."))
+    }
+
+    fn read_short(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_int(&mut self) -> Result<i32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+
+        Ok(i32::from_be_bytes(bytes))
+    }
+
+    fn read_long(&mut self) -> Result<i64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+
+        Ok(i64::from_be_bytes(bytes))
+    }
+
+    // Java's modified UTF-8 only differs for null characters and surrogates, which names do not use.
+    fn read_utf(&mut self) -> Result<String, String> {
+        let length = self.read_short()? as usize;
+
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn new_handle(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len() - 1
+    }
+
+    fn get_handle(&mut self) -> Result<Handle, String> {
+        let handle = self.read_int()?;
+
+        handle
+            .checked_sub(BASE_HANDLE)
+            .and_then(|index| self.handles.get(usize::try_from(index).ok()?))
+            .cloned()
+            .ok_or_else(|| format!("Invalid reference {:#x}.", handle))
+    }
+
+    // Objects and classes contain others, which are read within this.
+    fn read_nested<T>(&mut self, read: fn(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(String::from("Contents are nested too deep."));
+        }
+
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    fn read_content(&mut self) -> Result<Value, String> {
+        self.read_nested(JavaReader::read_content_code)
+    }
+
+    fn read_content_code(&mut self) -> Result<Value, String> {
+        match self.read_byte()? {
+            TC_NULL => Ok(Value::Null),
+            TC_REFERENCE => match self.get_handle()? {
+                Handle::Value(value) => Ok(value),
+                Handle::Class(_) => Ok(Value::Other),
+            },
+            TC_STRING => {
+                self.read_utf()?;
+                self.new_handle(Handle::Value(Value::Other));
+
+                Ok(Value::Other)
+            }
+            TC_LONGSTRING => {
+                let length = usize::try_from(self.read_long()?)
+                    .map_err(|_| String::from("Invalid string length."))?;
+                self.take(length)?;
+                self.new_handle(Handle::Value(Value::Other));
+
+                Ok(Value::Other)
+            }
+            TC_OBJECT => {
+                let class = self.read_class_desc()?;
+                // Objects referring back to themselves are not needed for levels.
+                self.new_handle(Handle::Value(Value::Other));
+
+                match class {
+                    Some(class) => {
+                        let fields = self.read_object_fields(&class)?;
+
+                        Ok(Value::Object(class.name.clone(), fields))
+                    }
+                    None => Err(String::from("Object without a class.")),
+                }
+            }
+            TC_ARRAY => {
+                let class = self
+                    .read_class_desc()?
+                    .ok_or_else(|| String::from("Array without a class."))?;
+                self.new_handle(Handle::Value(Value::Other));
+
+                let length = usize::try_from(self.read_int()?)
+                    .map_err(|_| String::from("Invalid array length."))?;
+
+                // Class names of arrays are "[" followed by the type of their elements.
+                match class.name.as_bytes().get(1).copied() {
+                    Some(b'B') => Ok(Value::Bytes(self.take(length)?.to_vec())),
+                    Some(element) => {
+                        for _ in 0..length {
+                            self.read_field(element)?;
+                        }
+
+                        Ok(Value::Other)
+                    }
+                    None => Err(format!("Invalid array class \"{}\".", class.name)),
+                }
+            }
+            TC_CLASS => {
+                let class = self
+                    .read_class_desc()?
+                    .ok_or_else(|| String::from("Class without a description."))?;
+                self.new_handle(Handle::Class(class));
+
+                Ok(Value::Other)
+            }
+            TC_ENUM => {
+                self.read_class_desc()?;
+                self.new_handle(Handle::Value(Value::Other));
+                self.read_content()?;
+
+                Ok(Value::Other)
+            }
+            TC_CLASSDESC | TC_PROXYCLASSDESC => {
+                self.index -= 1;
+                self.read_class_desc()?;
+
+                Ok(Value::Other)
+            }
+            TC_BLOCKDATA => {
+                let length = self.read_byte()? as usize;
+                self.take(length)?;
+
+                Ok(Value::Other)
+            }
+            TC_BLOCKDATALONG => {
+                let length = usize::try_from(self.read_int()?)
+                    .map_err(|_| String::from("Invalid block data length."))?;
+                self.take(length)?;
+
+                Ok(Value::Other)
+            }
+            TC_RESET => {
+                self.handles.clear();
+                self.read_content()
+            }
+            code => Err(format!("Unexpected type code {:#x}.", code)),
+        }
+    }
+
+    fn read_class_desc(&mut self) -> Result<Option<Rc<ClassDesc>>, String> {
+        self.read_nested(JavaReader::read_class_desc_code)
+    }
+
+    fn read_class_desc_code(&mut self) -> Result<Option<Rc<ClassDesc>>, String> {
+        match self.read_byte()? {
+            TC_NULL => Ok(None),
+            TC_REFERENCE => match self.get_handle()? {
+                Handle::Class(class) => Ok(Some(class)),
+                Handle::Value(_) => Err(String::from("Reference is not a class.")),
+            },
+            TC_CLASSDESC => {
+                let name = self.read_utf()?;
+                // Serial version UID.
+                self.read_long()?;
+
+                let handle = self.new_handle(Handle::Value(Value::Other));
+                let flags = self.read_byte()?;
+                let count = self.read_short()?;
+                let mut fields = Vec::with_capacity(count as usize);
+
+                for _ in 0..count {
+                    let code = self.read_byte()?;
+                    let field = self.read_utf()?;
+
+                    // Objects and arrays are followed by their class name.
+                    if code == b'L' || code == b'[' {
+                        self.read_content()?;
+                    }
+
+                    fields.push((code, field));
+                }
+
+                self.skip_annotations()?;
+
+                let class = Rc::new(ClassDesc {
+                    name,
+                    flags,
+                    fields,
+                    super_class: self.read_class_desc()?,
+                });
+
+                self.handles[handle] = Handle::Class(class.clone());
+
+                Ok(Some(class))
+            }
+            TC_PROXYCLASSDESC => {
+                let handle = self.new_handle(Handle::Value(Value::Other));
+
+                for _ in 0..self.read_int()? {
+                    self.read_utf()?;
+                }
+
+                self.skip_annotations()?;
+
+                let class = Rc::new(ClassDesc {
+                    name: String::new(),
+                    flags: SC_SERIALIZABLE,
+                    fields: Vec::new(),
+                    super_class: self.read_class_desc()?,
+                });
+
+                self.handles[handle] = Handle::Class(class.clone());
+
+                Ok(Some(class))
+            }
+            code => Err(format!("Unexpected class type code {:#x}.", code)),
+        }
+    }
+
+    // Skips what classes write after their fields, up to the end of the block data.
+    fn skip_annotations(&mut self) -> Result<(), String> {
+        while self.peek_byte()? != TC_ENDBLOCKDATA {
+            self.read_content()?;
+        }
+
+        self.index += 1;
+
+        Ok(())
+    }
+
+    // Fields of every class from the top superclass down, later classes replacing fields of the same name.
+    fn read_object_fields(&mut self, class: &ClassDesc) -> Result<HashMap<String, Value>, String> {
+        let mut classes = vec![class];
+
+        while let Some(super_class) = &classes[classes.len() - 1].super_class {
+            classes.push(super_class);
+        }
+
+        let mut fields = HashMap::new();
+
+        for class in classes.into_iter().rev() {
+            if class.flags & SC_SERIALIZABLE != 0 {
+                for (code, name) in &class.fields {
+                    let value = self.read_field(*code)?;
+                    fields.insert(name.clone(), value);
+                }
+
+                if class.flags & SC_WRITE_METHOD != 0 {
+                    self.skip_annotations()?;
+                }
+            } else if class.flags & SC_EXTERNALIZABLE != 0 {
+                if class.flags & SC_BLOCK_DATA == 0 {
+                    return Err(format!("Unable to read \"{}\".", class.name));
+                }
+
+                self.skip_annotations()?;
+            }
+        }
+
+        Ok(fields)
+    }
+
+    fn read_field(&mut self, code: u8) -> Result<Value, String> {
+        let value = match code {
+            b'B' => Value::Int(self.read_byte()? as i8 as i64),
+            b'Z' => Value::Int(self.read_byte()? as i64),
+            b'C' => Value::Int(self.read_short()? as i64),
+            b'S' => Value::Int(self.read_short()? as i16 as i64),
+            b'I' => Value::Int(self.read_int()? as i64),
+            b'J' => Value::Int(self.read_long()?),
+            b'F' => Value::Float(f32::from_bits(self.read_int()? as u32) as f64),
+            b'D' => Value::Float(f64::from_bits(self.read_long()? as u64)),
+            b'L' | b'[' => self.read_content()?,
+            code => return Err(format!("Unknown field type {:#x}.", code)),
+        };
+
+        Ok(value)
+    }
+}
+
+impl Map for ClassicMap {
+    fn get_size(&self) -> &Vec3D {
+        &self.size
+    }
+
+    fn get_chunks(&self) -> &Vec<u8> {
+        self.internal_map.get_chunks()
+    }
+
+    fn get_extended_blocks(&self) -> &BTreeMap<usize, u16> {
+        self.internal_map.get_extended_blocks()
+    }
+
+    fn get_block(&self, position: &Vec3D) -> u8 {
+        self.internal_map.get_block(position)
+    }
+
+    fn set_block(&mut self, position: &Vec3D, block: u8) {
+        self.internal_map.set_block(position, block);
+    }
+
+    fn get_extended_block(&self, position: &Vec3D) -> Option<u16> {
+        self.internal_map.get_extended_block(position)
+    }
+
+    fn restore_extended_block(&mut self, position: &Vec3D, id: u16) {
+        self.internal_map.restore_extended_block(position, id);
+    }
+
+    fn fill(&mut self, region: &Region, block: u8) -> Vec<Vec3D> {
+        self.internal_map.fill(region, block)
+    }
+
+    fn replace(&mut self, region: &Region, from: u8, to: u8) -> Vec<Vec3D> {
+        self.internal_map.replace(region, from, to)
+    }
+
+    fn count(&self, region: &Region, block: u8) -> usize {
+        self.internal_map.count(region, block)
+    }
+
+    fn read_region(&self, region: &Region) -> BlockBuffer {
+        self.internal_map.read_region(region)
+    }
+
+    fn paste(&mut self, origin: &Vec3D, buffer: &BlockBuffer) -> Vec<Vec3D> {
+        self.internal_map.paste(origin, buffer)
+    }
+
+    fn get_spawn(&self) -> Transform {
+        self.internal_map.get_spawn()
+    }
+
+    fn set_spawn(&mut self, spawn: Transform) {
+        self.internal_map.set_spawn(spawn);
+    }
+}
+
+#[cfg(test)]
+mod test_classic {
+    use super::*;
+
+    fn utf(data: &mut Vec<u8>, value: &str) {
+        data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        data.extend_from_slice(value.as_bytes());
+    }
+
+    #[test]
+    /// Version 2 levels are read from the serialized Level object, with their spawn.
+    pub fn serialized_level() {
+        let blocks: Vec<u8> = (0..2 * 3 * 4).map(|i| i as u8 % 50).collect();
+
+        let mut data = MAGIC.to_be_bytes().to_vec();
+        data.push(2);
+        data.extend_from_slice(&[0xac, 0xed, 0x00, 0x05]);
+
+        // Level class, handle 0.
+        data.extend_from_slice(&[TC_OBJECT, TC_CLASSDESC]);
+        utf(&mut data, "com.mojang.minecraft.level.Level");
+        data.extend_from_slice(&[0; 8]);
+        data.push(SC_SERIALIZABLE);
+        data.extend_from_slice(&9u16.to_be_bytes());
+
+        for name in &["width", "height", "depth", "xSpawn", "ySpawn", "zSpawn"] {
+            data.push(b'I');
+            utf(&mut data, name);
+        }
+
+        data.push(b'F');
+        utf(&mut data, "rotSpawn");
+
+        // Class names of fields, handles 1 and 2, then a reference to the second.
+        data.push(b'[');
+        utf(&mut data, "blocks");
+        data.push(TC_STRING);
+        utf(&mut data, "[B");
+        data.push(b'L');
+        utf(&mut data, "name");
+        data.push(TC_STRING);
+        utf(&mut data, "Ljava/lang/String;");
+
+        data.extend_from_slice(&[TC_ENDBLOCKDATA, TC_NULL]);
+
+        // Level values, the level itself being handle 3.
+        for value in &[2i32, 3, 4, 1, 2, 3] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+
+        data.extend_from_slice(&90f32.to_bits().to_be_bytes());
+
+        data.extend_from_slice(&[TC_ARRAY, TC_CLASSDESC]);
+        utf(&mut data, "[B");
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&[SC_SERIALIZABLE, 0, 0, TC_ENDBLOCKDATA, TC_NULL]);
+        data.extend_from_slice(&(blocks.len() as i32).to_be_bytes());
+        data.extend_from_slice(&blocks);
+
+        data.push(TC_REFERENCE);
+        data.extend_from_slice(&(BASE_HANDLE + 2).to_be_bytes());
+
+        let map = read_level(&data).unwrap();
+
+        // Height is the Z axis, depth the Y axis.
+        assert_eq!(*map.get_size(), Vec3D::new(2, 4, 3));
+        assert_eq!(map.get_chunks(), &blocks);
+        assert_eq!(map.get_spawn().get_feet_block(), Vec3D::new(1, 2, 3));
+        assert_eq!(map.get_spawn().get_yaw(), 64);
+    }
+
+    #[test]
+    /// Version 1 levels are read from their header, and refused when blocks are missing.
+    pub fn mine_level() {
+        let mut data = MAGIC.to_be_bytes().to_vec();
+        data.push(1);
+        utf(&mut data, "A Nice World");
+        utf(&mut data, "notch");
+        data.extend_from_slice(&[0; 8]);
+
+        for axis in &[4u16, 2, 3] {
+            data.extend_from_slice(&axis.to_be_bytes());
+        }
+
+        data.extend_from_slice(&[1; 4 * 2 * 3]);
+
+        let map = read_level(&data).unwrap();
+
+        assert_eq!(*map.get_size(), Vec3D::new(4, 3, 2));
+        assert_eq!(map.get_block(&Vec3D::new(3, 2, 1)), 1);
+
+        data.pop();
+        assert!(read_level(&data).is_err());
+    }
+
+    #[test]
+    /// Contents nested too deep are refused instead of overflowing the stack.
+    pub fn nested_level() {
+        let mut data = MAGIC.to_be_bytes().to_vec();
+        data.push(2);
+        data.extend_from_slice(&[0xac, 0xed, 0x00, 0x05]);
+
+        // Enums without a class, each holding the next.
+        for _ in 0..100_000 {
+            data.extend_from_slice(&[TC_ENUM, TC_NULL]);
+        }
+
+        data.push(TC_NULL);
+
+        assert_eq!(
+            read_level(&data).err(),
+            Some(String::from("Contents are nested too deep."))
+        );
+    }
+}
@@ -0,0 +1,400 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Maps of fCraft and its forks such as ProCraft, saved as FCMv3 ".fcm" files.
+//!
+//! The header holds the size, spawn, times, GUID and an index of layers. Metadata entries follow,
+//! compressed along with the blocks. fCraft keeps zones as metadata, which are read with the map.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use super::super::{
+    check_map_volume, BlockBuffer, Core, Map, Rank, Region, Transform, Vec3D, Zone, MAX_ZONES,
+};
+use super::MemoryMap;
+
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+
+const IDENTIFIER: u32 = 0x0fc2_af40;
+const REVISION: u8 = 13;
+
+/// Bytes of each entry of the layer index.
+const LAYER_ENTRY_SIZE: usize = 25;
+
+/// Longest metadata string read, far more than a zone with its players takes.
+const MAX_STRING_LENGTH: usize = 4096;
+
+// Uses an internal memory map which is generated by the given file.
+pub struct FcmMap {
+    size: Vec3D,
+
+    internal_map: MemoryMap,
+    zones: Vec<Zone>,
+}
+
+impl FcmMap {
+    /// Reads a map from an .fcm file, logging why it could not be read.
+    pub fn load(path: &Path) -> Option<FcmMap> {
+        match fs::read(path).and_then(|data| read_fcm(&data)) {
+            Ok((internal_map, zones)) => Some(FcmMap {
+                size: *internal_map.get_size(),
+                internal_map,
+                zones,
+            }),
+            Err(e) => {
+                Core::static_log(&format!(
+                    "Unable to read fCraft map \"{}\": {}",
+                    path.display(),
+                    e
+                ));
+
+                None
+            }
+        }
+    }
+
+    /// Zones kept in the metadata of the file.
+    pub fn get_zones(&self) -> &[Zone] {
+        &self.zones
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    read_array(reader).map(u16::from_le_bytes)
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    read_array(reader).map(i32::from_le_bytes)
+}
+
+// Strings are prefixed by their length in two bytes.
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = read_u16(reader)? as usize;
+
+    if length > MAX_STRING_LENGTH {
+        return Err(invalid("Metadata is too long."));
+    }
+
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn skip(data: &mut &[u8], count: usize) -> io::Result<()> {
+    *data = data
+        .get(count..)
+        .ok_or_else(|| invalid("Unexpected end of file."))?;
+
+    Ok(())
+}
+
+// fCraft's Z axis is the height, its Y axis being our Z axis.
+fn read_fcm(mut data: &[u8]) -> io::Result<(MemoryMap, Vec<Zone>)> {
+    let reader = &mut data;
+
+    if read_i32(reader)? as u32 != IDENTIFIER || read_array::<1>(reader)?[0] != REVISION {
+        return Err(invalid("Not an FCMv3 map."));
+    }
+
+    let width = read_u16(reader)?;
+    let height = read_u16(reader)?;
+    let length = read_u16(reader)?;
+
+    let size = Vec3D::new(width, height, length);
+    check_map_volume(size).map_err(|e| invalid(&e))?;
+
+    // Spawn is in fixed point, where the eyes of players are.
+    let mut spawn_axis =
+        || -> io::Result<u16> { Ok(read_i32(reader)?.clamp(0, u16::MAX as i32) as u16) };
+
+    let (spawn_x, spawn_z, spawn_y) = (spawn_axis()?, spawn_axis()?, spawn_axis()?);
+    let [yaw, pitch] = read_array(reader)?;
+
+    // Modification and creation times, GUID, then the layer index.
+    skip(reader, 4 + 4 + 16)?;
+
+    let layers = read_array::<1>(reader)?[0] as usize;
+    skip(reader, layers * LAYER_ENTRY_SIZE)?;
+
+    let entries = read_i32(reader)?;
+
+    // Compressed data is usually raw deflate, but zlib streams are read as well.
+    let is_zlib = data.len() >= 2
+        && data[0] & 0x0f == 8
+        && u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31);
+
+    let mut stream: Box<dyn Read> = if is_zlib {
+        Box::new(ZlibDecoder::new(data))
+    } else {
+        Box::new(DeflateDecoder::new(data))
+    };
+
+    let mut zones = Vec::new();
+
+    for _ in 0..entries {
+        let group = read_string(&mut stream)?;
+        let key = read_string(&mut stream)?;
+        let value = read_string(&mut stream)?;
+
+        if !group.eq_ignore_ascii_case("zones") || zones.len() == MAX_ZONES {
+            continue;
+        }
+
+        match parse_zone(&value) {
+            Some(zone) => zones.push(zone),
+            None => {
+                Core::static_log(&format!("Unable to import fCraft zone \"{}\".", key));
+            }
+        }
+    }
+
+    let mut blocks = vec![0; width as usize * height as usize * length as usize];
+    stream.read_exact(&mut blocks)?;
+
+    // Blocks are ordered the same way as in memory maps.
+    let mut map = MemoryMap::empty(size);
+    map.set_data_chunks(blocks);
+    map.set_spawn(Transform::new(
+        Vec3D::new(spawn_x, spawn_y, spawn_z),
+        yaw,
+        pitch,
+    ));
+
+    Ok((map, zones))
+}
+
+// fCraft zones are the name, both corners and rank separated by spaces, followed by
+// the players allowed and the players excluded, separated by commas.
+fn parse_zone(value: &str) -> Option<Zone> {
+    let mut parts = value.split(',');
+    let header: Vec<&str> = parts.next()?.split_ascii_whitespace().collect();
+
+    if header.len() < 8 {
+        return None;
+    }
+
+    let number = |index: usize| {
+        let value = header[index].parse::<i32>().ok()?;
+
+        Some(value.clamp(0, u16::MAX as i32) as u16)
+    };
+
+    Some(Zone {
+        name: String::from(header[0]),
+        region: Region::new(
+            Vec3D::new(number(1)?, number(3)?, number(2)?),
+            Vec3D::new(number(4)?, number(6)?, number(5)?),
+        ),
+        rank: get_rank(header[7]),
+        players: parts
+            .next()
+            .map(|players| players.split_ascii_whitespace().map(String::from).collect())
+            .unwrap_or_default(),
+    })
+}
+
+// fCraft ranks are kept as their name followed by an id. Ranks without a match only let operators build.
+fn get_rank(rank: &str) -> Rank {
+    let name = rank.split('#').next().unwrap_or(rank).to_lowercase();
+
+    name.parse().unwrap_or(match name.as_str() {
+        "regular" | "advbuilder" => Rank::Builder,
+        _ => Rank::Operator,
+    })
+}
+
+impl Map for FcmMap {
+    fn get_size(&self) -> &Vec3D {
+        &self.size
+    }
+
+    fn get_chunks(&self) -> &Vec<u8> {
+        self.internal_map.get_chunks()
+    }
+
+    fn get_extended_blocks(&self) -> &BTreeMap<usize, u16> {
+        self.internal_map.get_extended_blocks()
+    }
+
+    fn get_block(&self, position: &Vec3D) -> u8 {
+        self.internal_map.get_block(position)
+    }
+
+    fn set_block(&mut self, position: &Vec3D, block: u8) {
+        self.internal_map.set_block(position, block);
+    }
+
+    fn get_extended_block(&self, position: &Vec3D) -> Option<u16> {
+        self.internal_map.get_extended_block(position)
+    }
+
+    fn restore_extended_block(&mut self, position: &Vec3D, id: u16) {
+        self.internal_map.restore_extended_block(position, id);
+    }
+
+    fn fill(&mut self, region: &Region, block: u8) -> Vec<Vec3D> {
+        self.internal_map.fill(region, block)
+    }
+
+    fn replace(&mut self, region: &Region, from: u8, to: u8) -> Vec<Vec3D> {
+        self.internal_map.replace(region, from, to)
+    }
+
+    fn count(&self, region: &Region, block: u8) -> usize {
+        self.internal_map.count(region, block)
+    }
+
+    fn read_region(&self, region: &Region) -> BlockBuffer {
+        self.internal_map.read_region(region)
+    }
+
+    fn paste(&mut self, origin: &Vec3D, buffer: &BlockBuffer) -> Vec<Vec3D> {
+        self.internal_map.paste(origin, buffer)
+    }
+
+    fn get_spawn(&self) -> Transform {
+        self.internal_map.get_spawn()
+    }
+
+    fn set_spawn(&mut self, spawn: Transform) {
+        self.internal_map.set_spawn(spawn);
+    }
+}
+
+#[cfg(test)]
+mod test_fcm {
+    use super::*;
+
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    fn string(data: &mut Vec<u8>, value: &str) {
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend_from_slice(value.as_bytes());
+    }
+
+    // Header of a map without layers besides the blocks, spawning at its corner.
+    fn header(axes: [u16; 3], entries: i32) -> Vec<u8> {
+        let mut data = IDENTIFIER.to_le_bytes().to_vec();
+        data.push(REVISION);
+
+        for axis in &axes {
+            data.extend_from_slice(&axis.to_le_bytes());
+        }
+
+        for axis in &[48i32, 16, 80] {
+            data.extend_from_slice(&axis.to_le_bytes());
+        }
+
+        data.extend_from_slice(&[64, 0]);
+        data.extend_from_slice(&[0; 24]);
+
+        // One layer for the blocks.
+        data.push(1);
+        data.extend_from_slice(&[0; LAYER_ENTRY_SIZE]);
+        data.extend_from_slice(&entries.to_le_bytes());
+
+        data
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    /// Blocks, spawn and zones of fCraft maps are read, zone ranks matched to ours.
+    pub fn zones_and_spawn() {
+        let mut data = header([4, 3, 2], 2);
+
+        let mut compressed = Vec::new();
+        string(&mut compressed, "fCraft.General");
+        string(&mut compressed, "Name");
+        string(&mut compressed, "spawn");
+        string(&mut compressed, "zones");
+        string(&mut compressed, "house");
+        string(
+            &mut compressed,
+            "house 3 1 2 0 0 0 builder#a1b2,bob alice,carl,",
+        );
+        compressed.extend((0..4 * 3 * 2).map(|i| i as u8));
+
+        data.extend(compress(&compressed));
+
+        let (map, zones) = read_fcm(&data).unwrap();
+
+        assert_eq!(*map.get_size(), Vec3D::new(4, 3, 2));
+        assert_eq!(map.get_block(&Vec3D::new(1, 2, 1)), 1 + 4 + 2 * 4 * 2);
+        assert_eq!(*map.get_spawn().get_pos(), Vec3D::new(48, 80, 16));
+        assert_eq!(map.get_spawn().get_yaw(), 64);
+
+        assert_eq!(
+            zones,
+            vec![Zone {
+                name: String::from("house"),
+                region: Region::new(Vec3D::new(0, 0, 0), Vec3D::new(3, 2, 1)),
+                rank: Rank::Builder,
+                players: vec![String::from("bob"), String::from("alice")],
+            }]
+        );
+
+        assert_eq!(get_rank("mod#c3d4"), Rank::Operator);
+        assert_eq!(get_rank("Guest#e5f6"), Rank::Guest);
+    }
+
+    #[test]
+    /// Maps too large and metadata too long are refused before anything is allocated for them.
+    pub fn oversized() {
+        let error = |data: &[u8]| read_fcm(data).err().map(|e| e.to_string());
+
+        assert_eq!(
+            error(&header([u16::MAX, u16::MAX, u16::MAX], 0)),
+            Some(String::from("Map is too large."))
+        );
+        assert_eq!(
+            error(&header([4, 0, 2], 0)),
+            Some(String::from("Map is empty."))
+        );
+
+        let mut data = header([4, 3, 2], 1);
+        let mut compressed = u16::MAX.to_le_bytes().to_vec();
+        compressed.extend(vec![b'a'; u16::MAX as usize]);
+        data.extend(compress(&compressed));
+
+        assert_eq!(error(&data), Some(String::from("Metadata is too long.")));
+    }
+}
@@ -19,32 +19,142 @@
     OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
     SOFTWARE.
 */
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
 use std::io::prelude::*;
 use std::path::Path;
 
-use super::super::{util::BufferReader, Core, Map, Vec3D};
-use super::MemoryMap;
+use super::super::{
+    block, check_map_volume, util::BufferReader, BlockBuffer, Core, Map, Region, Transform, Vec3D,
+    MAX_MAP_VOLUME, PHYSICS_FULL, PHYSICS_NORMAL, PHYSICS_OFF,
+};
+use super::{get_block_position, MemoryMap};
 
 use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// MCGalaxy marks blocks past 255 with one of these, keeping the rest of their id in sections after the map.
+const EXTENDED_TAGS: [u8; 3] = [163, 198, 199];
+const EXTENDED_SECTIONS: u8 = 0xbd;
+
+/// MCGalaxy may keep the state of its physics after the sections, the last thing in a level.
+const PHYSICS_STATES: u8 = 0xfc;
+
+/// Folder MCGalaxy keeps the properties of its levels in, inside the levels folder.
+const PROPERTIES_FOLDER: &str = "level properties";
+
+/// Folder MCGalaxy keeps block definitions of its levels in, next to the levels folder.
+const BLOCK_DEFINITIONS_FOLDER: &str = "blockdefs";
+
+/// Sections are cubes of 16 blocks.
+const SECTION_SIZE: usize = 16;
+
+const SECTION_VOLUME: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+
+/// Most bytes read from a level: the blocks, their sections and what MCGalaxy keeps after them.
+const MAX_LEVEL_SIZE: usize = 3 * MAX_MAP_VOLUME;
+
+fn get_section_count(size: &Vec3D) -> usize {
+    let sections = |axis: u16| (axis as usize).div_ceil(SECTION_SIZE);
+
+    sections(size.0) * sections(size.1) * sections(size.2)
+}
+
+// Section of a block and its index inside the section, sections going by X, then Z, then Y.
+fn get_section_index(size: &Vec3D, position: &Vec3D) -> (usize, usize) {
+    let sections = |axis: u16| (axis as usize).div_ceil(SECTION_SIZE);
+    let Vec3D(x, y, z) = *position;
+    let (x, y, z) = (x as usize, y as usize, z as usize);
+
+    let section = (y / SECTION_SIZE * sections(size.2) + z / SECTION_SIZE) * sections(size.0)
+        + x / SECTION_SIZE;
+    let inside = (y % SECTION_SIZE) << 8 | (z % SECTION_SIZE) << 4 | (x % SECTION_SIZE);
+
+    (section, inside)
+}
+
+/// Fallbacks of the blocks MCGalaxy defines for a level, by their id. They are kept as JSON
+/// next to the level, or where MCGalaxy keeps them: "blockdefs/lvl_{name}.json" next to its folder.
+pub fn load_block_definitions(level: &Path) -> HashMap<u16, u8> {
+    let mcgalaxy = || {
+        let name = level.file_stem()?.to_str()?;
+        let folder = level.parent()?.parent()?;
+
+        Some(
+            folder
+                .join(BLOCK_DEFINITIONS_FOLDER)
+                .join(format!("lvl_{}.json", name)),
+        )
+    };
+
+    let content = match fs::read_to_string(level.with_extension("blockdefs.json"))
+        .or_else(|e| fs::read_to_string(mcgalaxy().ok_or(e)?))
+    {
+        Ok(content) => content,
+        Err(_) => return HashMap::new(),
+    };
+
+    // Definitions are flat objects, only their id and fallback are needed.
+    let field = |object: &str, key: &str| {
+        let value = &object[object.find(&format!("\"{}\"", key))? + key.len() + 2..];
+        let value = value.trim_start().strip_prefix(':')?.trim_start();
+        let end = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+
+        value[..end].parse::<u16>().ok()
+    };
+
+    content
+        .split('}')
+        .filter_map(|object| {
+            let object = &object[object.rfind('{')?..];
+
+            Some((field(object, "BlockID")?, field(object, "FallBack")?))
+        })
+        .filter(|(_, fallback)| *fallback <= block::MAX_CLASSIC_BLOCK as u16)
+        .map(|(id, fallback)| (id, fallback as u8))
+        .collect()
+}
+
+/// Physics level of a map set in its MCGalaxy properties, if any. They are kept next to the map,
+/// or where MCGalaxy keeps them: "level properties/{name}.properties" inside the maps folder.
+pub fn load_physics_level(maps_folder: &Path, name: &str) -> Option<u8> {
+    let file = format!("{}.properties", name);
+    let content = fs::read_to_string(maps_folder.join(&file))
+        .or_else(|_| fs::read_to_string(maps_folder.join(PROPERTIES_FOLDER).join(&file)))
+        .ok()?;
+
+    let (_, value) = content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("physics"))?;
+
+    // MCGalaxy goes from off to normal, advanced, hardcore, instant, then doors only.
+    match value.trim().parse::<u8>().ok()? {
+        1 => Some(PHYSICS_NORMAL),
+        2..=4 => Some(PHYSICS_FULL),
+        _ => Some(PHYSICS_OFF),
+    }
+}
 
 // Uses an internal memory map which is generated by the given file.
 pub struct MCSharpMap {
     size: Vec3D,
 
-    spawn_yaw: u8,
-    spawn_pitch: u8,
-    spawn_point: Vec3D,
-
     internal_map: MemoryMap,
+    physics_states: Vec<u8>,
 }
 
 impl MCSharpMap {
-    pub fn try_new(file: &str) -> Option<MCSharpMap> {
-        let file_name = format!("maps/{}.lvl", file);
+    /// Reads a map from a .lvl file, in the maps folder or anywhere else such as a backup.
+    pub fn load(path: &Path) -> Option<MCSharpMap> {
+        let file_name = path.display().to_string();
+        let file = &file_name;
 
         // Check if file exists.
-        if !Path::new(&file_name).exists() {
+        if !path.exists() {
             Core::static_log(&format!(
                 "Path does not exist for loading the map \"{}\".",
                 &file
@@ -53,9 +163,9 @@ impl MCSharpMap {
             return None;
         }
 
-        let f = File::open(&file_name);
+        let f = File::open(path);
         // Unable to read file.
-        if !f.is_ok() {
+        if f.is_err() {
             Core::static_log(&format!("Unable to read map file \"{}\".", &file_name));
 
             return None;
@@ -67,17 +177,27 @@ impl MCSharpMap {
         // Read the file.
         let f_result = f.read_to_end(&mut f_buffer);
 
-        if !f_result.is_ok() {
+        if f_result.is_err() {
             Core::static_log(&format!("Failed to read the whole map file \"{}\".", &file));
 
             return None;
         }
 
-        // Wrap the data inside a GzDecoder.
-        let mut gz_stream = GzDecoder::new(&f_buffer[..]);
+        // Wrap the data inside a GzDecoder, reading no more than a level may hold.
+        let mut gz_stream = GzDecoder::new(&f_buffer[..]).take(MAX_LEVEL_SIZE as u64 + 1);
         let mut data = Vec::new();
 
-        gz_stream.read_to_end(&mut data).unwrap();
+        if let Err(e) = gz_stream.read_to_end(&mut data) {
+            Core::static_log(&format!("Unable to decompress map \"{}\": {}", &file, e));
+
+            return None;
+        }
+
+        if data.len() > MAX_LEVEL_SIZE {
+            Core::static_log(&format!("Map \"{}\" is too large.", &file));
+
+            return None;
+        }
 
         let mut reader = BufferReader::new(&data);
 
@@ -96,58 +216,230 @@ impl MCSharpMap {
         }
 
         // Map has to be read in Int16, but our app works with UInt16.
-        let size = Vec3D::new(
-            reader.read_short_le() as u16,
-            reader.read_short_le() as u16,
-            reader.read_short_le() as u16,
-        );
-        let Vec3D(w, d, h) = size;
+        // Files store the width, length and then height, Y being the height for us.
+        let width = reader.read_short_le() as u16;
+        let length = reader.read_short_le() as u16;
+        let height = reader.read_short_le() as u16;
 
-        let mut internal_map = MemoryMap::new(size);
+        let size = Vec3D::new(width, height, length);
 
-        let spawn_x = reader.read_short_le() * 32;
-        let spawn_y = reader.read_short_le() * 32;
-        let spawn_z = reader.read_short_le() * 32;
+        if let Err(e) = check_map_volume(size) {
+            Core::static_log(&format!("Unable to load map \"{}\": {}", &file, e));
+
+            return None;
+        }
+
+        let mut internal_map = MemoryMap::new(size);
 
-        // Next 3 integers are spawn area.
-        //let spawn_point = Vec3D::new(reader.read_short_le() * 32, reader.read_short_le() * 32, reader.read_short_le() * 32);
-        let spawn_point = Vec3D(spawn_x as u16, spawn_y as u16, spawn_z as u16);
+        // Spawn is kept as the block the feet are in, negative values being outside of the map.
+        let mut spawn_axis = || reader.read_short_le().max(0) as u16;
+        let (spawn_x, spawn_z, spawn_y) = (spawn_axis(), spawn_axis(), spawn_axis());
 
         // And two bytes for yaw and pitch.
         let yaw = reader.read_byte();
         let pitch = reader.read_byte();
 
+        internal_map.set_spawn(Transform::at_block(
+            Vec3D::new(spawn_x, spawn_y, spawn_z),
+            yaw,
+            pitch,
+        ));
+
         // Visit permission, and build permission (Not needed).
         reader.read_byte();
         reader.read_byte();
 
         /* DATA CHUNK */
-        let data = reader.read_to_end();
+        let start = reader.get_index();
+        let volume = width as usize * height as usize * length as usize;
 
-        internal_map.set_data_chunks(data);
+        let blocks = match data.get(start..start + volume) {
+            Some(blocks) => blocks.to_vec(),
+            None => {
+                Core::static_log(&format!("Map \"{}\" is missing blocks.", &file));
 
-        let returning_map = MCSharpMap {
-            size,
+                return None;
+            }
+        };
+
+        // MCGalaxy sections keeping the rest of the ids of blocks past 255.
+        let mut extended = Vec::new();
+        let mut offset = start + volume;
+
+        if data.get(offset) == Some(&EXTENDED_SECTIONS) {
+            let mut stored = HashMap::new();
 
-            spawn_point,
+            offset += 1;
 
-            spawn_yaw: yaw,
-            spawn_pitch: pitch,
+            // Each section is flagged by whether it is stored.
+            for section in 0..get_section_count(&size) {
+                if data.get(offset) == Some(&1) {
+                    match data.get(offset + 1..offset + 1 + SECTION_VOLUME) {
+                        Some(ids) => stored.insert(section, ids),
+                        None => break,
+                    };
+
+                    offset += SECTION_VOLUME;
+                }
+
+                offset += 1;
+            }
+
+            for (index, raw) in blocks.iter().enumerate() {
+                let tag = match EXTENDED_TAGS.iter().position(|tag| tag == raw) {
+                    Some(tag) => tag,
+                    None => continue,
+                };
+
+                let (section, inside) = get_section_index(&size, &get_block_position(&size, index));
+
+                if let Some(ids) = stored.get(&section) {
+                    extended.push((index, 256 + ((tag << 8) | ids[inside] as usize) as u16));
+                }
+            }
+        }
+
+        // Physics states are written back as they were, our physics does not use them.
+        let physics_states = match data.get(offset) {
+            Some(&PHYSICS_STATES) => data[offset + 1..].to_vec(),
+            _ => Vec::new(),
+        };
+
+        internal_map.set_data_chunks(blocks);
+
+        let definitions = load_block_definitions(path);
+
+        for (index, id) in extended {
+            let fallback = definitions
+                .get(&(id - 256))
+                .copied()
+                .unwrap_or_else(|| block::get_fallback(id));
+
+            internal_map.set_extended_block(index, id, fallback);
+        }
 
+        let returning_map = MCSharpMap {
+            size,
             internal_map,
+            physics_states,
         };
 
         Some(returning_map)
     }
 }
 
+impl MCSharpMap {
+    /// Writes a map into {file}.lvl inside the maps folder, replacing the file only once it is fully written.
+    pub fn save(maps_folder: &Path, file: &str, map: &dyn Map) -> bool {
+        let file_name = maps_folder.join(format!("{}.lvl", file));
+        let temp_name = maps_folder.join(format!("{}.lvl.tmp", file));
+
+        let Vec3D(width, height, length) = *map.get_size();
+        let spawn = map.get_spawn();
+        let feet = spawn.get_feet_block();
+
+        let mut header = Vec::with_capacity(18);
+
+        for value in &[
+            0x752,
+            width,
+            length,
+            height,
+            feet.get_x(),
+            feet.get_z(),
+            feet.get_y(),
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // Spawn rotation, then the visit and build permissions.
+        header.extend_from_slice(&[spawn.get_yaw(), spawn.get_pitch(), 0, 0]);
+
+        // Extended blocks are written back in place of their fallback.
+        let size = *map.get_size();
+        let mut blocks = map.get_chunks().clone();
+        let mut sections: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+
+        for (index, id) in map.get_extended_blocks() {
+            let raw = match id.checked_sub(256) {
+                Some(raw) => raw as usize,
+                None => {
+                    blocks[*index] = *id as u8;
+                    continue;
+                }
+            };
+
+            if let Some(tag) = EXTENDED_TAGS.get(raw >> 8) {
+                let (section, inside) =
+                    get_section_index(&size, &get_block_position(&size, *index));
+
+                blocks[*index] = *tag;
+                sections
+                    .entry(section)
+                    .or_insert_with(|| vec![0; SECTION_VOLUME])[inside] = raw as u8;
+            }
+        }
+
+        let physics_states = map.get_physics_states();
+
+        // MCGalaxy reads physics states only after the sections.
+        if !sections.is_empty() || !physics_states.is_empty() {
+            blocks.push(EXTENDED_SECTIONS);
+
+            for section in 0..get_section_count(&size) {
+                match sections.get(&section) {
+                    Some(ids) => {
+                        blocks.push(1);
+                        blocks.extend_from_slice(ids);
+                    }
+                    None => blocks.push(0),
+                }
+            }
+        }
+
+        if !physics_states.is_empty() {
+            blocks.push(PHYSICS_STATES);
+            blocks.extend_from_slice(physics_states);
+        }
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+
+        let written = gz
+            .write_all(&header)
+            .and_then(|_| gz.write_all(&blocks))
+            .and_then(|_| gz.finish())
+            .and_then(|data| {
+                fs::create_dir_all(maps_folder)?;
+                fs::write(&temp_name, data)?;
+                fs::rename(&temp_name, &file_name)
+            });
+
+        match written {
+            Ok(()) => true,
+            Err(e) => {
+                Core::static_log(&format!("Unable to save map \"{}\": {}", file, e));
+
+                false
+            }
+        }
+    }
+}
+
 impl Map for MCSharpMap {
     fn get_size(&self) -> &Vec3D {
         &self.size
     }
 
     fn get_chunks(&self) -> &Vec<u8> {
-        &self.internal_map.get_chunks()
+        self.internal_map.get_chunks()
+    }
+
+    fn get_extended_blocks(&self) -> &BTreeMap<usize, u16> {
+        self.internal_map.get_extended_blocks()
+    }
+
+    fn get_physics_states(&self) -> &[u8] {
+        &self.physics_states
     }
 
     fn get_block(&self, position: &Vec3D) -> u8 {
@@ -158,15 +450,183 @@ impl Map for MCSharpMap {
         self.internal_map.set_block(position, block);
     }
 
-    fn get_spawnarea(&self) -> Vec3D {
-        self.spawn_point
+    fn get_extended_block(&self, position: &Vec3D) -> Option<u16> {
+        self.internal_map.get_extended_block(position)
+    }
+
+    fn restore_extended_block(&mut self, position: &Vec3D, id: u16) {
+        self.internal_map.restore_extended_block(position, id);
+    }
+
+    fn fill(&mut self, region: &Region, block: u8) -> Vec<Vec3D> {
+        self.internal_map.fill(region, block)
+    }
+
+    fn replace(&mut self, region: &Region, from: u8, to: u8) -> Vec<Vec3D> {
+        self.internal_map.replace(region, from, to)
+    }
+
+    fn count(&self, region: &Region, block: u8) -> usize {
+        self.internal_map.count(region, block)
+    }
+
+    fn read_region(&self, region: &Region) -> BlockBuffer {
+        self.internal_map.read_region(region)
+    }
+
+    fn paste(&mut self, origin: &Vec3D, buffer: &BlockBuffer) -> Vec<Vec3D> {
+        self.internal_map.paste(origin, buffer)
+    }
+
+    fn get_spawn(&self) -> Transform {
+        self.internal_map.get_spawn()
+    }
+
+    fn set_spawn(&mut self, spawn: Transform) {
+        self.internal_map.set_spawn(spawn);
+    }
+}
+
+#[cfg(test)]
+mod test_mcsharp {
+    use super::*;
+    use std::env;
+
+    #[test]
+    /// Blocks past the classic ones survive saving and loading, shown as their fallback.
+    pub fn extended_blocks() {
+        let name = "test_mcgalaxy_blocks";
+        let size = Vec3D::new(20, 4, 18);
+        let mut map = MemoryMap::empty(size);
+
+        // A door block of MCGalaxy itself, then custom blocks kept in the sections.
+        let mut blocks = vec![block::AIR; 20 * 4 * 18];
+        blocks[1] = 111;
+        map.set_data_chunks(blocks);
+
+        let far = map.get_data_index(&Vec3D::new(19, 3, 17));
+        map.set_extended_block(2, 256 + 70, block::STONE);
+        map.set_extended_block(far, 256 + 300, block::STONE);
+
+        let folder = env::temp_dir().join(format!("rcclassic-mcsharp-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+
+        assert!(MCSharpMap::save(&folder, name, &map));
+
+        fs::write(
+            folder.join(format!("{}.blockdefs.json", name)),
+            "[null, {\"BlockID\": 70, \"Name\": \"Red Glass\", \"FallBack\": 20}]",
+        )
+        .unwrap();
+        fs::write(folder.join(format!("{}.properties", name)), "Physics = 3\n").unwrap();
+
+        let loaded = MCSharpMap::load(&folder.join(format!("{}.lvl", name))).unwrap();
+        let physics = load_physics_level(&folder, name);
+
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(loaded.get_extended_blocks(), map.get_extended_blocks());
+        assert_eq!(loaded.get_chunks()[2], block::GLASS);
+        assert_eq!(loaded.get_chunks()[far], block::STONE);
+        assert_eq!(physics, Some(PHYSICS_FULL));
+
+        // Building over them forgets them.
+        map.fill(
+            &Region::new(Vec3D::new(0, 0, 0), Vec3D::new(1, 0, 0)),
+            block::STONE,
+        );
+        assert_eq!(
+            map.get_extended_blocks().keys().collect::<Vec<_>>(),
+            vec![&2, &far]
+        );
     }
 
-    fn get_spawnpitch(&self) -> u8 {
-        self.spawn_pitch
+    #[test]
+    /// Levels exported from MCGalaxy pick up the files kept in its folders, and keep their physics states.
+    pub fn mcgalaxy_folders() {
+        let name = "test_mcgalaxy_folders";
+        let folder = env::temp_dir().join(format!("rcclassic-mcgalaxy-{}", std::process::id()));
+        let levels = folder.join("levels");
+
+        let mut map = MemoryMap::empty(Vec3D::new(16, 16, 16));
+        map.set_extended_block(5, 256 + 70, block::STONE);
+        assert!(MCSharpMap::save(&levels, name, &map));
+
+        // Physics states follow the sections, up to the end of the level.
+        let path = levels.join(format!("{}.lvl", name));
+        let mut data = Vec::new();
+        GzDecoder::new(&fs::read(&path).unwrap()[..])
+            .read_to_end(&mut data)
+            .unwrap();
+        data.extend_from_slice(&[PHYSICS_STATES, 1, 0, 0, 0, 5, 0, 0, 0, 9, 0, 0, 0]);
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&data).unwrap();
+        fs::write(&path, gz.finish().unwrap()).unwrap();
+
+        fs::create_dir_all(folder.join(BLOCK_DEFINITIONS_FOLDER)).unwrap();
+        fs::write(
+            folder
+                .join(BLOCK_DEFINITIONS_FOLDER)
+                .join(format!("lvl_{}.json", name)),
+            "[{\"BlockID\": 70, \"FallBack\": 20}]",
+        )
+        .unwrap();
+        fs::create_dir_all(levels.join(PROPERTIES_FOLDER)).unwrap();
+        fs::write(
+            levels
+                .join(PROPERTIES_FOLDER)
+                .join(format!("{}.properties", name)),
+            "Physics = 1\n",
+        )
+        .unwrap();
+
+        let loaded = MCSharpMap::load(&path).unwrap();
+        assert_eq!(loaded.get_chunks()[5], block::GLASS);
+        assert_eq!(load_physics_level(&levels, name), Some(PHYSICS_NORMAL));
+
+        assert!(MCSharpMap::save(&levels, name, &loaded));
+        let saved = MCSharpMap::load(&path).unwrap();
+
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(saved.get_physics_states(), &data[data.len() - 12..]);
+        assert_eq!(saved.get_extended_blocks(), map.get_extended_blocks());
     }
 
-    fn get_spawnyaw(&self) -> u8 {
-        self.spawn_yaw
+    #[test]
+    /// Levels which are not gzipped, too large or missing blocks are refused instead of read.
+    pub fn invalid_levels() {
+        let folder = env::temp_dir().join(format!("rcclassic-lvl-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+
+        let load = |contents: &[u8]| {
+            let path = folder.join("test_invalid.lvl");
+            fs::write(&path, contents).unwrap();
+
+            MCSharpMap::load(&path).is_some()
+        };
+        let gzip = |axes: [u16; 3], blocks: usize| {
+            let mut data = 0x752u16.to_le_bytes().to_vec();
+
+            for value in &[axes[0], axes[1], axes[2], 0, 0, 0] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+
+            data.extend_from_slice(&[0; 4]);
+            data.extend(vec![block::STONE; blocks]);
+
+            let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+            gz.write_all(&data).unwrap();
+            gz.finish().unwrap()
+        };
+
+        assert!(load(&gzip([4, 4, 4], 64)));
+        assert!(!load(b"not a level"));
+        assert!(!load(&gzip([4, 4, 4], 63)));
+        assert!(!load(&gzip([32767, 32767, 32767], 64)));
+        assert!(!load(&gzip([4, 0, 4], 0)));
+
+        fs::remove_dir_all(&folder).unwrap();
     }
 }
@@ -19,43 +19,117 @@
     OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
     SOFTWARE.
 */
-use super::super::{math_min, Map, Vec3D};
+use std::collections::BTreeMap;
+
+use super::super::{block, BlockBuffer, Map, Region, Transform, Vec3D};
 
 pub struct MemoryMap {
     size: Vec3D,
+    spawn: Transform,
     data: Vec<u8>,
+    extended: BTreeMap<usize, u16>,
 }
 
 impl MemoryMap {
-    pub fn new(size: Vec3D) -> MemoryMap {
+    /// A map filled with air, spawning players above its center.
+    pub fn empty(size: Vec3D) -> MemoryMap {
         let Vec3D(w, d, h) = size;
 
-        let map = vec![0x0; w as usize * d as usize * h as usize];
+        MemoryMap {
+            data: vec![0x0; w as usize * d as usize * h as usize],
+            spawn: Transform::at_block(Vec3D::new(w / 2, d, h / 2), 0, 0),
+            extended: BTreeMap::new(),
+            size,
+        }
+    }
+
+    pub fn new(size: Vec3D) -> MemoryMap {
+        let Vec3D(w, d, h) = size;
 
         // Testing a map with a layer of grass.
-        // Note that currently using set_block only is very costly for CPU.
-        // A function is needed to change or set multiple blocks quickly and efficiently.
-        let mut returning_map = MemoryMap { data: map, size };
+        let mut returning_map = MemoryMap::empty(size);
 
         // Flat grass only 1 depth.
-        for x in 0..w {
-            for y in 0..h {
-                returning_map.set_block(&Vec3D::new(x, d / 2 - 1, y), 2);
-            }
+        if w > 0 && d > 1 && h > 0 {
+            let layer = Region::new(
+                Vec3D::new(0, d / 2 - 1, 0),
+                Vec3D::new(w - 1, d / 2 - 1, h - 1),
+            );
+
+            returning_map.fill(&layer, 2);
+            returning_map.spawn = Transform::at_block(Vec3D::new(w / 2, d / 2, h / 2), 0, 0);
         }
 
         returning_map
     }
 
-    // Internally used by other map formats.
+    /// Copy of a map in another size, cut or padded with air. The spawn is kept if it still fits.
+    pub fn resized(map: &dyn Map, size: Vec3D) -> MemoryMap {
+        let mut resized = MemoryMap::empty(size);
+        let Vec3D(x, y, z) = *map.get_size();
+        let origin = Vec3D::new(0, 0, 0);
+
+        if let Some(region) = Region::with_size(
+            origin,
+            Vec3D::new(x.min(size.0), y.min(size.1), z.min(size.2)),
+        ) {
+            resized.paste(&origin, &map.read_region(&region));
+        }
+
+        for (index, id) in map.get_extended_blocks() {
+            let position = get_block_position(map.get_size(), *index);
+
+            if position.0 < size.0 && position.1 < size.1 && position.2 < size.2 {
+                let index = resized.get_data_index(&position);
+                resized.extended.insert(index, *id);
+            }
+        }
+
+        let spawn = map.get_spawn();
+        let feet = spawn.get_feet_block();
+
+        if feet.0 < size.0 && feet.1 < size.1 && feet.2 < size.2 {
+            resized.spawn = spawn;
+        }
+
+        resized
+    }
+
+    // Internally used by other map formats. Blocks past the classic ones are kept as extended blocks.
     pub fn set_data_chunks(&mut self, data: Vec<u8>) {
-        for i in 0..data.len() - 1 {
-            self.data[i] = math_min(data[i], 50);
+        self.extended.clear();
+
+        for (index, (block, value)) in self.data.iter_mut().zip(data.iter()).enumerate() {
+            if *value > block::MAX_CLASSIC_BLOCK {
+                self.extended.insert(index, *value as u16);
+            }
+
+            *block = block::get_fallback(*value as u16);
         }
     }
 
+    /// Keeps a block past the classic ones at an index, clients seeing the fallback instead.
+    pub fn set_extended_block(&mut self, index: usize, id: u16, fallback: u8) {
+        if let Some(block) = self.data.get_mut(index) {
+            *block = fallback;
+            self.extended.insert(index, id);
+        }
+    }
+
+    // Extended blocks are forgotten once anything is built over them.
+    fn clear_extended(&mut self, region: &Region) {
+        if self.extended.is_empty() {
+            return;
+        }
+
+        let size = self.size;
+
+        self.extended
+            .retain(|index, _| !region.contains(&get_block_position(&size, *index)));
+    }
+
     pub fn get_data_index(&self, position: &Vec3D) -> usize {
-        let Vec3D(width, depth, height) = self.get_size();
+        let Vec3D(width, _, height) = self.get_size();
 
         // To prevent number overflows, we convert each number:
         let width = *width as usize;
@@ -67,6 +141,46 @@ impl MemoryMap {
 
         pos_x + (pos_z * width) + (pos_y * width * height)
     }
+
+    // Calls a function with every row of the region inside the map, along with the position starting it.
+    fn for_each_row<F: FnMut(Vec3D, &mut [u8])>(&mut self, region: &Region, mut f: F) {
+        let region = match region.clamp(&self.size) {
+            Some(region) => region,
+            None => return,
+        };
+
+        let (min, max) = (*region.get_min(), *region.get_max());
+        let length = (max.0 - min.0) as usize + 1;
+
+        for y in min.1..=max.1 {
+            for z in min.2..=max.2 {
+                let start = Vec3D::new(min.0, y, z);
+                let index = self.get_data_index(&start);
+
+                f(start, &mut self.data[index..index + length]);
+            }
+        }
+    }
+}
+
+/// Position of a block by its index into the chunks of a map.
+pub fn get_block_position(size: &Vec3D, index: usize) -> Vec3D {
+    let (width, length) = (size.0 as usize, size.2 as usize);
+
+    Vec3D::new(
+        (index % width) as u16,
+        (index / (width * length)) as u16,
+        (index / width % length) as u16,
+    )
+}
+
+// Positions of a row which are about to change, before the row is written.
+fn changed_in_row<F: Fn(usize, u8) -> bool>(start: Vec3D, row: &[u8], changes: F) -> Vec<Vec3D> {
+    row.iter()
+        .enumerate()
+        .filter(|(i, block)| changes(*i, **block))
+        .map(|(i, _)| Vec3D::new(start.0 + i as u16, start.1, start.2))
+        .collect()
 }
 
 impl Map for MemoryMap {
@@ -78,6 +192,18 @@ impl Map for MemoryMap {
         &self.data
     }
 
+    fn get_extended_blocks(&self) -> &BTreeMap<usize, u16> {
+        &self.extended
+    }
+
+    fn get_spawn(&self) -> Transform {
+        self.spawn.clone()
+    }
+
+    fn set_spawn(&mut self, spawn: Transform) {
+        self.spawn = spawn;
+    }
+
     fn get_block(&self, position: &Vec3D) -> u8 {
         self.data[self.get_data_index(position)]
     }
@@ -86,5 +212,208 @@ impl Map for MemoryMap {
         let index = self.get_data_index(position);
 
         self.data[index] = block;
+        self.extended.remove(&index);
+    }
+
+    fn get_extended_block(&self, position: &Vec3D) -> Option<u16> {
+        self.extended.get(&self.get_data_index(position)).copied()
+    }
+
+    fn restore_extended_block(&mut self, position: &Vec3D, id: u16) {
+        let index = self.get_data_index(position);
+
+        self.set_extended_block(index, id, block::get_fallback(id));
+    }
+
+    fn fill(&mut self, region: &Region, block: u8) -> Vec<Vec3D> {
+        let mut changed = Vec::new();
+
+        self.for_each_row(region, |start, row| {
+            changed.extend(changed_in_row(start, row, |_, old| old != block));
+
+            row.fill(block);
+        });
+
+        self.clear_extended(region);
+
+        changed
+    }
+
+    fn replace(&mut self, region: &Region, from: u8, to: u8) -> Vec<Vec3D> {
+        let mut changed = Vec::new();
+
+        if from == to {
+            return changed;
+        }
+
+        self.for_each_row(region, |start, row| {
+            changed.extend(changed_in_row(start, row, |_, old| old == from));
+
+            for block in row.iter_mut().filter(|block| **block == from) {
+                *block = to;
+            }
+        });
+
+        for position in &changed {
+            let index = self.get_data_index(position);
+            self.extended.remove(&index);
+        }
+
+        changed
+    }
+
+    fn count(&self, region: &Region, block: u8) -> usize {
+        let region = match region.clamp(&self.size) {
+            Some(region) => region,
+            None => return 0,
+        };
+
+        let (min, max) = (*region.get_min(), *region.get_max());
+        let length = (max.0 - min.0) as usize + 1;
+        let mut count = 0;
+
+        for y in min.1..=max.1 {
+            for z in min.2..=max.2 {
+                let index = self.get_data_index(&Vec3D::new(min.0, y, z));
+
+                count += self.data[index..index + length]
+                    .iter()
+                    .filter(|b| **b == block)
+                    .count();
+            }
+        }
+
+        count
+    }
+
+    fn read_region(&self, region: &Region) -> BlockBuffer {
+        let min = *region.get_min();
+        let Vec3D(x, y, z) = region.get_size();
+        let mut buffer = BlockBuffer::new(Vec3D::new(x as u16, y as u16, z as u16), 0);
+
+        if let Some(inside) = region.clamp(&self.size) {
+            let (from, to) = (*inside.get_min(), *inside.get_max());
+            let length = (to.0 - from.0) as usize + 1;
+
+            for y in from.1..=to.1 {
+                for z in from.2..=to.2 {
+                    let source = self.get_data_index(&Vec3D::new(from.0, y, z));
+                    let target =
+                        buffer.get_index(&Vec3D::new(from.0 - min.0, y - min.1, z - min.2));
+
+                    buffer.get_data_mut()[target..target + length]
+                        .copy_from_slice(&self.data[source..source + length]);
+                }
+            }
+        }
+
+        buffer
+    }
+
+    fn paste(&mut self, origin: &Vec3D, buffer: &BlockBuffer) -> Vec<Vec3D> {
+        let region = match Region::with_size(*origin, *buffer.get_size()) {
+            Some(region) => region,
+            None => return Vec::new(),
+        };
+
+        let mut changed = Vec::new();
+
+        self.for_each_row(&region, |start, row| {
+            let relative = Vec3D::new(0, start.1 - origin.1, start.2 - origin.2);
+            let index = buffer.get_index(&relative);
+            let source = &buffer.get_data()[index..index + row.len()];
+
+            changed.extend(changed_in_row(start, row, |i, old| old != source[i]));
+
+            row.copy_from_slice(source);
+        });
+
+        self.clear_extended(&region);
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test_memorymap {
+    use super::*;
+
+    // Only implements single block access, so the trait's block by block functions are used.
+    struct BlockByBlock(MemoryMap);
+
+    impl Map for BlockByBlock {
+        fn get_size(&self) -> &Vec3D {
+            self.0.get_size()
+        }
+
+        fn get_chunks(&self) -> &Vec<u8> {
+            self.0.get_chunks()
+        }
+
+        fn get_block(&self, position: &Vec3D) -> u8 {
+            self.0.get_block(position)
+        }
+
+        fn set_block(&mut self, position: &Vec3D, block: u8) {
+            self.0.set_block(position, block);
+        }
+    }
+
+    fn apply(map: &mut dyn Map) -> Vec<Vec<Vec3D>> {
+        let mut changes = Vec::new();
+
+        changes.push(map.fill(&Region::new(Vec3D::new(2, 1, 3), Vec3D::new(9, 6, 5)), 1));
+        // Partly outside of the map.
+        changes.push(map.fill(
+            &Region::new(Vec3D::new(12, 0, 12), Vec3D::new(40, 3, 40)),
+            4,
+        ));
+        changes.push(map.replace(
+            &Region::new(Vec3D::new(0, 0, 0), Vec3D::new(5, 15, 15)),
+            1,
+            5,
+        ));
+
+        let buffer = map.read_region(&Region::new(Vec3D::new(0, 0, 0), Vec3D::new(7, 7, 7)));
+        changes.push(map.paste(&Vec3D::new(10, 8, 10), &buffer));
+
+        changes
+    }
+
+    #[test]
+    /// Row by row region functions change and report the same blocks as going block by block.
+    pub fn regions_match_single_blocks() {
+        let mut rows = MemoryMap::new(Vec3D::new(16, 16, 16));
+        let mut blocks = BlockByBlock(MemoryMap::new(Vec3D::new(16, 16, 16)));
+
+        assert_eq!(apply(&mut rows), apply(&mut blocks));
+        assert!(rows.get_chunks() == blocks.get_chunks());
+
+        // Partly outside of the map.
+        let region = Region::new(Vec3D::new(1, 0, 2), Vec3D::new(20, 9, 20));
+        for block in 0..6 {
+            assert_eq!(rows.count(&region, block), blocks.count(&region, block));
+        }
+
+        assert_eq!(rows.get_block(&Vec3D::new(2, 1, 3)), 5);
+        assert_eq!(rows.get_block(&Vec3D::new(9, 6, 5)), 1);
+        assert_eq!(rows.get_block(&Vec3D::new(15, 3, 15)), 4);
+        // Pasted grass layer, from y 7 to 15.
+        assert_eq!(rows.get_block(&Vec3D::new(13, 15, 13)), 2);
+    }
+
+    #[test]
+    /// Filling only reports blocks which were different, reading outside of the map gives air.
+    pub fn changed_positions() {
+        let mut map = MemoryMap::new(Vec3D::new(8, 8, 8));
+        let layer = Region::new(Vec3D::new(0, 3, 0), Vec3D::new(7, 4, 7));
+
+        assert_eq!(map.fill(&layer, 2).len(), 64);
+        assert!(map.fill(&layer, 2).is_empty());
+
+        let buffer = map.read_region(&Region::new(Vec3D::new(6, 4, 6), Vec3D::new(9, 4, 9)));
+
+        assert_eq!(buffer.get_block(&Vec3D::new(1, 0, 1)), 2);
+        assert_eq!(buffer.get_block(&Vec3D::new(2, 0, 2)), 0);
     }
 }
@@ -20,8 +20,55 @@
     SOFTWARE.
 */
 
+use std::path::{Path, PathBuf};
+
+use super::{get_zones_path, save_zones, Core, Map};
+
+mod classic;
+mod fcm;
 mod mcsharp;
 mod memorymap;
 
+pub use self::classic::*;
+pub use self::fcm::*;
 pub use self::mcsharp::*;
 pub use self::memorymap::*;
+
+/// Extensions of the level files a map is loaded from, in order. Maps are always saved as .lvl.
+pub const LEVEL_EXTENSIONS: [&str; 4] = ["lvl", "dat", "mine", "fcm"];
+
+/// File of a map with the given extension, inside the maps folder.
+pub fn get_map_file(maps_folder: &Path, name: &str, extension: &str) -> PathBuf {
+    maps_folder.join(format!("{}.{}", name, extension))
+}
+
+/// Loads a map from the first of its level files in the maps folder.
+pub fn load_map(maps_folder: &Path, name: &str) -> Option<Box<dyn Map + Send + Sync>> {
+    for extension in &LEVEL_EXTENSIONS {
+        let path = get_map_file(maps_folder, name, extension);
+        let path = path.as_path();
+
+        if !path.exists() {
+            continue;
+        }
+
+        return match *extension {
+            "lvl" => MCSharpMap::load(path).map(|map| Box::new(map) as Box<dyn Map + Send + Sync>),
+            "fcm" => FcmMap::load(path).map(|map| {
+                // Zones are only imported once, they are kept in the zones file from then on.
+                let zones_path = get_zones_path(maps_folder, name);
+
+                if !zones_path.exists() && !map.get_zones().is_empty() {
+                    save_zones(&zones_path, map.get_zones());
+                }
+
+                Box::new(map) as Box<dyn Map + Send + Sync>
+            }),
+            _ => ClassicMap::load(path).map(|map| Box::new(map) as Box<dyn Map + Send + Sync>),
+        };
+    }
+
+    Core::static_log(&format!("No level file found for the map \"{}\".", name));
+
+    None
+}
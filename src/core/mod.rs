@@ -20,48 +20,88 @@
     SOFTWARE.
 */
 
+mod action;
+mod backup;
+pub mod block;
+mod blockdb;
+mod command;
+mod config;
+#[allow(clippy::module_inception)]
 mod core;
+mod history;
 mod map;
+mod native;
 mod network;
+mod physics;
 mod player;
+mod plugin;
+mod rank;
+mod scheduler;
+mod script;
+mod spawn;
+mod special;
 mod util;
+mod warp;
+mod websocket;
 mod world;
+mod zone;
 
 // Maps:
 mod maps;
+// Generators:
+pub mod generators;
 // Events:
 pub mod events;
-
+// Draw commands:
+pub mod draw;
+
+pub use self::action::*;
+pub use self::backup::*;
+pub use self::blockdb::*;
+pub use self::command::*;
+pub use self::config::*;
 pub use self::core::*;
-pub use self::events::*;
+pub use self::history::*;
 pub use self::map::*;
+pub use self::native::*;
 pub use self::network::*;
+pub use self::physics::*;
 pub use self::player::*;
+pub use self::plugin::*;
+pub use self::rank::*;
+pub use self::scheduler::*;
+pub use self::script::*;
+pub use self::spawn::*;
+pub use self::special::*;
 pub use self::util::*;
+pub use self::warp::*;
 pub use self::world::*;
+pub use self::zone::*;
 
 #[cfg(test)]
 mod test_core {
-    use super::super::network::*;
-    use super::*;
+    use std::env;
 
-    use std::thread;
+    use super::*;
 
     #[test]
     /// Tests whether or not core overrides threadsize in case it is zero.
     pub fn create_default_core() {
-        Core::new(0);
+        let folder = env::temp_dir().join(format!("rcclassic-default-core-{}", std::process::id()));
+
+        Core::with_config(0, Config::in_folder(&folder));
     }
 
     #[test]
     /// Tests core's memory channels in both receiving and sending ends.
     pub fn mem_test() {
-        let mut core = Core::new(4);
+        let folder = env::temp_dir().join(format!("rcclassic-mem-{}", std::process::id()));
+        let mut core = Core::with_config(4, Config::in_folder(&folder));
 
         core.generate_mem_chans();
 
-        let sender = core.sender_take();
-        let receiver = core.receiver_take();
+        let _sender = core.sender_take();
+        let _receiver = core.receiver_take();
 
         /*let sending_object = Box::new(PlayerIdentification {});
 
@@ -0,0 +1,460 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Plugins loaded at runtime from shared libraries in the "plugins" folder.
+//!
+//! A native plugin exports `rcclassic_plugin`, an `extern "C" fn() -> *const PluginDescriptor`.
+//! Everything crossing the boundary is `#[repr(C)]`, so plugins do not have to be built with the same
+//! compiler as the server; only `PLUGIN_ABI_VERSION` has to match.
+
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+use libloading::{Library, Symbol};
+
+use super::{Action, Command, Core, PlayerSnapshot, Rank, Vec3D};
+
+/// Version of the native plugin interface, bumped on every change to the types below.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Name of the function every native plugin exports.
+pub const PLUGIN_ENTRY_SYMBOL: &[u8] = b"rcclassic_plugin\0";
+
+/// Opaque handle to the core, only valid during the call it is passed to.
+#[repr(C)]
+pub struct CoreHandle {
+    _private: [u8; 0],
+}
+
+pub type PluginEntry = extern "C" fn() -> *const PluginDescriptor;
+pub type CommandCallback =
+    extern "C" fn(state: *mut c_void, core: *const CoreHandle, uid: usize, args: *const c_char);
+pub type TaskCallback = extern "C" fn(state: *mut c_void, core: *const CoreHandle);
+
+/// Describes a native plugin, returned by its entry point.
+#[repr(C)]
+pub struct PluginDescriptor {
+    /// Has to be `PLUGIN_ABI_VERSION`, checked before anything else is read.
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub version: *const c_char,
+    /// Called once loaded, returns the state passed back to every callback of the plugin.
+    pub on_load: extern "C" fn(api: *const PluginApi, core: *const CoreHandle) -> *mut c_void,
+    /// Called before unloading, the plugin frees its state here.
+    pub on_unload: extern "C" fn(state: *mut c_void, core: *const CoreHandle),
+}
+
+// Descriptors only point to constant strings, so plugins can keep them in a static.
+unsafe impl Sync for PluginDescriptor {}
+
+/// Core services available to native plugins. Strings are nul terminated UTF-8.
+#[repr(C)]
+pub struct PluginApi {
+    pub abi_version: u32,
+    pub log: extern "C" fn(core: *const CoreHandle, message: *const c_char),
+    pub broadcast: extern "C" fn(core: *const CoreHandle, message: *const c_char),
+    pub send_message: extern "C" fn(core: *const CoreHandle, uid: usize, message: *const c_char),
+    pub get_player_count: extern "C" fn(core: *const CoreHandle) -> usize,
+    /// Copies a player's name into the buffer, returns its length or -1 if the player or space is missing.
+    pub get_player_name: extern "C" fn(
+        core: *const CoreHandle,
+        uid: usize,
+        buffer: *mut c_char,
+        size: usize,
+    ) -> isize,
+    /// Copies the name of a player's world into the buffer, same as `get_player_name`.
+    pub get_player_world: extern "C" fn(
+        core: *const CoreHandle,
+        uid: usize,
+        buffer: *mut c_char,
+        size: usize,
+    ) -> isize,
+    pub join_world: extern "C" fn(core: *const CoreHandle, uid: usize, world: *const c_char),
+    /// Returns the block at a position, or -1 if the world is not loaded.
+    pub get_block:
+        extern "C" fn(core: *const CoreHandle, world: *const c_char, x: u16, y: u16, z: u16) -> i16,
+    /// Sets a block and sends it to the players in the world.
+    pub set_block: extern "C" fn(
+        core: *const CoreHandle,
+        world: *const c_char,
+        x: u16,
+        y: u16,
+        z: u16,
+        block: u8,
+    ) -> bool,
+    /// Registers a command usable from the given rank (0 guest, 1 builder, 2 operator, 3 owner).
+    pub register_command: extern "C" fn(
+        core: *const CoreHandle,
+        name: *const c_char,
+        description: *const c_char,
+        rank: u8,
+        callback: CommandCallback,
+    ) -> bool,
+    /// Schedules a callback after a delay in ticks, repeating every interval ticks unless it is 0.
+    pub schedule: extern "C" fn(
+        core: *const CoreHandle,
+        delay: u64,
+        interval: u64,
+        callback: TaskCallback,
+    ) -> bool,
+}
+
+static PLUGIN_API: PluginApi = PluginApi {
+    abi_version: PLUGIN_ABI_VERSION,
+    log: api_log,
+    broadcast: api_broadcast,
+    send_message: api_send_message,
+    get_player_count: api_get_player_count,
+    get_player_name: api_get_player_name,
+    get_player_world: api_get_player_world,
+    join_world: api_join_world,
+    get_block: api_get_block,
+    set_block: api_set_block,
+    register_command: api_register_command,
+    schedule: api_schedule,
+};
+
+// What a CoreHandle points to: the core and the plugin being called.
+struct Context<'a> {
+    core: &'a Core,
+    owner: &'a str,
+    state: &'a Arc<PluginState>,
+}
+
+impl<'a> Context<'a> {
+    fn as_handle(&self) -> *const CoreHandle {
+        self as *const Context as *const CoreHandle
+    }
+
+    fn from_handle(handle: *const CoreHandle) -> &'a Context<'a> {
+        // Handles are only given out for the duration of a call, pointing to a live context.
+        unsafe { &*(handle as *const Context) }
+    }
+}
+
+// State returned by a plugin's on_load, filled in once it returns.
+#[derive(Default)]
+struct PluginState(AtomicPtr<c_void>);
+
+impl PluginState {
+    fn get(&self) -> *mut c_void {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+fn to_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+
+    unsafe { CStr::from_ptr(string) }.to_str().ok()
+}
+
+fn copy_to_buffer(value: &str, buffer: *mut c_char, size: usize) -> isize {
+    if buffer.is_null() || value.len() + 1 > size {
+        return -1;
+    }
+
+    unsafe {
+        ptr::copy_nonoverlapping(value.as_ptr() as *const c_char, buffer, value.len());
+        *buffer.add(value.len()) = 0;
+    }
+
+    value.len() as isize
+}
+
+extern "C" fn api_log(core: *const CoreHandle, message: *const c_char) {
+    let context = Context::from_handle(core);
+
+    if let Some(message) = to_str(message) {
+        context
+            .core
+            .log(&format!("[{}] {}", context.owner, message));
+    }
+}
+
+extern "C" fn api_broadcast(core: *const CoreHandle, message: *const c_char) {
+    if let Some(message) = to_str(message) {
+        Context::from_handle(core).core.broadcast(message);
+    }
+}
+
+extern "C" fn api_send_message(core: *const CoreHandle, uid: usize, message: *const c_char) {
+    if let Some(message) = to_str(message) {
+        Context::from_handle(core)
+            .core
+            .send_message_to(uid, message);
+    }
+}
+
+extern "C" fn api_get_player_count(core: *const CoreHandle) -> usize {
+    Context::from_handle(core).core.get_player_count()
+}
+
+extern "C" fn api_get_player_name(
+    core: *const CoreHandle,
+    uid: usize,
+    buffer: *mut c_char,
+    size: usize,
+) -> isize {
+    match Context::from_handle(core).core.get_player_snapshot(uid) {
+        Some(player) => copy_to_buffer(&player.name, buffer, size),
+        None => -1,
+    }
+}
+
+extern "C" fn api_get_player_world(
+    core: *const CoreHandle,
+    uid: usize,
+    buffer: *mut c_char,
+    size: usize,
+) -> isize {
+    match Context::from_handle(core).core.get_player_snapshot(uid) {
+        Some(player) => copy_to_buffer(&player.world, buffer, size),
+        None => -1,
+    }
+}
+
+extern "C" fn api_join_world(core: *const CoreHandle, uid: usize, world: *const c_char) {
+    if let Some(world) = to_str(world) {
+        Context::from_handle(core)
+            .core
+            .queue_action(Action::JoinWorld(uid, String::from(world)));
+    }
+}
+
+extern "C" fn api_get_block(
+    core: *const CoreHandle,
+    world: *const c_char,
+    x: u16,
+    y: u16,
+    z: u16,
+) -> i16 {
+    let core = Context::from_handle(core).core;
+
+    to_str(world)
+        .and_then(|world| core.with_world(world, |world| world.get_block(&Vec3D::new(x, y, z))))
+        .map_or(-1, |block| block as i16)
+}
+
+extern "C" fn api_set_block(
+    core: *const CoreHandle,
+    world: *const c_char,
+    x: u16,
+    y: u16,
+    z: u16,
+    block: u8,
+) -> bool {
+    match to_str(world) {
+        Some(world) => Context::from_handle(core)
+            .core
+            .set_block(world, Vec3D::new(x, y, z), block),
+        None => false,
+    }
+}
+
+extern "C" fn api_register_command(
+    core: *const CoreHandle,
+    name: *const c_char,
+    description: *const c_char,
+    rank: u8,
+    callback: CommandCallback,
+) -> bool {
+    let context = Context::from_handle(core);
+
+    let (name, rank) = match (to_str(name), Rank::from_id(rank)) {
+        (Some(name), Some(rank)) => (name, rank),
+        _ => return false,
+    };
+
+    let owner = String::from(context.owner);
+    let state = context.state.clone();
+
+    context.core.register_command(Command::new(
+        context.owner,
+        name,
+        to_str(description).unwrap_or(""),
+        rank,
+        move |core: &Core, player: &PlayerSnapshot, args: &str| {
+            let context = Context {
+                core,
+                owner: &owner,
+                state: &state,
+            };
+            let args = CString::new(args).unwrap_or_default();
+
+            callback(state.get(), context.as_handle(), player.uid, args.as_ptr());
+        },
+    ))
+}
+
+extern "C" fn api_schedule(
+    core: *const CoreHandle,
+    delay: u64,
+    interval: u64,
+    callback: TaskCallback,
+) -> bool {
+    let context = Context::from_handle(core);
+
+    let owner = String::from(context.owner);
+    let state = context.state.clone();
+
+    let task = move |core: &Core| {
+        let context = Context {
+            core,
+            owner: &owner,
+            state: &state,
+        };
+
+        callback(state.get(), context.as_handle());
+    };
+
+    let mut scheduler = context.core.get_scheduler();
+
+    if interval == 0 {
+        scheduler.schedule(context.owner, delay, Box::new(task));
+    } else {
+        scheduler.schedule_repeating(context.owner, delay, interval, Box::new(task));
+    }
+
+    true
+}
+
+/// A plugin loaded from a shared library.
+pub struct NativePlugin {
+    name: String,
+    version: String,
+    descriptor: *const PluginDescriptor,
+    state: Arc<PluginState>,
+    // Kept last, the library has to outlive everything pointing into it.
+    _library: Library,
+}
+
+// Only the core thread calls into the plugin.
+unsafe impl Send for NativePlugin {}
+
+impl NativePlugin {
+    /// Loads a plugin, checks its ABI version and calls its on_load.
+    /// `is_taken` is asked whether the plugin's name is already in use.
+    pub fn load<F>(path: &Path, core: &Core, is_taken: F) -> Result<NativePlugin, String>
+    where
+        F: Fn(&str) -> bool,
+    {
+        // Loading a library runs its initialisers, plugins are trusted like the server itself.
+        let library = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
+
+        let descriptor = {
+            let entry: Symbol<PluginEntry> =
+                unsafe { library.get(PLUGIN_ENTRY_SYMBOL) }.map_err(|e| e.to_string())?;
+
+            entry()
+        };
+
+        if descriptor.is_null() {
+            return Err(String::from("Plugin did not describe itself."));
+        }
+
+        let abi_version = unsafe { (*descriptor).abi_version };
+
+        if abi_version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "Plugin was built for ABI version {}, the server uses {}.",
+                abi_version, PLUGIN_ABI_VERSION
+            ));
+        }
+
+        let (name, version) = unsafe {
+            (
+                to_str((*descriptor).name).map(String::from),
+                to_str((*descriptor).version).unwrap_or("?").to_string(),
+            )
+        };
+
+        let name = name.ok_or_else(|| String::from("Plugin has no name."))?;
+
+        if is_taken(&name) {
+            return Err(format!("A plugin named \"{}\" is already loaded.", name));
+        }
+
+        let state = Arc::new(PluginState::default());
+        let context = Context {
+            core,
+            owner: &name,
+            state: &state,
+        };
+
+        let plugin_state = unsafe { ((*descriptor).on_load)(&PLUGIN_API, context.as_handle()) };
+        state.0.store(plugin_state, Ordering::SeqCst);
+
+        Ok(NativePlugin {
+            name,
+            version,
+            descriptor,
+            state,
+            _library: library,
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    /// Calls the plugin's on_unload, its commands and tasks have to be removed beforehand.
+    pub fn unload(self, core: &Core) {
+        let context = Context {
+            core,
+            owner: &self.name,
+            state: &self.state,
+        };
+
+        unsafe { ((*self.descriptor).on_unload)(self.state.get(), context.as_handle()) };
+    }
+}
+
+#[cfg(test)]
+mod test_native {
+    use std::env;
+    use std::fs;
+
+    use super::super::Config;
+    use super::*;
+
+    #[test]
+    /// Files which are not plugins are refused with an error instead of crashing the server.
+    pub fn refuse_invalid_library() {
+        let folder = env::temp_dir().join(format!("rcclassic-native-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+
+        assert!(NativePlugin::load(Path::new("plugins/missing.so"), &core, |_| false).is_err());
+        assert!(NativePlugin::load(Path::new("Cargo.toml"), &core, |_| false).is_err());
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
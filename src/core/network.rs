@@ -20,38 +20,75 @@
     SOFTWARE.
 */
 
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::net::{Shutdown, TcpListener, TcpStream};
-use std::sync::{mpsc::Sender, Arc};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
 use std::time::Duration;
 
-use threadpool::ThreadPool;
-
 use super::super::network::*;
-use super::events; // TODO: call disconnect event.
-use super::{BufferReader, Core, NetworkPlayer, PlayerList};
+use super::websocket::{self, FrameDecoder};
+use super::{BufferReader, BufferWriter, Core};
 
 const HOSTNAME: &str = "0.0.0.0";
 const TIMEOUT_TIME: u64 = 30; // in Seconds.
 
+/// The way a client is connected to the server.
+/// Web clients (ClassiCube in browser) wrap every packet inside WebSocket frames.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Transport {
+    Classic,
+    WebSocket,
+}
+
+/// What the core queues for the writing thread of a client.
+pub enum Outgoing {
+    Data(Vec<u8>),
+    /// Closes the connection once everything queued before has been written.
+    Close,
+}
+
+/// Serializes a packet into the bytes sent over the given transport.
+/// Positions are written as ints for clients supporting ExtEntityPositions.
+pub fn encode_packet(
+    packet: &dyn NetworkPacket,
+    transport: Transport,
+    extended_positions: bool,
+) -> Vec<u8> {
+    let mut buffer = BufferWriter::new(packet.get_size());
+    buffer.set_extended_positions(extended_positions);
+
+    buffer.write_byte(packet.get_id());
+
+    packet.handle_send(&mut buffer);
+
+    match transport {
+        Transport::Classic => buffer.get_data().clone(),
+        Transport::WebSocket => {
+            websocket::encode_frame(websocket::OPCODE_BINARY, buffer.get_data())
+        }
+    }
+}
+
 pub struct Network {
     listener: TcpListener,
-    net_workers: ThreadPool,
 }
 
 impl Network {
     /// Instantiates a Network Instance on specific port.
-    pub fn new(port: usize, threadsize: usize) -> Network {
+    pub fn new(port: usize) -> Network {
         let listener = TcpListener::bind(format!("{}:{}", HOSTNAME, port)).unwrap();
 
-        Network {
-            listener,
-            net_workers: ThreadPool::new(threadsize),
-        }
+        Network { listener }
     }
 
     /// Locks the current thread, waiting to receive connections.
-    pub fn listen(&self, players_arc: PlayerList, core_tx: Sender<Box<dyn NetworkPacket + Send>>) {
+    /// Accepted connections are handed to the core, which owns the players. Each connection reads
+    /// from a thread of its own, reads blocking until the timeout, so no client waits for another.
+    pub fn listen(&self, core_tx: Sender<Box<dyn NetworkPacket + Send>>) {
+        // Session uids are never reused while the server is running, 0 belongs to the console.
+        let mut next_uid = 0;
+
         for stream in self.listener.incoming() {
             match stream {
                 Ok(stream) => {
@@ -61,9 +98,6 @@ impl Network {
                         stream.set_write_timeout(timeout_duration)?;
                         stream.set_read_timeout(timeout_duration)?;
 
-                        // This program needs sockets to be non-blocking in order for ThreadPool to work properly.
-                        stream.set_nonblocking(true)?; // Panic in case we cannot set our stream into non-blocking mode.
-
                         let cloned = stream.try_clone()?; // Clone the stream to simultaneously send to and receive from players.
 
                         Ok(cloned)
@@ -71,110 +105,41 @@ impl Network {
 
                     match try_clone_stream() {
                         Ok(mut receiver) => {
-                            let players = players_arc.clone();
+                            next_uid += 1;
+                            let player_uid = next_uid;
 
-                            let mut found_id = 0;
-                            'search: for _ in 1..(std::i8::MAX) as usize - 1 {
-                                found_id += 1;
+                            let tx = core_tx.clone();
 
-                                if players.contains_key(&(found_id as usize)) {
-                                    continue;
-                                }
+                            thread::spawn(move || {
+                                // Web and desktop clients share the same port, find out which one is connecting.
+                                let transport = match Network::accept_transport(&mut receiver) {
+                                    Ok(transport) => transport,
+                                    Err(e) => {
+                                        Core::static_log(&format!(
+                                            "Error accepting connection of uid \"{}\": {}",
+                                            player_uid, e
+                                        ));
 
-                                break 'search;
-                            }
+                                        receiver.shutdown(Shutdown::Both).ok();
 
-                            let player_uid = found_id;
-                            let uid_copy = player_uid.clone();
+                                        return;
+                                    }
+                                };
 
-                            let tx = core_tx.clone();
+                                let writer = Network::spawn_writer(stream);
 
-                            self.net_workers.execute(move || {
-                                let mut buffer = vec![0xff; 256];
-
-                                loop {
-                                    match receiver.read(&mut buffer) {
-                                        Ok(size) => {
-                                            if size <= 0 {
-                                                // Connection has been closed.
-                                                // Inform the core of player's disconnecction so the proper action can be taken.
-                                                // TODO: Send a disconnect packet to core, with sender ID 0 (console). (Dispose NEEDED!)
-                                                Core::static_log(&format!(
-                                                    "Player with uid \"{}\" disconnected.",
-                                                    uid_copy
-                                                ));
-
-                                                receiver.shutdown(Shutdown::Both).ok();
-
-                                                tx.send(Box::new(DisconnectPlayer::new(
-                                                    uid_copy,
-                                                    String::from("Server Disconnect"),
-                                                )))
-                                                .unwrap();
-
-                                                break; // TX clone should automatically be dropped by Rust's Ownership.
-                                            }
-
-                                            let mut buffer_reader = BufferReader::new(&buffer);
-
-                                            while buffer_reader.get_index() < size {
-                                                // Read the packet's op_code.
-                                                // TODO: Check the buffer for corrupt data.
-                                                let op_code = buffer_reader.read_byte();
-
-                                                // Handle receiving packets here, and send objects to core.
-                                                // TODO: match should return a packet, send must be done outside the match.
-                                                match op_code {
-                                                    PlayerIdentification::ID => {
-                                                        let identify_packet =
-                                                            PlayerIdentification::new(
-                                                                &mut buffer_reader,
-                                                                player_uid,
-                                                            );
-
-                                                        tx.send(Box::new(identify_packet)).unwrap();
-                                                    }
-                                                    PlayerSetBlock::ID => {
-                                                        let setblock_packet = PlayerSetBlock::new(
-                                                            &mut buffer_reader,
-                                                            player_uid,
-                                                        );
-
-                                                        tx.send(Box::new(setblock_packet)).unwrap();
-                                                    }
-                                                    PlayerPositionAndOrientation::ID => {
-                                                        let packet =
-                                                            PlayerPositionAndOrientation::new(
-                                                                &mut buffer_reader,
-                                                                player_uid,
-                                                            );
-                                                        tx.send(Box::new(packet)).unwrap();
-                                                    }
-                                                    PlayerMessage::ID => {
-                                                        let packet = PlayerMessage::new(
-                                                            &mut buffer_reader,
-                                                            player_uid,
-                                                        );
-                                                        tx.send(Box::new(packet)).unwrap();
-                                                    }
-                                                    _ => {}
-                                                }
-                                                //buffer.clear(); // Clean up the buffer after receiving a packet.
-                                            }
-                                        }
-                                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
-                                        Err(e) => {
-                                            Core::static_log(&format!(
-                                                "IO error on player received: {}",
-                                                e
-                                            ));
-                                        }
-                                    }
+                                // Core registers the player before handling any of its packets, as they share the channel.
+                                let opened =
+                                    ConnectionOpened::new(player_uid, writer.clone(), transport);
+
+                                if tx.send(Box::new(opened)).is_err() {
+                                    receiver.shutdown(Shutdown::Both).ok();
+
+                                    return;
                                 }
+
+                                Network::receive(receiver, writer, transport, player_uid, tx);
                             });
-                            // TODO: Let the core edit players. Insertion should be move into core, not network.
-                            let spawned_player = NetworkPlayer::new(uid_copy, stream);
-                            players.insert(uid_copy, Box::new(spawned_player));
                         }
                         Err(e) => {
                             Core::static_log(&format!(
@@ -190,4 +155,301 @@ impl Network {
             }
         }
     }
+
+    /// Writes what is queued for a client from its own thread, so the core never waits for a slow
+    /// client and packets are never written partially. The connection is shut down once writing
+    /// fails, it is closed, or the player is gone.
+    fn spawn_writer(mut stream: TcpStream) -> Sender<Outgoing> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for outgoing in rx {
+                let written = match outgoing {
+                    Outgoing::Data(data) => stream.write_all(&data),
+                    Outgoing::Close => break,
+                };
+
+                if written.is_err() {
+                    break;
+                }
+            }
+
+            // Lets the receiving thread know, which disconnects the player.
+            stream.shutdown(Shutdown::Both).ok();
+        });
+
+        tx
+    }
+
+    /// Peeks the first byte of a new connection to detect web clients, and handles their handshake.
+    fn accept_transport(stream: &mut TcpStream) -> Result<Transport, io::Error> {
+        let mut first_byte = [0u8; 1];
+
+        // Blocks until the client has sent something, or the read timeout is reached.
+        stream.set_nonblocking(false)?;
+
+        if stream.peek(&mut first_byte)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed before identification.",
+            ));
+        }
+
+        let transport = if websocket::is_http_request(first_byte[0]) {
+            websocket::handshake(stream)?;
+
+            Transport::WebSocket
+        } else {
+            Transport::Classic
+        };
+
+        Ok(transport)
+    }
+
+    /// Reads the player's stream until it is closed, sending received packets to the core.
+    fn receive(
+        mut receiver: TcpStream,
+        writer: Sender<Outgoing>,
+        transport: Transport,
+        player_uid: usize,
+        tx: Sender<Box<dyn NetworkPacket + Send>>,
+    ) {
+        let mut buffer = vec![0xff; 1024];
+        // Bytes of packets which have not been completely received yet.
+        let mut pending = Vec::new();
+        let mut decoder = FrameDecoder::new();
+        let mut extended_positions = false;
+
+        loop {
+            let mut closed = false;
+
+            match receiver.read(&mut buffer) {
+                Ok(size) => {
+                    if size == 0 {
+                        // Connection has been closed.
+                        closed = true;
+                    } else if transport == Transport::WebSocket {
+                        decoder.push(&buffer[..size]);
+
+                        loop {
+                            let frame = match decoder.next_frame() {
+                                Ok(Some(frame)) => frame,
+                                Ok(None) => break,
+                                Err(e) => {
+                                    Core::static_log(&format!(
+                                        "Closing connection of uid \"{}\": {}",
+                                        player_uid, e
+                                    ));
+
+                                    // Status 1009, the message is too big to process.
+                                    let close = websocket::encode_frame(
+                                        websocket::OPCODE_CLOSE,
+                                        &1009u16.to_be_bytes(),
+                                    );
+
+                                    writer.send(Outgoing::Data(close)).ok();
+                                    closed = true;
+
+                                    break;
+                                }
+                            };
+
+                            match frame.opcode {
+                                websocket::OPCODE_BINARY | websocket::OPCODE_CONTINUATION => {
+                                    pending.extend(frame.payload);
+                                }
+                                websocket::OPCODE_PING => {
+                                    let pong = websocket::encode_frame(
+                                        websocket::OPCODE_PONG,
+                                        &frame.payload,
+                                    );
+
+                                    writer.send(Outgoing::Data(pong)).ok();
+                                }
+                                websocket::OPCODE_CLOSE => {
+                                    let close =
+                                        websocket::encode_frame(websocket::OPCODE_CLOSE, &[]);
+
+                                    writer.send(Outgoing::Data(close)).ok();
+                                    closed = true;
+                                }
+                                _ => {}
+                            }
+                        }
+                    } else {
+                        pending.extend_from_slice(&buffer[..size]);
+                    }
+
+                    if let Err(e) = Network::dispatch_packets(
+                        &mut pending,
+                        player_uid,
+                        &mut extended_positions,
+                        &tx,
+                    ) {
+                        Core::static_log(&format!(
+                            "Closing connection of uid \"{}\": {}",
+                            player_uid, e
+                        ));
+
+                        let packet = DisconnectPlayer::new(player_uid, e);
+
+                        writer
+                            .send(Outgoing::Data(encode_packet(&packet, transport, false)))
+                            .ok();
+                        closed = true;
+                    }
+                }
+                // Read timeouts, players are only dropped once writing to them fails.
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => {
+                    Core::static_log(&format!("IO error on player received: {}", e));
+
+                    closed = true;
+                }
+            }
+
+            if closed {
+                // Inform the core of player's disconnecction so the proper action can be taken.
+                Core::static_log(&format!("Player with uid \"{}\" disconnected.", player_uid));
+
+                writer.send(Outgoing::Close).ok();
+
+                tx.send(Box::new(DisconnectPlayer::new(
+                    player_uid,
+                    String::from("Server Disconnect"),
+                )))
+                .ok();
+
+                break; // TX clone should automatically be dropped by Rust's Ownership.
+            }
+        }
+    }
+
+    /// Parses every complete packet inside the pending bytes, and sends them to the core.
+    /// Incomplete packets are kept until the rest of them is received.
+    /// Fails on unknown packets, as the rest of the data cannot be read without their size.
+    /// Clients which negotiated ExtEntityPositions send bigger position packets, which is
+    /// noticed from their ExtEntry packets.
+    fn dispatch_packets(
+        pending: &mut Vec<u8>,
+        player_uid: usize,
+        extended_positions: &mut bool,
+        tx: &Sender<Box<dyn NetworkPacket + Send>>,
+    ) -> Result<(), String> {
+        let mut index = 0;
+
+        while index < pending.len() {
+            // Read the packet's op_code.
+            let op_code = pending[index];
+
+            let size = match op_code {
+                PlayerIdentification::ID => PlayerIdentification::SIZE,
+                PlayerSetBlock::ID => PlayerSetBlock::SIZE,
+                PlayerPositionAndOrientation::ID if *extended_positions => {
+                    PlayerPositionAndOrientation::EXTENDED_SIZE
+                }
+                PlayerPositionAndOrientation::ID => PlayerPositionAndOrientation::SIZE,
+                PlayerMessage::ID => PlayerMessage::SIZE,
+                ExtInfo::ID => ExtInfo::SIZE,
+                ExtEntry::ID => ExtEntry::SIZE,
+                _ => {
+                    pending.drain(..index);
+
+                    return Err(format!("Unknown packet {:#04x}.", op_code));
+                }
+            };
+
+            if pending.len() - index < size {
+                break;
+            }
+
+            let data = pending[index + 1..index + size].to_vec();
+            let mut buffer_reader = BufferReader::new(&data);
+
+            // Handle receiving packets here, and send objects to core.
+            let packet: Box<dyn NetworkPacket + Send> = match op_code {
+                PlayerIdentification::ID => {
+                    Box::new(PlayerIdentification::new(&mut buffer_reader, player_uid))
+                }
+                PlayerSetBlock::ID => Box::new(PlayerSetBlock::new(&mut buffer_reader, player_uid)),
+                PlayerPositionAndOrientation::ID => Box::new(PlayerPositionAndOrientation::new(
+                    &mut buffer_reader,
+                    player_uid,
+                    *extended_positions,
+                )),
+                ExtInfo::ID => Box::new(ExtInfo::from(&mut buffer_reader, player_uid)),
+                ExtEntry::ID => {
+                    let entry = ExtEntry::from(&mut buffer_reader, player_uid);
+
+                    if entry.get_name() == "ExtEntityPositions"
+                        && is_supported_extension(entry.get_name(), entry.get_version())
+                    {
+                        *extended_positions = true;
+                    }
+
+                    Box::new(entry)
+                }
+                _ => Box::new(PlayerMessage::new(&mut buffer_reader, player_uid)),
+            };
+
+            tx.send(packet).ok();
+
+            index += size;
+        }
+
+        pending.drain(..index);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_network {
+    use super::*;
+
+    fn ext_entry(name: &str, version: i32) -> Vec<u8> {
+        let mut buffer = BufferWriter::new(ExtEntry::SIZE);
+
+        buffer.write_byte(ExtEntry::ID);
+        ExtEntry::new(String::from(name), version).handle_send(&mut buffer);
+
+        buffer.get_data().clone()
+    }
+
+    #[test]
+    /// Position packets are read with int coordinates once ExtEntityPositions is negotiated.
+    pub fn extended_positions_after_ext_entry() {
+        let (tx, rx) = mpsc::channel();
+        let mut extended_positions = false;
+
+        let mut pending = ext_entry("SelectionCuboid", 1);
+        pending.extend(ext_entry("ExtEntityPositions", 1));
+
+        // Position packet with int coordinates, split before its end.
+        pending.extend(&[PlayerPositionAndOrientation::ID, 0xff]);
+        pending.extend(&[0, 0, 1, 0, 0, 0, 2]);
+
+        Network::dispatch_packets(&mut pending, 1, &mut extended_positions, &tx).unwrap();
+
+        assert!(extended_positions);
+        assert_eq!(rx.try_iter().count(), 2);
+        assert_eq!(pending.len(), 9);
+
+        pending.extend(&[0, 0, 0, 0, 3, 0, 0]);
+        Network::dispatch_packets(&mut pending, 1, &mut extended_positions, &tx).unwrap();
+
+        assert_eq!(rx.try_iter().count(), 1);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    /// Unknown packets cannot be skipped, the connection is closed instead.
+    pub fn unknown_packet() {
+        let (tx, _rx) = mpsc::channel();
+        let mut pending = vec![0x42, 0, 0];
+
+        assert!(Network::dispatch_packets(&mut pending, 1, &mut false, &tx).is_err());
+    }
 }
@@ -0,0 +1,481 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Blocks which change by themselves. Changed blocks and their neighbours are queued,
+//! and the queue of each world is worked through on the ticks of the core.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+use super::generators::{tree_blocks, Random};
+use super::{block, Vec3D, World};
+
+/// Physics are off, blocks never change by themselves.
+pub const PHYSICS_OFF: u8 = 0;
+/// Fluids spread, sand and gravel fall, sponges soak up water.
+pub const PHYSICS_NORMAL: u8 = 1;
+/// Grass grows and dies with light, saplings grow into trees.
+pub const PHYSICS_FULL: u8 = 2;
+
+// Ticks before a block is updated, random ones are taken between both.
+const WATER_DELAY: u64 = 4;
+const LAVA_DELAY: u64 = 20;
+const FALL_DELAY: u64 = 1;
+const GRASS_DELAY: (u64, u64) = (40, 400);
+const SAPLING_DELAY: (u64, u64) = (600, 2400);
+
+/// Distance from a sponge water cannot be in.
+pub const SPONGE_RADIUS: i32 = 2;
+
+/// Queue of blocks to update in a world.
+pub struct Physics {
+    level: u8,
+    tick: u64,
+    queue: BinaryHeap<Reverse<(u64, u16, u16, u16)>>,
+    queued: HashSet<Vec3D>,
+    random: Random,
+}
+
+impl Physics {
+    pub fn new(level: u8) -> Physics {
+        Physics {
+            level,
+            tick: 0,
+            queue: BinaryHeap::new(),
+            queued: HashSet::new(),
+            random: Random::new(0x5eed),
+        }
+    }
+
+    pub fn get_level(&self) -> u8 {
+        self.level
+    }
+
+    /// Changes the level, turning physics off forgets the queued blocks.
+    pub fn set_level(&mut self, level: u8) {
+        self.level = level.min(PHYSICS_FULL);
+
+        if self.level == PHYSICS_OFF {
+            self.queue.clear();
+            self.queued.clear();
+        }
+    }
+
+    /// Number of blocks waiting to be updated.
+    pub fn get_queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    // Ticks until a block is updated, None for blocks which do nothing on this level.
+    fn get_delay(&mut self, block: u8) -> Option<u64> {
+        let level = self.level;
+        let mut between = |(min, max): (u64, u64)| min + self.random.next_u64() % (max - min);
+
+        match (block, level) {
+            (_, PHYSICS_OFF) => None,
+            (block::WATER, _) => Some(WATER_DELAY),
+            (block::LAVA, _) => Some(LAVA_DELAY),
+            (block::SAND, _) | (block::GRAVEL, _) | (block::SPONGE, _) => Some(FALL_DELAY),
+            (block::GRASS, PHYSICS_FULL) | (block::DIRT, PHYSICS_FULL) => {
+                Some(between(GRASS_DELAY))
+            }
+            (block::SAPLING, PHYSICS_FULL) => Some(between(SAPLING_DELAY)),
+            _ => None,
+        }
+    }
+
+    /// Queues a block if it does something, keeping the earlier update of blocks already queued.
+    pub(super) fn schedule(&mut self, position: Vec3D, block: u8) {
+        if self.queued.contains(&position) {
+            return;
+        }
+
+        if let Some(delay) = self.get_delay(block) {
+            let Vec3D(x, y, z) = position;
+
+            self.queue.push(Reverse((self.tick + delay, x, y, z)));
+            self.queued.insert(position);
+        }
+    }
+
+    // Takes the next block due on the current tick.
+    fn pop_due(&mut self) -> Option<Vec3D> {
+        match self.queue.peek() {
+            Some(Reverse((due, _, _, _))) if *due <= self.tick => {}
+            _ => return None,
+        }
+
+        let Reverse((_, x, y, z)) = self.queue.pop()?;
+        let position = Vec3D::new(x, y, z);
+        self.queued.remove(&position);
+
+        Some(position)
+    }
+}
+
+/// A position and the six blocks around it, the ones inside a map of the given size.
+pub fn neighbours(position: &Vec3D, size: &Vec3D) -> Vec<Vec3D> {
+    offset_all(
+        position,
+        size,
+        &[
+            (0, 0, 0),
+            (0, -1, 0),
+            (0, 1, 0),
+            (-1, 0, 0),
+            (1, 0, 0),
+            (0, 0, -1),
+            (0, 0, 1),
+        ],
+    )
+}
+
+fn offset_all(position: &Vec3D, size: &Vec3D, offsets: &[(i32, i32, i32)]) -> Vec<Vec3D> {
+    offsets
+        .iter()
+        .filter_map(|offset| offset_by(position, size, *offset))
+        .collect()
+}
+
+fn offset_by(position: &Vec3D, size: &Vec3D, (dx, dy, dz): (i32, i32, i32)) -> Option<Vec3D> {
+    let axis = |value: u16, offset: i32, length: u16| {
+        let moved = i32::from(value) + offset;
+
+        if moved >= 0 && moved < i32::from(length) {
+            Some(moved as u16)
+        } else {
+            None
+        }
+    };
+
+    Some(Vec3D::new(
+        axis(position.0, dx, size.0)?,
+        axis(position.1, dy, size.1)?,
+        axis(position.2, dz, size.2)?,
+    ))
+}
+
+fn cube(position: &Vec3D, size: &Vec3D, radius: i32) -> Vec<Vec3D> {
+    let mut offsets = Vec::new();
+
+    for dy in -radius..=radius {
+        for dz in -radius..=radius {
+            for dx in -radius..=radius {
+                offsets.push((dx, dy, dz));
+            }
+        }
+    }
+
+    offset_all(position, size, &offsets)
+}
+
+fn is_water(block: u8) -> bool {
+    block == block::WATER || block == block::STILL_WATER
+}
+
+fn is_lava(block: u8) -> bool {
+    block == block::LAVA || block == block::STILL_LAVA
+}
+
+/// Whether light goes through a block.
+fn is_transparent(block: u8) -> bool {
+    matches!(
+        block,
+        block::AIR
+            | block::GLASS
+            | block::LEAVES
+            | block::SAPLING
+            | block::RED_FLOWER
+            | block::YELLOW_FLOWER
+            | block::BROWN_MUSHROOM
+            | block::RED_MUSHROOM
+    )
+}
+
+// Whether sunlight reaches a block, nothing above it stopping the light.
+fn is_lit(world: &World, position: &Vec3D) -> bool {
+    (position.1 + 1..world.get_size().1)
+        .all(|y| is_transparent(world.get_block(&Vec3D::new(position.0, y, position.2))))
+}
+
+fn near_sponge(world: &World, position: &Vec3D) -> bool {
+    cube(position, world.get_size(), SPONGE_RADIUS)
+        .iter()
+        .any(|near| world.get_block(near) == block::SPONGE)
+}
+
+/// Updates up to a number of due blocks of a world.
+/// Returns the number of blocks updated and the positions which changed.
+pub fn tick(world: &mut World, budget: usize) -> (usize, Vec<Vec3D>) {
+    let mut changed = Vec::new();
+    let mut updated = 0;
+
+    world.get_physics_mut().tick += 1;
+
+    while updated < budget {
+        let position = match world.get_physics_mut().pop_due() {
+            Some(position) => position,
+            None => break,
+        };
+
+        update(world, &position, &mut changed);
+        updated += 1;
+    }
+
+    (updated, changed)
+}
+
+fn set(world: &mut World, position: &Vec3D, block: u8, changed: &mut Vec<Vec3D>) {
+    world.set_block(position, block, false);
+    changed.push(*position);
+}
+
+fn update(world: &mut World, position: &Vec3D, changed: &mut Vec<Vec3D>) {
+    let size = *world.get_size();
+    let level = world.get_physics_mut().get_level();
+
+    match world.get_block(position) {
+        block::WATER => flow(world, position, block::WATER, changed),
+        block::LAVA => flow(world, position, block::LAVA, changed),
+        block::SAND | block::GRAVEL => fall(world, position, changed),
+        block::SPONGE => {
+            for near in cube(position, &size, SPONGE_RADIUS) {
+                if is_water(world.get_block(&near)) {
+                    set(world, &near, block::AIR, changed);
+                }
+            }
+        }
+        block::GRASS if level == PHYSICS_FULL && !is_lit(world, position) => {
+            set(world, position, block::DIRT, changed)
+        }
+        block::DIRT if level == PHYSICS_FULL => {
+            let grass_near = neighbours(position, &size)
+                .iter()
+                .any(|near| world.get_block(near) == block::GRASS);
+
+            if grass_near && is_lit(world, position) {
+                set(world, position, block::GRASS, changed);
+            }
+        }
+        block::SAPLING if level == PHYSICS_FULL => grow_tree(world, position, changed),
+        _ => {}
+    }
+}
+
+// Spreads a fluid down and to the sides. Water and lava meeting turn the lava into stone.
+fn flow(world: &mut World, position: &Vec3D, fluid: u8, changed: &mut Vec<Vec3D>) {
+    let size = *world.get_size();
+
+    for target in offset_all(
+        position,
+        &size,
+        &[(0, -1, 0), (-1, 0, 0), (1, 0, 0), (0, 0, -1), (0, 0, 1)],
+    ) {
+        let block = world.get_block(&target);
+
+        if block == block::AIR {
+            if fluid == block::WATER && near_sponge(world, &target) {
+                continue;
+            }
+
+            set(world, &target, fluid, changed);
+        } else if fluid == block::WATER && is_lava(block) {
+            let stone = match block {
+                block::STILL_LAVA => block::OBSIDIAN,
+                _ => block::STONE,
+            };
+
+            set(world, &target, stone, changed);
+        } else if fluid == block::LAVA && is_water(block) {
+            set(world, position, block::STONE, changed);
+
+            return;
+        }
+    }
+}
+
+// Drops sand and gravel onto the first solid block below.
+fn fall(world: &mut World, position: &Vec3D, changed: &mut Vec<Vec3D>) {
+    let falls_through = |block: u8| block == block::AIR || is_water(block) || is_lava(block);
+
+    let mut bottom = position.1;
+
+    while bottom > 0
+        && falls_through(world.get_block(&Vec3D::new(position.0, bottom - 1, position.2)))
+    {
+        bottom -= 1;
+    }
+
+    if bottom != position.1 {
+        let block = world.get_block(position);
+
+        set(world, position, block::AIR, changed);
+        set(
+            world,
+            &Vec3D::new(position.0, bottom, position.2),
+            block,
+            changed,
+        );
+    }
+}
+
+// Grows a sapling standing in light on grass or dirt into a tree, if there is room for it.
+fn grow_tree(world: &mut World, position: &Vec3D, changed: &mut Vec<Vec3D>) {
+    let size = *world.get_size();
+
+    if position.1 == 0 || !is_lit(world, position) {
+        return;
+    }
+
+    let ground = Vec3D::new(position.0, position.1 - 1, position.2);
+
+    if !matches!(world.get_block(&ground), block::GRASS | block::DIRT) {
+        return;
+    }
+
+    let random = &mut world.get_physics_mut().random;
+    let trunk = 4 + random.next_int(3) as u16;
+    let blocks = tree_blocks(random, trunk);
+
+    let room = blocks
+        .iter()
+        .all(|(offset, _)| offset_by(&ground, &size, *offset).is_some());
+    let clear = (2..=i32::from(trunk) + 1).all(|dy| {
+        offset_by(&ground, &size, (0, dy, 0)).map(|above| world.get_block(&above))
+            == Some(block::AIR)
+    });
+
+    if !room || !clear {
+        return;
+    }
+
+    set(world, &ground, block::DIRT, changed);
+
+    for (offset, tree_block) in blocks {
+        let target = offset_by(&ground, &size, offset).unwrap();
+
+        if tree_block == block::LOG || world.get_block(&target) == block::AIR {
+            set(world, &target, tree_block, changed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_physics {
+    use super::super::maps::MemoryMap;
+    use super::*;
+
+    fn world(level: u8) -> World {
+        let mut world = World::new(
+            String::from("test"),
+            Box::new(MemoryMap::empty(Vec3D::new(16, 16, 16))),
+        );
+        world.get_physics_mut().set_level(level);
+
+        // Stone floor at y 0.
+        world.fill(
+            &super::super::Region::new(Vec3D::new(0, 0, 0), Vec3D::new(15, 0, 15)),
+            block::STONE,
+        );
+
+        world
+    }
+
+    fn run(world: &mut World, ticks: usize) {
+        for _ in 0..ticks {
+            tick(world, usize::MAX);
+        }
+    }
+
+    fn count(world: &World, block: u8) -> usize {
+        world.get_chunks().iter().filter(|b| **b == block).count()
+    }
+
+    #[test]
+    /// Water spreads along the floor, lava touching it turns into stone, sand falls.
+    pub fn fluids_and_falling() {
+        let mut world = world(PHYSICS_NORMAL);
+
+        world.set_block(&Vec3D::new(8, 1, 8), block::WATER, false);
+        world.set_block(&Vec3D::new(3, 5, 3), block::SAND, false);
+
+        // Updates are limited by the budget, the rest waits for the next ticks.
+        run(&mut world, WATER_DELAY as usize * 2 - 1);
+        let (updated, _) = tick(&mut world, 1);
+        assert_eq!(updated, 1);
+        assert!(world.get_physics().get_queued() > 3);
+
+        run(&mut world, 200);
+        assert_eq!(count(&world, block::WATER), 16 * 16 - 1);
+        assert_eq!(world.get_block(&Vec3D::new(3, 1, 3)), block::SAND);
+        assert_eq!(world.get_block(&Vec3D::new(3, 5, 3)), block::AIR);
+
+        world.set_block(&Vec3D::new(0, 2, 0), block::LAVA, false);
+        run(&mut world, LAVA_DELAY as usize + 1);
+        assert_eq!(world.get_block(&Vec3D::new(0, 2, 0)), block::STONE);
+
+        // Nothing happens with physics off.
+        let mut world = super::test_physics::world(PHYSICS_OFF);
+        world.set_block(&Vec3D::new(8, 1, 8), block::WATER, false);
+        run(&mut world, 20);
+        assert_eq!(count(&world, block::WATER), 1);
+    }
+
+    #[test]
+    /// Sponges soak up the water around them, which flows back once they are gone.
+    pub fn sponges() {
+        let mut world = world(PHYSICS_NORMAL);
+
+        world.set_block(&Vec3D::new(8, 1, 8), block::WATER, false);
+        run(&mut world, 200);
+
+        world.set_block(&Vec3D::new(8, 2, 8), block::SPONGE, false);
+        run(&mut world, 200);
+        assert_eq!(count(&world, block::WATER), 16 * 16 - 5 * 5);
+
+        world.set_block(&Vec3D::new(8, 2, 8), block::AIR, false);
+        run(&mut world, 200);
+        assert_eq!(count(&world, block::WATER), 16 * 16);
+    }
+
+    #[test]
+    /// Grass dies in the dark and spreads in light, saplings grow into trees.
+    pub fn grass_and_trees() {
+        let mut world = world(PHYSICS_FULL);
+
+        world.fill(
+            &super::super::Region::new(Vec3D::new(0, 1, 0), Vec3D::new(15, 1, 15)),
+            block::DIRT,
+        );
+        world.set_block(&Vec3D::new(8, 1, 8), block::GRASS, false);
+        world.set_block(&Vec3D::new(0, 1, 0), block::GRASS, false);
+        world.set_block(&Vec3D::new(0, 2, 0), block::STONE, false);
+        world.set_block(&Vec3D::new(8, 2, 8), block::SAPLING, false);
+
+        run(&mut world, SAPLING_DELAY.1 as usize);
+
+        assert_eq!(world.get_block(&Vec3D::new(0, 1, 0)), block::DIRT);
+        assert_eq!(world.get_block(&Vec3D::new(8, 2, 8)), block::LOG);
+        assert!(count(&world, block::LEAVES) > 0);
+        assert!(count(&world, block::GRASS) > 16);
+    }
+}
@@ -20,12 +20,51 @@
     SOFTWARE.
 */
 
-use std::io::Write;
-use std::net::TcpStream;
+use std::sync::mpsc::Sender;
+
+use super::super::network::{
+    DisconnectPlayer, Message, NetworkPacket, ServerPositionAndOrientation,
+};
+use super::{encode_packet, Action, Core, Outgoing, Rank, Transform, Transport, SELF_ENTITY_ID};
+
+/// Stages of a player's connection, packets are only handled in the stage they belong to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoginState {
+    /// Connection is registered, waiting for PlayerIdentification.
+    Connected,
+    /// CPE client has identified, extensions are being exchanged.
+    Negotiating,
+    /// Player has identified, the first map is on its way.
+    Identified,
+    /// Player has received a map and is inside a world.
+    InWorld,
+}
+
+/// Copy of a player's state, readable without locking the player.
+#[derive(Clone)]
+pub struct PlayerSnapshot {
+    pub uid: usize,
+    pub name: String,
+    pub display_name: String,
+    pub world: String,
+    pub transform: Transform,
+    pub login_state: LoginState,
+    pub rank: Rank,
+}
 
-use super::super::network::{Message, NetworkPacket, ServerPositionAndOrientation};
-use super::events;
-use super::{BufferWriter, Core, Transform};
+impl PlayerSnapshot {
+    pub fn new(player: &dyn Player) -> PlayerSnapshot {
+        PlayerSnapshot {
+            uid: player.get_uid(),
+            name: String::from(player.get_name()),
+            display_name: String::from(player.get_display_name()),
+            world: String::from(player.get_world()),
+            transform: player.get_transform().clone(),
+            login_state: player.get_login_state(),
+            rank: player.get_rank(),
+        }
+    }
+}
 
 pub trait Player {
     fn set_uid(&mut self, id: usize);
@@ -45,26 +84,56 @@ pub trait Player {
     fn get_transform(&self) -> &Transform;
     fn get_transform_mut(&mut self) -> &mut Transform;
 
-    fn update_transform(&mut self, transform: Transform) {}
+    fn set_login_state(&mut self, _state: LoginState) {}
+    fn get_login_state(&self) -> LoginState {
+        LoginState::InWorld
+    }
+
+    fn set_rank(&mut self, _rank: Rank) {}
+    fn get_rank(&self) -> Rank {
+        Rank::Guest
+    }
+
+    fn update_transform(&mut self, _transform: Transform) {}
 
     fn is_console(&self) -> bool {
         false
     }
 
+    /// Whether the client supports a CPE extension.
+    fn supports_extension(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Marks a CPE extension as negotiated with the client.
+    fn add_extension(&mut self, _name: &str) {}
+
+    /// ExtEntry packets the client has yet to send while negotiating.
+    fn set_remaining_extensions(&mut self, _count: u16) {}
+    fn get_remaining_extensions(&self) -> u16 {
+        0
+    }
+
     fn handle_packet(&mut self, packet: Box<dyn NetworkPacket>);
 
     fn kill(&mut self) {}
+    /// Closes the player's connection, the core cleans up once the network thread notices.
+    fn disconnect(&mut self, _reason: &str) {}
     fn send_message(&mut self, message: &str) {
         // Message sent to console.
         Core::static_log(message);
     }
 
-    fn try_join_world(&mut self, core: &Core, map: &str) {}
+    /// Moves the player into a world (loading it if needed), once the current message is handled.
+    fn try_join_world(&mut self, core: &Core, map: &str) {
+        core.queue_action(Action::JoinWorld(self.get_uid(), String::from(map)));
+    }
 }
 
 pub struct NetworkPlayer {
     uid: usize,
-    stream: TcpStream,
+    writer: Sender<Outgoing>,
+    transport: Transport,
 
     username: String,
     nickname: String,
@@ -72,24 +141,43 @@ pub struct NetworkPlayer {
     world: String,
 
     transform: Transform,
+
+    login_state: LoginState,
+
+    rank: Rank,
+
+    extensions: Vec<String>,
+    remaining_extensions: u16,
 }
 
 impl NetworkPlayer {
     /// Creates a network based player.
-    pub fn new(uid: usize, stream: TcpStream) -> NetworkPlayer {
+    pub fn new(uid: usize, writer: Sender<Outgoing>, transport: Transport) -> NetworkPlayer {
         let default_name = String::from("Uninitialized Player");
 
         NetworkPlayer {
             uid,
-            stream,
+            writer,
+            transport,
             nickname: default_name.clone(),
             username: default_name,
             world: String::from(""),
 
             transform: Transform::default(),
+
+            login_state: LoginState::Connected,
+
+            rank: Rank::Guest,
+
+            extensions: Vec::new(),
+            remaining_extensions: 0,
         }
     }
 
+    pub fn get_transport(&self) -> Transport {
+        self.transport
+    }
+
     /*pub fn listen_network(&self) {
         if let Ok(pool) = self.threadpool.lock() {
             pool.execute(move || {
@@ -126,12 +214,43 @@ impl Player for NetworkPlayer {
         &mut self.transform
     }
 
+    fn set_login_state(&mut self, state: LoginState) {
+        self.login_state = state;
+    }
+    fn get_login_state(&self) -> LoginState {
+        self.login_state
+    }
+
+    fn set_rank(&mut self, rank: Rank) {
+        self.rank = rank;
+    }
+    fn get_rank(&self) -> Rank {
+        self.rank
+    }
+
+    fn supports_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+
+    fn add_extension(&mut self, name: &str) {
+        if !self.supports_extension(name) {
+            self.extensions.push(String::from(name));
+        }
+    }
+
+    fn set_remaining_extensions(&mut self, count: u16) {
+        self.remaining_extensions = count;
+    }
+    fn get_remaining_extensions(&self) -> u16 {
+        self.remaining_extensions
+    }
+
     fn update_transform(&mut self, transform: Transform) {
         // TODO: Remove double cloning.
         self.transform = transform;
 
         let packet = Box::new(ServerPositionAndOrientation::new(
-            -1,
+            SELF_ENTITY_ID,
             self.transform.clone(),
         ));
 
@@ -164,57 +283,29 @@ impl Player for NetworkPlayer {
         println!("Player died.")
     }
 
-    fn send_message(&mut self, message: &str) {
-        let packet = Box::new(Message::new(0, String::from(message)));
+    fn disconnect(&mut self, reason: &str) {
+        let packet = Box::new(DisconnectPlayer::new(self.uid, String::from(reason)));
 
         self.handle_packet(packet);
-    }
 
-    fn try_join_world(&mut self, core: &Core, map: &str) {
-        // Map found:
-        if let Some(mut map) = core.get_world_mut(map) {
-            // Check events, false means event was not surpressed.
-            if !events::world::on_join(&core, self, &mut map) {
-                core.send_map(self, &mut map);
-
-                events::world::on_joined(&core, self, map);
-            }
-        } else {
-            // See if map exists in map folder, then try to load it.
-            if core.try_load_map(self, map) {
-                if let Some(mut map) = core.get_world_mut(map) {
-                    // Check events, false means event was not surpressed.
-                    if !events::world::on_join(&core, self, &mut map) {
-                        core.send_map(self, &mut map);
-
-                        events::world::on_joined(&core, self, map);
-                    }
-                }
-            } else {
-                // Map could not be loaded.
-                events::world::on_notfound(core, self, map);
-            }
-        }
+        self.writer.send(Outgoing::Close).ok();
     }
 
-    fn handle_packet(&mut self, packet: Box<dyn NetworkPacket>) {
-        let buffer_size = packet.get_size();
-        let packet_id = packet.get_id();
-
-        let mut buffer = BufferWriter::new(buffer_size);
-
-        buffer.write_byte(packet_id);
+    fn send_message(&mut self, message: &str) {
+        let packet = Box::new(Message::new(0, String::from(message)));
 
-        packet.handle_send(&mut buffer);
+        self.handle_packet(packet);
+    }
 
-        if let Ok(sent_bytes) = self.stream.write(buffer.get_data()) {
-            // TODO: Check writing and error of packet sending.
-            /*Core::static_log(&format!(
-                "Sent Packet ID \"{}\", \"{}\" bytes.",
-                packet.get_id(),
-                sent_bytes
-            ));*/
-        }
+    fn handle_packet(&mut self, packet: Box<dyn NetworkPacket>) {
+        let data = encode_packet(
+            packet.as_ref(),
+            self.transport,
+            self.supports_extension("ExtEntityPositions"),
+        );
+
+        // Fails once the connection is closed, the network thread disconnects the player then.
+        self.writer.send(Outgoing::Data(data)).ok();
     }
 }
 
@@ -231,6 +322,12 @@ impl Console {
     }
 }
 
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Player for Console {
     fn get_transform(&self) -> &Transform {
         &self.transform
@@ -252,5 +349,9 @@ impl Player for Console {
         "&0Console"
     }
 
+    fn get_rank(&self) -> Rank {
+        Rank::Owner
+    }
+
     fn handle_packet(&mut self, _packet: Box<dyn NetworkPacket>) {}
 }
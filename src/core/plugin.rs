@@ -0,0 +1,323 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use super::Core;
+
+/// Order in which handlers of an event are called, highest first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+    Lowest,
+    Low,
+    Normal,
+    High,
+    Highest,
+}
+
+/// Something handlers can listen to. Cancelling an event skips the handlers after it,
+/// and the server does not do what the event was about.
+pub trait Event: 'static {
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+
+    /// Events which cannot be cancelled ignore this.
+    fn set_cancelled(&mut self, _cancelled: bool) {}
+}
+
+pub type Handler<E> = Box<dyn Fn(&Core, &mut E) + Send + Sync>;
+
+struct Registered<E> {
+    priority: Priority,
+    handler: Handler<E>,
+}
+
+/// Handlers registered by plugins, called by the core as things happen.
+///
+/// Handlers are called while the acting player is locked, so they should reach players
+/// through `Core::send_message_to`, `Core::queue_action` and snapshots.
+#[derive(Default)]
+pub struct EventBus {
+    handlers: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    /// Registers a handler. Handlers with the same priority are called in the order they are registered.
+    pub fn register<E, F>(&mut self, priority: Priority, handler: F)
+    where
+        E: Event,
+        F: Fn(&Core, &mut E) + Send + Sync + 'static,
+    {
+        let list = self.get_list_mut::<E>();
+        let index = list
+            .iter()
+            .position(|registered| registered.priority < priority)
+            .unwrap_or(list.len());
+
+        list.insert(
+            index,
+            Registered {
+                priority,
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    /// Calls the handlers of an event. Returns true if the event got cancelled.
+    pub fn call<E: Event>(&self, core: &Core, event: &mut E) -> bool {
+        if let Some(list) = self
+            .handlers
+            .get(&TypeId::of::<E>())
+            .and_then(|list| list.downcast_ref::<Vec<Registered<E>>>())
+        {
+            for registered in list {
+                if event.is_cancelled() {
+                    break;
+                }
+
+                (registered.handler)(core, event);
+            }
+        }
+
+        event.is_cancelled()
+    }
+
+    /// Number of handlers registered for an event.
+    pub fn get_handler_count<E: Event>(&self) -> usize {
+        self.handlers
+            .get(&TypeId::of::<E>())
+            .and_then(|list| list.downcast_ref::<Vec<Registered<E>>>())
+            .map_or(0, |list| list.len())
+    }
+
+    fn get_list_mut<E: Event>(&mut self) -> &mut Vec<Registered<E>> {
+        self.handlers
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Vec::<Registered<E>>::new()))
+            .downcast_mut::<Vec<Registered<E>>>()
+            .unwrap()
+    }
+}
+
+/// Extends the server without changing the crate, by registering event handlers.
+pub trait Plugin {
+    fn get_name(&self) -> &str;
+
+    /// Called once when the plugin is added to the core, handlers are registered here.
+    fn register(&mut self, events: &mut EventBus);
+
+    /// Called once the handlers are registered, commands and tasks are added here.
+    fn enable(&mut self, _core: &Core) {}
+}
+
+/// Plugins added to the core, along with their handlers.
+#[derive(Default)]
+pub struct PluginList {
+    events: EventBus,
+    plugins: Vec<Box<dyn Plugin + Send + Sync>>,
+}
+
+impl PluginList {
+    pub fn new() -> PluginList {
+        PluginList::default()
+    }
+
+    /// Adds a plugin and registers its handlers. Plugin names are unique.
+    pub fn add(&mut self, mut plugin: Box<dyn Plugin + Send + Sync>) -> bool {
+        if self.contains(plugin.get_name()) {
+            return false;
+        }
+
+        plugin.register(&mut self.events);
+
+        self.plugins.push(plugin);
+
+        true
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.get_name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_names(&self) -> Vec<String> {
+        self.plugins
+            .iter()
+            .map(|plugin| String::from(plugin.get_name()))
+            .collect()
+    }
+
+    pub fn get_events(&self) -> &EventBus {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod test_plugin {
+    use std::env;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::super::events::PlayerChatEvent;
+    use super::super::Config;
+    use super::*;
+
+    struct Counter {
+        calls: Vec<&'static str>,
+        cancelled: bool,
+    }
+
+    impl Event for Counter {
+        fn is_cancelled(&self) -> bool {
+            self.cancelled
+        }
+
+        fn set_cancelled(&mut self, cancelled: bool) {
+            self.cancelled = cancelled;
+        }
+    }
+
+    #[test]
+    /// Handlers run from highest to lowest priority, in registration order within one priority.
+    pub fn priority_order() {
+        let folder = env::temp_dir().join(format!("rcclassic-priorities-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+        let mut events = EventBus::new();
+
+        events.register(Priority::Low, |_, e: &mut Counter| e.calls.push("low"));
+        events.register(Priority::Highest, |_, e: &mut Counter| {
+            e.calls.push("highest")
+        });
+        events.register(Priority::Normal, |_, e: &mut Counter| {
+            e.calls.push("normal1")
+        });
+        events.register(Priority::Normal, |_, e: &mut Counter| {
+            e.calls.push("normal2")
+        });
+
+        let mut event = Counter {
+            calls: Vec::new(),
+            cancelled: false,
+        };
+
+        assert!(!events.call(&core, &mut event));
+        assert_eq!(event.calls, vec!["highest", "normal1", "normal2", "low"]);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    /// Cancelling an event skips the handlers with lower priority.
+    pub fn cancellation() {
+        let folder = env::temp_dir().join(format!("rcclassic-cancellation-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+        let mut events = EventBus::new();
+
+        events.register(Priority::High, |_, e: &mut Counter| {
+            e.calls.push("high");
+            e.set_cancelled(true);
+        });
+        events.register(Priority::Low, |_, e: &mut Counter| e.calls.push("low"));
+
+        let mut event = Counter {
+            calls: Vec::new(),
+            cancelled: false,
+        };
+
+        assert!(events.call(&core, &mut event));
+        assert_eq!(event.calls, vec!["high"]);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    struct Censor {
+        censored: Arc<AtomicUsize>,
+    }
+
+    impl Plugin for Censor {
+        fn get_name(&self) -> &str {
+            "Censor"
+        }
+
+        fn register(&mut self, events: &mut EventBus) {
+            let censored = self.censored.clone();
+
+            events.register(Priority::Highest, move |_, event: &mut PlayerChatEvent| {
+                if event.message.contains("/help") {
+                    censored.fetch_add(1, Ordering::SeqCst);
+                    event.set_cancelled(true);
+                }
+            });
+        }
+    }
+
+    #[test]
+    /// Plugins registered on the core run before the builtin commands, names are unique.
+    pub fn register_on_core() {
+        let folder = env::temp_dir().join(format!("rcclassic-plugins-{}", std::process::id()));
+        let mut core = Core::with_config(1, Config::in_folder(&folder));
+        let censored = Arc::new(AtomicUsize::new(0));
+
+        assert!(core.register_plugin(Box::new(Censor {
+            censored: censored.clone()
+        })));
+        assert!(!core.register_plugin(Box::new(Censor {
+            censored: censored.clone()
+        })));
+        assert_eq!(
+            core.get_plugin_names(),
+            vec![
+                "Builtin",
+                "Draw",
+                "Zones",
+                "SpecialBlocks",
+                "Backups",
+                "BlockDB",
+                "Spawns",
+                "Warps",
+                "Scripts",
+                "Censor"
+            ]
+        );
+
+        let console = core.get_player_snapshot(0).unwrap();
+        let mut event = PlayerChatEvent::new(console, String::from("/help"));
+
+        assert!(core.call_event(&mut event));
+        assert_eq!(censored.load(Ordering::SeqCst), 1);
+
+        let console = core.get_player_snapshot(0).unwrap();
+        let mut event = PlayerChatEvent::new(console, String::from("hello"));
+
+        assert!(!core.call_event(&mut event));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,148 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use super::Core;
+
+const RANKS_FILE: &str = "ranks.properties";
+
+/// Permission level of a player, higher ranks can do everything lower ranks can.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Rank {
+    Guest,
+    Builder,
+    Operator,
+    Owner,
+}
+
+impl Rank {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Rank::Guest => "guest",
+            Rank::Builder => "builder",
+            Rank::Operator => "operator",
+            Rank::Owner => "owner",
+        }
+    }
+
+    /// Chat color prefixed to the names of players with this rank.
+    pub fn get_color(&self) -> &'static str {
+        match self {
+            Rank::Guest => "&7",
+            Rank::Builder => "&a",
+            Rank::Operator => "&c",
+            Rank::Owner => "&4",
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Rank> {
+        match id {
+            0 => Some(Rank::Guest),
+            1 => Some(Rank::Builder),
+            2 => Some(Rank::Operator),
+            3 => Some(Rank::Owner),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Rank {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Rank, ()> {
+        match name.to_lowercase().as_str() {
+            "guest" => Ok(Rank::Guest),
+            "builder" => Ok(Rank::Builder),
+            "operator" | "op" => Ok(Rank::Operator),
+            "owner" => Ok(Rank::Owner),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Ranks of players by name, read from "ranks.properties" (name = rank per line).
+/// Players not listed get the default rank.
+pub struct RankList {
+    ranks: RwLock<HashMap<String, Rank>>,
+    default_rank: Rank,
+}
+
+impl RankList {
+    pub fn new(default_rank: Rank) -> RankList {
+        RankList {
+            ranks: RwLock::new(HashMap::new()),
+            default_rank,
+        }
+    }
+
+    /// Loads the ranks file, leaving the list empty if it does not exist.
+    pub fn load(default_rank: Rank) -> RankList {
+        let list = RankList::new(default_rank);
+
+        if let Ok(content) = fs::read_to_string(RANKS_FILE) {
+            for line in content.lines() {
+                let line = line.trim();
+
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                if let Some(index) = line.find('=') {
+                    let (name, rank) = line.split_at(index);
+
+                    match rank[1..].trim().parse::<Rank>() {
+                        Ok(rank) => list.set(name.trim(), rank),
+                        Err(_) => {
+                            Core::static_log(&format!(
+                                "Invalid rank \"{}\" for player \"{}\".",
+                                rank[1..].trim(),
+                                name.trim()
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        list
+    }
+
+    pub fn get(&self, name: &str) -> Rank {
+        self.ranks
+            .read()
+            .unwrap()
+            .get(&name.to_lowercase())
+            .copied()
+            .unwrap_or(self.default_rank)
+    }
+
+    pub fn set(&self, name: &str, rank: Rank) {
+        self.ranks
+            .write()
+            .unwrap()
+            .insert(name.to_lowercase(), rank);
+    }
+}
@@ -0,0 +1,177 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use super::Core;
+
+pub type TaskHandler = Box<dyn FnMut(&Core) + Send>;
+
+pub(crate) struct Task {
+    pub(crate) owner: String,
+    due: u64,
+    // Repeating tasks are scheduled again this many ticks later.
+    interval: Option<u64>,
+    pub(crate) handler: TaskHandler,
+}
+
+/// Tasks run by the core on its ticks.
+#[derive(Default)]
+pub struct Scheduler {
+    tick: u64,
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Number of ticks passed since the server started.
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Schedules a task to run once, a number of ticks from now.
+    pub fn schedule(&mut self, owner: &str, delay: u64, handler: TaskHandler) {
+        self.tasks.push(Task {
+            owner: String::from(owner),
+            due: self.tick + delay,
+            interval: None,
+            handler,
+        });
+    }
+
+    /// Schedules a task to run every interval ticks, the first time after a delay.
+    pub fn schedule_repeating(
+        &mut self,
+        owner: &str,
+        delay: u64,
+        interval: u64,
+        handler: TaskHandler,
+    ) {
+        // A task running more than once per tick would never let the tick end.
+        let interval = interval.max(1);
+
+        self.tasks.push(Task {
+            owner: String::from(owner),
+            due: self.tick + delay,
+            interval: Some(interval),
+            handler,
+        });
+    }
+
+    /// Cancels every task of a plugin.
+    pub fn cancel_owned_by(&mut self, owner: &str) {
+        self.tasks.retain(|task| task.owner != owner);
+    }
+
+    pub fn get_task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Moves on to the next tick, returning the tasks due.
+    /// Tasks are taken out so they can schedule further tasks while running.
+    pub(crate) fn advance(&mut self) -> Vec<Task> {
+        self.tick += 1;
+
+        let tick = self.tick;
+        let (due, waiting) = self.tasks.drain(..).partition(|task| task.due <= tick);
+
+        self.tasks = waiting;
+
+        due
+    }
+
+    pub(crate) fn reschedule(&mut self, mut task: Task) {
+        if let Some(interval) = task.interval {
+            task.due = self.tick + interval;
+
+            self.tasks.push(task);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_scheduler {
+    use std::env;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::super::Config;
+    use super::*;
+
+    #[test]
+    /// Delayed tasks run once, repeating tasks on every interval until cancelled.
+    pub fn delays_and_intervals() {
+        let folder = env::temp_dir().join(format!("rcclassic-scheduler-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+        let once = Arc::new(AtomicUsize::new(0));
+        let repeating = Arc::new(AtomicUsize::new(0));
+
+        let counter = once.clone();
+        core.schedule("Builtin", 2, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let counter = repeating.clone();
+        core.schedule_repeating("Builtin", 3, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        for _ in 0..9 {
+            core.tick();
+        }
+
+        assert_eq!(once.load(Ordering::SeqCst), 1);
+        assert_eq!(repeating.load(Ordering::SeqCst), 3);
+
+        core.get_scheduler().cancel_owned_by("Builtin");
+        core.tick();
+        core.tick();
+        core.tick();
+
+        assert_eq!(repeating.load(Ordering::SeqCst), 3);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    /// Tasks of plugins which are not loaded never run.
+    pub fn unloaded_owner() {
+        let folder =
+            env::temp_dir().join(format!("rcclassic-scheduler-owner-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let counter = runs.clone();
+        core.schedule("Unloaded", 1, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        core.tick();
+        core.tick();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
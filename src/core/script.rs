@@ -0,0 +1,760 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Scripts loaded from the "scripts" folder, written in Rhai.
+//!
+//! Every `.rhai` file is a script. Its top level runs once when it is loaded, after that the core calls
+//! the functions it defines for events: `on_load()`, `on_unload()`, `on_tick()`, `on_join(player)`,
+//! `on_leave(player)`, `on_chat(player, message)`, `on_move(player)`,
+//! `on_set_block(player, x, y, z, block, destroy)`, `on_world_join(player, world)`,
+//! `on_world_joined(player, world)`, `on_world_load(world)` and `on_world_unload(world)`.
+//! Returning `true` cancels a cancellable event, `on_chat` may return a string replacing the message
+//! and `on_set_block` a block id replacing the block. Functions share the script's state through `this`.
+//!
+//! Scripts are checked for changes every second and reloaded without restarting the server.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, FuncArgs, Map as ScriptMap, Scope, AST};
+
+use super::events::{
+    PlayerChatEvent, PlayerJoinEvent, PlayerLeaveEvent, PlayerMoveEvent, SetBlockEvent,
+    WorldJoinEvent, WorldJoinedEvent, WorldLoadEvent, WorldUnloadEvent,
+};
+use super::{
+    Action, Command, Core, Event, EventBus, PlayerSnapshot, Plugin, Priority, Rank, Transform,
+    Vec3D,
+};
+
+/// Folder scripts are loaded from.
+pub const SCRIPTS_FOLDER: &str = "scripts";
+
+/// Ticks between two checks of the scripts folder.
+const RELOAD_INTERVAL: u64 = 20;
+
+/// Operations a single call may run, so a script stuck in a loop cannot hang the core.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Height of a player's eyes above their feet, in player units (1/32 of a block).
+const EYE_HEIGHT: u16 = 51;
+
+thread_local! {
+    // Core running the current script call, set only while a script is being called.
+    static CURRENT_CORE: Cell<*const Core> = const { Cell::new(ptr::null()) };
+}
+
+/// Runs a function with the core calling the current script, or returns the default outside of a call.
+fn with_core<R: Default, F: FnOnce(&Core) -> R>(f: F) -> R {
+    let core = CURRENT_CORE.with(|current| current.get());
+
+    if core.is_null() {
+        return R::default();
+    }
+
+    // The pointer is only set by CoreScope, which outlives the call.
+    f(unsafe { &*core })
+}
+
+// Makes the core available to the bindings until dropped.
+struct CoreScope(*const Core);
+
+impl CoreScope {
+    fn new(core: &Core) -> CoreScope {
+        CoreScope(CURRENT_CORE.with(|current| current.replace(core)))
+    }
+}
+
+impl Drop for CoreScope {
+    fn drop(&mut self) {
+        CURRENT_CORE.with(|current| current.set(self.0));
+    }
+}
+
+/// Converts a player position to the block the player stands in.
+fn to_block(transform: &Transform) -> (i64, i64, i64) {
+    let position = transform.get_pos();
+
+    (
+        i64::from(position.get_x() / 32),
+        i64::from(position.get_y().saturating_sub(EYE_HEIGHT) / 32),
+        i64::from(position.get_z() / 32),
+    )
+}
+
+/// Converts block coordinates to a position, if they fit inside a map.
+fn to_position(x: i64, y: i64, z: i64) -> Option<Vec3D> {
+    let convert = |value: i64| u16::try_from(value).ok();
+
+    Some(Vec3D::new(convert(x)?, convert(y)?, convert(z)?))
+}
+
+/// Converts block coordinates to the position of a player standing in the middle of that block,
+/// if it fits inside a map.
+fn to_player_position(x: i64, y: i64, z: i64) -> Option<Vec3D> {
+    let convert = |value: i64, offset: i64| value.checked_mul(32)?.checked_add(offset);
+
+    to_position(
+        convert(x, 16)?,
+        convert(y, i64::from(EYE_HEIGHT))?,
+        convert(z, 16)?,
+    )
+}
+
+/// What scripts see of a player.
+fn player_to_map(player: &PlayerSnapshot) -> Dynamic {
+    let (x, y, z) = to_block(&player.transform);
+    let mut map = ScriptMap::new();
+
+    map.insert("uid".into(), Dynamic::from_int(player.uid as i64));
+    map.insert("name".into(), player.name.clone().into());
+    map.insert("display_name".into(), player.display_name.clone().into());
+    map.insert("world".into(), player.world.clone().into());
+    map.insert("rank".into(), player.rank.get_name().into());
+    map.insert("x".into(), Dynamic::from_int(x));
+    map.insert("y".into(), Dynamic::from_int(y));
+    map.insert("z".into(), Dynamic::from_int(z));
+
+    map.into()
+}
+
+fn create_engine() -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    engine.on_print(|message| {
+        Core::static_log(message);
+    });
+    engine.on_debug(|message, source, position| {
+        Core::static_log(&format!(
+            "[{}:{}] {}",
+            source.unwrap_or("script"),
+            position,
+            message
+        ));
+    });
+
+    engine.register_fn("broadcast", |message: &str| {
+        with_core(|core| core.broadcast(message))
+    });
+    engine.register_fn("send_message", |uid: i64, message: &str| {
+        with_core(|core| core.send_message_to(uid as usize, message))
+    });
+    engine.register_fn("get_players", || -> Array {
+        with_core(|core| {
+            core.get_player_snapshots()
+                .iter()
+                .filter(|player| player.uid != 0)
+                .map(player_to_map)
+                .collect()
+        })
+    });
+    engine.register_fn("get_player", |uid: i64| -> Dynamic {
+        with_core(|core| {
+            core.get_player_snapshot(uid as usize)
+                .map(|player| player_to_map(&player))
+                .unwrap_or_default()
+        })
+    });
+    engine.register_fn("get_block", |world: &str, x: i64, y: i64, z: i64| -> i64 {
+        match to_position(x, y, z) {
+            Some(position) => with_core(|core| {
+                core.with_world(world, |world| i64::from(world.get_block(&position)))
+            })
+            .unwrap_or(-1),
+            None => -1,
+        }
+    });
+    engine.register_fn(
+        "set_block",
+        |world: &str, x: i64, y: i64, z: i64, block: i64| -> bool {
+            match (to_position(x, y, z), u8::try_from(block)) {
+                (Some(position), Ok(block)) => {
+                    with_core(|core| core.set_block(world, position, block))
+                }
+                _ => false,
+            }
+        },
+    );
+    engine.register_fn("teleport", |uid: i64, x: i64, y: i64, z: i64| -> bool {
+        with_core(|core| {
+            let player = match core.get_player_snapshot(uid as usize) {
+                Some(player) => player,
+                None => return false,
+            };
+
+            let position = match to_player_position(x, y, z) {
+                Some(position) => position,
+                None => return false,
+            };
+
+            let transform = Transform::new(
+                position,
+                player.transform.get_yaw(),
+                player.transform.get_pitch(),
+            );
+
+            core.queue_action(Action::Teleport(player.uid, transform));
+
+            true
+        })
+    });
+    engine.register_fn("join_world", |uid: i64, world: &str| {
+        with_core(|core| core.queue_action(Action::JoinWorld(uid as usize, String::from(world))))
+    });
+
+    engine
+}
+
+// A loaded script and the state its functions share.
+struct Script {
+    name: String,
+    ast: AST,
+    state: Dynamic,
+}
+
+impl Script {
+    fn has_function(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == params)
+    }
+}
+
+/// Scripts of a folder, compiled with the bindings to the core.
+pub struct ScriptList {
+    engine: Engine,
+    folder: PathBuf,
+    scripts: Vec<Script>,
+    // Modification time of every file seen, including the ones failing to compile.
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl ScriptList {
+    pub fn new<P: AsRef<Path>>(folder: P) -> ScriptList {
+        ScriptList {
+            engine: create_engine(),
+            folder: folder.as_ref().to_path_buf(),
+            scripts: Vec::new(),
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Names of the loaded scripts, in the order they were loaded.
+    pub fn get_names(&self) -> Vec<String> {
+        self.scripts
+            .iter()
+            .map(|script| script.name.clone())
+            .collect()
+    }
+
+    /// Compiles a script and runs its top level, replacing the script with the same name.
+    pub fn load(&mut self, core: &Core, name: &str, source: &str) -> Result<(), String> {
+        let ast = self
+            .engine
+            .compile(source)
+            .map_err(|e| format!("{} ({})", e, e.position()))?;
+
+        self.unload(core, name);
+
+        let _scope = CoreScope::new(core);
+
+        self.engine.run_ast(&ast).map_err(|e| e.to_string())?;
+
+        self.scripts.push(Script {
+            name: String::from(name),
+            ast,
+            state: ScriptMap::new().into(),
+        });
+
+        self.call_one(core, name, "on_load", ());
+
+        Ok(())
+    }
+
+    /// Calls `on_unload` of a script and removes it.
+    pub fn unload(&mut self, core: &Core, name: &str) -> bool {
+        if !self.get_names().iter().any(|loaded| loaded == name) {
+            return false;
+        }
+
+        self.call_one(core, name, "on_unload", ());
+        self.scripts.retain(|script| script.name != name);
+
+        true
+    }
+
+    /// Loads new and changed scripts of the folder, unloading the ones removed.
+    /// Returns whether anything changed.
+    pub fn reload(&mut self, core: &Core) -> bool {
+        let mut found = HashMap::new();
+
+        if let Ok(entries) = fs::read_dir(&self.folder) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "rhai")
+                {
+                    if let Ok(modified) = entry.metadata().and_then(|data| data.modified()) {
+                        found.insert(path, modified);
+                    }
+                }
+            }
+        }
+
+        let removed: Vec<PathBuf> = self
+            .modified
+            .keys()
+            .filter(|path| !found.contains_key(*path))
+            .cloned()
+            .collect();
+        let mut changed = !removed.is_empty();
+
+        for path in removed {
+            self.modified.remove(&path);
+
+            if self.unload(core, &script_name(&path)) {
+                core.log(&format!(
+                    "Script \"{}\" has been unloaded.",
+                    script_name(&path)
+                ));
+            }
+        }
+
+        let mut paths: Vec<(PathBuf, SystemTime)> = found.into_iter().collect();
+        paths.sort();
+
+        for (path, modified) in paths {
+            if self.modified.get(&path) == Some(&modified) {
+                continue;
+            }
+
+            self.modified.insert(path.clone(), modified);
+            changed = true;
+
+            let name = script_name(&path);
+            let result = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| self.load(core, &name, &source));
+
+            match result {
+                Ok(()) => core.log(&format!("Script \"{}\" has been loaded.", name)),
+                Err(e) => core.log(&format!("Unable to load script \"{}\": {}", name, e)),
+            };
+        }
+
+        changed
+    }
+
+    /// Calls a function on every script defining it, returning what each of them returned.
+    /// Errors are logged, a failing script does not keep the others from being called.
+    pub fn call<A: FuncArgs + Clone>(
+        &mut self,
+        core: &Core,
+        function: &str,
+        args: A,
+    ) -> Vec<Dynamic> {
+        let names: Vec<String> = self.get_names();
+
+        names
+            .iter()
+            .filter_map(|name| self.call_one(core, name, function, args.clone()))
+            .collect()
+    }
+
+    fn call_one<A: FuncArgs>(
+        &mut self,
+        core: &Core,
+        name: &str,
+        function: &str,
+        args: A,
+    ) -> Option<Dynamic> {
+        let mut arg_values = Vec::new();
+        args.parse(&mut arg_values);
+
+        let engine = &self.engine;
+        let script = self
+            .scripts
+            .iter_mut()
+            .find(|script| script.name == name)
+            .filter(|script| script.has_function(function, arg_values.len()))?;
+
+        let _scope = CoreScope::new(core);
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut script.state);
+
+        match engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &script.ast,
+            function,
+            arg_values,
+        ) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                core.log(&format!(
+                    "Script \"{}\" failed in {}: {}",
+                    name, function, e
+                ));
+
+                None
+            }
+        }
+    }
+}
+
+fn script_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether any script asked for the event to be cancelled.
+fn is_cancelled(results: &[Dynamic]) -> bool {
+    results
+        .iter()
+        .any(|result| result.as_bool().unwrap_or(false))
+}
+
+type SharedScripts = Arc<Mutex<ScriptList>>;
+
+// Calls the scripts unless they are already running, like an event fired by a binding.
+fn call_scripts<A: FuncArgs + Clone>(
+    scripts: &SharedScripts,
+    core: &Core,
+    function: &str,
+    args: A,
+) -> Vec<Dynamic> {
+    match scripts.try_lock() {
+        Ok(mut scripts) => scripts.call(core, function, args),
+        Err(_) => {
+            core.log(&format!(
+                "Scripts are busy, {} is not called for them.",
+                function
+            ));
+
+            Vec::new()
+        }
+    }
+}
+
+// Registers a handler passing an event to the scripts.
+fn forward<E: Event, F>(events: &mut EventBus, scripts: &SharedScripts, handler: F)
+where
+    F: Fn(&SharedScripts, &Core, &mut E) + Send + Sync + 'static,
+{
+    let scripts = scripts.clone();
+
+    events.register(Priority::Normal, move |core: &Core, event: &mut E| {
+        handler(&scripts, core, event)
+    });
+}
+
+/// Runs the scripts of a folder on events, reloading them when they change.
+pub struct ScriptPlugin {
+    scripts: SharedScripts,
+}
+
+impl ScriptPlugin {
+    pub fn new<P: AsRef<Path>>(folder: P) -> ScriptPlugin {
+        ScriptPlugin {
+            scripts: Arc::new(Mutex::new(ScriptList::new(folder))),
+        }
+    }
+}
+
+impl Plugin for ScriptPlugin {
+    fn get_name(&self) -> &str {
+        "Scripts"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut PlayerJoinEvent| {
+                let results =
+                    call_scripts(scripts, core, "on_join", (player_to_map(&event.player),));
+
+                event.set_cancelled(is_cancelled(&results));
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut PlayerLeaveEvent| {
+                call_scripts(scripts, core, "on_leave", (player_to_map(&event.player),));
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut PlayerChatEvent| {
+                let args = (player_to_map(&event.player), event.message.clone());
+
+                for result in call_scripts(scripts, core, "on_chat", args) {
+                    if result.is_string() {
+                        event.message = result.to_string();
+                    } else if result.as_bool().unwrap_or(false) {
+                        event.set_cancelled(true);
+                    }
+                }
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut PlayerMoveEvent| {
+                let results =
+                    call_scripts(scripts, core, "on_move", (player_to_map(&event.player),));
+
+                event.set_cancelled(is_cancelled(&results));
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut SetBlockEvent| {
+                let position = &event.position;
+                let args = (
+                    player_to_map(&event.player),
+                    i64::from(position.get_x()),
+                    i64::from(position.get_y()),
+                    i64::from(position.get_z()),
+                    i64::from(event.block),
+                    event.destroy,
+                );
+
+                for result in call_scripts(scripts, core, "on_set_block", args) {
+                    if let Ok(block) = result.as_int() {
+                        if let Ok(block) = u8::try_from(block) {
+                            event.block = block;
+                            event.destroy = false;
+                        }
+                    } else if result.as_bool().unwrap_or(false) {
+                        event.set_cancelled(true);
+                    }
+                }
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut WorldJoinEvent| {
+                let args = (player_to_map(&event.player), event.world.clone());
+                let results = call_scripts(scripts, core, "on_world_join", args);
+
+                event.set_cancelled(is_cancelled(&results));
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut WorldJoinedEvent| {
+                let args = (player_to_map(&event.player), event.world.clone());
+
+                call_scripts(scripts, core, "on_world_joined", args);
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut WorldLoadEvent| {
+                call_scripts(scripts, core, "on_world_load", (event.world.clone(),));
+            },
+        );
+        forward(
+            events,
+            &self.scripts,
+            |scripts, core, event: &mut WorldUnloadEvent| {
+                let results =
+                    call_scripts(scripts, core, "on_world_unload", (event.world.clone(),));
+
+                event.set_cancelled(is_cancelled(&results));
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let name = self.get_name();
+
+        self.scripts.lock().unwrap().reload(core);
+
+        let scripts = self.scripts.clone();
+        let mut ticks = 0;
+
+        core.schedule_repeating(name, 1, move |core| {
+            ticks += 1;
+
+            if ticks % RELOAD_INTERVAL == 0 {
+                scripts.lock().unwrap().reload(core);
+            }
+
+            call_scripts(&scripts, core, "on_tick", ());
+        });
+
+        let scripts = self.scripts.clone();
+
+        core.register_command(Command::new(
+            name,
+            "scripts",
+            "Lists or reloads scripts.",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| match args.trim() {
+                "" => {
+                    core.send_message_to(player.uid, "&6Scripts:");
+
+                    for name in scripts.lock().unwrap().get_names() {
+                        core.send_message_to(player.uid, &format!("&7{}", name));
+                    }
+                }
+                "reload" if player.rank < Rank::Operator => {
+                    core.send_message_to(
+                        player.uid,
+                        "&8You need to be operator to reload scripts.",
+                    );
+                }
+                "reload" => {
+                    let mut scripts = scripts.lock().unwrap();
+
+                    // Forget every file so all of them are loaded again.
+                    scripts.modified.clear();
+                    scripts.reload(core);
+
+                    core.send_message_to(
+                        player.uid,
+                        &format!("&7Reloaded {} scripts.", scripts.get_names().len()),
+                    );
+                }
+                _ => core.send_message_to(player.uid, "&7Usage: /scripts [reload]"),
+            },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test_script {
+    use super::super::Config;
+    use super::*;
+    use std::env;
+
+    fn scripts_folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("rcclassic-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        folder
+    }
+
+    #[test]
+    /// Scripts change events through their return values and the world through the bindings.
+    pub fn events_reach_scripts() {
+        let folder = scripts_folder("events");
+
+        fs::write(
+            folder.join("rules.rhai"),
+            r#"
+            fn on_load() { this.said = 0; }
+            fn on_chat(player, message) {
+                this.said += 1;
+                if message == "secret" { return true; }
+                message.to_upper() + " " + this.said
+            }
+            fn on_set_block(player, x, y, z, block, destroy) {
+                set_block("main", x, y + 1, z, block);
+                if teleport(player.uid, 9223372036854775807, y, z) { return 0; }
+                4
+            }
+            "#,
+        )
+        .unwrap();
+
+        let core = Core::with_config(
+            1,
+            Config {
+                scripts_folder: folder.clone(),
+                ..Config::in_folder(&folder)
+            },
+        );
+
+        let console = core.get_player_snapshot(0).unwrap();
+
+        let mut event = PlayerChatEvent::new(console.clone(), String::from("hello"));
+        assert!(!core.call_event(&mut event));
+        assert_eq!(event.message, "HELLO 1");
+
+        let mut event = PlayerChatEvent::new(console.clone(), String::from("secret"));
+        assert!(core.call_event(&mut event));
+
+        // Building is refused by the read-only mode before scripts see it, call them directly.
+        let mut scripts = ScriptList::new(&folder);
+        scripts.reload(&core);
+
+        let args = (player_to_map(&console), 1_i64, 1_i64, 1_i64, 1_i64, false);
+        let results = scripts.call(&core, "on_set_block", args);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_int(), Ok(4));
+        assert_eq!(
+            core.with_world("main", |world| world.get_block(&Vec3D::new(1, 2, 1))),
+            Some(1)
+        );
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    /// New, changed and removed files are picked up, broken or endless scripts do not stop the core.
+    pub fn reload_folder() {
+        let folder = scripts_folder("reload");
+        let core = Core::with_config(1, Config::in_folder(&folder));
+        let mut scripts = ScriptList::new(&folder);
+
+        fs::write(folder.join("a.rhai"), "fn on_tick() { 1 }").unwrap();
+        fs::write(folder.join("broken.rhai"), "fn on_tick( {").unwrap();
+        assert!(scripts.reload(&core));
+        assert_eq!(scripts.get_names(), vec!["a"]);
+        assert!(!scripts.reload(&core));
+
+        fs::write(folder.join("b.rhai"), "fn on_tick() { loop {} }").unwrap();
+        assert!(scripts.reload(&core));
+        assert_eq!(scripts.get_names(), vec!["a", "b"]);
+        assert_eq!(scripts.call(&core, "on_tick", ()).len(), 1);
+
+        fs::remove_file(folder.join("a.rhai")).unwrap();
+        assert!(scripts.reload(&core));
+        assert_eq!(scripts.get_names(), vec!["b"]);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
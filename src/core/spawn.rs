@@ -0,0 +1,511 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Spawns players set for themselves in each world, and where they last were.
+//!
+//! Each player has a file in the folder, with a line per position: "spawn" or "last", the
+//! world, then x, y, z, yaw and pitch.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::super::network::SetSpawnpoint;
+use super::events::{PlayerLeaveEvent, WorldJoinEvent, WorldJoinedEvent};
+use super::{
+    Action, Command, Core, EventBus, PlayerSnapshot, Plugin, Priority, Rank, Transform, Vec3D,
+};
+
+/// Folder positions of players are kept in.
+pub const SPAWNS_FOLDER: &str = "spawns";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PositionKind {
+    /// Where the player respawns in a world.
+    Spawn,
+    /// Where the player was when leaving a world.
+    Last,
+}
+
+impl PositionKind {
+    fn get_name(self) -> &'static str {
+        match self {
+            PositionKind::Spawn => "spawn",
+            PositionKind::Last => "last",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<PositionKind> {
+        match name {
+            "spawn" => Some(PositionKind::Spawn),
+            "last" => Some(PositionKind::Last),
+            _ => None,
+        }
+    }
+}
+
+type Positions = HashMap<(PositionKind, String), Transform>;
+
+/// Positions of players by world, read from their files once needed.
+pub struct Spawns {
+    folder: PathBuf,
+    players: Mutex<HashMap<String, Positions>>,
+}
+
+impl Spawns {
+    pub fn new<P: AsRef<Path>>(folder: P) -> Spawns {
+        Spawns {
+            folder: folder.as_ref().to_path_buf(),
+            players: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_path(&self, player: &str) -> PathBuf {
+        self.folder.join(format!("{}.txt", player.to_lowercase()))
+    }
+
+    fn read(&self, player: &str) -> Positions {
+        let content = fs::read_to_string(self.get_path(player)).unwrap_or_default();
+
+        content
+            .lines()
+            .filter_map(|line| {
+                let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+
+                if parts.len() != 7 {
+                    return None;
+                }
+
+                let number = |index: usize| parts[index].parse::<u16>().ok();
+                let byte = |index: usize| parts[index].parse::<u8>().ok();
+
+                Some((
+                    (PositionKind::from_name(parts[0])?, String::from(parts[1])),
+                    Transform::new(
+                        Vec3D::new(number(2)?, number(3)?, number(4)?),
+                        byte(5)?,
+                        byte(6)?,
+                    ),
+                ))
+            })
+            .collect()
+    }
+
+    fn write(&self, player: &str, positions: &Positions) -> io::Result<()> {
+        let mut lines: Vec<String> = positions
+            .iter()
+            .map(|((kind, world), transform)| {
+                let Vec3D(x, y, z) = *transform.get_pos();
+
+                format!(
+                    "{} {} {} {} {} {} {}",
+                    kind.get_name(),
+                    world,
+                    x,
+                    y,
+                    z,
+                    transform.get_yaw(),
+                    transform.get_pitch()
+                )
+            })
+            .collect();
+
+        lines.sort();
+
+        fs::create_dir_all(&self.folder)?;
+        fs::write(self.get_path(player), lines.join("\n") + "\n")
+    }
+
+    pub fn get(&self, player: &str, kind: PositionKind, world: &str) -> Option<Transform> {
+        let mut players = self.players.lock().unwrap();
+
+        players
+            .entry(player.to_lowercase())
+            .or_insert_with(|| self.read(player))
+            .get(&(kind, String::from(world)))
+            .cloned()
+    }
+
+    /// Sets or removes a position of a player, writing their file.
+    pub fn set(
+        &self,
+        player: &str,
+        kind: PositionKind,
+        world: &str,
+        transform: Option<Transform>,
+    ) -> io::Result<()> {
+        let mut players = self.players.lock().unwrap();
+        let positions = players
+            .entry(player.to_lowercase())
+            .or_insert_with(|| self.read(player));
+
+        let key = (kind, String::from(world));
+
+        match transform {
+            Some(transform) => positions.insert(key, transform),
+            None => positions.remove(&key),
+        };
+
+        self.write(player, positions)
+    }
+
+    /// Drops the positions of a player from memory, they are read again once needed.
+    pub fn forget(&self, player: &str) {
+        self.players.lock().unwrap().remove(&player.to_lowercase());
+    }
+}
+
+/// Spawn of a player in a world, their own if they set one.
+fn get_spawn(spawns: &Spawns, core: &Core, player: &str, world: &str) -> Option<Transform> {
+    spawns
+        .get(player, PositionKind::Spawn, world)
+        .or_else(|| core.with_world(world, |world| world.get_spawn()))
+}
+
+/// Tells a player's client where they respawn, if it supports doing so.
+fn send_spawnpoint(core: &Core, uid: usize, spawn: Transform) {
+    core.queue_action(Action::SendExtPacket(
+        uid,
+        "SetSpawnpoint",
+        Box::new(SetSpawnpoint::new(spawn)),
+    ));
+}
+
+/// Sends players to their own spawn when joining a world, remembering where they left the last.
+pub struct SpawnPlugin {
+    spawns: Arc<Spawns>,
+}
+
+impl SpawnPlugin {
+    pub fn new<P: AsRef<Path>>(folder: P) -> SpawnPlugin {
+        SpawnPlugin {
+            spawns: Arc::new(Spawns::new(folder)),
+        }
+    }
+}
+
+impl Plugin for SpawnPlugin {
+    fn get_name(&self) -> &str {
+        "Spawns"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        let spawns = self.spawns.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut WorldJoinEvent| {
+                let player = &event.player;
+
+                if !player.world.is_empty() {
+                    remember(&spawns, core, player);
+                }
+
+                let remembered = || {
+                    if core.get_config().remember_positions {
+                        spawns.get(&player.name, PositionKind::Last, &event.world)
+                    } else {
+                        None
+                    }
+                };
+
+                if event.position.is_none() {
+                    event.position = spawns
+                        .get(&player.name, PositionKind::Spawn, &event.world)
+                        .or_else(remembered);
+                }
+            },
+        );
+
+        let spawns = self.spawns.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut WorldJoinedEvent| {
+                let player = &event.player;
+
+                if let Some(spawn) = get_spawn(&spawns, core, &player.name, &event.world) {
+                    send_spawnpoint(core, player.uid, spawn);
+                }
+            },
+        );
+
+        let spawns = self.spawns.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut PlayerLeaveEvent| {
+                if !event.player.world.is_empty() {
+                    remember(&spawns, core, &event.player);
+                }
+
+                spawns.forget(&event.player.name);
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let spawns = self.spawns.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "setspawn",
+            "Sets the spawn of your world where you are, or your own with me. [me|me del]",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let args: Vec<&str> = args.split_ascii_whitespace().collect();
+
+                match args.as_slice() {
+                    [] => set_world_spawn(&spawns, core, player),
+                    ["me"] | ["me", "del"] => {
+                        let transform = match args.len() {
+                            1 => Some(player.transform.clone()),
+                            _ => None,
+                        };
+
+                        let message = match spawns.set(
+                            &player.name,
+                            PositionKind::Spawn,
+                            &player.world,
+                            transform,
+                        ) {
+                            Ok(()) if args.len() == 1 => "&7Your spawn has been set.",
+                            Ok(()) => "&7Your spawn has been removed.",
+                            Err(e) => {
+                                core.log(&format!(
+                                    "Unable to save the spawn of {}: {}",
+                                    player.name, e
+                                ));
+
+                                "&8Unable to save your spawn."
+                            }
+                        };
+
+                        if let Some(spawn) = get_spawn(&spawns, core, &player.name, &player.world) {
+                            send_spawnpoint(core, player.uid, spawn);
+                        }
+
+                        core.send_message_to(player.uid, message);
+                    }
+                    _ => core.send_message_to(player.uid, "&7Usage: /setspawn [me|me del]"),
+                }
+            },
+        ));
+
+        let spawns = self.spawns.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "spawn",
+            "Takes you back to your spawn.",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, _args: &str| {
+                if let Some(spawn) = get_spawn(&spawns, core, &player.name, &player.world) {
+                    core.queue_action(Action::Teleport(player.uid, spawn));
+                }
+            },
+        ));
+    }
+}
+
+// Keeps where a player is as their last position in their world.
+fn remember(spawns: &Spawns, core: &Core, player: &PlayerSnapshot) {
+    if let Err(e) = spawns.set(
+        &player.name,
+        PositionKind::Last,
+        &player.world,
+        Some(player.transform.clone()),
+    ) {
+        core.log(&format!(
+            "Unable to save the position of {}: {}",
+            player.name, e
+        ));
+    }
+}
+
+fn set_world_spawn(spawns: &Spawns, core: &Core, player: &PlayerSnapshot) {
+    if player.rank < Rank::Operator {
+        core.send_message_to(
+            player.uid,
+            "&8Only operators can set the spawn of a world, see /setspawn me.",
+        );
+
+        return;
+    }
+
+    let saved = core.with_world_mut(&player.world, |world| {
+        world.set_spawn(player.transform.clone());
+
+        (
+            world.save(core.get_maps_folder()),
+            world.get_players().clone(),
+        )
+    });
+
+    let (saved, players) = match saved {
+        Some(saved) => saved,
+        None => return,
+    };
+
+    // Players with a spawn of their own keep it.
+    for other in core.get_player_snapshots() {
+        if players.contains(&other.uid)
+            && spawns
+                .get(&other.name, PositionKind::Spawn, &player.world)
+                .is_none()
+        {
+            send_spawnpoint(core, other.uid, player.transform.clone());
+        }
+    }
+
+    let message = if saved {
+        format!("&7Spawn of \"{}\" has been set.", player.world)
+    } else {
+        format!(
+            "&8Spawn of \"{}\" set, but the map could not be saved.",
+            player.world
+        )
+    };
+
+    core.send_message_to(player.uid, &message);
+}
+
+#[cfg(test)]
+mod test_spawn {
+    use super::super::{Config, NetworkPlayer, Outgoing, Player, Transport};
+    use super::*;
+    use std::env;
+    use std::sync::mpsc;
+
+    #[test]
+    /// Positions are kept by world and read back from files.
+    pub fn save_and_load() {
+        let folder = env::temp_dir().join(format!("rcclassic-spawns-{}", std::process::id()));
+        let spawns = Spawns::new(&folder);
+        let spawn = Transform::at_block(Vec3D::new(3, 10, 4), 64, 0);
+
+        assert!(spawns
+            .set("Alice", PositionKind::Spawn, "lobby", Some(spawn.clone()))
+            .is_ok());
+        assert!(spawns
+            .set(
+                "Alice",
+                PositionKind::Last,
+                "main",
+                Some(Transform::default())
+            )
+            .is_ok());
+
+        let read = Spawns::new(&folder);
+        let loaded = read.get("alice", PositionKind::Spawn, "lobby").unwrap();
+
+        assert_eq!(loaded.get_pos(), spawn.get_pos());
+        assert_eq!(loaded.get_yaw(), 64);
+        assert!(read.get("alice", PositionKind::Spawn, "main").is_none());
+        assert!(read.get("alice", PositionKind::Last, "main").is_some());
+
+        assert!(spawns
+            .set("alice", PositionKind::Spawn, "lobby", None)
+            .is_ok());
+        spawns.forget("alice");
+        assert!(spawns.get("alice", PositionKind::Spawn, "lobby").is_none());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    /// Players joining a world appear at their own spawn there.
+    pub fn join_at_own_spawn() {
+        let folder = env::temp_dir().join(format!("rcclassic-join-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+
+        let mut player = core.get_player_snapshot(0).unwrap();
+        player.transform = Transform::at_block(Vec3D::new(5, 40, 6), 0, 0);
+        player.rank = Rank::Guest;
+
+        assert!(core.try_run_command(&player, "/setspawn me"));
+
+        let mut event = WorldJoinEvent::new(player.clone(), String::from("main"));
+        assert!(!core.call_event(&mut event));
+
+        assert_eq!(
+            event.position.map(|position| position.get_feet_block()),
+            Some(Vec3D::new(5, 40, 6))
+        );
+
+        // Only operators can move the spawn of the world.
+        assert!(core.try_run_command(&player, "/setspawn"));
+        assert_eq!(
+            core.with_world("main", |world| world.get_spawn().get_feet_block()),
+            Some(Vec3D::new(32, 32, 32))
+        );
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    /// Respawn points are only sent to clients which negotiated SetSpawnpoint.
+    pub fn spawnpoint_needs_extension() {
+        let folder = env::temp_dir().join(format!("rcclassic-spawnpoint-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+
+        let mut inboxes = Vec::new();
+
+        for (uid, extension) in [(1, None), (2, Some("SetSpawnpoint"))] {
+            let (writer, inbox) = mpsc::channel();
+            let mut player = NetworkPlayer::new(uid, writer, Transport::Classic);
+
+            player.set_name(&format!("player{}", uid));
+            player.set_world("main");
+
+            if let Some(extension) = extension {
+                player.add_extension(extension);
+            }
+
+            core.add_player(uid, Box::new(player));
+
+            let snapshot = core.get_player_snapshot(uid).unwrap();
+            assert!(core.try_run_command(&snapshot, "/setspawn me"));
+
+            inboxes.push(inbox);
+        }
+
+        core.process_actions();
+
+        let spawnpoints = |inbox: &mpsc::Receiver<Outgoing>| {
+            inbox
+                .try_iter()
+                .filter(|outgoing| match outgoing {
+                    Outgoing::Data(data) => data[0] == SetSpawnpoint::ID,
+                    Outgoing::Close => false,
+                })
+                .count()
+        };
+
+        assert_eq!(spawnpoints(&inboxes[0]), 0);
+        assert_eq!(spawnpoints(&inboxes[1]), 1);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,671 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Blocks doing something when players walk into them, kept next to the map in "{world}.blocks".
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::events::{PlayerLeaveEvent, PlayerMoveEvent, SetBlockEvent};
+use super::{
+    is_valid_map_name, parse_duration, Action, Command, Core, Event, EventBus, PlayerSnapshot,
+    Plugin, Priority, Rank, Transform, Vec3D,
+};
+
+/// What a block does when walked into.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SpecialBlock {
+    /// Moves the player into a world, at a block or its spawn if there is none.
+    Portal(String, Option<Vec3D>),
+    /// Shows a message to the player, also when clicking the block.
+    Message(String),
+    /// Runs commands as the player, with their own rank.
+    /// Players wait the cooldown before running them again.
+    Commands(Duration, Vec<String>),
+}
+
+/// Time players wait between running the commands of a block, unless given.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(1);
+
+impl SpecialBlock {
+    // One line of a blocks file, starting with the kind and position of the block.
+    fn to_line(&self, position: &Vec3D) -> String {
+        let Vec3D(x, y, z) = *position;
+
+        match self {
+            SpecialBlock::Portal(world, Some(Vec3D(dx, dy, dz))) => {
+                format!("portal {} {} {} {} {} {} {}", x, y, z, world, dx, dy, dz)
+            }
+            SpecialBlock::Portal(world, None) => format!("portal {} {} {} {}", x, y, z, world),
+            SpecialBlock::Message(message) => format!("message {} {} {} {}", x, y, z, message),
+            SpecialBlock::Commands(cooldown, commands) => format!(
+                "commands {} {} {} {} {}",
+                x,
+                y,
+                z,
+                cooldown.as_secs(),
+                commands.join("; ")
+            ),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<(Vec3D, SpecialBlock)> {
+        let mut parts = line.splitn(5, ' ');
+        let kind = parts.next()?;
+        let mut number = || parts.next()?.parse::<u16>().ok();
+        let position = Vec3D::new(number()?, number()?, number()?);
+        let rest = parts.next()?;
+
+        let block = match kind {
+            "portal" => {
+                let parts: Vec<&str> = rest.split_ascii_whitespace().collect();
+                let number = |index: usize| parts.get(index)?.parse::<u16>().ok();
+
+                let destination = match parts.len() {
+                    1 => None,
+                    4 => Some(Vec3D::new(number(1)?, number(2)?, number(3)?)),
+                    _ => return None,
+                };
+
+                SpecialBlock::Portal(String::from(parts[0]), destination)
+            }
+            "message" => SpecialBlock::Message(String::from(rest)),
+            "commands" => {
+                // Blocks used to keep the rank of who placed them, which is skipped.
+                let rest = match rest.split_once(' ') {
+                    Some((rank, rest)) if rank.parse::<Rank>().is_ok() => rest,
+                    _ => rest,
+                };
+                let (cooldown, commands) = rest.split_once(' ')?;
+
+                SpecialBlock::Commands(
+                    Duration::from_secs(cooldown.parse().ok()?),
+                    parse_commands(commands)?,
+                )
+            }
+            _ => return None,
+        };
+
+        Some((position, block))
+    }
+}
+
+/// Splits commands separated by semicolons, None unless each starts with a slash.
+pub fn parse_commands(commands: &str) -> Option<Vec<String>> {
+    let commands: Vec<String> = commands
+        .split(';')
+        .map(|command| String::from(command.trim()))
+        .collect();
+
+    if commands
+        .iter()
+        .any(|command| !command.starts_with('/') || command.len() < 2)
+    {
+        return None;
+    }
+
+    Some(commands)
+}
+
+/// File the special blocks of a world are kept in, next to its map.
+pub fn get_special_blocks_path(maps_folder: &Path, world: &str) -> PathBuf {
+    maps_folder.join(format!("{}.blocks", world))
+}
+
+/// Reads special blocks from a file, none if it does not exist. Invalid lines are skipped.
+pub fn load_special_blocks(path: &Path) -> HashMap<Vec3D, SpecialBlock> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return HashMap::new(),
+    };
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let block = SpecialBlock::from_line(line);
+
+            if block.is_none() {
+                Core::static_log(&format!(
+                    "Invalid special block \"{}\" in \"{}\".",
+                    line,
+                    path.display()
+                ));
+            }
+
+            block
+        })
+        .collect()
+}
+
+/// Writes special blocks into a file, removing the file once there are none.
+pub fn save_special_blocks(path: &Path, blocks: &HashMap<Vec3D, SpecialBlock>) -> bool {
+    let written = if blocks.is_empty() {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    } else {
+        // Same order as map chunks, so the file does not change when the blocks do not.
+        let mut positions: Vec<&Vec3D> = blocks.keys().collect();
+        positions.sort_by_key(|position| (position.1, position.2, position.0));
+
+        let lines: Vec<String> = positions
+            .into_iter()
+            .map(|position| blocks[position].to_line(position))
+            .collect();
+
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, lines.join("\n") + "\n"))
+    };
+
+    match written {
+        Ok(()) => true,
+        Err(e) => {
+            Core::static_log(&format!(
+                "Unable to save special blocks into \"{}\": {}",
+                path.display(),
+                e
+            ));
+
+            false
+        }
+    }
+}
+
+/// Blocks a player's body is in, feet first, from the position of their eyes.
+pub fn get_body_blocks(transform: &Transform) -> [Vec3D; 2] {
+    let feet = transform.get_feet_block();
+
+    [feet, Vec3D::new(feet.0, feet.1 + 1, feet.2)]
+}
+
+// Special block the next click sets, None removing the clicked one.
+type Sessions = Arc<Mutex<HashMap<usize, Option<SpecialBlock>>>>;
+// When players last ran the commands of a block, by their uid, world and the block.
+type Cooldowns = Arc<Mutex<HashMap<(usize, String, Vec3D), Instant>>>;
+
+/// Portals, message and command blocks, placed with /portal, /mb and /cb.
+pub struct SpecialBlockPlugin {
+    sessions: Sessions,
+    cooldowns: Cooldowns,
+}
+
+impl SpecialBlockPlugin {
+    pub fn new() -> SpecialBlockPlugin {
+        SpecialBlockPlugin {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            cooldowns: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl Default for SpecialBlockPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for SpecialBlockPlugin {
+    fn get_name(&self) -> &str {
+        "SpecialBlocks"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        let sessions = self.sessions.clone();
+        let cooldowns = self.cooldowns.clone();
+
+        events.register(
+            Priority::Highest,
+            move |core: &Core, event: &mut SetBlockEvent| click(&sessions, &cooldowns, core, event),
+        );
+
+        let cooldowns = self.cooldowns.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |core: &Core, event: &mut PlayerMoveEvent| walk_into(&cooldowns, core, event),
+        );
+
+        let sessions = self.sessions.clone();
+        let cooldowns = self.cooldowns.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |_: &Core, event: &mut PlayerLeaveEvent| {
+                let uid = event.player.uid;
+
+                sessions.lock().unwrap().remove(&uid);
+                cooldowns
+                    .lock()
+                    .unwrap()
+                    .retain(|(player, _, _), _| *player != uid);
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let sessions = self.sessions.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "portal",
+            "Makes a block move players into a world. {world} [x y z]|del",
+            Rank::Operator,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let args: Vec<&str> = args.split_ascii_whitespace().collect();
+                let number = |index: usize| args.get(index).and_then(|n| n.parse::<u16>().ok());
+
+                let block = match (args.as_slice(), number(1), number(2), number(3)) {
+                    (["del"], _, _, _) => None,
+                    ([world], _, _, _) if is_valid_map_name(world) => {
+                        Some(SpecialBlock::Portal(world.to_lowercase(), None))
+                    }
+                    ([world, _, _, _], Some(x), Some(y), Some(z)) if is_valid_map_name(world) => {
+                        Some(SpecialBlock::Portal(
+                            world.to_lowercase(),
+                            Some(Vec3D::new(x, y, z)),
+                        ))
+                    }
+                    _ => {
+                        core.send_message_to(player.uid, "&7Usage: /portal {world} [x y z]|del");
+
+                        return;
+                    }
+                };
+
+                start(&sessions, core, player, block);
+            },
+        ));
+
+        let sessions = self.sessions.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "mb",
+            "Makes a block show a message. {message}|del",
+            Rank::Operator,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let block = match args.trim() {
+                    "" => {
+                        core.send_message_to(player.uid, "&7Usage: /mb {message}|del");
+
+                        return;
+                    }
+                    "del" => None,
+                    message => Some(SpecialBlock::Message(String::from(message))),
+                };
+
+                start(&sessions, core, player, block);
+            },
+        ));
+
+        let sessions = self.sessions.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "cb",
+            "Makes a block run commands, with the rank of who uses it. [cooldown] {/command}[; /command...]|del",
+            Rank::Operator,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let args = args.trim();
+
+                let (cooldown, commands) = match args.split_once(' ') {
+                    Some((first, rest)) if !first.starts_with('/') => (parse_duration(first), rest),
+                    _ => (Some(DEFAULT_COOLDOWN), args),
+                };
+
+                let block = match (args, cooldown, parse_commands(commands)) {
+                    ("del", _, _) => None,
+                    (_, Some(cooldown), Some(commands)) => {
+                        Some(SpecialBlock::Commands(cooldown, commands))
+                    }
+                    _ => {
+                        core.send_message_to(
+                            player.uid,
+                            "&7Usage: /cb [cooldown] {/command}[; /command...]|del",
+                        );
+
+                        return;
+                    }
+                };
+
+                start(&sessions, core, player, block);
+            },
+        ));
+    }
+}
+
+fn start(sessions: &Sessions, core: &Core, player: &PlayerSnapshot, block: Option<SpecialBlock>) {
+    let message = match block {
+        Some(_) => "&7Place or break a block to make it special.",
+        None => "&7Place or break a block to make it normal again.",
+    };
+
+    sessions.lock().unwrap().insert(player.uid, block);
+    core.send_message_to(player.uid, message);
+}
+
+fn click(sessions: &Sessions, cooldowns: &Cooldowns, core: &Core, event: &mut SetBlockEvent) {
+    let uid = event.player.uid;
+    let pending = sessions.lock().unwrap().remove(&uid);
+
+    let pending = match pending {
+        Some(pending) => pending,
+        None => {
+            let block = core
+                .with_world(&event.world, |world| {
+                    world.get_special_block(&event.position).cloned()
+                })
+                .flatten();
+
+            // Clicking a message or command block uses it, and leaves the block as it is.
+            match block {
+                Some(SpecialBlock::Message(message)) => core.send_message_to(uid, &message),
+                Some(SpecialBlock::Commands(cooldown, commands)) => run_commands(
+                    cooldowns,
+                    core,
+                    &event.player,
+                    (&event.world, event.position),
+                    cooldown,
+                    &commands,
+                ),
+                _ => return,
+            }
+
+            event.set_cancelled(true);
+
+            return;
+        }
+    };
+
+    event.set_cancelled(true);
+
+    let blocks = core.with_world_mut(&event.world, |world| {
+        world.set_special_block(event.position, pending.clone());
+
+        world.get_special_blocks().clone()
+    });
+
+    let message = match (blocks, pending) {
+        (Some(blocks), pending) => {
+            save_special_blocks(
+                &get_special_blocks_path(core.get_maps_folder(), &event.world),
+                &blocks,
+            );
+
+            match pending {
+                Some(SpecialBlock::Portal(world, _)) => {
+                    format!("&7Portal to \"{}\" placed.", world)
+                }
+                Some(SpecialBlock::Message(_)) => String::from("&7Message block placed."),
+                Some(SpecialBlock::Commands(..)) => String::from("&7Command block placed."),
+                None => String::from("&7Block is normal again."),
+            }
+        }
+        (None, _) => String::from("&8Unable to change the block."),
+    };
+
+    core.send_message_to(uid, &message);
+}
+
+// Runs the commands of a block at a position in a world, unless the player is cooling down.
+fn run_commands(
+    cooldowns: &Cooldowns,
+    core: &Core,
+    player: &PlayerSnapshot,
+    (world, position): (&str, Vec3D),
+    cooldown: Duration,
+    commands: &[String],
+) {
+    {
+        let mut cooldowns = cooldowns.lock().unwrap();
+        let key = (player.uid, String::from(world), position);
+
+        match cooldowns.get(&key) {
+            Some(last) if last.elapsed() < cooldown => return,
+            _ => cooldowns.insert(key, Instant::now()),
+        };
+    }
+
+    for command in commands {
+        core.try_run_command(player, command);
+    }
+}
+
+fn walk_into(cooldowns: &Cooldowns, core: &Core, event: &mut PlayerMoveEvent) {
+    let before = get_body_blocks(&event.from);
+    let after = get_body_blocks(&event.to);
+
+    // Only blocks just walked into, so standing inside one does not repeat it.
+    if before == after {
+        return;
+    }
+
+    let block = core
+        .with_world(&event.player.world, |world| {
+            after
+                .iter()
+                .filter(|position| !before.contains(position))
+                .find_map(|position| Some((*position, world.get_special_block(position)?.clone())))
+        })
+        .flatten();
+
+    let uid = event.player.uid;
+
+    match block {
+        Some((_, SpecialBlock::Message(message))) => core.send_message_to(uid, &message),
+        Some((position, SpecialBlock::Commands(cooldown, commands))) => run_commands(
+            cooldowns,
+            core,
+            &event.player,
+            (&event.player.world, position),
+            cooldown,
+            &commands,
+        ),
+        Some((_, SpecialBlock::Portal(world, destination))) => {
+            if world != event.player.world {
+                core.queue_action(Action::JoinWorld(uid, world.clone()));
+            }
+
+            // Without a destination, joining a world puts players at its spawn already.
+            let transform = match destination {
+                Some(destination) => Some(Transform::at_block(
+                    destination,
+                    event.to.get_yaw(),
+                    event.to.get_pitch(),
+                )),
+                None if world == event.player.world => {
+                    core.with_world(&world, |world| world.get_spawn())
+                }
+                None => None,
+            };
+
+            if let Some(transform) = transform {
+                core.queue_action(Action::Teleport(uid, transform));
+            }
+        }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod test_special {
+    use super::super::Config;
+    use super::*;
+    use std::env;
+
+    #[test]
+    /// Special blocks are saved and read back.
+    pub fn save_and_load() {
+        let mut blocks = HashMap::new();
+
+        blocks.insert(
+            Vec3D::new(1, 2, 3),
+            SpecialBlock::Portal(String::from("lobby"), Some(Vec3D::new(4, 5, 6))),
+        );
+        blocks.insert(
+            Vec3D::new(2, 2, 3),
+            SpecialBlock::Portal(String::from("main"), None),
+        );
+        blocks.insert(
+            Vec3D::new(0, 9, 0),
+            SpecialBlock::Message(String::from("&aWelcome to  the server!")),
+        );
+        blocks.insert(
+            Vec3D::new(0, 9, 1),
+            SpecialBlock::Commands(
+                Duration::from_secs(30),
+                vec![String::from("/main"), String::from("/tp Alice")],
+            ),
+        );
+
+        let path = env::temp_dir().join(format!("rcclassic-blocks-{}", std::process::id()));
+
+        assert!(save_special_blocks(&path, &blocks));
+        assert_eq!(load_special_blocks(&path), blocks);
+
+        assert!(save_special_blocks(&path, &HashMap::new()));
+        assert!(!path.exists());
+
+        // The rank blocks used to keep is skipped.
+        assert_eq!(
+            SpecialBlock::from_line("commands 0 9 1 Operator 30 /main"),
+            Some((
+                Vec3D::new(0, 9, 1),
+                SpecialBlock::Commands(Duration::from_secs(30), vec![String::from("/main")])
+            ))
+        );
+    }
+
+    #[test]
+    /// Blocks are made special by clicking them, message blocks answer clicks.
+    pub fn place_and_click() {
+        let folder =
+            env::temp_dir().join(format!("rcclassic-special-click-{}", std::process::id()));
+        let mut core = Core::with_config(1, Config::in_folder(&folder));
+        core.get_config_mut().read_only = false;
+        let mut owner = core.get_player_snapshot(0).unwrap();
+        owner.rank = Rank::Operator;
+
+        let click = |core: &Core| {
+            let mut event = SetBlockEvent::new(
+                owner.clone(),
+                String::from("main"),
+                Vec3D::new(1, 40, 1),
+                1,
+                false,
+            );
+
+            core.call_event(&mut event)
+        };
+
+        // Placed directly, as placing with /mb would save into the maps folder.
+        core.with_world_mut("main", |world| {
+            world.set_special_block(
+                Vec3D::new(1, 40, 1),
+                Some(SpecialBlock::Message(String::from("Hello"))),
+            )
+        });
+
+        // Clicking it shows the message instead of building.
+        assert!(click(&core));
+        assert!(click(&core));
+
+        assert!(core.try_run_command(&owner, "/mb del"));
+        assert!(click(&core));
+        assert_eq!(
+            core.with_world("main", |world| world.get_special_blocks().len()),
+            Some(0)
+        );
+        assert!(!click(&core));
+
+        let from = Transform::new(Vec3D::new(16, 32 * 40 + 51, 16), 0, 0);
+        let to = Transform::new(Vec3D::new(48, 32 * 40 + 51, 16), 0, 0);
+        assert_eq!(
+            get_body_blocks(&to),
+            [Vec3D::new(1, 40, 0), Vec3D::new(1, 41, 0)]
+        );
+        assert_ne!(get_body_blocks(&from), get_body_blocks(&to));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    /// Command blocks run their commands with the rank of who uses them, once per cooldown.
+    pub fn command_block() {
+        let folder =
+            env::temp_dir().join(format!("rcclassic-special-commands-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+        let mut operator = core.get_player_snapshot(0).unwrap();
+        operator.rank = Rank::Operator;
+        // Cooldowns are kept for each player.
+        let mut guest = operator.clone();
+        guest.uid = 1;
+        guest.rank = Rank::Guest;
+        let position = Vec3D::new(1, 40, 1);
+
+        assert_eq!(
+            parse_commands("/a;/b c"),
+            Some(vec![String::from("/a"), String::from("/b c")])
+        );
+        assert_eq!(parse_commands("/a; b"), None);
+
+        core.with_world_mut("main", |world| {
+            world.set_special_block(
+                position,
+                Some(SpecialBlock::Commands(
+                    Duration::from_secs(60),
+                    vec![String::from("/physics 0")],
+                )),
+            )
+        });
+
+        let click = |core: &Core, player: &PlayerSnapshot| {
+            let mut event =
+                SetBlockEvent::new(player.clone(), String::from("main"), position, 1, false);
+
+            core.call_event(&mut event)
+        };
+        let level = |core: &Core| core.with_world("main", |world| world.get_physics().get_level());
+
+        core.with_world_mut("main", |world| world.get_physics_mut().set_level(2));
+
+        // Guests cannot change the physics, even through a block an operator placed.
+        assert!(click(&core, &guest));
+        assert_eq!(level(&core), Some(2));
+
+        assert!(click(&core, &operator));
+        assert_eq!(level(&core), Some(0));
+
+        core.with_world_mut("main", |world| world.get_physics_mut().set_level(2));
+
+        assert!(click(&core, &operator));
+        assert_eq!(level(&core), Some(2));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
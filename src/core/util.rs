@@ -28,6 +28,7 @@ pub fn math_min(num1: u8, num2: u8) -> u8 {
     }
 }
 
+#[derive(PartialEq, Eq, Hash, Debug)]
 pub struct Vec3D<T = u16>(pub T, pub T, pub T)
 where
     T: Copy,
@@ -36,7 +37,7 @@ where
 
 impl Clone for Vec3D {
     fn clone(&self) -> Self {
-        Self(self.0, self.1, self.2)
+        *self
     }
 }
 impl Copy for Vec3D {}
@@ -48,7 +49,7 @@ where
     T: Clone,
 {
     pub fn new(x: T, y: T, z: T) -> Vec3D<T> {
-        Vec3D { 0: x, 1: y, 2: z }
+        Vec3D(x, y, z)
     }
 
     pub fn get_x(&self) -> T {
@@ -76,6 +77,9 @@ where
     }
 }
 
+/// Height of a player's eyes above their feet, in 1/32 of a block.
+pub const EYE_HEIGHT: u16 = 51;
+
 pub struct Transform {
     position: Vec3D,
     yaw: u8,
@@ -88,6 +92,12 @@ impl Clone for Transform {
     }
 }
 
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(Vec3D::new(0, 0, 0), 0, 0)
+    }
+}
+
 impl Transform {
     pub fn new(position: Vec3D, yaw: u8, pitch: u8) -> Transform {
         Transform {
@@ -97,8 +107,26 @@ impl Transform {
         }
     }
 
-    pub fn default() -> Transform {
-        Self::new(Vec3D::new(0, 0, 0), 0, 0)
+    /// Player standing at the center of a block, positions being where their eyes are.
+    pub fn at_block(block: Vec3D, yaw: u8, pitch: u8) -> Transform {
+        let fixed = |axis: u16, offset: u16| axis.saturating_mul(32).saturating_add(offset);
+
+        Transform::new(
+            Vec3D::new(
+                fixed(block.0, 16),
+                fixed(block.1, EYE_HEIGHT),
+                fixed(block.2, 16),
+            ),
+            yaw,
+            pitch,
+        )
+    }
+
+    /// Block the feet of a player are in.
+    pub fn get_feet_block(&self) -> Vec3D {
+        let Vec3D(x, y, z) = self.position;
+
+        Vec3D::new(x / 32, y.saturating_sub(EYE_HEIGHT) / 32, z / 32)
     }
 
     pub fn get_pos(&self) -> &Vec3D {
@@ -130,15 +158,22 @@ impl Transform {
 
 pub struct BufferWriter {
     buffer: Vec<u8>,
+    extended_positions: bool,
 }
 
 impl BufferWriter {
     pub fn new(size: usize) -> BufferWriter {
         BufferWriter {
             buffer: Vec::<u8>::with_capacity(size),
+            extended_positions: false,
         }
     }
 
+    /// Writes transform positions as ints, for clients supporting ExtEntityPositions.
+    pub fn set_extended_positions(&mut self, extended_positions: bool) {
+        self.extended_positions = extended_positions;
+    }
+
     pub fn get_data(&self) -> &Vec<u8> {
         &self.buffer
     }
@@ -173,7 +208,7 @@ impl BufferWriter {
         let mut char_iter = data.as_bytes().iter();
 
         for _ in 0..64 {
-            self.buffer.push(*char_iter.next().unwrap_or(&b' ') as u8);
+            self.buffer.push(*char_iter.next().unwrap_or(&b' '));
         }
     }
 
@@ -184,7 +219,15 @@ impl BufferWriter {
     }
 
     pub fn write_transform(&mut self, data: &Transform) {
-        self.write_vec3d(data.get_pos());
+        if self.extended_positions {
+            let position = data.get_pos();
+
+            self.write_int(i32::from(position.0));
+            self.write_int(i32::from(position.1));
+            self.write_int(i32::from(position.2));
+        } else {
+            self.write_vec3d(data.get_pos());
+        }
 
         self.write_byte(data.yaw);
         self.write_byte(data.pitch);
@@ -207,7 +250,7 @@ pub struct BufferReader<'a> {
 }
 
 impl<'a> BufferReader<'a> {
-    pub fn new(buffer: &'a Vec<u8>) -> BufferReader {
+    pub fn new(buffer: &'a Vec<u8>) -> BufferReader<'a> {
         BufferReader { index: 0, buffer }
     }
 
@@ -257,6 +300,15 @@ impl<'a> BufferReader<'a> {
         (b2 as i16) << 8 | b1 as i16
     }
 
+    pub fn read_int(&mut self) -> i32 {
+        let b1 = self.read_byte();
+        let b2 = self.read_byte();
+        let b3 = self.read_byte();
+        let b4 = self.read_byte();
+
+        i32::from_be_bytes([b1, b2, b3, b4])
+    }
+
     pub fn read_string(&mut self) -> String {
         let grabbed = String::from_utf8(self.buffer[self.index..self.index + 64].to_vec())
             .unwrap_or(String::from(""));
@@ -0,0 +1,436 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Named places players can go to: warps shared by the server, and homes of each player.
+//!
+//! Warps are kept in "{folder}/warps.txt" and homes in "{folder}/homes/{player}.txt", with a
+//! line per place: its name, the world, then x, y, z, yaw and pitch.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{
+    is_valid_map_name, Action, Command, Core, EventBus, PlayerSnapshot, Plugin, Rank, Transform,
+    Vec3D,
+};
+
+/// Folder warps and homes are kept in.
+pub const WARPS_FOLDER: &str = "warps";
+
+/// Most homes a player may set.
+pub const MAX_HOMES: usize = 16;
+
+/// Name of the home used when none is given.
+const DEFAULT_HOME: &str = "home";
+
+/// Place inside a world.
+#[derive(Clone)]
+pub struct Location {
+    pub world: String,
+    pub transform: Transform,
+}
+
+impl Location {
+    pub fn new(world: String, transform: Transform) -> Location {
+        Location { world, transform }
+    }
+
+    fn to_line(&self, name: &str) -> String {
+        let Vec3D(x, y, z) = *self.transform.get_pos();
+
+        format!(
+            "{} {} {} {} {} {} {}",
+            name,
+            self.world,
+            x,
+            y,
+            z,
+            self.transform.get_yaw(),
+            self.transform.get_pitch()
+        )
+    }
+
+    fn from_line(line: &str) -> Option<(String, Location)> {
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+
+        if parts.len() != 7 {
+            return None;
+        }
+
+        let number = |index: usize| parts[index].parse::<u16>().ok();
+        let byte = |index: usize| parts[index].parse::<u8>().ok();
+
+        Some((
+            String::from(parts[0]),
+            Location::new(
+                String::from(parts[1]),
+                Transform::new(
+                    Vec3D::new(number(2)?, number(3)?, number(4)?),
+                    byte(5)?,
+                    byte(6)?,
+                ),
+            ),
+        ))
+    }
+}
+
+/// Places by their lowercase name.
+pub type Locations = BTreeMap<String, Location>;
+
+/// Reads places from a file, none if it does not exist. Invalid lines are skipped.
+pub fn load_locations(path: &Path) -> Locations {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(Location::from_line)
+        .collect()
+}
+
+/// Writes places into a file, removing the file once there are none.
+pub fn save_locations(path: &Path, locations: &Locations) -> io::Result<()> {
+    if locations.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let lines: Vec<String> = locations
+        .iter()
+        .map(|(name, location)| location.to_line(name))
+        .collect();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+/// Moves a player to a place, joining its world first if they are elsewhere.
+pub fn go_to(core: &Core, player: &PlayerSnapshot, location: Location) {
+    if location.world != player.world {
+        core.queue_action(Action::JoinWorld(player.uid, location.world));
+    }
+
+    core.queue_action(Action::Teleport(player.uid, location.transform));
+}
+
+/// Warps with /warp, /setwarp, /delwarp and /warps, homes with /home, /sethome and /delhome.
+pub struct WarpPlugin {
+    folder: PathBuf,
+    warps: Arc<Mutex<Locations>>,
+}
+
+impl WarpPlugin {
+    pub fn new<P: AsRef<Path>>(folder: P) -> WarpPlugin {
+        let folder = folder.as_ref().to_path_buf();
+        let warps = load_locations(&folder.join("warps.txt"));
+
+        WarpPlugin {
+            folder,
+            warps: Arc::new(Mutex::new(warps)),
+        }
+    }
+}
+
+impl Plugin for WarpPlugin {
+    fn get_name(&self) -> &str {
+        "Warps"
+    }
+
+    fn register(&mut self, _events: &mut EventBus) {}
+
+    fn enable(&mut self, core: &Core) {
+        let warps = self.warps.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "warp",
+            "Takes you to a warp. {name}",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim().to_lowercase();
+                let warp = warps.lock().unwrap().get(&name).cloned();
+
+                match warp {
+                    Some(warp) => go_to(core, player, warp),
+                    None if name.is_empty() => {
+                        core.send_message_to(player.uid, "&7Usage: /warp {name}, see /warps.")
+                    }
+                    None => core.send_message_to(
+                        player.uid,
+                        &format!("&8There is no warp named \"{}\", see /warps.", name),
+                    ),
+                }
+            },
+        ));
+
+        let warps = self.warps.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "warps",
+            "Lists the warps.",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, _args: &str| {
+                let names: Vec<String> = warps.lock().unwrap().keys().cloned().collect();
+
+                let message = if names.is_empty() {
+                    String::from("&7There are no warps.")
+                } else {
+                    format!("&7Warps: {}", names.join(", "))
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let (warps, path) = (self.warps.clone(), self.folder.join("warps.txt"));
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "setwarp",
+            "Sets a warp where you are. {name}",
+            Rank::Operator,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim().to_lowercase();
+
+                if !is_valid_map_name(&name) {
+                    core.send_message_to(player.uid, "&7Usage: /setwarp {name}");
+
+                    return;
+                }
+
+                let mut warps = warps.lock().unwrap();
+                warps.insert(
+                    name.clone(),
+                    Location::new(player.world.clone(), player.transform.clone()),
+                );
+
+                let message = match save_locations(&path, &warps) {
+                    Ok(()) => format!("&7Warp \"{}\" has been set.", name),
+                    Err(e) => {
+                        core.log(&format!("Unable to save warps: {}", e));
+
+                        format!("&8Warp \"{}\" set, but it could not be saved.", name)
+                    }
+                };
+
+                drop(warps);
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let (warps, path) = (self.warps.clone(), self.folder.join("warps.txt"));
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "delwarp",
+            "Removes a warp. {name}",
+            Rank::Operator,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = args.trim().to_lowercase();
+                let mut warps = warps.lock().unwrap();
+
+                let message = match warps.remove(&name) {
+                    Some(_) => match save_locations(&path, &warps) {
+                        Ok(()) => format!("&7Warp \"{}\" has been removed.", name),
+                        Err(e) => {
+                            core.log(&format!("Unable to save warps: {}", e));
+
+                            format!("&8Warp \"{}\" removed, but it could not be saved.", name)
+                        }
+                    },
+                    None => format!("&8There is no warp named \"{}\".", name),
+                };
+
+                drop(warps);
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let homes = self.folder.join("homes");
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "home",
+            "Takes you to one of your homes. [name]",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = get_home_name(args);
+                let mut locations = load_locations(&get_homes_path(&homes, &player.name));
+
+                match locations.remove(&name) {
+                    Some(home) => go_to(core, player, home),
+                    None if locations.is_empty() => {
+                        core.send_message_to(player.uid, "&7You have no homes, see /sethome.")
+                    }
+                    None => core.send_message_to(
+                        player.uid,
+                        &format!(
+                            "&8You have no home named \"{}\", your homes: {}",
+                            name,
+                            locations.keys().cloned().collect::<Vec<_>>().join(", ")
+                        ),
+                    ),
+                }
+            },
+        ));
+
+        let homes = self.folder.join("homes");
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "sethome",
+            "Sets one of your homes where you are. [name]",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = get_home_name(args);
+
+                if !is_valid_map_name(&name) {
+                    core.send_message_to(player.uid, "&7Usage: /sethome [name]");
+
+                    return;
+                }
+
+                let path = get_homes_path(&homes, &player.name);
+                let mut locations = load_locations(&path);
+
+                if !locations.contains_key(&name) && locations.len() >= MAX_HOMES {
+                    core.send_message_to(
+                        player.uid,
+                        &format!("&8You cannot have more than {} homes.", MAX_HOMES),
+                    );
+
+                    return;
+                }
+
+                locations.insert(
+                    name.clone(),
+                    Location::new(player.world.clone(), player.transform.clone()),
+                );
+
+                let message = match save_locations(&path, &locations) {
+                    Ok(()) => format!("&7Home \"{}\" has been set.", name),
+                    Err(e) => {
+                        core.log(&format!("Unable to save homes of {}: {}", player.name, e));
+
+                        String::from("&8Unable to save your home.")
+                    }
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+
+        let homes = self.folder.join("homes");
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "delhome",
+            "Removes one of your homes. [name]",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                let name = get_home_name(args);
+                let path = get_homes_path(&homes, &player.name);
+                let mut locations = load_locations(&path);
+
+                let message = match locations.remove(&name) {
+                    Some(_) => match save_locations(&path, &locations) {
+                        Ok(()) => format!("&7Home \"{}\" has been removed.", name),
+                        Err(e) => {
+                            core.log(&format!("Unable to save homes of {}: {}", player.name, e));
+
+                            String::from("&8Unable to remove your home.")
+                        }
+                    },
+                    None => format!("&8You have no home named \"{}\".", name),
+                };
+
+                core.send_message_to(player.uid, &message);
+            },
+        ));
+    }
+}
+
+fn get_home_name(args: &str) -> String {
+    match args.trim() {
+        "" => String::from(DEFAULT_HOME),
+        name => name.to_lowercase(),
+    }
+}
+
+fn get_homes_path(folder: &Path, player: &str) -> PathBuf {
+    folder.join(format!("{}.txt", player.to_lowercase()))
+}
+
+#[cfg(test)]
+mod test_warp {
+    use super::super::Config;
+    use super::*;
+    use std::env;
+
+    #[test]
+    /// Warps and homes are saved, and players can only keep so many homes.
+    pub fn warps_and_homes() {
+        let folder = env::temp_dir().join(format!("rcclassic-warps-{}", std::process::id()));
+        let core = Core::with_config(
+            1,
+            Config {
+                warps_folder: folder.clone(),
+                ..Config::in_folder(&folder)
+            },
+        );
+
+        let mut player = core.get_player_snapshot(0).unwrap();
+        player.transform = Transform::at_block(Vec3D::new(5, 40, 6), 32, 0);
+
+        assert!(core.try_run_command(&player, "/setwarp Spawn"));
+        assert!(core.try_run_command(&player, "/sethome"));
+
+        let warps = load_locations(&folder.join("warps.txt"));
+        let warp = &warps["spawn"];
+
+        assert_eq!(warp.world, "main");
+        assert_eq!(warp.transform.get_pos(), player.transform.get_pos());
+        assert_eq!(warp.transform.get_yaw(), 32);
+
+        let homes_path = get_homes_path(&folder.join("homes"), &player.name);
+        assert!(load_locations(&homes_path).contains_key(DEFAULT_HOME));
+
+        for home in 0..MAX_HOMES {
+            assert!(core.try_run_command(&player, &format!("/sethome h{}", home)));
+        }
+
+        // The default home was already there, the last one does not fit.
+        assert_eq!(load_locations(&homes_path).len(), MAX_HOMES);
+
+        assert!(core.try_run_command(&player, "/delwarp spawn"));
+        assert!(!folder.join("warps.txt").exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
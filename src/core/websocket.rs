@@ -0,0 +1,307 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+use sha1::{Digest, Sha1};
+
+// Magic string appended to the client's key, defined by RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Upper limit for the HTTP upgrade request, anything bigger is not a real client.
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+/// Largest payload accepted in a frame, client packets of Classic being far smaller.
+pub const MAX_FRAME_SIZE: usize = 4096;
+
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+pub const OPCODE_BINARY: u8 = 0x2;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xa;
+
+/// Checks the first byte of a connection for an HTTP request.
+/// Desktop clients always start with PlayerIdentification (0x00), web clients with "GET".
+pub fn is_http_request(first_byte: u8) -> bool {
+    first_byte == b'G'
+}
+
+/// Generates the "Sec-WebSocket-Accept" value for a client's key.
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+
+    hasher.update(key.trim().as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+
+    base64::encode(hasher.finalize())
+}
+
+/// Reads the HTTP upgrade request of a web client and answers it.
+/// Stream has to be in blocking mode while handshaking.
+pub fn handshake(stream: &mut TcpStream) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 512];
+
+    // Clients wait for our response before sending any frame, so the request is all we read here.
+    while !request.ends_with(b"\r\n\r\n") {
+        let size = stream.read(&mut buffer)?;
+
+        if size == 0 || request.len() + size > MAX_HANDSHAKE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Incomplete WebSocket handshake.",
+            ));
+        }
+
+        request.extend_from_slice(&buffer[..size]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+
+    let mut key = None;
+    let mut protocol = None;
+
+    for line in request.lines().skip(1) {
+        if let Some(index) = line.find(':') {
+            let (name, value) = line.split_at(index);
+            let value = value[1..].trim();
+
+            if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(String::from(value));
+            } else if name.eq_ignore_ascii_case("Sec-WebSocket-Protocol") {
+                // ClassiCube asks for the "ClassiCube" sub protocol, which has to be echoed back.
+                protocol = value.split(',').next().map(|p| String::from(p.trim()));
+            }
+        }
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n")?;
+
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "WebSocket handshake without a key.",
+            ));
+        }
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n",
+        accept_key(&key)
+    );
+
+    if let Some(protocol) = protocol {
+        response.push_str(&format!("Sec-WebSocket-Protocol: {}\r\n", protocol));
+    }
+
+    response.push_str("\r\n");
+
+    stream.write_all(response.as_bytes())
+}
+
+/// Wraps data sent by the server into a single (unmasked) frame.
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+
+    // FIN bit is always set, we never fragment.
+    frame.push(0x80 | opcode);
+
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend(&(payload.len() as u64).to_be_bytes());
+    }
+
+    frame.extend(payload);
+
+    frame
+}
+
+pub struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Collects the bytes received from a web client and splits them into frames.
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        FrameDecoder { buffer: Vec::new() }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete frame, unmasked. None if more data is needed.
+    /// Fails for frames larger than `MAX_FRAME_SIZE`, the connection has to be closed then.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        if self.buffer.len() < 2 {
+            return Ok(None);
+        }
+
+        let opcode = self.buffer[0] & 0x0f;
+        let masked = self.buffer[1] & 0x80 != 0;
+
+        let (payload_len, mut index) = match self.buffer[1] & 0x7f {
+            126 => {
+                if self.buffer.len() < 4 {
+                    return Ok(None);
+                }
+
+                (
+                    u16::from_be_bytes([self.buffer[2], self.buffer[3]]) as usize,
+                    4,
+                )
+            }
+            127 => {
+                if self.buffer.len() < 10 {
+                    return Ok(None);
+                }
+
+                let mut length = [0u8; 8];
+                length.copy_from_slice(&self.buffer[2..10]);
+
+                (
+                    usize::try_from(u64::from_be_bytes(length)).unwrap_or(usize::MAX),
+                    10,
+                )
+            }
+            length => (length as usize, 2),
+        };
+
+        if payload_len > MAX_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "WebSocket frame is too large.",
+            ));
+        }
+
+        let mut mask = [0u8; 4];
+
+        if masked {
+            if self.buffer.len() < index + 4 {
+                return Ok(None);
+            }
+
+            mask.copy_from_slice(&self.buffer[index..index + 4]);
+            index += 4;
+        }
+
+        let end = index
+            .checked_add(payload_len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid frame length."))?;
+
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+
+        let mut payload: Vec<u8> = self.buffer.drain(..end).skip(index).collect();
+
+        if masked {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        Ok(Some(Frame { opcode, payload }))
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test_websocket {
+    use super::*;
+
+    #[test]
+    /// Checks the accept key against the example given in RFC 6455.
+    pub fn rfc_accept_key() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    /// Masked frames split over multiple reads should only be returned once complete.
+    pub fn decode_split_masked_frame() {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let payload = [0x0d, 0xff, 0x48, 0x69];
+
+        let mut frame = vec![0x80 | OPCODE_BINARY, 0x80 | payload.len() as u8];
+        frame.extend(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+
+        let mut decoder = FrameDecoder::new();
+
+        decoder.push(&frame[..5]);
+        assert!(decoder.next_frame().unwrap().is_none());
+
+        decoder.push(&frame[5..]);
+        let decoded = decoder.next_frame().unwrap().unwrap();
+
+        assert_eq!(decoded.opcode, OPCODE_BINARY);
+        assert_eq!(decoded.payload, payload);
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    /// Frames declaring more than the size limit fail before anything else is read.
+    pub fn reject_oversized_frame() {
+        let mut decoder = FrameDecoder::new();
+
+        decoder.push(&[0x80 | OPCODE_BINARY, 0x80 | 127]);
+        decoder.push(&u64::MAX.to_be_bytes());
+        assert!(decoder.next_frame().is_err());
+
+        let mut decoder = FrameDecoder::new();
+
+        decoder.push(&[0x80 | OPCODE_BINARY, 126]);
+        decoder.push(&(MAX_FRAME_SIZE as u16 + 1).to_be_bytes());
+        assert!(decoder.next_frame().is_err());
+    }
+
+    #[test]
+    /// Server frames use the extended length once payload reaches 126 bytes.
+    pub fn encode_extended_length() {
+        let frame = encode_frame(OPCODE_BINARY, &[0u8; 1028]);
+
+        assert_eq!(frame[0], 0x80 | OPCODE_BINARY);
+        assert_eq!(frame[1], 126);
+        assert_eq!(u16::from_be_bytes([frame[2], frame[3]]), 1028);
+        assert_eq!(frame.len(), 1028 + 4);
+    }
+}
@@ -20,20 +20,69 @@
     SOFTWARE.
 */
 
-use super::{Map, Vec3D};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::maps::{get_block_position, MCSharpMap};
+use super::physics::{self, Physics, PHYSICS_NORMAL};
+use super::{
+    block, BlockBuffer, Map, Player, Rank, Region, SpecialBlock, Transform, Vec3D, Zone, MAX_ZONES,
+};
+
+/// Highest entity id classic clients can display, as they read ids as signed bytes (-1 being themselves).
+pub const MAX_CLASSIC_ENTITY_ID: u8 = 127;
+/// Clients supporting ExtEntityPositions read ids as unsigned bytes (255 being themselves).
+pub const MAX_EXTENDED_ENTITY_ID: u8 = 254;
+/// Entity id players see themselves as, -1 for classic clients.
+pub const SELF_ENTITY_ID: u8 = 255;
+
+/// Whether the viewer's client is able to display an entity with the given id.
+pub fn can_see_entity(viewer: &dyn Player, entity_id: u8) -> bool {
+    entity_id <= MAX_CLASSIC_ENTITY_ID || viewer.supports_extension("ExtEntityPositions")
+}
+
+/// Block which changed inside a world, with what it was before.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockChange {
+    pub position: Vec3D,
+    pub old: u8,
+    pub new: u8,
+    /// Extended block built over, old being its fallback.
+    pub extended: Option<u16>,
+}
+
+// Region read before changing it while recording, with the extended blocks inside it.
+type Journaled = (Region, BlockBuffer, HashMap<Vec3D, u16>);
 
 pub struct World {
     name: String,
     players: Vec<usize>,
-    map: Box<dyn Map>,
+    // Entity ids of players inside this world, by their session uid.
+    entity_ids: HashMap<usize, u8>,
+    map: Box<dyn Map + Send + Sync>,
+    // Changes made while recording.
+    journal: Option<Vec<BlockChange>>,
+    physics: Physics,
+    zones: Vec<Zone>,
+    special_blocks: HashMap<Vec3D, SpecialBlock>,
+    // Whether the map changed since it was loaded or saved.
+    modified: bool,
+    read_only: bool,
 }
 
 impl World {
-    pub fn new(name: String, map: Box<dyn Map>) -> World {
+    pub fn new(name: String, map: Box<dyn Map + Send + Sync>) -> World {
         World {
             name,
             players: vec![],
+            entity_ids: HashMap::new(),
             map,
+            journal: None,
+            physics: Physics::new(PHYSICS_NORMAL),
+            zones: Vec::new(),
+            special_blocks: HashMap::new(),
+            modified: false,
+            read_only: false,
         }
     }
 
@@ -41,24 +90,31 @@ impl World {
         &self.name
     }
 
-    pub fn add_player(&mut self, player_uid: usize) {
+    /// Adds a player into the world, returning the entity id given to them.
+    /// Lowest free id is used, None is returned if the world is full.
+    pub fn add_player(&mut self, player_uid: usize) -> Option<u8> {
+        if let Some(entity_id) = self.entity_ids.get(&player_uid) {
+            return Some(*entity_id);
+        }
+
+        let entity_id = (0..=MAX_EXTENDED_ENTITY_ID)
+            .find(|id| !self.entity_ids.values().any(|taken| taken == id))?;
+
         self.players.push(player_uid);
+        self.entity_ids.insert(player_uid, entity_id);
+
+        Some(entity_id)
     }
 
-    pub fn remove_player(&mut self, player_uid: usize) {
-        let mut deleting_index = 0;
-        // TODO: use better functions.
-        for (index, player) in self.players.iter().enumerate() {
-            if *player == player_uid {
-                deleting_index = index;
-            }
-        }
+    /// Removes a player from the world, returning the entity id they had.
+    pub fn remove_player(&mut self, player_uid: usize) -> Option<u8> {
+        self.players.retain(|player| *player != player_uid);
 
-        if deleting_index <= 0 {
-            return;
-        }
+        self.entity_ids.remove(&player_uid)
+    }
 
-        self.players.remove(deleting_index);
+    pub fn get_entity_id(&self, player_uid: usize) -> Option<u8> {
+        self.entity_ids.get(&player_uid).copied()
     }
 
     pub fn get_players(&self) -> &Vec<usize> {
@@ -72,13 +128,254 @@ impl World {
             block = 0x0;
         }
 
+        if let Some(journal) = &mut self.journal {
+            let old = self.map.get_block(coordinates);
+
+            if old != block {
+                journal.push(BlockChange {
+                    position: *coordinates,
+                    old,
+                    new: block,
+                    extended: self.map.get_extended_block(coordinates),
+                });
+            }
+        }
+
+        let old = self.map.get_block(coordinates);
         self.map.set_block(coordinates, block);
+        self.modified |= old != block;
+
+        // Water flows back where a sponge kept it out.
+        if old == block::SPONGE && block != block::SPONGE {
+            let Vec3D(x, y, z) = *coordinates;
+            let reach = physics::SPONGE_RADIUS as u16 + 1;
+            let around = Region::new(
+                Vec3D::new(
+                    x.saturating_sub(reach),
+                    y.saturating_sub(reach),
+                    z.saturating_sub(reach),
+                ),
+                Vec3D::new(
+                    x.saturating_add(reach),
+                    y.saturating_add(reach),
+                    z.saturating_add(reach),
+                ),
+            );
+
+            self.activate(
+                around
+                    .clamp(self.map.get_size())
+                    .iter()
+                    .flat_map(|region| region.positions()),
+            );
+        } else {
+            self.activate(std::iter::once(*coordinates));
+        }
     }
 
     pub fn get_block(&self, coordinates: &Vec3D) -> u8 {
         self.map.get_block(coordinates)
     }
 
+    /// Sets every block of a region, returning the positions which changed.
+    pub fn fill(&mut self, region: &Region, block: u8) -> Vec<Vec3D> {
+        let before = self.read_journaled(Some(*region));
+        let changed = self.map.fill(region, block);
+
+        self.modified |= !changed.is_empty();
+        self.journal_changes(before, &changed);
+        self.activate(changed.iter().copied());
+
+        changed
+    }
+
+    /// Replaces one block with another inside a region, returning the positions which changed.
+    pub fn replace(&mut self, region: &Region, from: u8, to: u8) -> Vec<Vec3D> {
+        let before = self.read_journaled(Some(*region));
+        let changed = self.map.replace(region, from, to);
+
+        self.modified |= !changed.is_empty();
+        self.journal_changes(before, &changed);
+        self.activate(changed.iter().copied());
+
+        changed
+    }
+
+    /// Counts one block inside a region.
+    pub fn count(&self, region: &Region, block: u8) -> usize {
+        self.map.count(region, block)
+    }
+
+    pub fn read_region(&self, region: &Region) -> BlockBuffer {
+        self.map.read_region(region)
+    }
+
+    /// Writes a buffer with its lowest corner at the origin, returning the positions which changed.
+    pub fn paste(&mut self, origin: &Vec3D, buffer: &BlockBuffer) -> Vec<Vec3D> {
+        let before = self.read_journaled(Region::with_size(*origin, *buffer.get_size()));
+        let changed = self.map.paste(origin, buffer);
+
+        self.modified |= !changed.is_empty();
+        self.journal_changes(before, &changed);
+        self.activate(changed.iter().copied());
+
+        changed
+    }
+
+    pub fn get_zones(&self) -> &Vec<Zone> {
+        &self.zones
+    }
+
+    pub fn set_zones(&mut self, zones: Vec<Zone>) {
+        self.zones = zones;
+    }
+
+    /// Adds a zone, unless one with the same name exists or the world has too many.
+    pub fn add_zone(&mut self, zone: Zone) -> bool {
+        if self.zones.len() >= MAX_ZONES
+            || self
+                .zones
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(&zone.name))
+        {
+            return false;
+        }
+
+        self.zones.push(zone);
+
+        true
+    }
+
+    pub fn remove_zone(&mut self, name: &str) -> Option<Zone> {
+        let index = self
+            .zones
+            .iter()
+            .position(|zone| zone.name.eq_ignore_ascii_case(name))?;
+
+        Some(self.zones.remove(index))
+    }
+
+    /// First zone keeping a player from building at a position, None if they can build there.
+    pub fn get_blocking_zone(&self, player: &str, rank: Rank, position: &Vec3D) -> Option<&Zone> {
+        self.zones
+            .iter()
+            .find(|zone| zone.region.contains(position) && !zone.can_build(player, rank))
+    }
+
+    pub fn get_special_blocks(&self) -> &HashMap<Vec3D, SpecialBlock> {
+        &self.special_blocks
+    }
+
+    pub fn set_special_blocks(&mut self, blocks: HashMap<Vec3D, SpecialBlock>) {
+        self.special_blocks = blocks;
+    }
+
+    pub fn get_special_block(&self, position: &Vec3D) -> Option<&SpecialBlock> {
+        self.special_blocks.get(position)
+    }
+
+    /// Makes a block special, or normal again with None.
+    pub fn set_special_block(&mut self, position: Vec3D, block: Option<SpecialBlock>) {
+        match block {
+            Some(block) => self.special_blocks.insert(position, block),
+            None => self.special_blocks.remove(&position),
+        };
+    }
+
+    pub fn get_physics(&self) -> &Physics {
+        &self.physics
+    }
+
+    pub fn get_physics_mut(&mut self) -> &mut Physics {
+        &mut self.physics
+    }
+
+    /// Updates blocks queued for physics, up to the budget.
+    /// Returns the number of blocks updated and the positions which changed.
+    pub fn tick_physics(&mut self, budget: usize) -> (usize, Vec<Vec3D>) {
+        physics::tick(self, budget)
+    }
+
+    // Queues changed blocks and their neighbours for physics.
+    fn activate<I: Iterator<Item = Vec3D>>(&mut self, positions: I) {
+        if self.physics.get_level() == physics::PHYSICS_OFF {
+            return;
+        }
+
+        let size = *self.map.get_size();
+
+        for position in positions {
+            for near in physics::neighbours(&position, &size) {
+                self.physics.schedule(near, self.map.get_block(&near));
+            }
+        }
+    }
+
+    /// Runs a closure on the world, returning every block it changed along with its result.
+    pub fn record<R, F: FnOnce(&mut World) -> R>(&mut self, f: F) -> (R, Vec<BlockChange>) {
+        self.journal = Some(Vec::new());
+
+        let result = f(self);
+
+        (result, self.journal.take().unwrap_or_default())
+    }
+
+    /// Puts back what recorded changes built over, extended blocks included.
+    pub fn revert(&mut self, changes: &[BlockChange]) {
+        for change in changes.iter().rev() {
+            self.set_block(&change.position, change.old, false);
+
+            if let Some(id) = change.extended {
+                self.map.restore_extended_block(&change.position, id);
+            }
+        }
+    }
+
+    // Blocks of the part of a region inside the map along with its extended blocks, only read while recording.
+    fn read_journaled(&self, region: Option<Region>) -> Option<Journaled> {
+        self.journal.as_ref()?;
+
+        let region = region?.clamp(self.map.get_size())?;
+        let size = *self.map.get_size();
+        let index = |position: &Vec3D| {
+            let (width, length) = (size.0 as usize, size.2 as usize);
+
+            position.0 as usize + position.2 as usize * width + position.1 as usize * width * length
+        };
+
+        let extended = self
+            .map
+            .get_extended_blocks()
+            .range(index(region.get_min())..=index(region.get_max()))
+            .map(|(index, id)| (get_block_position(&size, *index), *id))
+            .filter(|(position, _)| region.contains(position))
+            .collect();
+
+        Some((region, self.map.read_region(&region), extended))
+    }
+
+    fn journal_changes(&mut self, before: Option<Journaled>, changed: &[Vec3D]) {
+        let (journal, (region, before, extended)) = match (&mut self.journal, before) {
+            (Some(journal), Some(before)) => (journal, before),
+            _ => return,
+        };
+
+        let min = *region.get_min();
+
+        for position in changed {
+            journal.push(BlockChange {
+                position: *position,
+                old: before.get_block(&Vec3D::new(
+                    position.0 - min.0,
+                    position.1 - min.1,
+                    position.2 - min.2,
+                )),
+                new: self.map.get_block(position),
+                extended: extended.get(position).copied(),
+            });
+        }
+    }
+
     pub fn get_chunks(&self) -> &Vec<u8> {
         self.map.get_chunks()
     }
@@ -87,18 +384,152 @@ impl World {
         self.map.get_size()
     }
 
-    pub fn get_spawnarea(&self) -> Vec3D {
-        self.map.get_spawnarea()
+    pub fn get_spawn(&self) -> Transform {
+        self.map.get_spawn()
+    }
+
+    pub fn set_spawn(&mut self, spawn: Transform) {
+        self.map.set_spawn(spawn);
+        self.modified = true;
+    }
+
+    /// Swaps the map for another one, dropping the blocks queued for physics.
+    pub fn set_map(&mut self, map: Box<dyn Map + Send + Sync>, modified: bool) {
+        self.map = map;
+        self.physics = Physics::new(self.physics.get_level());
+        self.modified = modified;
     }
 
-    pub fn get_spawnyaw(&self) -> u8 {
-        self.map.get_spawnyaw()
+    pub fn get_map(&self) -> &dyn Map {
+        self.map.as_ref()
     }
 
-    pub fn get_spawnpitch(&self) -> u8 {
-        self.map.get_spawnpitch()
+    /// Whether the map changed since it was loaded or saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Whether players are kept from building in this world, whatever the server's state.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Writes the map into the maps folder, under the name of the world.
+    /// Saves the map into the maps folder, as a .lvl file.
+    pub fn save(&mut self, maps_folder: &Path) -> bool {
+        let saved = MCSharpMap::save(maps_folder, &self.name, self.map.as_ref());
+
+        self.modified &= !saved;
+
+        saved
     }
 
     // TODO: Add unload function to safely unload and save the map.
     // TODO: Add save functionality, both to world and to map.
 }
+
+#[cfg(test)]
+mod test_world {
+    use super::super::maps::MemoryMap;
+    use super::*;
+
+    #[test]
+    /// Entity ids are allocated lowest first, and reused once a player leaves.
+    pub fn entity_id_reuse() {
+        let mut world = World::new(
+            String::from("test"),
+            Box::new(MemoryMap::new(Vec3D::new(16, 16, 16))),
+        );
+
+        assert_eq!(world.add_player(10), Some(0));
+        assert_eq!(world.add_player(11), Some(1));
+        assert_eq!(world.add_player(12), Some(2));
+        // Joining twice keeps the same id.
+        assert_eq!(world.add_player(11), Some(1));
+
+        assert_eq!(world.remove_player(11), Some(1));
+        assert_eq!(world.get_entity_id(11), None);
+        assert_eq!(world.add_player(13), Some(1));
+    }
+
+    #[test]
+    /// Worlds hold up to 255 players, beyond the classic 128 entity limit.
+    pub fn entity_id_limit() {
+        let mut world = World::new(
+            String::from("test"),
+            Box::new(MemoryMap::new(Vec3D::new(16, 16, 16))),
+        );
+
+        for uid in 1..=255 {
+            assert!(world.add_player(uid).is_some());
+        }
+
+        assert_eq!(world.get_entity_id(255), Some(MAX_EXTENDED_ENTITY_ID));
+        assert_eq!(world.add_player(256), None);
+    }
+
+    #[test]
+    /// Recording keeps what every changed block was before, whichever way it was changed.
+    pub fn record_changes() {
+        let mut world = World::new(
+            String::from("test"),
+            Box::new(MemoryMap::empty(Vec3D::new(16, 16, 16))),
+        );
+
+        world.set_block(&Vec3D::new(1, 1, 1), 3, false);
+
+        let region = Region::new(Vec3D::new(0, 1, 0), Vec3D::new(2, 1, 2));
+        let (changed, changes) = world.record(|world| {
+            world.set_block(&Vec3D::new(5, 5, 5), 4, false);
+
+            world.fill(&region, 2)
+        });
+
+        assert_eq!(changed.len(), 9);
+        assert_eq!(changes.len(), 10);
+        assert!(changes.contains(&BlockChange {
+            position: Vec3D::new(1, 1, 1),
+            old: 3,
+            new: 2,
+            extended: None
+        }));
+
+        // Changes outside of recording are not kept.
+        world.set_block(&Vec3D::new(6, 6, 6), 1, false);
+        assert!(world.record(|_| ()).1.is_empty());
+    }
+
+    #[test]
+    /// Reverting recorded changes puts extended blocks back, not only their fallback.
+    pub fn revert_extended() {
+        let mut map = MemoryMap::new(Vec3D::new(16, 16, 16));
+        let position = Vec3D::new(2, 1, 3);
+        let index = map.get_data_index(&position);
+        map.set_extended_block(index, 256 + 70, block::STONE);
+
+        let mut world = World::new(String::from("test"), Box::new(map));
+        let region = Region::new(Vec3D::new(0, 0, 0), Vec3D::new(4, 4, 4));
+
+        let (_, changes) = world.record(|world| {
+            world.fill(&region, block::GOLD);
+            world.set_block(&position, block::IRON, false)
+        });
+
+        let extended: Vec<_> = changes
+            .iter()
+            .filter_map(|change| Some((change.position, change.extended?)))
+            .collect();
+        assert_eq!(extended, vec![(position, 256 + 70)]);
+        assert!(world.map.get_extended_blocks().is_empty());
+
+        world.revert(&changes);
+
+        assert_eq!(world.get_block(&position), block::STONE);
+        assert_eq!(world.map.get_extended_block(&position), Some(256 + 70));
+        assert_eq!(world.count(&region, block::GOLD), 0);
+    }
+}
@@ -0,0 +1,621 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Zones of a world only some players may build in, kept next to the map in "{world}.zones".
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::super::network::{RemoveSelection, SelectionCuboid};
+use super::events::{PlayerLeaveEvent, SetBlockEvent};
+use super::{
+    is_valid_map_name, Action, Command, Core, Event, EventBus, PlayerSnapshot, Plugin, Priority,
+    Rank, Region, Vec3D,
+};
+
+/// Most zones a world may have, as clients outline them by a byte id.
+pub const MAX_ZONES: usize = 255;
+
+/// Cuboid inside a world where only players of a rank, or listed by name, can build.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Zone {
+    pub name: String,
+    pub region: Region,
+    pub rank: Rank,
+    pub players: Vec<String>,
+}
+
+impl Zone {
+    pub fn can_build(&self, player: &str, rank: Rank) -> bool {
+        rank >= self.rank
+            || self
+                .players
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(player))
+    }
+
+    // One line of a zones file: name, both corners, rank, then the players separated by commas.
+    fn to_line(&self) -> String {
+        let (min, max) = (self.region.get_min(), self.region.get_max());
+
+        format!(
+            "{} {} {} {} {} {} {} {} {}",
+            self.name,
+            min.0,
+            min.1,
+            min.2,
+            max.0,
+            max.1,
+            max.2,
+            self.rank.get_name(),
+            self.players.join(",")
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Zone> {
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+
+        if parts.len() < 8 || parts.len() > 9 {
+            return None;
+        }
+
+        let number = |index: usize| parts[index].parse::<u16>().ok();
+
+        Some(Zone {
+            name: String::from(parts[0]),
+            region: Region::new(
+                Vec3D::new(number(1)?, number(2)?, number(3)?),
+                Vec3D::new(number(4)?, number(5)?, number(6)?),
+            ),
+            rank: parts[7].parse().ok()?,
+            players: parts
+                .get(8)
+                .map(|players| players.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// File the zones of a world are kept in, next to its map.
+pub fn get_zones_path(maps_folder: &Path, world: &str) -> PathBuf {
+    maps_folder.join(format!("{}.zones", world))
+}
+
+/// Reads zones from a file, none if it does not exist. Invalid lines are skipped.
+pub fn load_zones(path: &Path) -> Vec<Zone> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let zone = Zone::from_line(line);
+
+            if zone.is_none() {
+                Core::static_log(&format!(
+                    "Invalid zone \"{}\" in \"{}\".",
+                    line,
+                    path.display()
+                ));
+            }
+
+            zone
+        })
+        .collect()
+}
+
+/// Writes zones into a file, removing the file once there are none.
+pub fn save_zones(path: &Path, zones: &[Zone]) -> bool {
+    let written = if zones.is_empty() {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    } else {
+        let lines: Vec<String> = zones.iter().map(Zone::to_line).collect();
+
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, lines.join("\n") + "\n"))
+    };
+
+    match written {
+        Ok(()) => true,
+        Err(e) => {
+            Core::static_log(&format!(
+                "Unable to save zones into \"{}\": {}",
+                path.display(),
+                e
+            ));
+
+            false
+        }
+    }
+}
+
+// Outline colors of zones by the rank they need, red, green, blue and opacity.
+fn get_color(rank: Rank) -> [u16; 4] {
+    match rank {
+        Rank::Guest => [160, 160, 160, 48],
+        Rank::Builder => [64, 224, 64, 48],
+        Rank::Operator => [224, 64, 64, 48],
+        Rank::Owner => [160, 0, 0, 48],
+    }
+}
+
+/// Outlines the zones of a world for a player, removing the outlines sent before.
+pub fn show_zones(core: &Core, uid: usize, world: &str, previous: usize) {
+    let zones = core
+        .with_world(world, |world| world.get_zones().clone())
+        .unwrap_or_default();
+
+    for id in 0..previous.min(MAX_ZONES) {
+        core.queue_action(Action::SendExtPacket(
+            uid,
+            "SelectionCuboid",
+            Box::new(RemoveSelection::new(id as u8)),
+        ));
+    }
+
+    for (id, zone) in zones.iter().enumerate().take(MAX_ZONES) {
+        let (min, max) = (*zone.region.get_min(), *zone.region.get_max());
+
+        core.queue_action(Action::SendExtPacket(
+            uid,
+            "SelectionCuboid",
+            Box::new(SelectionCuboid::new(
+                id as u8,
+                zone.name.clone(),
+                min,
+                Vec3D::new(max.0 + 1, max.1 + 1, max.2 + 1),
+                get_color(zone.rank),
+            )),
+        ));
+    }
+}
+
+// Zone being marked by a player: its name, who can build, and the corners so far.
+struct Pending {
+    zone: Zone,
+    world: String,
+    marks: Vec<Vec3D>,
+}
+
+type Sessions = Arc<Mutex<HashMap<usize, Pending>>>;
+
+/// Commands adding zones by marking their corners, and listing them.
+pub struct ZonePlugin {
+    sessions: Sessions,
+}
+
+impl ZonePlugin {
+    pub fn new() -> ZonePlugin {
+        ZonePlugin {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl Default for ZonePlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for ZonePlugin {
+    fn get_name(&self) -> &str {
+        "Zones"
+    }
+
+    fn register(&mut self, events: &mut EventBus) {
+        let sessions = self.sessions.clone();
+
+        events.register(
+            Priority::Highest,
+            move |core: &Core, event: &mut SetBlockEvent| mark(&sessions, core, event),
+        );
+
+        let sessions = self.sessions.clone();
+
+        events.register(
+            Priority::Lowest,
+            move |_: &Core, event: &mut PlayerLeaveEvent| {
+                sessions.lock().unwrap().remove(&event.player.uid);
+            },
+        );
+    }
+
+    fn enable(&mut self, core: &Core) {
+        let sessions = self.sessions.clone();
+
+        core.register_command(Command::new(
+            self.get_name(),
+            "zone",
+            "Manages zones. add {name} [rank] [players...]|del {name}|list|info [name]",
+            Rank::Guest,
+            move |core: &Core, player: &PlayerSnapshot, args: &str| {
+                zone_command(&sessions, core, player, args)
+            },
+        ));
+    }
+}
+
+fn zone_command(sessions: &Sessions, core: &Core, player: &PlayerSnapshot, args: &str) {
+    let args: Vec<&str> = args.split_ascii_whitespace().collect();
+    let uid = player.uid;
+
+    match args.as_slice() {
+        ["add", ..] | ["del", ..] if player.rank < Rank::Operator => {
+            core.send_message_to(uid, "&8You need to be operator to change zones.");
+        }
+        ["add", name, rest @ ..] => {
+            if !is_valid_map_name(name) {
+                core.send_message_to(uid, &format!("&8\"{}\" is not a valid zone name.", name));
+
+                return;
+            }
+
+            let mut zone = Zone {
+                name: String::from(*name),
+                region: Region::new(Vec3D::new(0, 0, 0), Vec3D::new(0, 0, 0)),
+                rank: Rank::Operator,
+                players: Vec::new(),
+            };
+
+            for entry in rest {
+                match entry.parse() {
+                    Ok(rank) => zone.rank = rank,
+                    Err(_) => zone.players.push(String::from(*entry)),
+                }
+            }
+
+            sessions.lock().unwrap().insert(
+                uid,
+                Pending {
+                    zone,
+                    world: player.world.clone(),
+                    marks: Vec::new(),
+                },
+            );
+
+            core.send_message_to(uid, "&7Place or break two blocks to mark the corners.");
+        }
+        ["del", name] => {
+            let removed = core
+                .with_world_mut(&player.world, |world| {
+                    let before = world.get_zones().len();
+
+                    world.remove_zone(name).map(|_| before)
+                })
+                .flatten();
+
+            match removed {
+                Some(before) => {
+                    zones_changed(core, &player.world, before);
+                    core.send_message_to(uid, &format!("&7Zone \"{}\" removed.", name));
+                }
+                None => core.send_message_to(uid, &format!("&8No zone named \"{}\".", name)),
+            }
+        }
+        ["list"] => {
+            let zones = core
+                .with_world(&player.world, |world| world.get_zones().clone())
+                .unwrap_or_default();
+
+            core.send_message_to(
+                uid,
+                &format!("&6Zones of \"{}\": &7{}", player.world, zones.len()),
+            );
+
+            for zone in zones {
+                core.send_message_to(uid, &format!("&7{}", zone.name));
+            }
+        }
+        ["info", rest @ ..] if rest.len() <= 1 => {
+            let position = player.transform.get_pos();
+            let here = Vec3D::new(position.0 / 32, position.1 / 32, position.2 / 32);
+
+            let zones: Vec<Zone> = core
+                .with_world(&player.world, |world| {
+                    world
+                        .get_zones()
+                        .iter()
+                        .filter(|zone| match rest.first() {
+                            Some(name) => zone.name.eq_ignore_ascii_case(name),
+                            None => zone.region.contains(&here),
+                        })
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            if zones.is_empty() {
+                core.send_message_to(uid, "&7No zones found.");
+            }
+
+            for zone in zones {
+                let (min, max) = (zone.region.get_min(), zone.region.get_max());
+
+                core.send_message_to(
+                    uid,
+                    &format!(
+                        "&6{}&7: ({}, {}, {}) to ({}, {}, {}), {} and up",
+                        zone.name,
+                        min.0,
+                        min.1,
+                        min.2,
+                        max.0,
+                        max.1,
+                        max.2,
+                        zone.rank.get_name()
+                    ),
+                );
+
+                if !zone.players.is_empty() {
+                    core.send_message_to(uid, &format!("&7Also: {}", zone.players.join(", ")));
+                }
+            }
+        }
+        _ => core.send_message_to(
+            uid,
+            "&7Usage: /zone add {name} [rank] [players...]|del {name}|list|info [name]",
+        ),
+    }
+}
+
+// Saves the zones of a world and outlines them again for everyone inside.
+fn zones_changed(core: &Core, world: &str, previous: usize) {
+    let zones = core
+        .with_world(world, |world| world.get_zones().clone())
+        .unwrap_or_default();
+
+    save_zones(&get_zones_path(core.get_maps_folder(), world), &zones);
+
+    for uid in core.get_world_players(world) {
+        show_zones(core, uid, world, previous);
+    }
+}
+
+fn mark(sessions: &Sessions, core: &Core, event: &mut SetBlockEvent) {
+    let mut sessions = sessions.lock().unwrap();
+
+    let pending = match sessions.get_mut(&event.player.uid) {
+        Some(pending) => pending,
+        None => return,
+    };
+
+    event.set_cancelled(true);
+
+    if pending.world != event.world {
+        pending.world = event.world.clone();
+        pending.marks.clear();
+    }
+
+    pending.marks.push(event.position);
+
+    if pending.marks.len() < 2 {
+        let Vec3D(x, y, z) = event.position;
+
+        core.send_message_to(
+            event.player.uid,
+            &format!("&7Marked ({}, {}, {}), place the next mark.", x, y, z),
+        );
+
+        return;
+    }
+
+    let Pending {
+        mut zone,
+        world,
+        marks,
+    } = sessions.remove(&event.player.uid).unwrap();
+    drop(sessions);
+
+    zone.region = Region::new(marks[0], marks[1]);
+    let name = zone.name.clone();
+
+    let added = core.with_world_mut(&world, |world| {
+        let before = world.get_zones().len();
+
+        (world.add_zone(zone), before)
+    });
+
+    match added {
+        Some((true, before)) => {
+            zones_changed(core, &world, before);
+            core.send_message_to(event.player.uid, &format!("&7Zone \"{}\" added.", name));
+        }
+        Some((false, _)) => core.send_message_to(
+            event.player.uid,
+            &format!(
+                "&8Zone \"{}\" already exists, or the world has {} zones.",
+                name, MAX_ZONES
+            ),
+        ),
+        None => core.send_message_to(event.player.uid, "&8Unable to add the zone."),
+    }
+}
+
+#[cfg(test)]
+mod test_zone {
+    use super::super::maps::MemoryMap;
+    use super::super::{Config, NetworkPlayer, Outgoing, Player, Transport, World};
+    use super::*;
+    use std::env;
+    use std::sync::mpsc;
+
+    #[test]
+    /// Zones are saved and read back, only letting their rank and players build.
+    pub fn save_and_load() {
+        let zone = Zone {
+            name: String::from("spawn"),
+            region: Region::new(Vec3D::new(4, 0, 4), Vec3D::new(1, 10, 2)),
+            rank: Rank::Operator,
+            players: vec![String::from("Alice"), String::from("bob")],
+        };
+
+        assert!(zone.can_build("alice", Rank::Guest));
+        assert!(zone.can_build("carol", Rank::Owner));
+        assert!(!zone.can_build("carol", Rank::Builder));
+
+        let path = env::temp_dir().join(format!("rcclassic-zones-{}", std::process::id()));
+        let open = Zone {
+            name: String::from("open"),
+            players: Vec::new(),
+            rank: Rank::Guest,
+            ..zone.clone()
+        };
+
+        assert!(save_zones(&path, &[zone.clone(), open.clone()]));
+        assert_eq!(load_zones(&path), vec![zone.clone(), open]);
+
+        assert!(save_zones(&path, &[]));
+        assert!(!path.exists());
+
+        let mut world = World::new(
+            String::from("test"),
+            Box::new(MemoryMap::new(Vec3D::new(16, 16, 16))),
+        );
+
+        assert!(world.add_zone(zone.clone()));
+        assert!(!world.add_zone(zone));
+        assert_eq!(
+            world
+                .get_blocking_zone("carol", Rank::Builder, &Vec3D::new(2, 5, 3))
+                .map(|zone| zone.name.as_str()),
+            Some("spawn")
+        );
+        assert!(world
+            .get_blocking_zone("carol", Rank::Builder, &Vec3D::new(5, 5, 3))
+            .is_none());
+        assert!(world.remove_zone("SPAWN").is_some());
+    }
+
+    #[test]
+    /// Players outside of a zone's rank cannot build in it, by hand or with draw commands.
+    pub fn enforced() {
+        let folder =
+            env::temp_dir().join(format!("rcclassic-zones-enforced-{}", std::process::id()));
+        let mut core = Core::with_config(1, Config::in_folder(&folder));
+        core.get_config_mut().read_only = false;
+
+        let mut builder = core.get_player_snapshot(0).unwrap();
+        builder.rank = Rank::Builder;
+
+        core.with_world_mut("main", |world| {
+            world.add_zone(Zone {
+                name: String::from("spawn"),
+                region: Region::new(Vec3D::new(0, 40, 0), Vec3D::new(3, 45, 3)),
+                rank: Rank::Operator,
+                players: Vec::new(),
+            })
+        });
+
+        let click = |core: &Core, player: &PlayerSnapshot, x: u16| {
+            let mut event = SetBlockEvent::new(
+                player.clone(),
+                String::from("main"),
+                Vec3D::new(x, 40, 1),
+                1,
+                false,
+            );
+
+            core.call_event(&mut event)
+        };
+
+        assert!(click(&core, &builder, 1));
+        assert!(!click(&core, &builder, 5));
+
+        // Half of the cuboid is inside the zone, so none of it is drawn.
+        assert!(core.try_run_command(&builder, "/cuboid gold"));
+        assert!(click(&core, &builder, 2));
+        assert!(click(&core, &builder, 6));
+
+        let gold = core.with_world("main", |world| {
+            world
+                .get_chunks()
+                .iter()
+                .filter(|b| **b == super::super::block::GOLD)
+                .count()
+        });
+        assert_eq!(gold, Some(0));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    /// Outlines only reach clients which negotiated SelectionCuboid.
+    pub fn outlines_need_extension() {
+        let folder =
+            env::temp_dir().join(format!("rcclassic-zones-outlines-{}", std::process::id()));
+        let core = Core::with_config(1, Config::in_folder(&folder));
+
+        core.with_world_mut("main", |world| {
+            world.add_zone(Zone {
+                name: String::from("spawn"),
+                region: Region::new(Vec3D::new(0, 0, 0), Vec3D::new(3, 3, 3)),
+                rank: Rank::Operator,
+                players: Vec::new(),
+            })
+        });
+
+        let mut inboxes = Vec::new();
+
+        for (uid, extension) in [(1, None), (2, Some("SelectionCuboid"))] {
+            let (writer, inbox) = mpsc::channel();
+            let mut player = NetworkPlayer::new(uid, writer, Transport::Classic);
+
+            if let Some(extension) = extension {
+                player.add_extension(extension);
+            }
+
+            core.add_player(uid, Box::new(player));
+            show_zones(&core, uid, "main", 1);
+            inboxes.push(inbox);
+        }
+
+        core.process_actions();
+
+        let ids = |inbox: &mpsc::Receiver<Outgoing>| {
+            inbox
+                .try_iter()
+                .filter_map(|outgoing| match outgoing {
+                    Outgoing::Data(data) => Some(data[0]),
+                    Outgoing::Close => None,
+                })
+                .collect::<Vec<u8>>()
+        };
+
+        assert!(ids(&inboxes[0]).is_empty());
+        assert_eq!(
+            ids(&inboxes[1]),
+            vec![RemoveSelection::ID, SelectionCuboid::ID]
+        );
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
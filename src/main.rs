@@ -21,9 +21,8 @@
 */
 
 use std::env;
-use std::thread;
 
-use rcclassic::core::{Core, Network};
+use rcclassic::core::Core;
 
 fn main() {
     // Thread size.
@@ -36,6 +35,9 @@ fn main() {
     // Initialize memory channels.
     core.generate_mem_chans();
 
+    core.load_native_plugins();
+    core.start_ticking();
+
     // Move receiver into main thread to handle receiving network packets.
     //let main_receiver = core.take_receiver();
     core.network_listen();
@@ -21,9 +21,14 @@
 */
 
 use super::super::core::events;
-use super::super::core::{BufferReader, Core, Player, Vec3D};
+use super::super::core::{BufferReader, Core, LoginState, Player, PlayerSnapshot, Vec3D};
 use super::*;
 
+/// Padding byte of PlayerIdentification sent by clients supporting CPE.
+pub const CPE_MAGIC_NUMBER: u8 = 0x42;
+/// Software name sent to CPE clients.
+pub const SERVER_SOFTWARE: &str = "RustCraftClassic";
+
 pub struct PlayerIdentification {
     sender: usize,
     protocol_version: u8,
@@ -52,6 +57,22 @@ impl PlayerIdentification {
             magic_number,
         }
     }
+
+    pub fn get_protocol_version(&self) -> u8 {
+        self.protocol_version
+    }
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn get_verification_key(&self) -> &str {
+        &self.verification_key
+    }
+
+    pub fn get_magic_number(&self) -> u8 {
+        self.magic_number
+    }
 }
 
 impl NetworkPacket for PlayerIdentification {
@@ -66,40 +87,83 @@ impl NetworkPacket for PlayerIdentification {
         self.sender
     }
 
+    fn get_required_state(&self) -> Option<LoginState> {
+        Some(LoginState::Connected)
+    }
+
     fn handle_receive(&self, core: &mut Core) {
         if let Some(mut player) = self.get_sender_mut(core) {
-            player.set_name(&self.username);
-            // TODO: Add actual ranks/colors. (Or option for it).
-            let color_code = {
-                "&7"
+            let config = core.get_config();
+            let verified = config.is_name_verified(&self.username, &self.verification_key);
+
+            if config.verify_names && !verified {
+                Core::static_log(&format!(
+                    "Refused login of \"{}\", the name could not be verified.",
+                    self.username
+                ));
+
+                player.disconnect("Login failed! Close the game and sign in again.");
+
+                return;
+            }
+
+            // Names are only trusted once verified, anyone could log in with the name of an operator.
+            let rank = if verified {
+                core.get_ranks().get(&self.username)
+            } else {
+                config.default_rank
             };
 
-            player.set_display_name(&format!("{}{}", color_code, &self.username));
+            player.set_name(&self.username);
+            player.set_rank(rank);
+            player.set_display_name(&format!("{}{}", rank.get_color(), &self.username));
 
             player.set_uid(self.get_sender_uid());
 
-            // TODO: Send CPE packets.
+            // CPE clients mark the identification, the login goes on once extensions are exchanged.
+            if self.magic_number == CPE_MAGIC_NUMBER {
+                player.set_login_state(LoginState::Negotiating);
 
-            let identify_packet = Box::new(ServerIdentification::new(
-                0x07,
-                String::from("RustCraftClassic by Ali Deym (Rust <3)"),
-                String::from("RustCraftClassic by Ali Deym (Rust <3) +hax"),
-                0x00,
-            ));
+                player.handle_packet(Box::new(ExtInfo::new(
+                    0,
+                    String::from(SERVER_SOFTWARE),
+                    SUPPORTED_EXTENSIONS.len() as u16,
+                )));
 
-            player.handle_packet(identify_packet);
+                for (name, version) in SUPPORTED_EXTENSIONS.iter() {
+                    player.handle_packet(Box::new(ExtEntry::new(String::from(*name), *version)));
+                }
+            } else {
+                complete_login(core, player.as_mut());
+            }
+        }
+    }
+}
 
-            let mut main_world = core.get_world_mut("main").unwrap(); // TODO: Give proper message (main does not exist.)
+/// Identifies the server to the player and sends them to the main world.
+pub fn complete_login(core: &Core, player: &mut dyn Player) {
+    player.set_login_state(LoginState::Identified);
 
-            Core::static_log(&format!(
-                "Player instantiated: {}",
-                player.get_display_name()
-            ));
+    let identify_packet = Box::new(ServerIdentification::new(
+        0x07,
+        String::from("RustCraftClassic by Ali Deym (Rust <3)"),
+        String::from("RustCraftClassic by Ali Deym (Rust <3) +hax"),
+        0x00,
+    ));
 
-            events::player::on_joined(core, player.as_mut());
+    player.handle_packet(identify_packet);
 
-            core.send_map_direct(player, &mut main_world);
-        } // TODO: Handle case where player is not found or not instantiated.
+    Core::static_log(&format!(
+        "Player instantiated: {}",
+        player.get_display_name()
+    ));
+
+    let mut event = events::PlayerJoinEvent::new(PlayerSnapshot::new(player));
+
+    if core.call_event(&mut event) {
+        player.disconnect("You are not allowed to join.");
+    } else if !core.send_map(player, "main") {
+        player.disconnect("Unable to join the main world.");
     }
 }
 
@@ -143,43 +207,59 @@ impl NetworkPacket for PlayerSetBlock {
         self.sender
     }
 
+    fn get_required_state(&self) -> Option<LoginState> {
+        Some(LoginState::InWorld)
+    }
+
     fn handle_receive(&self, core: &mut Core) {
         if let Some(mut player) = self.get_sender_mut(core) {
-            if let Some(mut world) = core.get_world_mut(player.get_world()) {
-                let mut destroy = false;
+            let world = String::from(player.get_world());
+            let destroy = self.mode == 0x0;
 
-                if self.mode == 0x0 {
-                    destroy = true;
-                }
+            // Clients cannot click outside of the map, such packets are ignored.
+            let inside = core.with_world(&world, |world| {
+                let size = world.get_size();
+                let position = &self.position;
+
+                position.0 < size.0 && position.1 < size.1 && position.2 < size.2
+            });
 
-                // Events did not block the placement/destroy of block:
-                if !events::world::on_setblock(
-                    core,
-                    player.as_mut(),
-                    &mut world,
-                    self.position,
-                    self.block,
-                    destroy,
-                ) {
-                    world.set_block(&self.position, self.block, destroy);
-
-                    let sending_block = world.get_block(&self.position);
-
-                    for pid in world.get_players() {
-                        if *pid != player.get_uid() {
-                            if let Some(mut other) = core.get_player_by_uid_mut(*pid) {
-                                other.handle_packet(Box::new(ServerSetBlock::new(
-                                    self.position,
-                                    sending_block,
-                                )));
-                            }
-                        }
-                    }
+            if inside != Some(true) {
+                return;
+            }
+
+            let mut event = events::SetBlockEvent::new(
+                PlayerSnapshot::new(player.as_ref()),
+                world.clone(),
+                self.position,
+                self.block,
+                destroy,
+            );
+
+            // Events did not block the placement/destroy of block:
+            if !core.call_event(&mut event) {
+                let changed = core.with_world_mut(&world, |world| {
+                    let (_, changes) = world.record(|world| {
+                        world.set_block(&self.position, event.block, event.destroy)
+                    });
+
+                    (world.get_block(&self.position), changes)
+                });
+
+                if let Some((sending_block, changes)) = changed {
+                    core.record_edit(player.get_name(), &world, changes);
+
+                    core.send_to_world(&world, player.get_uid(), || {
+                        Box::new(ServerSetBlock::new(self.position, sending_block))
+                    });
                 }
+            }
 
-                let sending_block = world.get_block(&self.position);
-                // Player should receive the block no matter what.
-                // If the block is changed, they see the changes, otherwise they see the original.
+            // Player should receive the block no matter what.
+            // If the block is changed, they see the changes, otherwise they see the original.
+            if let Some(sending_block) =
+                core.with_world(&world, |world| world.get_block(&self.position))
+            {
                 player.handle_packet(Box::new(ServerSetBlock::new(self.position, sending_block)));
             }
         } // TODO: Handle case where player is not found or not instantiated.
@@ -199,13 +279,28 @@ pub struct PlayerPositionAndOrientation {
 impl PlayerPositionAndOrientation {
     pub const ID: u8 = 0x08;
     pub const SIZE: usize = 10;
-
-    pub fn new(buffer_reader: &mut BufferReader, sender: usize) -> PlayerPositionAndOrientation {
+    /// Size for clients supporting ExtEntityPositions, which send positions as ints.
+    pub const EXTENDED_SIZE: usize = 16;
+
+    pub fn new(
+        buffer_reader: &mut BufferReader,
+        sender: usize,
+        extended_positions: bool,
+    ) -> PlayerPositionAndOrientation {
         let player_id = buffer_reader.read_byte();
 
-        let x = buffer_reader.read_ushort();
-        let y = buffer_reader.read_ushort();
-        let z = buffer_reader.read_ushort();
+        let mut read_axis = || {
+            if extended_positions {
+                // Positions outside of what worlds can hold are clamped.
+                buffer_reader.read_int().clamp(0, i32::from(u16::MAX)) as u16
+            } else {
+                buffer_reader.read_ushort()
+            }
+        };
+
+        let x = read_axis();
+        let y = read_axis();
+        let z = read_axis();
 
         let yaw = buffer_reader.read_byte();
         let pitch = buffer_reader.read_byte();
@@ -220,6 +315,10 @@ impl PlayerPositionAndOrientation {
             yaw,
         }
     }
+
+    pub fn get_player_id(&self) -> u8 {
+        self.player_id
+    }
 }
 
 impl NetworkPacket for PlayerPositionAndOrientation {
@@ -234,9 +333,14 @@ impl NetworkPacket for PlayerPositionAndOrientation {
         self.sender
     }
 
+    fn get_required_state(&self) -> Option<LoginState> {
+        Some(LoginState::InWorld)
+    }
+
     fn handle_receive(&self, core: &mut Core) {
         if let Some(mut player) = self.get_sender_mut(core) {
             let pid = player.get_uid();
+            let from = player.get_transform().clone();
             let transform = player.get_transform_mut();
 
             transform.set_pos(self.x, self.y, self.z);
@@ -245,18 +349,31 @@ impl NetworkPacket for PlayerPositionAndOrientation {
 
             let transform = transform.clone(); // Create a copied version cause we have modified it.
 
-            if let Some(world) = core.get_world(player.get_world()) {
-                // TODO: Update player to the others.
-                for p in world.get_players() {
-                    if *p != pid {
-                        if let Some(mut other) = core.get_player_by_uid_mut(*p) {
-                            other.handle_packet(Box::new(ServerPositionAndOrientation::new(
-                                pid as i8,
-                                transform.clone(),
-                            )));
-                        }
-                    }
-                }
+            let mut event = events::PlayerMoveEvent::new(
+                PlayerSnapshot::new(player.as_ref()),
+                from.clone(),
+                transform.clone(),
+            );
+
+            // Cancelled, back to where the player was.
+            if core.call_event(&mut event) {
+                player.update_transform(from);
+
+                return;
+            }
+
+            let world = String::from(player.get_world());
+
+            if let Some(entity_id) = core
+                .with_world(&world, |world| world.get_entity_id(pid))
+                .flatten()
+            {
+                core.send_entity_to_world(&world, entity_id, pid, || {
+                    Box::new(ServerPositionAndOrientation::new(
+                        entity_id,
+                        transform.clone(),
+                    ))
+                });
             }
         } // TODO: Handle case where player is not found or not instantiated.
     }
@@ -283,6 +400,10 @@ impl PlayerMessage {
         }
     }
 
+    pub fn get_unused(&self) -> u8 {
+        self.unused
+    }
+
     pub fn get_message(&self) -> &str {
         &self.message
     }
@@ -300,13 +421,19 @@ impl NetworkPacket for PlayerMessage {
         self.sender
     }
 
+    fn get_required_state(&self) -> Option<LoginState> {
+        Some(LoginState::InWorld)
+    }
+
     fn handle_receive(&self, core: &mut Core) {
         if let Some(mut player) = self.get_sender_mut(core) {
-            let event_handled =
-                events::server::on_message(&core, &mut player, self.message.clone());
+            let mut event = events::PlayerChatEvent::new(
+                PlayerSnapshot::new(player.as_ref()),
+                self.message.clone(),
+            );
 
-            if !event_handled {
-                let gen_str = format!("{}: &f{}", player.get_display_name(), self.message);
+            if !core.call_event(&mut event) {
+                let gen_str = format!("{}: &f{}", player.get_display_name(), event.message);
 
                 core.broadcast_message(player.as_mut(), &gen_str);
             }
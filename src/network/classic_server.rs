@@ -20,7 +20,9 @@
     SOFTWARE.
 */
 
-use super::super::core::{events, BufferWriter, Core, Transform, Vec3D};
+use super::super::core::{
+    events, BufferWriter, Core, LoginState, PlayerSnapshot, Transform, Vec3D,
+};
 use super::NetworkPacket;
 
 pub struct ServerIdentification {
@@ -78,6 +80,12 @@ impl LevelInitialize {
     }
 }
 
+impl Default for LevelInitialize {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NetworkPacket for LevelInitialize {
     fn get_id(&self) -> u8 {
         Self::ID
@@ -186,8 +194,9 @@ impl NetworkPacket for ServerSetBlock {
     }
 }
 
+/// Entity ids are sent as unsigned bytes, SELF_ENTITY_ID being the receiving player.
 pub struct SpawnPlayer {
-    player_id: i8,
+    player_id: u8,
     player_name: String,
     transform: Transform,
 }
@@ -196,7 +205,7 @@ impl SpawnPlayer {
     pub const ID: u8 = 0x07;
     pub const SIZE: usize = 74;
 
-    pub fn new(player_id: i8, player_name: String, transform: Transform) -> SpawnPlayer {
+    pub fn new(player_id: u8, player_name: String, transform: Transform) -> SpawnPlayer {
         SpawnPlayer {
             player_id,
             player_name,
@@ -214,7 +223,7 @@ impl NetworkPacket for SpawnPlayer {
     }
 
     fn handle_send(&self, buffer: &mut BufferWriter) {
-        buffer.write_sbyte(self.player_id);
+        buffer.write_byte(self.player_id);
 
         buffer.write_string(&self.player_name);
 
@@ -223,7 +232,7 @@ impl NetworkPacket for SpawnPlayer {
 }
 
 pub struct ServerPositionAndOrientation {
-    player_id: i8,
+    player_id: u8,
     transform: Transform,
 }
 
@@ -231,7 +240,7 @@ impl ServerPositionAndOrientation {
     pub const ID: u8 = 0x08;
     pub const SIZE: usize = 10;
 
-    pub fn new(player_id: i8, transform: Transform) -> ServerPositionAndOrientation {
+    pub fn new(player_id: u8, transform: Transform) -> ServerPositionAndOrientation {
         ServerPositionAndOrientation {
             player_id,
             transform,
@@ -248,21 +257,21 @@ impl NetworkPacket for ServerPositionAndOrientation {
     }
 
     fn handle_send(&self, buffer: &mut BufferWriter) {
-        buffer.write_sbyte(self.player_id);
+        buffer.write_byte(self.player_id);
 
         buffer.write_transform(&self.transform);
     }
 }
 
 pub struct DespawnPlayer {
-    player_id: i8,
+    player_id: u8,
 }
 
 impl DespawnPlayer {
     pub const ID: u8 = 0x0c;
     pub const SIZE: usize = 2;
 
-    pub fn new(player_id: i8) -> DespawnPlayer {
+    pub fn new(player_id: u8) -> DespawnPlayer {
         DespawnPlayer { player_id }
     }
 }
@@ -276,7 +285,7 @@ impl NetworkPacket for DespawnPlayer {
     }
 
     fn handle_send(&self, buffer: &mut BufferWriter) {
-        buffer.write_sbyte(self.player_id);
+        buffer.write_byte(self.player_id);
     }
 }
 
@@ -340,35 +349,28 @@ impl NetworkPacket for DisconnectPlayer {
 
     // Received from Network Thread.
     fn handle_receive(&self, core: &mut Core) {
-        let mut player_id = 0;
-        let mut found = false;
-
-        if let Some(mut ply) = self.get_sender_mut(core) {
-            // Player is found.
-            player_id = ply.get_uid();
-            found = true;
-
-            if let Some(mut world) = core.get_world_mut(ply.get_world()) {
-                // Despawn player from others.
-                for ply_id in world.get_players() {
-                    if *ply_id != ply.get_uid() {
-                        if let Some(mut other) = core.get_player_by_uid_mut(*ply_id) {
-                            let packet = Box::new(DespawnPlayer::new(ply.get_uid() as i8));
-
-                            other.handle_packet(packet);
-                        }
-                    }
-                }
-
-                // Remove from current world.
-                world.remove_player(ply.get_uid());
+        if let Some(ply) = self.get_sender_mut(core) {
+            // Player never made it past the identification, nobody knows about them.
+            if ply.get_login_state() == LoginState::Connected {
+                drop(ply);
+                core.remove_player_by_uid(self.sender);
+
+                return;
             }
 
+            // Remove from current world, despawning from others.
+            let world = String::from(ply.get_world());
+            core.leave_world(ply.get_uid(), &world);
+
             // Send message to everyone else. At this point, player entity is still valid. (Network may not be available though).
-            events::player::on_left(core, ply.as_mut());
+            core.call_event(&mut events::PlayerLeaveEvent::new(PlayerSnapshot::new(
+                ply.as_ref(),
+            )));
         }
 
-        // Finally, remove from core (If player existed):
-        core.remove_player_by_uid(player_id);
+        // Finally, remove from core (If player existed). Console can never disconnect.
+        if self.sender != 0 {
+            core.remove_player_by_uid(self.sender);
+        }
     }
 }
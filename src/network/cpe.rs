@@ -20,9 +20,24 @@
     SOFTWARE.
 */
 
-use super::super::core::{BufferReader, BufferWriter};
-use super::NetworkPacket;
+use super::super::core::{BufferReader, BufferWriter, Core, LoginState, Transform, Vec3D};
+use super::{complete_login, NetworkPacket};
 
+/// Extensions the server negotiates with CPE clients, with their versions.
+pub const SUPPORTED_EXTENSIONS: [(&str, i32); 3] = [
+    ("ExtEntityPositions", 1),
+    ("SelectionCuboid", 1),
+    ("SetSpawnpoint", 1),
+];
+
+/// Whether an extension the client announced is one the server negotiates.
+pub fn is_supported_extension(name: &str, version: i32) -> bool {
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .any(|(supported, supported_version)| *supported == name && *supported_version == version)
+}
+
+/// Starts the extension negotiation, followed by one ExtEntry per extension.
 pub struct ExtInfo {
     sender: usize,
     app_name: String,
@@ -30,6 +45,9 @@ pub struct ExtInfo {
 }
 
 impl ExtInfo {
+    pub const ID: u8 = 0x10;
+    pub const SIZE: usize = 67;
+
     pub fn new(sender: usize, app_name: String, ext_count: u16) -> ExtInfo {
         ExtInfo {
             sender,
@@ -48,17 +66,232 @@ impl ExtInfo {
             ext_count,
         }
     }
+
+    pub fn get_app_name(&self) -> &str {
+        &self.app_name
+    }
+
+    pub fn get_ext_count(&self) -> u16 {
+        self.ext_count
+    }
 }
 
 impl NetworkPacket for ExtInfo {
     fn get_id(&self) -> u8 {
-        0x00 // FIX-ME
+        Self::ID
+    }
+    fn get_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn get_sender_uid(&self) -> usize {
+        self.sender
+    }
+
+    fn get_required_state(&self) -> Option<LoginState> {
+        Some(LoginState::Negotiating)
+    }
+
+    fn handle_receive(&self, core: &mut Core) {
+        if let Some(mut player) = self.get_sender_mut(core) {
+            Core::static_log(&format!(
+                "{} is using {} with {} extension(s).",
+                player.get_name(),
+                self.app_name,
+                self.ext_count
+            ));
+
+            player.set_remaining_extensions(self.ext_count);
+
+            if self.ext_count == 0 {
+                complete_login(core, player.as_mut());
+            }
+        }
+    }
+
+    fn handle_send(&self, buffer: &mut BufferWriter) {
+        buffer.write_string(&self.app_name);
+        buffer.write_short(self.ext_count);
+    }
+}
+
+/// Extension supported by the sender, the login goes on once all of the client's are received.
+pub struct ExtEntry {
+    sender: usize,
+    name: String,
+    version: i32,
+}
+
+impl ExtEntry {
+    pub const ID: u8 = 0x11;
+    pub const SIZE: usize = 69;
+
+    pub fn new(name: String, version: i32) -> ExtEntry {
+        ExtEntry {
+            sender: 0,
+            name,
+            version,
+        }
+    }
+
+    pub fn from(buffer_reader: &mut BufferReader, sender: usize) -> ExtEntry {
+        let name = buffer_reader.read_string();
+        let version = buffer_reader.read_int();
+
+        ExtEntry {
+            sender,
+            name,
+            version,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_version(&self) -> i32 {
+        self.version
+    }
+}
+
+impl NetworkPacket for ExtEntry {
+    fn get_id(&self) -> u8 {
+        Self::ID
     }
     fn get_size(&self) -> usize {
-        0 // FIX-ME
+        Self::SIZE
     }
 
     fn get_sender_uid(&self) -> usize {
         self.sender
     }
+
+    fn get_required_state(&self) -> Option<LoginState> {
+        Some(LoginState::Negotiating)
+    }
+
+    fn handle_receive(&self, core: &mut Core) {
+        if let Some(mut player) = self.get_sender_mut(core) {
+            if is_supported_extension(&self.name, self.version) {
+                player.add_extension(&self.name);
+            }
+
+            let remaining = player.get_remaining_extensions().saturating_sub(1);
+            player.set_remaining_extensions(remaining);
+
+            if remaining == 0 {
+                complete_login(core, player.as_mut());
+            }
+        }
+    }
+
+    fn handle_send(&self, buffer: &mut BufferWriter) {
+        buffer.write_string(&self.name);
+        buffer.write_int(self.version);
+    }
+}
+
+/// Outlines a cuboid for clients supporting the SelectionCuboid extension.
+pub struct SelectionCuboid {
+    selection_id: u8,
+    label: String,
+    start: Vec3D,
+    end: Vec3D,
+    color: [u16; 4],
+}
+
+impl SelectionCuboid {
+    pub const ID: u8 = 0x1a;
+    pub const SIZE: usize = 86;
+
+    /// Selection from the start up to the end, the end excluded. Color is red, green, blue and opacity.
+    pub fn new(
+        selection_id: u8,
+        label: String,
+        start: Vec3D,
+        end: Vec3D,
+        color: [u16; 4],
+    ) -> SelectionCuboid {
+        SelectionCuboid {
+            selection_id,
+            label,
+            start,
+            end,
+            color,
+        }
+    }
+}
+
+impl NetworkPacket for SelectionCuboid {
+    fn get_id(&self) -> u8 {
+        Self::ID
+    }
+    fn get_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn handle_send(&self, buffer: &mut BufferWriter) {
+        buffer.write_byte(self.selection_id);
+        buffer.write_string(&self.label);
+
+        buffer.write_vec3d(&self.start);
+        buffer.write_vec3d(&self.end);
+
+        for channel in &self.color {
+            buffer.write_short(*channel);
+        }
+    }
+}
+
+pub struct RemoveSelection {
+    selection_id: u8,
+}
+
+impl RemoveSelection {
+    pub const ID: u8 = 0x1b;
+    pub const SIZE: usize = 2;
+
+    pub fn new(selection_id: u8) -> RemoveSelection {
+        RemoveSelection { selection_id }
+    }
+}
+
+impl NetworkPacket for RemoveSelection {
+    fn get_id(&self) -> u8 {
+        Self::ID
+    }
+    fn get_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn handle_send(&self, buffer: &mut BufferWriter) {
+        buffer.write_byte(self.selection_id);
+    }
+}
+
+/// Moves where clients supporting the SetSpawnpoint extension respawn.
+pub struct SetSpawnpoint {
+    spawn: Transform,
+}
+
+impl SetSpawnpoint {
+    pub const ID: u8 = 0x2e;
+    pub const SIZE: usize = 9;
+
+    pub fn new(spawn: Transform) -> SetSpawnpoint {
+        SetSpawnpoint { spawn }
+    }
+}
+
+impl NetworkPacket for SetSpawnpoint {
+    fn get_id(&self) -> u8 {
+        Self::ID
+    }
+    fn get_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn handle_send(&self, buffer: &mut BufferWriter) {
+        buffer.write_transform(&self.spawn);
+    }
 }
@@ -0,0 +1,120 @@
+/*
+    Copyright (c) 2020 Ali Deym
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+use std::sync::mpsc::Sender;
+
+use super::super::core::{encode_packet, Core, NetworkPlayer, Outgoing, Transport};
+use super::*;
+
+/// Sent by the network thread once a client has connected (and finished the WebSocket handshake, if any).
+/// Not a protocol packet; the core registers the player when receiving it.
+pub struct ConnectionOpened {
+    sender: usize,
+    writer: Sender<Outgoing>,
+    transport: Transport,
+}
+
+impl ConnectionOpened {
+    // Internal message, never sent to clients.
+    pub const ID: u8 = 0xff;
+    pub const SIZE: usize = 0;
+
+    pub fn new(sender: usize, writer: Sender<Outgoing>, transport: Transport) -> ConnectionOpened {
+        ConnectionOpened {
+            sender,
+            writer,
+            transport,
+        }
+    }
+}
+
+impl NetworkPacket for ConnectionOpened {
+    fn get_id(&self) -> u8 {
+        Self::ID
+    }
+    fn get_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn get_sender_uid(&self) -> usize {
+        self.sender
+    }
+
+    fn handle_receive(&self, core: &mut Core) {
+        if core.get_player_count() >= core.get_config().max_players {
+            Core::static_log(&format!(
+                "Refused connection of uid \"{}\", server is full.",
+                self.sender
+            ));
+
+            let packet = DisconnectPlayer::new(self.sender, String::from("Server is full!"));
+
+            // Closing the stream lets the network thread know, which sends back a disconnect to clean up.
+            self.writer
+                .send(Outgoing::Data(encode_packet(
+                    &packet,
+                    self.transport,
+                    false,
+                )))
+                .ok();
+            self.writer.send(Outgoing::Close).ok();
+
+            return;
+        }
+
+        core.add_player(
+            self.sender,
+            Box::new(NetworkPlayer::new(
+                self.sender,
+                self.writer.clone(),
+                self.transport,
+            )),
+        );
+    }
+}
+
+/// Sent by the tick thread every `TICK_INTERVAL`, runs scheduled tasks.
+#[derive(Default)]
+pub struct Tick;
+
+impl Tick {
+    // Internal message, never sent to clients.
+    pub const ID: u8 = 0xfe;
+    pub const SIZE: usize = 0;
+
+    pub fn new() -> Tick {
+        Tick
+    }
+}
+
+impl NetworkPacket for Tick {
+    fn get_id(&self) -> u8 {
+        Self::ID
+    }
+    fn get_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn handle_receive(&self, core: &mut Core) {
+        core.tick();
+    }
+}
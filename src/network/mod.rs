@@ -23,9 +23,11 @@
 mod classic_client;
 mod classic_server;
 mod cpe;
+mod internal;
 mod packet;
 
 pub use self::classic_client::*;
 pub use self::classic_server::*;
 pub use self::cpe::*;
+pub use self::internal::*;
 pub use self::packet::*;
@@ -22,9 +22,7 @@
 
 use std::cmp::PartialEq;
 
-use chashmap::{ReadGuard, WriteGuard};
-
-use super::super::core::{BufferWriter, Core, Player};
+use super::super::core::{BufferWriter, Core, LoginState, PlayerGuard};
 
 pub trait NetworkPacket {
     fn get_id(&self) -> u8;
@@ -34,27 +32,22 @@ pub trait NetworkPacket {
         0
     }
 
-    fn get_sender<'a>(
-        &'a self,
-        core: &'a Core,
-    ) -> Option<ReadGuard<usize, Box<dyn Player + Send + Sync>>> {
-        let uid = self.get_sender_uid();
-
-        core.get_player_by_uid(uid)
+    /// Login state the sender has to be in for the core to handle this packet.
+    /// None for internal messages, which are always handled.
+    fn get_required_state(&self) -> Option<LoginState> {
+        None
     }
 
-    fn get_sender_mut<'a>(
-        &'a self,
-        core: &'a Core,
-    ) -> Option<WriteGuard<usize, Box<dyn Player + Send + Sync>>> {
+    /// Locks the sending player, which stays locked until the guard is dropped.
+    fn get_sender_mut<'a>(&'a self, core: &'a Core) -> Option<PlayerGuard<'a>> {
         let uid = self.get_sender_uid();
 
         core.get_player_by_uid_mut(uid)
     }
 
-    fn handle_receive(&self, core: &mut Core) {}
+    fn handle_receive(&self, _core: &mut Core) {}
 
-    fn handle_send(&self, buffer: &mut BufferWriter) {}
+    fn handle_send(&self, _buffer: &mut BufferWriter) {}
 }
 /*
 impl PartialEq<dyn NetworkPacket> for dyn NetworkPacket {